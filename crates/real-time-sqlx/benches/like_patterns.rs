@@ -0,0 +1,73 @@
+//! Benchmark of matching rows against `like` and `ilike` subscriptions, with patterns
+//! parsed at each check or compiled once per subscription.
+//!
+//! Run with `cargo bench --bench like_patterns`.
+
+use std::time::{Duration, Instant};
+
+use real_time_sqlx::{
+    operations::serialize::{object_from_value, JsonObject},
+    queries::{serialize::QueryTree, Checkable, PatternCache},
+};
+
+/// Number of rows checked per measurement
+const ITERATIONS: u32 = 100_000;
+
+/// Query of a subscription with fixed patterns
+fn query() -> QueryTree {
+    serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "or",
+            "conditions": [
+                { "type": "single", "constraint": { "column": "title", "operator": "like", "value": "%urgent%review%" } },
+                { "type": "single", "constraint": { "column": "content", "operator": "ilike", "value": "%deadline ____-__-__%" } }
+            ]
+        }
+    }))
+    .unwrap()
+}
+
+/// Rows to check, most of them not matching
+fn rows() -> Vec<JsonObject> {
+    (0..100)
+        .map(|id| {
+            object_from_value(serde_json::json!({
+                "id": id,
+                "title": format!("Todo {id}: an urgent task that needs some work before the review"),
+                "content": format!("This is the content of todo {id}, with a DEADLINE on 2024-{:02}-01", id % 12 + 1),
+            }))
+            .unwrap()
+        })
+        .collect()
+}
+
+/// Time the checks of the rows against the query
+fn measure(query: &QueryTree, rows: &[JsonObject], patterns: &PatternCache) -> Duration {
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        std::hint::black_box(query.check_with(&rows[i as usize % rows.len()], patterns));
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let query = query();
+    let rows = rows();
+
+    for compiled in [false, true] {
+        let patterns = if compiled {
+            PatternCache::new(&query)
+        } else {
+            PatternCache::default()
+        };
+        let elapsed = measure(&query, &rows, &patterns);
+        println!(
+            "compiled patterns {}: {:?} per check",
+            if compiled { "on" } else { "off" },
+            elapsed / ITERATIONS
+        );
+    }
+}
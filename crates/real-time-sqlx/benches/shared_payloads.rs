@@ -0,0 +1,122 @@
+//! Benchmark of the notifications fan-out with and without shared payloads,
+//! counting the allocations made for channels writing JSON text.
+//!
+//! Run with `cargo bench --bench shared_payloads`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use real_time_sqlx::{
+    dispatcher::{NotificationChannel, Subscription, TableSubscriptions},
+    error::ChannelError,
+    operations::serialize::{JsonObject, OperationNotification},
+    queries::serialize::{FinalType, QueryTree},
+};
+use serde_json::json;
+
+/// Number of subscriptions receiving each notification
+const SUBSCRIPTIONS: usize = 200;
+
+/// Number of notifications per measurement
+const ITERATIONS: usize = 1_000;
+
+/// System allocator counting the allocations
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Channel writing the JSON text of its notifications, like a websocket server
+struct TextChannel;
+
+impl NotificationChannel for TextChannel {
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        std::hint::black_box(value.to_string());
+        Ok(())
+    }
+
+    fn send_serialized(&self, payload: Arc<str>) -> Result<(), ChannelError> {
+        std::hint::black_box(payload.as_bytes());
+        Ok(())
+    }
+}
+
+/// Subscriptions of every channel to the whole table
+async fn subscriptions(shared_payloads: bool) -> TableSubscriptions<TextChannel> {
+    let mut subscriptions = TableSubscriptions::new();
+    subscriptions.set_shared_payloads(shared_payloads);
+
+    let query: QueryTree =
+        serde_json::from_value(json!({ "return": "many", "table": "todos" })).unwrap();
+    for index in 0..SUBSCRIPTIONS {
+        subscriptions
+            .subscribe(
+                &index.to_string(),
+                Subscription::new(query.clone(), TextChannel),
+            )
+            .await
+            .unwrap();
+    }
+
+    subscriptions
+}
+
+/// Time the fan-out of update notifications, and count its allocations
+async fn measure(subscriptions: &TableSubscriptions<TextChannel>) -> (Duration, usize) {
+    let data: JsonObject = serde_json::from_value(json!({
+        "id": 1,
+        "title": "Benchmark todo",
+        "content": "A todo with a few fields to serialize",
+        "done": false
+    }))
+    .unwrap();
+    let notification = OperationNotification::Update {
+        table: "todos".to_string(),
+        id: FinalType::Number(1.into()),
+        data,
+        previous: None,
+    };
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(subscriptions.process(&notification).await);
+    }
+
+    (
+        start.elapsed(),
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+    )
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    for (label, shared_payloads) in [("per channel", false), ("shared", true)] {
+        let subscriptions = subscriptions(shared_payloads).await;
+        let (elapsed, allocations) = measure(&subscriptions).await;
+        println!(
+            "{label}: {:?} and {} allocations per notification ({SUBSCRIPTIONS} subscriptions)",
+            elapsed / ITERATIONS as u32,
+            allocations / ITERATIONS
+        );
+    }
+}
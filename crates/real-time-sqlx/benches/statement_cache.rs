@@ -0,0 +1,79 @@
+//! Benchmark of the fetch path with and without statement caching.
+//!
+//! Run with `cargo bench --bench statement_cache`.
+
+use std::time::{Duration, Instant};
+
+use real_time_sqlx::{
+    database::{set_statement_caching, sqlite::fetch_sqlite_query},
+    queries::serialize::QueryTree,
+};
+use sqlx::{Pool, Sqlite};
+
+/// Number of fetches per measurement
+const ITERATIONS: u32 = 10_000;
+
+/// Create an in-memory database with a seeded table
+async fn database() -> Pool<Sqlite> {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+
+    sqlx::query("CREATE TABLE todos (id INTEGER PRIMARY KEY, title TEXT NOT NULL)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    for id in 0..100 {
+        sqlx::query("INSERT INTO todos (id, title) VALUES (?, ?)")
+            .bind(id)
+            .bind(format!("Todo {id}"))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    pool
+}
+
+/// Time the fetches of a query, whose values change at each iteration
+async fn measure(pool: &Pool<Sqlite>, caching: bool) -> Duration {
+    set_statement_caching(caching);
+
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        let query: QueryTree = serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "or",
+                "conditions": [
+                    { "type": "single", "constraint": { "column": "id", "operator": "<", "value": i % 100 } },
+                    { "type": "single", "constraint": { "column": "title", "operator": "like", "value": "Todo 9%" } }
+                ]
+            },
+            "paginate": { "perPage": 10, "orderBy": { "column": "id", "order": "desc" } }
+        }))
+        .unwrap();
+
+        fetch_sqlite_query(&query, pool).await.unwrap();
+    }
+
+    start.elapsed()
+}
+
+#[tokio::main]
+async fn main() {
+    let pool = database().await;
+
+    for caching in [false, true] {
+        let elapsed = measure(&pool, caching).await;
+        println!(
+            "statement caching {}: {:?} per fetch",
+            if caching { "on" } else { "off" },
+            elapsed / ITERATIONS
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! Benchmark of the conversion of wide SQLite rows to JSON.
+//!
+//! Run with `cargo bench --bench wide_rows`.
+
+use std::time::{Duration, Instant};
+
+use real_time_sqlx::database::sqlite::sqlite_rows_to_json;
+use sqlx::{sqlite::SqliteRow, Pool, Sqlite};
+
+/// Number of columns of the wide table
+const COLUMNS: usize = 300;
+
+/// Number of rows of the wide table
+const ROWS: usize = 100;
+
+/// Number of conversions per measurement
+const ITERATIONS: u32 = 100;
+
+/// Create an in-memory database with a seeded wide table
+async fn database() -> Pool<Sqlite> {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+
+    let columns: Vec<String> = (0..COLUMNS)
+        .map(|index| match index % 3 {
+            0 => format!("int_{index} INTEGER"),
+            1 => format!("real_{index} REAL"),
+            _ => format!("text_{index} TEXT"),
+        })
+        .collect();
+    sqlx::query(&format!("CREATE TABLE wide ({})", columns.join(", ")))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let values: Vec<String> = (0..COLUMNS)
+        .map(|index| match index % 3 {
+            0 => index.to_string(),
+            1 => format!("{index}.5"),
+            _ => format!("'value {index}'"),
+        })
+        .collect();
+    for _ in 0..ROWS {
+        sqlx::query(&format!("INSERT INTO wide VALUES ({})", values.join(", ")))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    pool
+}
+
+/// Time the conversions of the rows to JSON
+fn measure(rows: &[SqliteRow]) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(sqlite_rows_to_json(rows));
+    }
+
+    start.elapsed()
+}
+
+#[tokio::main]
+async fn main() {
+    let pool = database().await;
+    let rows = sqlx::query("SELECT * FROM wide")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    let elapsed = measure(&rows);
+    println!(
+        "{COLUMNS} columns: {:?} per row",
+        elapsed / (ITERATIONS * ROWS as u32)
+    );
+}
@@ -1,130 +1,49 @@
 //! Tauri Channel-related operation processing implementations.
 
-use std::{collections::HashMap, hash::RandomState};
-
-use serde::Serialize;
-use tauri::ipc::Channel;
-use tokio::sync::RwLock;
+use tauri::{ipc::Channel, AppHandle, Emitter, Runtime};
 
 use crate::{
-    operations::serialize::{object_array_from_value, object_from_value, OperationNotification},
-    queries::{serialize::QueryTree, Checkable},
+    dispatcher::{EventChannel, EventEmitter, NotificationChannel},
+    error::ChannelError,
 };
 
-/// Process a database operation notification and notify the relevant
-/// Tauri channels about the change that occured.
-///
-/// Returns a list of channel uuid identifiers that errored out and should be pruned.
-pub fn process_channel_event<'a, T>(
-    channels: &'a HashMap<String, (QueryTree, Channel<serde_json::Value>)>,
-    operation: &OperationNotification<T>,
-) -> Vec<&'a str>
-where
-    T: Clone + Serialize,
-{
-    let serialized_operation = serde_json::to_value(operation).unwrap();
-    let data = serialized_operation.get("data").unwrap();
-
-    // Channels that error out, scheduled for pruning at the end.
-    let mut failing_channels: Vec<&str> = Vec::new();
-
-    match operation {
-        // For single-row operations, we simply push the operation to the channel
-        // if the query matches
-        OperationNotification::Create { .. } | OperationNotification::Delete { .. } => {
-            let object = object_from_value(data.clone()).unwrap();
-
-            for (key, (query, channel)) in channels.iter() {
-                if query.check(&object) {
-                    // Send an item to the channel, or schedule the channel for deletion
-                    if channel.send(serialized_operation.clone()).is_err() {
-                        failing_channels.push(key);
-                    }
-                }
-            }
-        }
-        OperationNotification::Update {
-            table,
-            data: notif_data,
-            id,
-        } => {
-            let object = object_from_value(data.clone()).unwrap();
-
-            for (key, (query, channel)) in channels.iter() {
-                if query.check(&object) {
-                    if channel.send(serialized_operation.clone()).is_err() {
-                        failing_channels.push(key);
-                    }
-                } else {
-                    // Trick: because the object has been updated, it is possible that the query
-                    // once matched it, but does not anymore. We send a false `Delete`
-                    // operation to the frontend to signal that if it ever had this object
-                    // in store, it must delete it.
-                    let delete_operation = serde_json::to_value(OperationNotification::Delete {
-                        table: table.clone(),
-                        data: notif_data.clone(),
-                        id: id.clone(),
-                    })
-                    .unwrap();
-
-                    if channel.send(delete_operation).is_err() {
-                        failing_channels.push(key);
-                    }
-                }
-            }
-        }
-        // For multiple-row operations, we check each row individually for matches against
-        // the query. We build per-query personalized vectors of matching objects and send
-        // them to the corresponding channels
-        OperationNotification::CreateMany {
-            data: unserialized_data,
-            ..
-        } => {
-            let objects = object_array_from_value(data.clone()).unwrap();
+impl NotificationChannel for Channel<serde_json::Value> {
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        self.send(value).map_err(|_| ChannelError::Closed)
+    }
+}
 
-            for (key, (query, channel)) in channels.iter() {
-                let mut matching_objects: Vec<T> = Vec::new();
-                for (index, object) in objects.iter().enumerate() {
-                    if query.check(&object) {
-                        matching_objects.push(unserialized_data[index].clone());
-                    }
-                }
+impl<R: Runtime> EventEmitter for AppHandle<R> {
+    fn emit_value(&self, event: &str, payload: serde_json::Value) -> Result<(), ChannelError> {
+        self.emit(event, payload).map_err(|_| ChannelError::Closed)
+    }
+}
 
-                if !matching_objects.is_empty() {
-                    let serialized_operation =
-                        serde_json::to_value(OperationNotification::CreateMany {
-                            table: "todos".to_string(),
-                            data: matching_objects,
-                        })
-                        .unwrap();
-                    if channel.send(serialized_operation).is_err() {
-                        failing_channels.push(key);
-                    }
-                }
-            }
-        }
-    };
+/// Delivery mechanism of the notifications of a subscription: a dedicated `Channel`,
+/// or global events of the application emitted on a per-table event name
+#[derive(Clone)]
+pub enum TauriChannel {
+    Channel(Channel<serde_json::Value>),
+    Event(EventChannel<AppHandle>),
+}
 
-    // Return the channels that errored out
-    failing_channels
+impl From<Channel<serde_json::Value>> for TauriChannel {
+    fn from(channel: Channel<serde_json::Value>) -> Self {
+        TauriChannel::Channel(channel)
+    }
 }
 
-/// Process a database operation notification, notify the relevant
-/// Tauri channels about the change that occured, and remove the Tauri
-/// channels that errored out.
-pub async fn process_event_and_update_channels<T>(
-    channels: &RwLock<HashMap<String, (QueryTree, Channel<serde_json::Value>), RandomState>>,
-    operation: &OperationNotification<T>,
-) where
-    T: Clone + Serialize,
-{
-    let subscriptions = channels.read().await;
-    let failing_channels = process_channel_event(&subscriptions, operation);
+impl From<EventChannel<AppHandle>> for TauriChannel {
+    fn from(channel: EventChannel<AppHandle>) -> Self {
+        TauriChannel::Event(channel)
+    }
+}
 
-    if !failing_channels.is_empty() {
-        let mut subscriptions = channels.write().await;
-        for key in failing_channels {
-            subscriptions.remove(key);
+impl NotificationChannel for TauriChannel {
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        match self {
+            TauriChannel::Channel(channel) => channel.send_value(value),
+            TauriChannel::Event(channel) => channel.send_value(value),
         }
     }
 }
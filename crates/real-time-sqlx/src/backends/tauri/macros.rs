@@ -13,7 +13,7 @@ macro_rules! real_time_tauri {
         $crate::real_time_dispatcher!($db_type, $(($table_name, $struct)),+);
 
         // Generate the function to statically serialize rows
-        $crate::serialize_rows_static!(sqlite, ("todos", Todo), ("again", Todo));
+        $crate::serialize_rows_static!($db_type, $(($table_name, $struct)),+);
 
         // Tauri endpoints
         /// Subscribe to a real-time query
@@ -22,25 +22,220 @@ macro_rules! real_time_tauri {
             // Managed by Tauri
             pool: tauri::State<'_, $crate::database_pool!($db_type)>,
             dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            webview: tauri::Webview,
             // Passed as arguments
             query: $crate::queries::serialize::QueryTree,
             channel_id: String,
             channel: tauri::ipc::Channel<serde_json::Value>,
+            request_id: Option<String>,
+            watch_columns: Option<Vec<String>>,
+            include_previous: Option<bool>,
+            include_diff: Option<bool>,
+            dedupe: Option<bool>,
+            emit_leave_events: Option<bool>,
+            known_hash: Option<String>,
+            key_by: Option<String>,
+            columns: Option<Vec<String>>,
+            label: Option<String>,
+            ack: Option<bool>,
+            split_batches: Option<bool>,
+            tail: Option<bool>,
+            refetch_window_ms: Option<u64>,
         ) -> tauri::Result<serde_json::Value> {
-            let pool: &$crate::database_pool!($db_type) = &pool;
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let query = dispatcher.order(query);
 
-            // Process the immediate query value to be returned
-            let rows = $crate::database::$db_type::fetch_sqlite_query(&query, pool).await;
-            let value = serialize_rows_static(&rows, &query.table);
+            // Process the immediate query value to be returned, aborting the query
+            // if the webview disconnects in the meantime
+            let token = dispatcher.client_tokens.token(webview.label());
+            let rows = $crate::fetch_query_cancellable_fn!($db_type)(
+                &query,
+                pool,
+                &token,
+                dispatcher.acquire_timeout,
+            )
+            .await
+            .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let mut value = dispatcher.project(&query.table, serialize_rows_static(&rows, &query.table));
+            // Rows held by the client of a tail subscription
+            let held_ids = tail
+                .unwrap_or(false)
+                .then(|| dispatcher.snapshot_ids(&query.table, &value));
+            if let Some(columns) = &columns {
+                $crate::dispatcher::select_columns(&mut value, columns);
+            }
+            // Served by the refetches exceeding their soft timeout until one completes
+            let snapshot = value.clone();
+            let value = match &key_by {
+                Some(key_column) => $crate::snapshots::keyed_snapshot(value, key_column)
+                    .map_err(|err| tauri::Error::Anyhow(err.into()))?,
+                None => value,
+            };
+            let value = $crate::snapshots::with_snapshot_hash(value, known_hash.as_deref());
+            let value = dispatcher.wrap_snapshot(value);
+            let value = $crate::utils::tag_request_id(value, request_id.as_deref());
 
             // Add the channel to the dispatcher
+            let subscription = $crate::dispatcher::Subscription::new(
+                query,
+                $crate::backends::tauri::channels::TauriChannel::from(channel),
+            )
+            .with_snapshot(snapshot)
+            .with_request_id(request_id)
+            .with_watch_columns(watch_columns)
+            .with_previous(include_previous.unwrap_or(false))
+            .with_diff(include_diff.unwrap_or(false))
+            .with_leave_events(emit_leave_events.unwrap_or(true))
+            .with_columns(columns)
+            .with_label(label)
+            .with_ack(ack.unwrap_or(false))
+            .with_split_batches(split_batches.unwrap_or(false))
+            .with_refetch_window(refetch_window_ms.map(std::time::Duration::from_millis))
+            // Duplicate subscriptions are coalesced per webview
+            .with_client_id(dedupe.unwrap_or(false).then(|| webview.label().to_string()));
+            let subscription = match held_ids {
+                Some(ids) => subscription.with_tail(ids),
+                None => subscription,
+            };
+            dispatcher
+                .subscribe_channel(&subscription.query.table.clone(), &channel_id, subscription)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            Ok(value)
+        }
+
+        /// Subscribe to a real-time query through the global event system of the application
+        /// instead of a channel. Notifications are emitted on the `real-time-sqlx:<table>`
+        /// event, and carry the `channelId` of the subscription for client-side routing.
+        #[tauri::command]
+        pub async fn subscribe_events(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            app: tauri::AppHandle,
+            // Passed as arguments
+            query: $crate::queries::serialize::QueryTree,
+            channel_id: String,
+            request_id: Option<String>,
+            label: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let query = dispatcher.order(query);
+
+            // Process the immediate query value to be returned
+            let mut conn = dispatcher.acquire(pool).await?;
+            let rows = $crate::fetch_query_fn!($db_type)(&query, &mut *conn)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let value = dispatcher.project(&query.table, serialize_rows_static(&rows, &query.table));
+            let value = dispatcher.wrap_snapshot(value);
+            let value = $crate::utils::tag_request_id(value, request_id.as_deref());
+
+            // Add the event channel to the dispatcher
+            let table = query.table.clone();
+            let channel = $crate::dispatcher::EventChannel::new(app, &table, &channel_id);
+            let subscription = $crate::dispatcher::Subscription::new(
+                query,
+                $crate::backends::tauri::channels::TauriChannel::from(channel),
+            )
+            .with_request_id(request_id)
+            .with_label(label);
             dispatcher
-                .subscribe_channel(&query.table.clone(), &channel_id, query, channel)
-                .await;
+                .subscribe_channel(&table, &channel_id, subscription)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
 
             Ok(value)
         }
 
+        /// Subscribe to a single row by primary key.
+        /// Returns the row (or `null`), and its channel only receives the creation,
+        /// the updates and the deletion of this row.
+        #[tauri::command]
+        pub async fn subscribe_by_id(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            table: String,
+            id: $crate::queries::serialize::FinalType,
+            channel_id: String,
+            channel: tauri::ipc::Channel<serde_json::Value>,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            let subscription = $crate::dispatcher::Subscription::by_id(
+                &table,
+                id,
+                $crate::backends::tauri::channels::TauriChannel::from(channel),
+            )
+            .with_request_id(request_id.clone());
+
+            // Process the immediate row value to be returned
+            let mut conn = dispatcher.acquire(pool).await?;
+            let rows = $crate::fetch_query_fn!($db_type)(&subscription.query, &mut *conn)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let value = dispatcher.project(&table, serialize_rows_static(&rows, &table));
+            let value = dispatcher.wrap_snapshot(value);
+            let value = $crate::utils::tag_request_id(value, request_id.as_deref());
+
+            dispatcher
+                .subscribe_channel(&table, &channel_id, subscription)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            Ok(value)
+        }
+
+        /// Subscribe to the number of rows matching a query instead of the rows, for badge
+        /// counts. Returns a `count` message, and its channel is sent a new one whenever
+        /// the count changes, or a refetch message when it cannot be updated locally.
+        #[tauri::command]
+        pub async fn subscribe_count(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            query: $crate::queries::serialize::QueryTree,
+            channel_id: String,
+            channel: tauri::ipc::Channel<serde_json::Value>,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            // The initial count is that of the fetched rows
+            let mut conn = dispatcher.acquire(pool).await?;
+            let count = $crate::fetch_query_fn!($db_type)(&query, &mut *conn)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?
+                .len() as u64;
+            drop(conn);
+
+            let subscription = $crate::dispatcher::Subscription::new(
+                query,
+                $crate::backends::tauri::channels::TauriChannel::from(channel),
+            )
+            .with_request_id(request_id.clone())
+            .with_count(count);
+            dispatcher
+                .subscribe_channel(&subscription.query.table.clone(), &channel_id, subscription)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            Ok($crate::utils::tag_request_id(
+                $crate::dispatcher::count_message(count),
+                request_id.as_deref(),
+            ))
+        }
+
         /// Unsubscribe from a real-time query
         #[tauri::command]
         pub async fn unsubscribe(
@@ -50,24 +245,193 @@ macro_rules! real_time_tauri {
             channel_id: String,
             table: String,
         ) -> tauri::Result<()> {
-            dispatcher.unsubscribe_channel(&table, &channel_id).await;
+            dispatcher
+                .unsubscribe_channel(&table, &channel_id)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))
+        }
+
+        /// Subscribe to a named group of real-time queries sharing a channel, for views
+        /// composed of several queries. Returns the snapshots of the queries, in order.
+        /// The notifications of each query carry the `group` name and their `queryIndex`.
+        /// A group subscribed again under the same name replaces the previous one.
+        #[tauri::command]
+        pub async fn subscribe_group(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            webview: tauri::Webview,
+            // Passed as arguments
+            group: String,
+            queries: Vec<$crate::queries::serialize::QueryTree>,
+            channel: tauri::ipc::Channel<serde_json::Value>,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+
+            // Reject the whole group if any of its queries is invalid
+            let mut ordered = Vec::with_capacity(queries.len());
+            for query in queries {
+                query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+                dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+                ordered.push(dispatcher.order(query));
+            }
+
+            let token = dispatcher.client_tokens.token(webview.label());
+            let mut snapshots = Vec::with_capacity(ordered.len());
+            for query in &ordered {
+                let rows = $crate::fetch_query_cancellable_fn!($db_type)(
+                    query,
+                    pool,
+                    &token,
+                    dispatcher.acquire_timeout,
+                )
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+                let value = dispatcher.project(&query.table, serialize_rows_static(&rows, &query.table));
+                snapshots.push(dispatcher.wrap_snapshot(value));
+            }
+
+            dispatcher.unsubscribe_group(&group).await;
+            let channel = $crate::backends::tauri::channels::TauriChannel::from(channel);
+            for (index, query) in ordered.into_iter().enumerate() {
+                let table = query.table.clone();
+                let subscription = $crate::dispatcher::Subscription::new(query, channel.clone())
+                    .with_request_id(request_id.clone())
+                    .with_group(&group, index);
+                let channel_id = $crate::dispatcher::group_channel_id(&group, index);
+                if let Err(err) = dispatcher.subscribe_channel(&table, &channel_id, subscription).await {
+                    dispatcher.unsubscribe_group(&group).await;
+                    return Err(tauri::Error::Anyhow(err.into()));
+                }
+            }
+
+            Ok($crate::utils::tag_request_id(
+                serde_json::Value::Array(snapshots),
+                request_id.as_deref(),
+            ))
+        }
+
+        /// Unsubscribe all the queries of a subscription group at once
+        #[tauri::command]
+        pub async fn unsubscribe_group(
+            // Managed by Tauri
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            group: String,
+        ) -> tauri::Result<()> {
+            dispatcher.unsubscribe_group(&group).await;
+
+            Ok(())
+        }
+
+        /// Signal that the client of a subscription is still alive, for dispatchers pruning
+        /// the channels idle for too long. Returns whether the channel is subscribed.
+        #[tauri::command]
+        pub async fn keep_alive(
+            // Managed by Tauri
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            channel_id: String,
+            table: String,
+        ) -> tauri::Result<bool> {
+            Ok(dispatcher.touch_channel(&table, &channel_id).await)
+        }
+
+        /// Open an admin subscription, receiving every operation notification of every table
+        /// with the label of the webview it originates from. Fails unless the admin
+        /// authorizer of the dispatcher accepts the webview.
+        #[tauri::command]
+        pub async fn subscribe_admin(
+            // Managed by Tauri
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            webview: tauri::Webview,
+            // Passed as arguments
+            channel_id: String,
+            channel: tauri::ipc::Channel<serde_json::Value>,
+        ) -> tauri::Result<()> {
+            dispatcher
+                .admin_channels
+                .subscribe(
+                    webview.label(),
+                    &channel_id,
+                    $crate::backends::tauri::channels::TauriChannel::from(channel),
+                )
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))
+        }
+
+        /// Close an admin subscription
+        #[tauri::command]
+        pub async fn unsubscribe_admin(
+            // Managed by Tauri
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            channel_id: String,
+        ) -> tauri::Result<()> {
+            dispatcher.admin_channels.unsubscribe(&channel_id).await;
 
             Ok(())
         }
 
+        /// List the active subscriptions of every table, with their labels
+        #[tauri::command]
+        pub async fn inspect_subscriptions(
+            // Managed by Tauri
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+        ) -> tauri::Result<Vec<$crate::dispatcher::SubscriptionInfo>> {
+            Ok(dispatcher.inspect().await)
+        }
+
+        /// Readiness probe for health endpoints: fails unless the dispatcher was not
+        /// shut down and the database answers (see `RealTimeDispatcher::ready`)
+        #[tauri::command]
+        pub async fn ready(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+        ) -> tauri::Result<()> {
+            dispatcher.ready(&pool).await
+        }
+
         /// Execute a tauri granular operation
         #[tauri::command]
         pub async fn execute(
             // Managed by Tauri
             pool: tauri::State<'_, $crate::database_pool!($db_type)>,
             dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            webview: tauri::Webview,
             // Passed as arguments
             operation: $crate::operations::serialize::GranularOperation,
+            request_id: Option<String>,
+            include_notices: Option<bool>,
         ) -> tauri::Result<serde_json::Value> {
             let pool: &$crate::database_pool!($db_type) = &pool;
-            let serialized_notification = dispatcher.process_operation(operation, pool).await;
 
-            Ok(serialized_notification)
+            // Over-limit operations are rejected before touching the database
+            if let Some(rate_limiter) = &dispatcher.rate_limiter {
+                rate_limiter
+                    .check(webview.label())
+                    .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            }
+
+            // Operations on read-only tables, and values outside of the set of an enum
+            // column, are rejected as well
+            dispatcher
+                .check_operation(&operation)
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            let serialized_notification = dispatcher
+                .process_operation_with_notices(
+                    operation,
+                    pool,
+                    Some(webview.label()),
+                    include_notices.unwrap_or(false),
+                )
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            Ok($crate::utils::tag_request_id(serialized_notification, request_id.as_deref()))
         }
 
         /// Fetch a query once (without subscription)
@@ -75,15 +439,317 @@ macro_rules! real_time_tauri {
         pub async fn fetch(
             // Managed by Tauri
             pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
             // Passed as arguments
             query: $crate::queries::serialize::QueryTree,
+            request_id: Option<String>,
+            known_hash: Option<String>,
+            channel_id: Option<String>,
+            soft_timeout_ms: Option<u64>,
+            key_by: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let query = dispatcher.order(query);
+
+            let value = match (channel_id, soft_timeout_ms) {
+                // Refetch of a subscription, which may serve its cached snapshot
+                (Some(channel_id), Some(soft_timeout_ms)) => {
+                    let table = query.table.clone();
+                    // The refetch outlives the command if it exceeds the soft timeout:
+                    // it owns its connection, acquired within the acquire timeout
+                    let mut conn = dispatcher.acquire(pool).await?;
+                    let refetch = async move {
+                        let rows = $crate::fetch_query_fn!($db_type)(&query, &mut *conn).await?;
+                        Ok::<_, $crate::error::QueryError>(serialize_rows_static(&rows, &query.table))
+                    };
+                    dispatcher
+                        .refetch_with_soft_timeout(
+                            &table,
+                            &channel_id,
+                            std::time::Duration::from_millis(soft_timeout_ms),
+                            refetch,
+                        )
+                        .await
+                        .map_err(|err| tauri::Error::Anyhow(err.into()))?
+                }
+                _ => {
+                    let mut conn = dispatcher.acquire(pool).await?;
+                    let rows = $crate::fetch_query_fn!($db_type)(&query, &mut *conn)
+                        .await
+                        .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+                    dispatcher.project(&query.table, serialize_rows_static(&rows, &query.table))
+                }
+            };
+            let value = match &key_by {
+                Some(key_column) => $crate::snapshots::keyed_snapshot(value, key_column)
+                    .map_err(|err| tauri::Error::Anyhow(err.into()))?,
+                None => value,
+            };
+            let value = $crate::snapshots::with_snapshot_hash(value, known_hash.as_deref());
+            let value = dispatcher.wrap_snapshot(value);
+
+            Ok($crate::utils::tag_request_id(value, request_id.as_deref()))
+        }
+
+        /// Fetch several independent queries at once (without subscription), concurrently
+        /// on the pool. Returns their snapshots in order, or `{ "type": "error", "message" }`
+        /// for the queries that failed, without failing the others. Each value is tagged
+        /// with the request id.
+        #[tauri::command]
+        pub async fn fetch_many(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            queries: Vec<$crate::queries::serialize::QueryTree>,
+            concurrency: Option<usize>,
+            request_id: Option<String>,
+        ) -> tauri::Result<Vec<serde_json::Value>> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+
+            // Invalid and refused queries are reported as their own error entries,
+            // and only the others are fetched: the table of each checked query, in order
+            let mut checked: Vec<Result<String, String>> = Vec::with_capacity(queries.len());
+            let mut fetched = Vec::with_capacity(queries.len());
+            for query in queries {
+                let check = query
+                    .validate()
+                    .map_err(|err| err.to_string())
+                    .and_then(|()| dispatcher.check_query(&query).map_err(|err| err.to_string()));
+                match check {
+                    Ok(()) => {
+                        checked.push(Ok(query.table.clone()));
+                        fetched.push(dispatcher.order(query));
+                    }
+                    Err(message) => checked.push(Err(message)),
+                }
+            }
+
+            let mut results = $crate::fetch_many_fn!($db_type)(
+                fetched,
+                pool,
+                concurrency.unwrap_or($crate::database::DEFAULT_FETCH_CONCURRENCY),
+                dispatcher.acquire_timeout,
+            )
+            .await
+            .into_iter();
+
+            let values = checked
+                .into_iter()
+                .map(|check| {
+                    let result = check.and_then(|table| match results.next().unwrap() {
+                        Ok(rows) => Ok(dispatcher.wrap_snapshot(
+                            dispatcher.project(&table, serialize_rows_static(&rows, &table)),
+                        )),
+                        Err(err) => Err(err.to_string()),
+                    });
+                    let value = match result {
+                        Ok(value) => value,
+                        Err(message) => serde_json::json!({ "type": "error", "message": message }),
+                    };
+                    $crate::utils::tag_request_id(value, request_id.as_deref())
+                })
+                .collect();
+
+            Ok(values)
+        }
+
+        /// Create multiple rows non-atomically: each row is inserted individually,
+        /// and the rows that fail are reported by index instead of failing the batch
+        #[tauri::command]
+        pub async fn create_many_partial(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            webview: tauri::Webview,
+            // Passed as arguments
+            table: String,
+            data: Vec<$crate::operations::serialize::JsonObject>,
+            request_id: Option<String>,
         ) -> tauri::Result<serde_json::Value> {
             let pool: &$crate::database_pool!($db_type) = &pool;
 
-            let rows = $crate::database::$db_type::fetch_sqlite_query(&query, pool).await;
-            let value = serialize_rows_static(&rows, &query.table);
+            if let Some(rate_limiter) = &dispatcher.rate_limiter {
+                rate_limiter
+                    .check(webview.label())
+                    .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            }
 
-            Ok(value)
+            // Rows are inserted individually, but read-only tables and invalid enum values
+            // reject the whole batch
+            dispatcher
+                .check_operation(&$crate::operations::serialize::GranularOperation::CreateMany {
+                    table: table.clone(),
+                    data: data.clone(),
+                    on_conflict: Default::default(),
+                    client_ref: None,
+                })
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            let value = dispatcher
+                .process_create_many_partial(&table, data, pool, Some(webview.label()))
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            Ok($crate::utils::tag_request_id(value, request_id.as_deref()))
+        }
+
+        /// Bring the rows of a table matching a condition to a desired set in a single
+        /// transaction, creating, updating and deleting the rows that differ
+        #[tauri::command]
+        pub async fn sync_set(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            webview: tauri::Webview,
+            // Passed as arguments
+            sync: $crate::operations::serialize::SyncSet,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = &pool;
+
+            if let Some(rate_limiter) = &dispatcher.rate_limiter {
+                rate_limiter
+                    .check(webview.label())
+                    .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            }
+
+            // The desired rows go through the checks of a creation, and the condition
+            // through the operator policies of the queries
+            dispatcher
+                .check_operation(&$crate::operations::serialize::GranularOperation::CreateMany {
+                    table: sync.table.clone(),
+                    data: sync.rows.clone(),
+                    on_conflict: Default::default(),
+                    client_ref: None,
+                })
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let query = sync.query();
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher
+                .check_query(&query)
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            let value = dispatcher
+                .process_sync_set(sync, pool, Some(webview.label()))
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            Ok($crate::utils::tag_request_id(value, request_id.as_deref()))
+        }
+
+        /// Validate an operation without writing it: it goes through the same checks as
+        /// `execute`, then is executed in a transaction that is rolled back to catch
+        /// conversion errors and constraint violations. Nothing is notified.
+        #[tauri::command]
+        pub async fn validate_operation(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            operation: $crate::operations::serialize::GranularOperation,
+        ) -> tauri::Result<()> {
+            let pool: &$crate::database_pool!($db_type) = &pool;
+
+            dispatcher
+                .check_operation(&operation)
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            let mut conn = dispatcher.acquire(pool).await?;
+            $crate::execute_validate_operation_fn!($db_type)(operation, &mut conn)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))
+        }
+
+        /// Replay the notifications of a table recorded after a sequence number,
+        /// for a client to catch up after a reconnection or a server restart
+        #[tauri::command]
+        pub async fn replay(
+            // Managed by Tauri
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            table: String,
+            after: u64,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let value = dispatcher.replay(&table, after)?;
+
+            Ok($crate::utils::tag_request_id(value, request_id.as_deref()))
+        }
+
+        /// Fetch a keyset page of a paginated query, along with the cursor of the next page
+        #[tauri::command]
+        pub async fn fetch_page(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            query: $crate::queries::serialize::QueryTree,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            let mut conn = dispatcher.acquire(pool).await?;
+            let rows = $crate::fetch_query_fn!($db_type)(&$crate::database::page_query(&query), &mut *conn)
+                .await
+                .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let rows = match serialize_rows_static(&rows, &query.table) {
+                serde_json::Value::Object(mut value) => value.remove("data"),
+                _ => None,
+            };
+            let rows = match rows {
+                Some(serde_json::Value::Array(rows)) => rows,
+                _ => vec![],
+            };
+
+            let page = $crate::database::paginate_rows(rows, &query);
+            let value = dispatcher.project(&query.table, serde_json::to_value(page).unwrap());
+            let value = dispatcher.wrap_page(value);
+
+            Ok($crate::utils::tag_request_id(value, request_id.as_deref()))
+        }
+
+        /// Fetch all the rows of a query if there are at most `threshold` of them,
+        /// or their first keyset page with a `paginated` flag otherwise
+        #[tauri::command]
+        pub async fn fetch_adaptive(
+            // Managed by Tauri
+            pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
+            // Passed as arguments
+            query: $crate::queries::serialize::QueryTree,
+            threshold: u64,
+            request_id: Option<String>,
+        ) -> tauri::Result<serde_json::Value> {
+            let pool: &$crate::database_pool!($db_type) = dispatcher.reader(&pool);
+            query.validate().map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            dispatcher.check_query(&query).map_err(|err| tauri::Error::Anyhow(err.into()))?;
+
+            let mut conn = dispatcher.acquire(pool).await?;
+            let rows = $crate::fetch_query_fn!($db_type)(
+                &$crate::database::adaptive_query(&query, threshold),
+                &mut *conn,
+            )
+            .await
+            .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+            let rows = match serialize_rows_static(&rows, &query.table) {
+                serde_json::Value::Object(mut value) => value.remove("data"),
+                _ => None,
+            };
+            let rows = match rows {
+                Some(serde_json::Value::Array(rows)) => rows,
+                _ => vec![],
+            };
+
+            let page = $crate::database::adaptive_rows(rows, &query, threshold);
+            let value = dispatcher.project(&query.table, serde_json::to_value(page).unwrap());
+            let value = dispatcher.wrap_page(value);
+
+            Ok($crate::utils::tag_request_id(value, request_id.as_deref()))
         }
 
         /// Execute a raw SQL query with prepared statements
@@ -91,6 +757,7 @@ macro_rules! real_time_tauri {
         pub async fn raw(
             // Managed by Tauri
             pool: tauri::State<'_, $crate::database_pool!($db_type)>,
+            dispatcher: tauri::State<'_, RealTimeDispatcher>,
             // Passed as arguments
             sql: String,
             values: Vec<$crate::queries::serialize::FinalType>,
@@ -101,9 +768,14 @@ macro_rules! real_time_tauri {
 
             $crate::macros::paste::paste! {
                 for value in values {
-                    query = $crate::database::$db_type::[<bind_ $db_type _value>](query, value);
+                    query = $crate::database::$db_type::[<bind_ $db_type _value>](query, value)
+                        .map_err(|err| tauri::Error::Anyhow(err.into()))?;
                 }
-                let rows = query.fetch_all(pool).await.unwrap();
+                let mut conn = dispatcher.acquire(pool).await?;
+                let rows = query
+                    .fetch_all(&mut *conn)
+                    .await
+                    .map_err(|err| tauri::Error::Anyhow(err.into()))?;
                 let serialized_rows = $crate::database::$db_type::[<$db_type _rows_to_json>](&rows);
             }
 
@@ -122,82 +794,749 @@ macro_rules! real_time_dispatcher {
             pub struct RealTimeDispatcher {
                 // Define allRwLocked channels for the given tables
                 $(
-                        pub [<$table_name _channels>]: tokio::sync::RwLock<std::collections::HashMap<String, ($crate::queries::serialize::QueryTree, tauri::ipc::Channel<serde_json::Value>), std::hash::RandomState>>,
+                        pub [<$table_name _channels>]: $crate::dispatcher::TableSubscriptions<$crate::backends::tauri::channels::TauriChannel>,
                 )+
+                /// Optional rate limiter of the operations executed by each webview
+                pub rate_limiter: Option<$crate::rate_limit::RateLimiter>,
+                /// Optional log of the notifications of all tables
+                pub notification_log: Option<std::sync::Arc<$crate::notification_log::NotificationLog>>,
+                /// Admin subscriptions, receiving the notifications of every table
+                pub admin_channels: $crate::dispatcher::AdminSubscriptions<$crate::backends::tauri::channels::TauriChannel>,
+                /// Optional envelope of the snapshots returned to clients
+                pub envelope: Option<Box<dyn $crate::snapshots::SnapshotEnvelope>>,
+                /// Cancellation tokens of the in-flight fetches of each webview
+                pub client_tokens: $crate::cancellation::ClientTokens,
+                /// Optional maximum wait for a pooled connection, before failing with
+                /// `QueryError::PoolExhausted`
+                pub acquire_timeout: Option<std::time::Duration>,
+                /// Lock serializing the operations, when enabled for SQLite
+                pub write_serializer: $crate::writes::WriteSerializer,
+                /// Hooks run after the operations commit, once their notifications are sent
+                pub post_commit_hooks: $crate::hooks::PostCommitHooks,
+                /// Optional periodic pruning of the stale channels of every table
+                pub stale_pruning: Option<$crate::liveness::StalePruning>,
+                /// Optional interval at which the deferred refetches of the throttled
+                /// subscriptions of every table are sent
+                pub refetch_flushing: Option<$crate::dispatcher::RefetchFlushing>,
+                /// Optional pool of a read replica, serving the fetches instead of the
+                /// managed pool, which keeps executing the operations
+                pub read_pool: Option<$crate::database_pool!($db_type)>,
             }
         }
 
         $crate::macros::paste::paste! {
             impl RealTimeDispatcher {
                 /// Implement the generic handler function for all tables and channels.
-                /// Returns a serialized operation notification option.
+                /// Returns a serialized operation notification option, with the number
+                /// of channels that received it in a `notified` field. Admin channels also
+                /// receive the notification, with the client it originates from.
+                /// Failed operations notify no channel, and their error is returned.
                 pub async fn process_operation(
                     &self,
                     operation: $crate::operations::serialize::GranularOperation,
                     pool: &$crate::database_pool!($db_type),
-                ) -> serde_json::Value {
+                    origin: Option<&str>,
+                ) -> Result<serde_json::Value, $crate::error::OperationError> {
+                    self.process_operation_with_notices(operation, pool, origin, false).await
+                }
+
+                /// Same as `process_operation`, adding the warnings and notices raised by the
+                /// database during the operation in a `notices` field if `include_notices` is set.
+                /// Notices are only returned to the caller, and never sent to the channels.
+                pub async fn process_operation_with_notices(
+                    &self,
+                    operation: $crate::operations::serialize::GranularOperation,
+                    pool: &$crate::database_pool!($db_type),
+                    origin: Option<&str>,
+                    include_notices: bool,
+                ) -> Result<serde_json::Value, $crate::error::OperationError> {
                     use $crate::operations::serialize::Tabled;
                     match operation.get_table() {
                         $(
                             $table_name => {
                                 // 1. Process the operation and obtain an operation notification
-                                let result: Option<$crate::operations::serialize::OperationNotification<$struct>> =
-                                    $crate::granular_operation_fn!($db_type)(operation, pool).await;
+                                let (result, notices): (Option<$crate::operations::serialize::OperationNotification<$struct>>, _) =
+                                    if include_notices {
+                                        // Connections are acquired once the write is serialized,
+                                        // instead of being held while it waits
+                                        let outcome = self.write_serializer
+                                            .run(async {
+                                                let mut conn = $crate::database::acquire_connection(pool, self.acquire_timeout).await?;
+                                                $crate::execute_operation_with_notices_fn!($db_type)(
+                                                    operation,
+                                                    &mut conn,
+                                                    &$crate::database::OperationOptions::default(),
+                                                )
+                                                .await
+                                            })
+                                            .await?;
+                                        (outcome.notification, Some(outcome.notices))
+                                    } else {
+                                        let result = self.write_serializer
+                                            .run(async {
+                                                let mut conn = $crate::database::acquire_connection(pool, self.acquire_timeout).await?;
+                                                $crate::execute_operation_fn!($db_type)(
+                                                    operation,
+                                                    &mut conn,
+                                                    &$crate::database::OperationOptions::default(),
+                                                )
+                                                .await
+                                            })
+                                            .await?;
+                                        (result, None)
+                                    };
 
                                 if let Some(result) = result {
-                                    // 2. Process the operation notification and update the channels
-                                    $crate::backends::tauri::channels::process_event_and_update_channels(
-                                        &self.[<$table_name _channels>],
-                                        &result,
-                                    ).await;
-                                    return serde_json::to_value(Some(result)).unwrap();
+                                    // 2. Process the operation notification and update the channels,
+                                    // including the queries of other tables depending on this one
+                                    let notified = self.[<$table_name _channels>].process(&result).await
+                                        + self.refetch_dependents($table_name).await;
+                                    let mut value = self.[<$table_name _channels>]
+                                        .project(serde_json::to_value(Some(&result)).unwrap());
+                                    self.admin_channels.process(&value, origin).await;
+                                    // 3. Run the side effects of the committed operation
+                                    self.post_commit_hooks.run(&result).await;
+                                    // 4. Report the number of channels that were notified,
+                                    // and the notices of the database if asked for
+                                    value["notified"] = notified.into();
+                                    if let Some(notices) = notices {
+                                        value["notices"] = serde_json::to_value(notices).unwrap();
+                                    }
+                                    return Ok(value);
                                 }
 
-                                serde_json::Value::Null
+                                Ok(serde_json::Value::Null)
                             }
                         )+
-                        _ => panic!("Table not found"),
+                        table => Err($crate::error::OperationError::UnknownTable(table.to_string())),
                     }
                 }
 
+                /// Insert rows of a table individually, notify the channels of the rows that were
+                /// created, and return the serialized per-index successes and errors, with the
+                /// number of notified channels.
+                pub async fn process_create_many_partial(
+                    &self,
+                    table: &str,
+                    data: Vec<$crate::operations::serialize::JsonObject>,
+                    pool: &$crate::database_pool!($db_type),
+                    origin: Option<&str>,
+                ) -> Result<serde_json::Value, $crate::error::QueryError> {
+                    match table {
+                        $(
+                            $table_name => {
+                                let result: $crate::operations::serialize::PartialCreateMany<$struct> =
+                                    self.write_serializer
+                                        .run(async {
+                                            let mut conn = $crate::database::acquire_connection(pool, self.acquire_timeout).await?;
+                                            $crate::execute_create_many_partial_fn!($db_type)(table, data, &mut conn).await
+                                        })
+                                        .await?;
+
+                                let notified = match result.notification() {
+                                    Some(notification) => {
+                                        let admin_notification = self.[<$table_name _channels>]
+                                            .project(serde_json::to_value(&notification).unwrap());
+                                        self.admin_channels.process(&admin_notification, origin).await;
+                                        let notified = self.[<$table_name _channels>].process(&notification).await
+                                            + self.refetch_dependents($table_name).await;
+                                        self.post_commit_hooks.run(&notification).await;
+                                        notified
+                                    }
+                                    None => 0,
+                                };
+
+                                let mut value = self.[<$table_name _channels>]
+                                    .project(serde_json::to_value(result).unwrap());
+                                value["notified"] = notified.into();
+                                Ok(value)
+                            }
+                        )+
+                        _ => Err($crate::error::QueryError::UnknownTable(table.to_string())),
+                    }
+                }
+
+                /// Sync the rows of a table matching a condition to a desired set, notify the
+                /// channels of each created, updated and deleted row, and return the serialized
+                /// notifications with the number of notified channels. Nothing is notified if
+                /// the synchronization fails, as it is rolled back as a whole.
+                pub async fn process_sync_set(
+                    &self,
+                    sync: $crate::operations::serialize::SyncSet,
+                    pool: &$crate::database_pool!($db_type),
+                    origin: Option<&str>,
+                ) -> Result<serde_json::Value, $crate::error::OperationError> {
+                    match sync.table.as_str() {
+                        $(
+                            $table_name => {
+                                let notifications: Vec<$crate::operations::serialize::OperationNotification<$struct>> =
+                                    self.write_serializer
+                                        .run(async {
+                                            let mut conn = $crate::database::acquire_connection(pool, self.acquire_timeout).await?;
+                                            $crate::execute_sync_set_fn!($db_type)(
+                                                sync,
+                                                &mut conn,
+                                                &$crate::database::OperationOptions::default(),
+                                            )
+                                            .await
+                                        })
+                                        .await?;
+
+                                let mut notified = 0;
+                                let mut values = Vec::with_capacity(notifications.len());
+                                for notification in &notifications {
+                                    notified += self.[<$table_name _channels>].process(notification).await;
+                                    let value = self.[<$table_name _channels>]
+                                        .project(serde_json::to_value(notification).unwrap());
+                                    self.admin_channels.process(&value, origin).await;
+                                    self.post_commit_hooks.run(notification).await;
+                                    values.push(value);
+                                }
+                                if !notifications.is_empty() {
+                                    notified += self.refetch_dependents($table_name).await;
+                                }
+
+                                Ok(serde_json::json!({
+                                    "table": $table_name,
+                                    "notifications": values,
+                                    "notified": notified,
+                                }))
+                            }
+                        )+
+                        table => Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                }
+
+                /// Refetch the subscriptions of every table whose queries depend on a table
+                /// through a correlated subquery, after an operation on that table.
+                /// Returns the number of channels that were sent a refetch message.
+                pub async fn refetch_dependents(&self, table: &str) -> usize {
+                    let mut notified = 0;
+                    $(
+                        notified += self.[<$table_name _channels>].refetch_dependents(table).await;
+                    )+
+                    notified
+                }
+
                 /// Unsubscribe a channel from the dispatcher
-                pub async fn unsubscribe_channel(&self, table: &str, channel_id: &str) {
+                pub async fn unsubscribe_channel(
+                    &self,
+                    table: &str,
+                    channel_id: &str,
+                ) -> Result<(), $crate::error::ChannelError> {
                     match table {
                         $(
                             $table_name => {
-                                let mut channels = self.[<$table_name _channels>].write().await;
-                                channels.remove(channel_id);
+                                self.[<$table_name _channels>].unsubscribe(channel_id).await;
+                                Ok(())
                             }
                         )+
-                        _ => panic!("Table not found"),
+                        _ => Err($crate::error::ChannelError::UnknownTable(table.to_string())),
                     }
                 }
 
-                /// Subscribe a channel to the dispatcher
+                /// Unsubscribe all the queries of a subscription group, whatever their table.
+                /// Returns the number of removed subscriptions.
+                pub async fn unsubscribe_group(&self, group: &str) -> usize {
+                    let mut removed = 0;
+                    $(
+                        removed += self.[<$table_name _channels>].unsubscribe_group(group).await;
+                    )+
+                    removed
+                }
+
+                /// Subscribe a channel to the dispatcher.
+                /// Fails if the dispatcher has been shut down.
                 pub async fn subscribe_channel(
                     &self,
                     table: &str,
                     channel_id: &str,
-                    query: $crate::queries::serialize::QueryTree,
-                    channel: tauri::ipc::Channel<serde_json::Value>,
-                ) {
+                    subscription: $crate::dispatcher::Subscription<$crate::backends::tauri::channels::TauriChannel>,
+                ) -> Result<(), $crate::error::ChannelError> {
                     match table {
                         $(
                             $table_name => {
-                                let mut channels = self.[<$table_name _channels>].write().await;
-                                channels.insert(channel_id.to_string(), (query, channel));
+                                self.[<$table_name _channels>].subscribe(channel_id, subscription).await
                             }
                         )+
-                        _ => panic!("Table not found"),
+                        _ => Err($crate::error::ChannelError::UnknownTable(table.to_string())),
+                    }
+                }
+
+                /// Register the access control hook deciding which webviews, by label,
+                /// can open admin subscriptions
+                pub fn with_admin_authorizer(
+                    mut self,
+                    authorizer: impl Fn(&str) -> bool + Send + Sync + 'static,
+                ) -> Self {
+                    self.admin_channels.set_authorizer(Box::new(authorizer));
+                    self
+                }
+
+                /// Return the snapshots to clients in another envelope than `{ "type", "data" }`
+                pub fn with_envelope(
+                    mut self,
+                    envelope: impl $crate::snapshots::SnapshotEnvelope + 'static,
+                ) -> Self {
+                    self.envelope = Some(Box::new(envelope));
+                    self
+                }
+
+                /// Rewrap a serialized snapshot in the configured envelope, if any
+                pub fn wrap_snapshot(&self, value: serde_json::Value) -> serde_json::Value {
+                    match &self.envelope {
+                        Some(envelope) => $crate::snapshots::wrap_snapshot(value, envelope.as_ref()),
+                        None => value,
+                    }
+                }
+
+                /// Rewrap a serialized page in the configured envelope, if any,
+                /// its pagination fields becoming metadata
+                pub fn wrap_page(&self, mut value: serde_json::Value) -> serde_json::Value {
+                    if self.envelope.is_none() {
+                        return value;
+                    }
+                    if let serde_json::Value::Object(object) = &mut value {
+                        object.insert("type".to_string(), serde_json::Value::from("many"));
+                    }
+                    self.wrap_snapshot(value)
+                }
+
+                /// Fail commands with `QueryError::PoolExhausted` when no pooled connection
+                /// becomes available within `timeout`, instead of hanging
+                pub fn with_acquire_timeout(mut self, timeout: std::time::Duration) -> Self {
+                    self.acquire_timeout = Some(timeout);
+                    self
+                }
+
+                /// Serialize the operations of all tables, so that concurrent `execute` calls
+                /// queue up instead of failing with `SQLITE_BUSY`. No-op for MySQL and PostgreSQL.
+                pub fn with_write_serialization(mut self) -> Self {
+                    self.write_serializer = $crate::writes::WriteSerializer::new($crate::database_kind!($db_type));
+                    self
+                }
+
+                /// Run hooks after the operations commit, such as sending push notifications.
+                /// They run once the notifications are sent, and their failures are logged
+                /// without affecting the committed operation nor the response.
+                pub fn with_post_commit_hooks(mut self, hooks: $crate::hooks::PostCommitHooks) -> Self {
+                    self.post_commit_hooks = hooks;
+                    self
+                }
+
+                /// Prune the stale channels of every table periodically, once the background
+                /// task is started with `spawn_stale_pruning`
+                pub fn with_stale_pruning(mut self, config: $crate::liveness::StalePruning) -> Self {
+                    self.stale_pruning = Some(config);
+                    self
+                }
+
+                /// Send the deferred refetches of the throttled subscriptions (subscribed with
+                /// a `refetch_window_ms`) periodically, once the background task is started
+                /// with `spawn_refetch_flushing`
+                pub fn with_refetch_flushing(mut self, config: $crate::dispatcher::RefetchFlushing) -> Self {
+                    self.refetch_flushing = Some(config);
+                    self
+                }
+
+                /// Serve the fetches (snapshots of new subscriptions, fetches, refetches and
+                /// pages) from the pool of a read replica. Operations still execute on the
+                /// managed pool: because of replication lag, a fetch following an operation
+                /// may not reflect it yet, while its notification always does.
+                pub fn with_read_pool(mut self, pool: $crate::database_pool!($db_type)) -> Self {
+                    self.read_pool = Some(pool);
+                    self
+                }
+
+                /// Pool serving the fetches: the read replica if any, or the managed pool
+                pub fn reader<'a>(
+                    &'a self,
+                    pool: &'a $crate::database_pool!($db_type),
+                ) -> &'a $crate::database_pool!($db_type) {
+                    self.read_pool.as_ref().unwrap_or(pool)
+                }
+
+                /// Acquire a connection from the pool within the configured acquire timeout
+                pub async fn acquire(
+                    &self,
+                    pool: &$crate::database_pool!($db_type),
+                ) -> tauri::Result<sqlx::pool::PoolConnection<<$crate::database_row!($db_type) as sqlx::Row>::Database>> {
+                    $crate::database::acquire_connection(pool, self.acquire_timeout)
+                        .await
+                        .map_err(|err| tauri::Error::Anyhow(err.into()))
+                }
+
+                /// Readiness probe: check that the dispatcher can serve subscriptions, that is
+                /// it was not shut down, and the managed pool and the read replica (if any)
+                /// answer a trivial query within the acquire timeout
+                pub async fn ready(&self, pool: &$crate::database_pool!($db_type)) -> tauri::Result<()> {
+                    if self.is_closed() {
+                        return Err(tauri::Error::Anyhow($crate::error::ChannelError::ShutDown.into()));
+                    }
+                    for pool in std::iter::once(pool).chain(self.read_pool.as_ref()) {
+                        let mut conn = self.acquire(pool).await?;
+                        $crate::check_connection_fn!($db_type)(&mut *conn)
+                            .await
+                            .map_err(|err| tauri::Error::Anyhow(err.into()))?;
+                    }
+                    Ok(())
+                }
+
+                /// Limit the rate of the operations executed by each webview
+                pub fn with_rate_limit(mut self, rate_limiter: $crate::rate_limit::RateLimiter) -> Self {
+                    self.rate_limiter = Some(rate_limiter);
+                    self
+                }
+
+                /// Record the notifications of every table in a notification log, shared
+                /// between tables, so that clients can replay them after a restart
+                pub fn with_notification_log(
+                    mut self,
+                    log: $crate::notification_log::NotificationLog,
+                ) -> Self {
+                    let log = std::sync::Arc::new(log);
+                    $(
+                        self.[<$table_name _channels>].set_log(log.clone());
+                    )+
+                    self.notification_log = Some(log);
+                    self
+                }
+
+                /// Notifications of a table recorded after a sequence number, with the last
+                /// recorded sequence number. Empty if there is no notification log.
+                pub fn replay(&self, table: &str, after: u64) -> std::io::Result<serde_json::Value> {
+                    let Some(log) = &self.notification_log else {
+                        return Ok(serde_json::json!({ "data": [], "sequence": 0 }));
+                    };
+
+                    let data: Vec<serde_json::Value> = log
+                        .replay(after)?
+                        .into_iter()
+                        .filter(|entry| entry.table == table)
+                        .map(|entry| {
+                            let mut notification = entry.notification;
+                            notification["sequence"] = entry.sequence.into();
+                            notification
+                        })
+                        .collect();
+
+                    Ok(serde_json::json!({ "data": data, "sequence": log.last_sequence() }))
+                }
+
+                /// Register a projection applied to every row of a table sent to clients,
+                /// in snapshots, notifications and operation results alike
+                pub fn with_projection(
+                    mut self,
+                    table: &str,
+                    projection: $crate::dispatcher::Projection,
+                ) -> Result<Self, $crate::error::OperationError> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].set_projection(projection),
+                        )+
+                        _ => return Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                    Ok(self)
+                }
+
+                /// Refetch the snapshot of a subscription, serving its cached snapshot
+                /// if the refetch exceeds a soft timeout (see `TableSubscriptions::refetch_with_soft_timeout`)
+                pub async fn refetch_with_soft_timeout<F, E>(
+                    &self,
+                    table: &str,
+                    channel_id: &str,
+                    soft_timeout: std::time::Duration,
+                    refetch: F,
+                ) -> Result<serde_json::Value, E>
+                where
+                    F: std::future::Future<Output = Result<serde_json::Value, E>> + Send + 'static,
+                    E: Send + 'static,
+                {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>]
+                                .refetch_with_soft_timeout(channel_id, soft_timeout, refetch)
+                                .await,
+                        )+
+                        // Without subscriptions, there is no cached snapshot to fall back on
+                        _ => refetch.await,
+                    }
+                }
+
+                /// Register the primary key columns of a table not keyed by `id`,
+                /// identifying the rows removed from the results of a query
+                pub fn with_key_columns(
+                    mut self,
+                    table: &str,
+                    columns: Vec<String>,
+                ) -> Result<Self, $crate::error::OperationError> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].set_key_columns(columns),
+                        )+
+                        _ => return Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                    Ok(self)
+                }
+
+                /// Register a default ordering applied to the `Many` queries of a table
+                /// without pagination options nor ordering of their own
+                pub fn with_default_order(
+                    mut self,
+                    table: &str,
+                    order_by: $crate::queries::serialize::OrderBy,
+                ) -> Result<Self, $crate::error::OperationError> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].set_default_order(order_by),
+                        )+
+                        _ => return Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                    Ok(self)
+                }
+
+                /// Apply the default ordering registered for the table of a query
+                pub fn order(
+                    &self,
+                    query: $crate::queries::serialize::QueryTree,
+                ) -> $crate::queries::serialize::QueryTree {
+                    match query.table.as_str() {
+                        $(
+                            $table_name => self.[<$table_name _channels>].order(query),
+                        )+
+                        _ => query,
+                    }
+                }
+
+                /// Register the operators that clients may use on each column of a table
+                pub fn with_operator_policy(
+                    mut self,
+                    table: &str,
+                    policy: $crate::operators::OperatorPolicy,
+                ) -> Result<Self, $crate::error::OperationError> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].set_operator_policy(policy),
+                        )+
+                        _ => return Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                    Ok(self)
+                }
+
+                /// Check a query and its correlated subqueries against the operator policies
+                /// of their tables. Subqueries on tables without subscriptions are not checked.
+                pub fn check_query(
+                    &self,
+                    query: &$crate::queries::serialize::QueryTree,
+                ) -> Result<(), $crate::error::PolicyError> {
+                    match query.table.as_str() {
+                        $(
+                            $table_name => self.[<$table_name _channels>].check_query(query)?,
+                        )+
+                        _ => {}
+                    }
+                    query
+                        .subqueries()
+                        .into_iter()
+                        .try_for_each(|subquery| self.check_query(subquery))
+                }
+
+                /// Register the allowed values of the text enum columns of a table
+                pub fn with_enum_columns(
+                    mut self,
+                    table: &str,
+                    enum_columns: $crate::enums::EnumColumns,
+                ) -> Result<Self, $crate::error::OperationError> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].set_enum_columns(enum_columns),
+                        )+
+                        _ => return Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                    Ok(self)
+                }
+
+                /// Register a table as read-only, such as a SQL view: its queries can be fetched
+                /// and subscribed to, but the operations on it are rejected
+                pub fn with_read_only(
+                    mut self,
+                    table: &str,
+                ) -> Result<Self, $crate::error::OperationError> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].set_read_only(true),
+                        )+
+                        _ => return Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                    Ok(self)
+                }
+
+                /// Check an operation before executing it: its table must not be read-only,
+                /// and the written values must fit the enum columns of its table
+                pub fn check_operation(
+                    &self,
+                    operation: &$crate::operations::serialize::GranularOperation,
+                ) -> Result<(), $crate::error::OperationError> {
+                    use $crate::operations::serialize::Tabled;
+                    match operation.get_table() {
+                        $(
+                            $table_name => self.[<$table_name _channels>].check_operation(operation),
+                        )+
+                        table => Err($crate::error::OperationError::UnknownTable(table.to_string())),
+                    }
+                }
+
+                /// Apply the projection registered for a table to a serialized value
+                pub fn project(&self, table: &str, value: serde_json::Value) -> serde_json::Value {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].project(value),
+                        )+
+                        _ => value,
                     }
                 }
 
+                /// Primary keys of the rows of a serialized snapshot of a table
+                pub fn snapshot_ids(&self, table: &str, snapshot: &serde_json::Value) -> Vec<serde_json::Value> {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].snapshot_ids(snapshot),
+                        )+
+                        _ => $crate::dispatcher::snapshot_ids(snapshot, None),
+                    }
+                }
+
+                /// Cancel the in-flight `subscribe` fetches of a webview, by label.
+                /// To be called when the webview is destroyed or reloaded.
+                pub fn disconnect(&self, client_id: &str) {
+                    self.client_tokens.disconnect(client_id);
+                }
+
+                /// Describe the active subscriptions of every table
+                pub async fn inspect(&self) -> Vec<$crate::dispatcher::SubscriptionInfo> {
+                    let mut infos = Vec::new();
+                    $(
+                        infos.extend(self.[<$table_name _channels>].inspect().await);
+                    )+
+                    infos
+                }
+
+                /// Record an activity of the client of a channel, for the `Liveness::Idle`
+                /// criterion of the stale channels pruning. Returns whether it is subscribed.
+                pub async fn touch_channel(&self, table: &str, channel_id: &str) -> bool {
+                    match table {
+                        $(
+                            $table_name => self.[<$table_name _channels>].touch(channel_id).await,
+                        )+
+                        _ => false,
+                    }
+                }
+
+                /// Remove the dead channels of every table according to the configured
+                /// liveness criterion (probing them by default).
+                /// Returns the number of removed subscriptions.
+                pub async fn prune_stale(&self) -> usize {
+                    let liveness = self
+                        .stale_pruning
+                        .map(|config| config.liveness)
+                        .unwrap_or_default();
+                    let mut pruned = 0;
+                    $(
+                        pruned += self.[<$table_name _channels>].prune_stale(liveness).await;
+                    )+
+                    pruned
+                }
+
+                /// Send the deferred refetches of the throttled subscriptions of every table
+                /// whose window elapsed. Returns the number of channels that received one.
+                pub async fn flush_refetches(&self) -> usize {
+                    let mut notified = 0;
+                    $(
+                        notified += self.[<$table_name _channels>].flush_refetches().await;
+                    )+
+                    notified
+                }
+
+                /// Spawn the background task sending the deferred refetches of the dispatcher
+                /// managed by the application, if configured with `with_refetch_flushing`.
+                /// To be called once the dispatcher is managed, for instance in the setup hook.
+                /// The task stops once the dispatcher is shut down.
+                pub fn spawn_refetch_flushing<R: tauri::Runtime>(
+                    app: &tauri::AppHandle<R>,
+                ) -> Option<tauri::async_runtime::JoinHandle<()>> {
+                    use tauri::Manager;
+
+                    let config = app.state::<RealTimeDispatcher>().refetch_flushing?;
+                    let app = app.clone();
+
+                    Some(tauri::async_runtime::spawn(async move {
+                        loop {
+                            config.tick().await;
+                            let dispatcher = app.state::<RealTimeDispatcher>();
+                            if dispatcher.is_closed() {
+                                return;
+                            }
+                            dispatcher.flush_refetches().await;
+                        }
+                    }))
+                }
+
+                /// Spawn the background task pruning the stale channels of the dispatcher
+                /// managed by the application, if configured with `with_stale_pruning`.
+                /// To be called once the dispatcher is managed, for instance in the setup hook.
+                /// The task stops once the dispatcher is shut down.
+                pub fn spawn_stale_pruning<R: tauri::Runtime>(
+                    app: &tauri::AppHandle<R>,
+                ) -> Option<tauri::async_runtime::JoinHandle<()>> {
+                    use tauri::Manager;
+
+                    let config = app.state::<RealTimeDispatcher>().stale_pruning?;
+                    let app = app.clone();
+
+                    Some(tauri::async_runtime::spawn(async move {
+                        loop {
+                            config.tick().await;
+                            let dispatcher = app.state::<RealTimeDispatcher>();
+                            if dispatcher.is_closed() {
+                                return;
+                            }
+                            dispatcher.prune_stale().await;
+                        }
+                    }))
+                }
+
+                /// Whether the dispatcher has been shut down
+                pub fn is_closed(&self) -> bool {
+                    $(
+                        self.[<$table_name _channels>].is_closed() &&
+                    )+ true
+                }
+
+                /// Shut down the dispatcher: send a terminal message to every channel of
+                /// every table, remove all subscriptions, and refuse new ones.
+                pub async fn shutdown(&self) {
+                    $(
+                        self.[<$table_name _channels>].shutdown().await;
+                    )+
+                }
+
                 /// Create a new instance of the dispatcher
                 pub fn new() -> Self {
                    RealTimeDispatcher {
                        $(
-                           [<$table_name _channels>]: tokio::sync::RwLock::new(std::collections::HashMap::new()),
+                           [<$table_name _channels>]: $crate::dispatcher::TableSubscriptions::new(),
                        )+
+                       rate_limiter: None,
+                       notification_log: None,
+                       admin_channels: $crate::dispatcher::AdminSubscriptions::new(),
+                       envelope: None,
+                       client_tokens: $crate::cancellation::ClientTokens::new(),
+                       acquire_timeout: None,
+                       write_serializer: $crate::writes::WriteSerializer::default(),
+                       post_commit_hooks: $crate::hooks::PostCommitHooks::new(),
+                       stale_pruning: None,
+                       refetch_flushing: None,
+                       read_pool: None,
                    }
                 }
             }
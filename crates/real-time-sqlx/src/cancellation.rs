@@ -0,0 +1,85 @@
+//! Cancellation of in-flight fetches when their client goes away.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Token shared between the fetches of a client and its connection.
+/// Cancelling it aborts all the fetches tied to it, and clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+impl CancellationToken {
+    /// Create a new, non-cancelled token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, waking up all the fetches waiting on it
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    /// Whether the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before checking the flag, not to miss a concurrent cancellation
+            let notified = self.state.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Cancellation tokens tied to the lifetime of each client connection, by client id
+#[derive(Debug, Default)]
+pub struct ClientTokens {
+    tokens: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl ClientTokens {
+    /// Create an empty set of client tokens
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the token of a connected client, creating it on its first fetch
+    pub fn token(&self, client_id: &str) -> CancellationToken {
+        self.tokens
+            .lock()
+            .unwrap()
+            .entry(client_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Cancel the in-flight fetches of a disconnected client.
+    /// A client reconnecting with the same id gets a fresh token.
+    pub fn disconnect(&self, client_id: &str) {
+        if let Some(token) = self.tokens.lock().unwrap().remove(client_id) {
+            token.cancel();
+        }
+    }
+}
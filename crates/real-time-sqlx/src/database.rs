@@ -1,15 +1,35 @@
 //! Query utilities and particularized database implementations
 //! Some implementations need to be particularized because of trait generics hell.
 
-use serde::Serialize;
-use sqlx::FromRow;
+use std::{
+    collections::HashMap,
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    database::HasStatementCache, pool::PoolConnection, query::Query, Column, ColumnIndex, Database,
+    Decode, FromRow, Pool, Row, Type, TypeInfo, ValueRef,
+};
+use tokio::{sync::Semaphore, time::error::Elapsed};
 
 use crate::{
+    cancellation::CancellationToken,
+    error::{BindError, QueryError, SnapshotError},
+    operations::serialize::{ConflictPolicy, OperationNotification},
     queries::serialize::{
-        Condition, Constraint, ConstraintValue, FinalType, OrderBy, PaginateOptions, QueryData,
-        QueryTree,
+        AdaptivePage, Condition, Constraint, ConstraintValue, FinalType, Operator, Page,
+        PaginateOptions, QueryData, QueryTree, ReturnType, ScalarFunction,
     },
-    utils::{placeholders, sanitize_identifier},
+    snapshots::Identifiable,
+    utils::{placeholders, prefix_upper_bound, sanitize_identifier, struct_fields},
 };
 
 #[cfg(feature = "mysql")]
@@ -21,28 +41,891 @@ pub mod postgres;
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
 
+/// Supported database backends, for behaviors that differ between them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbKind {
+    Sqlite,
+    Postgres,
+    MySql,
+}
+
+/// Whether the generated statements are cached on their connection
+static STATEMENT_CACHING: AtomicBool = AtomicBool::new(true);
+
+/// Enable or disable the caching of the statements generated for queries and operations.
+///
+/// When enabled (the default), each connection keeps its prepared statements keyed by
+/// their SQL, up to the `statement_cache_capacity` of its connect options, so that
+/// statements of the same shape are only prepared once per connection.
+pub fn set_statement_caching(enabled: bool) {
+    STATEMENT_CACHING.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the generated statements are cached on their connection
+pub fn statement_caching() -> bool {
+    STATEMENT_CACHING.load(Ordering::Relaxed)
+}
+
+/// Largest integer that JavaScript numbers represent exactly (`Number.MAX_SAFE_INTEGER`)
+pub const MAX_SAFE_INTEGER: i64 = 9_007_199_254_740_991;
+
+/// Whether integers beyond the JavaScript safe range are serialized as strings
+static SAFE_INTEGERS: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable the serialization of integers beyond the JavaScript safe range
+/// as strings in all the `*_row_to_json` functions.
+///
+/// Disabled by default: such integers are serialized as JSON numbers, which `JSON.parse`
+/// silently rounds. Use `ColumnDecoder::SafeInteger` to enable it for some columns only.
+pub fn set_safe_integers(enabled: bool) {
+    SAFE_INTEGERS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether integers beyond the JavaScript safe range are serialized as strings
+pub fn safe_integers() -> bool {
+    SAFE_INTEGERS.load(Ordering::Relaxed)
+}
+
+/// Whether the values bound to failing queries are included in their errors
+static DEBUG_BINDINGS: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable the inclusion of the values bound to a failing query in its
+/// `QueryError::Statement` error, for debugging.
+///
+/// Disabled by default: only the types of the values are included, as they may be
+/// sensitive and errors are often logged or sent to clients.
+pub fn set_debug_bindings(enabled: bool) {
+    DEBUG_BINDINGS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the values bound to failing queries are included in their errors
+pub fn debug_bindings() -> bool {
+    DEBUG_BINDINGS.load(Ordering::Relaxed)
+}
+
+/// Description of the values bound to a statement: their types, or their values
+/// if `debug_bindings` is enabled
+pub(crate) fn describe_bindings(values: &[FinalType]) -> String {
+    let debug = debug_bindings();
+    let describe = |value: &FinalType| match value {
+        value if debug => value.to_string(),
+        FinalType::Number(_) => "number".to_string(),
+        FinalType::String(_) => "string".to_string(),
+        FinalType::Bool(_) => "bool".to_string(),
+        FinalType::Null => "null".to_string(),
+        FinalType::Array(values) => format!("array({})", values.len()),
+        FinalType::Binary(bytes) => format!("binary({})", bytes.len()),
+    };
+
+    format!(
+        "[{}]",
+        values.iter().map(describe).collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// Attach a generated SQL statement and the description of its bindings
+/// to the database error of its execution
+pub(crate) fn statement_error(
+    sql: &str,
+    bindings: String,
+) -> impl FnOnce(sqlx::Error) -> QueryError + '_ {
+    move |source| QueryError::Statement {
+        sql: sql.to_string(),
+        bindings,
+        source,
+    }
+}
+
+/// Serialize an integer, as a string if it is beyond the JavaScript safe range
+pub fn safe_integer_to_json(integer: i64) -> serde_json::Value {
+    if integer.unsigned_abs() > MAX_SAFE_INTEGER as u64 {
+        serde_json::Value::from(integer.to_string())
+    } else {
+        serde_json::Value::from(integer)
+    }
+}
+
+/// Serialize an integer column value, depending on `safe_integers`
+pub(crate) fn integer_to_json(integer: i64) -> serde_json::Value {
+    if safe_integers() {
+        safe_integer_to_json(integer)
+    } else {
+        serde_json::Value::from(integer)
+    }
+}
+
+/// Replace the integers beyond the JavaScript safe range of a serialized value by
+/// strings, recursively. Serialized structs are not converted by the `*_row_to_json`
+/// functions: this function can be registered as the projection of their table.
+pub fn stringify_large_integers(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Number(number) => {
+            let large = match (number.as_i64(), number.as_u64()) {
+                (Some(integer), _) => integer.unsigned_abs() > MAX_SAFE_INTEGER as u64,
+                (None, Some(_)) => true,
+                (None, None) => false,
+            };
+            if large {
+                *value = serde_json::Value::from(number.to_string());
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(stringify_large_integers),
+        serde_json::Value::Object(object) => object.values_mut().for_each(stringify_large_integers),
+        _ => {}
+    }
+}
+
+/// Create a query from a generated SQL statement, cached depending on `statement_caching`
+pub(crate) fn cached_query<DB: Database + HasStatementCache>(
+    sql: &str,
+) -> Query<'_, DB, <DB as Database>::Arguments<'_>> {
+    sqlx::query(sql).persistent(statement_caching())
+}
+
+/// Convert the outcome of a query wrapped in `tokio::time::timeout` into a query result.
+/// Both the client-side timeout and the database errors matched by `is_timeout`
+/// (statement cancelled on the server side) are reported as `QueryError::Timeout`.
+pub(crate) fn timeout_result<T>(
+    result: Result<Result<T, QueryError>, Elapsed>,
+    timeout: Duration,
+    is_timeout: impl Fn(&sqlx::Error) -> bool,
+) -> Result<T, QueryError> {
+    match result {
+        Ok(Ok(data)) => Ok(data),
+        Ok(Err(err)) if err.database_error().is_some_and(&is_timeout) => {
+            Err(QueryError::Timeout(timeout))
+        }
+        Ok(Err(err)) => Err(err),
+        Err(_) => Err(QueryError::Timeout(timeout)),
+    }
+}
+
+/// Acquire a connection from a pool, failing with `QueryError::PoolExhausted` if none
+/// becomes available within `timeout` (or the acquire timeout of the pool, if shorter or
+/// if no timeout is given) instead of blocking the caller.
+/// The connection can then be used as the executor of any query or operation.
+pub async fn acquire_connection<DB: Database>(
+    pool: &Pool<DB>,
+    timeout: Option<Duration>,
+) -> Result<PoolConnection<DB>, QueryError> {
+    let pool_timeout = pool.options().get_acquire_timeout();
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, pool.acquire())
+            .await
+            .map_err(|_| QueryError::PoolExhausted(timeout))?,
+        None => pool.acquire().await,
+    };
+
+    match result {
+        Err(sqlx::Error::PoolTimedOut) => Err(QueryError::PoolExhausted(pool_timeout)),
+        result => Ok(result?),
+    }
+}
+
+/// Check an insertion that produced no row: it was skipped because of a conflict if
+/// conflicts are ignored, and was suppressed by the database (such as by a trigger)
+/// otherwise, which fails with `QueryError::NoRowInserted`
+pub(crate) fn no_row_inserted(table: &str, on_conflict: ConflictPolicy) -> Result<(), QueryError> {
+    match on_conflict {
+        ConflictPolicy::Ignore => Ok(()),
+        _ => Err(QueryError::NoRowInserted(table.to_string())),
+    }
+}
+
+/// Pools of a primary database and of an optional read replica.
+/// Queries are routed to the replica if any, and operations to the primary.
+///
+/// Replicas lag behind their primary: a query following an operation may not reflect
+/// it yet, whereas its notification always does.
+#[derive(Debug, Clone)]
+pub struct ReadWritePools<DB: Database> {
+    /// Pool executing the operations
+    pub write: Pool<DB>,
+    /// Optional pool executing the queries
+    pub read: Option<Pool<DB>>,
+}
+
+impl<DB: Database> ReadWritePools<DB> {
+    /// Route both queries and operations to the primary pool
+    pub fn new(write: Pool<DB>) -> Self {
+        ReadWritePools { write, read: None }
+    }
+
+    /// Route the queries to the pool of a read replica
+    pub fn with_read(mut self, read: Pool<DB>) -> Self {
+        self.read = Some(read);
+        self
+    }
+
+    /// Pool executing the queries: the read replica if any, or the primary
+    pub fn reader(&self) -> &Pool<DB> {
+        self.read.as_ref().unwrap_or(&self.write)
+    }
+
+    /// Pool executing the operations
+    pub fn writer(&self) -> &Pool<DB> {
+        &self.write
+    }
+}
+
+/// Run a query until it completes or the token is cancelled, in which case the query
+/// future is dropped and `QueryError::Cancelled` is returned
+#[cfg(feature = "sqlite")]
+pub(crate) async fn cancellable<T>(
+    fetch: impl Future<Output = Result<T, QueryError>>,
+    token: &CancellationToken,
+) -> Result<T, QueryError> {
+    use std::task::Poll;
+
+    let mut fetch = pin!(fetch);
+    let mut cancelled = pin!(token.cancelled());
+
+    poll_fn(|cx| {
+        if cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(QueryError::Cancelled));
+        }
+        fetch.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Run a query along with a future cancelling it on the database once the token is cancelled.
+/// The query is awaited until it completes, so that the connection is left in a clean state:
+/// if the cancellation fails, the query simply runs to completion.
+#[cfg(any(feature = "mysql", feature = "postgres"))]
+pub(crate) async fn with_server_cancel<T, C>(
+    fetch: impl Future<Output = Result<T, QueryError>>,
+    token: &CancellationToken,
+    cancel: impl FnOnce() -> C,
+) -> Result<T, QueryError>
+where
+    C: Future<Output = Result<(), sqlx::Error>>,
+{
+    let mut fetch = pin!(fetch);
+    let mut cancel = pin!(async move {
+        token.cancelled().await;
+        let _ = cancel().await;
+    });
+    let mut cancel_done = false;
+
+    poll_fn(|cx| {
+        if !cancel_done && cancel.as_mut().poll(cx).is_ready() {
+            cancel_done = true;
+        }
+        fetch.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Default maximum number of queries of a batch fetched at the same time
+pub const DEFAULT_FETCH_CONCURRENCY: usize = 4;
+
+/// Validate and fetch independent queries concurrently, at most `concurrency` at a time,
+/// and return their results in order. A failing query does not fail the others.
+pub(crate) async fn fetch_many_with<R, F, Fut>(
+    queries: Vec<QueryTree>,
+    concurrency: usize,
+    fetch: F,
+) -> Vec<Result<R, QueryError>>
+where
+    F: Fn(QueryTree) -> Fut,
+    Fut: Future<Output = Result<R, QueryError>> + Send + 'static,
+    R: Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let handles: Vec<_> = queries
+        .into_iter()
+        .map(|query| {
+            let semaphore = semaphore.clone();
+            let fetch = query.validate().map(|_| fetch(query));
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                fetch?.await
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.unwrap());
+    }
+    results
+}
+
+/// Number the `?` placeholders of a query, binding identical values once: the
+/// occurrences of a value already bound reference its placeholder again (`$1 OR $1`)
+fn reuse_placeholders(query: &str, values: Vec<FinalType>) -> (String, Vec<FinalType>) {
+    let mut result = String::with_capacity(query.len());
+    let mut unique: Vec<FinalType> = Vec::with_capacity(values.len());
+    let mut values = values.into_iter();
+
+    for c in query.chars() {
+        if c != '?' {
+            result.push(c);
+            continue;
+        }
+        let value = values.next().unwrap_or(FinalType::Null);
+        let index = match unique.iter().position(|bound| *bound == value) {
+            Some(index) => index,
+            None => {
+                unique.push(value);
+                unique.len() - 1
+            }
+        };
+        result.push_str(&format!("${}", index + 1));
+    }
+
+    (result, unique)
+}
+
+/// Check that a query only uses features supported by a database backend
+pub(crate) fn check_query_support(query: &QueryTree, backend: DbKind) -> Result<(), QueryError> {
+    if !query.distinct_on.is_empty() && backend != DbKind::Postgres {
+        return Err(QueryError::Unsupported {
+            feature: "DISTINCT ON",
+            backend,
+        });
+    }
+
+    Ok(())
+}
+
 /// Produce a prepared SQL string and a list of argument values for binding
-/// from a deserialized query, and for use in a SQLx query
-fn prepare_sqlx_query(query: &QueryTree) -> (String, Vec<FinalType>) {
-    let mut string_query = "SELECT * FROM ".to_string();
+/// from a deserialized query, and for use in a SQLx query.
+/// The query is validated first (see `QueryTree::validate`): constraint values
+/// coming from clients never reach the traversal with an incompatible operator.
+///
+/// On PostgreSQL, the placeholders are numbered (`$1`) and identical values are bound
+/// once, each occurrence referencing the same placeholder. SQLite and MySQL keep one
+/// anonymous `?` placeholder per occurrence, as they cannot be referenced twice.
+pub(crate) fn prepare_sqlx_query(
+    query: &QueryTree,
+    backend: DbKind,
+) -> Result<(String, Vec<FinalType>), QueryError> {
+    query.validate()?;
+
+    let distinct_on: Vec<String> = query
+        .distinct_on
+        .iter()
+        .map(|column| sanitize_identifier(column))
+        .collect();
+
+    let mut string_query = if distinct_on.is_empty() {
+        "SELECT * FROM ".to_string()
+    } else {
+        format!("SELECT DISTINCT ON ({}) * FROM ", distinct_on.join(", "))
+    };
     let mut values = vec![];
     string_query.push_str(&sanitize_identifier(&query.table));
 
-    if let Some(condition) = &query.condition {
-        string_query.push_str(" WHERE ");
-        let (placeholders, args) = condition.traverse();
-        string_query.push_str(&placeholders);
-        values.extend(args);
+    let cursor = query
+        .paginate
+        .as_ref()
+        .and_then(|paginate| paginate.traverse_cursor());
+
+    match (&query.condition, cursor) {
+        (Some(condition), Some((cursor, cursor_value))) => {
+            let (placeholders, args) = condition.traverse(&query.table, backend);
+            string_query.push_str(&format!(" WHERE ({placeholders}) AND {cursor}"));
+            values.extend(args);
+            values.push(cursor_value);
+        }
+        (Some(condition), None) => {
+            string_query.push_str(" WHERE ");
+            let (placeholders, args) = condition.traverse(&query.table, backend);
+            string_query.push_str(&placeholders);
+            values.extend(args);
+        }
+        (None, Some((cursor, cursor_value))) => {
+            string_query.push_str(&format!(" WHERE {cursor}"));
+            values.push(cursor_value);
+        }
+        (None, None) => {}
     }
 
     if let Some(paginate) = &query.paginate {
-        string_query.push_str(" ");
-        let pagination = paginate.traverse();
+        string_query.push(' ');
+        let pagination = paginate.traverse_distinct_on(&distinct_on);
         string_query.push_str(&pagination.0);
         values.extend(pagination.1);
+    } else if let Some(order_by) = &query.order_by {
+        let (column, descending) = order_by.ordering();
+        string_query.push_str(" ORDER BY ");
+        string_query.push_str(&ordering_clause(&distinct_on, column, descending));
+    } else if !distinct_on.is_empty() {
+        // DISTINCT ON requires the leading ORDER BY columns to match its columns
+        string_query.push_str(" ORDER BY ");
+        string_query.push_str(&distinct_on.join(", "));
+    }
+
+    if backend == DbKind::Postgres {
+        return Ok(reuse_placeholders(&string_query, values));
+    }
+    Ok((string_query, values))
+}
+
+/// Query fetching the rows of a keyset page, with one extra row to know
+/// whether there is a next page
+pub fn page_query(query: &QueryTree) -> QueryTree {
+    let mut query = query.clone();
+    query.return_type = ReturnType::Many;
+
+    if let Some(paginate) = &mut query.paginate {
+        paginate.per_page += 1;
+    }
+
+    query
+}
+
+/// Build a keyset page from the rows fetched with `page_query`: the extra row is
+/// dropped, and the next cursor is the ordering column value of the last returned row.
+/// Queries without pagination options return all their rows in a single page.
+pub fn paginate_rows<T: Serialize>(mut rows: Vec<T>, query: &QueryTree) -> Page<T> {
+    let Some(paginate) = &query.paginate else {
+        return Page {
+            data: rows,
+            next_cursor: None,
+        };
+    };
+
+    if rows.len() as u64 <= paginate.per_page {
+        return Page {
+            data: rows,
+            next_cursor: None,
+        };
+    }
+
+    rows.truncate(paginate.per_page as usize);
+    let (column, _) = paginate.ordering();
+    let next_cursor = rows
+        .last()
+        .map(|row| serde_json::to_value(row).unwrap())
+        .and_then(|row| row.get(column).cloned())
+        .and_then(|value| FinalType::try_from(value).ok());
+
+    Page {
+        data: rows,
+        next_cursor,
+    }
+}
+
+/// Paginated version of a query for adaptive pagination: its own pagination options,
+/// or pages of `threshold` rows in the order of the query
+fn paged_query(query: &QueryTree, threshold: u64) -> QueryTree {
+    let mut query = query.clone();
+    query.return_type = ReturnType::Many;
+
+    let order_by = query.order_by.clone();
+    query.paginate.get_or_insert(PaginateOptions {
+        per_page: threshold,
+        offset: None,
+        order_by,
+        cursor: None,
+    });
+
+    query
+}
+
+/// Query fetching the rows of an adaptive page: one row past the threshold or past the
+/// first page, whichever is larger, to know whether the rows exceed the threshold
+/// without counting them in a separate query
+pub fn adaptive_query(query: &QueryTree, threshold: u64) -> QueryTree {
+    let mut query = paged_query(query, threshold);
+
+    if let Some(paginate) = &mut query.paginate {
+        paginate.per_page = paginate.per_page.max(threshold) + 1;
+    }
+
+    query
+}
+
+/// Build an adaptive page from the rows fetched with `adaptive_query`: all of them if
+/// there are at most `threshold`, or the first page with its next cursor otherwise
+pub fn adaptive_rows<T: Serialize>(
+    rows: Vec<T>,
+    query: &QueryTree,
+    threshold: u64,
+) -> AdaptivePage<T> {
+    if rows.len() as u64 <= threshold {
+        return AdaptivePage {
+            data: rows,
+            paginated: false,
+            next_cursor: None,
+        };
+    }
+
+    let page = paginate_rows(rows, &paged_query(query, threshold));
+    AdaptivePage {
+        data: page.data,
+        paginated: true,
+        next_cursor: page.next_cursor,
+    }
+}
+
+/// Explicit decoder for a column, overriding the type name reported by the database
+/// when converting rows to JSON (SQLite affinity quirks, booleans stored as integers,
+/// JSON stored as text...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnDecoder {
+    /// Boolean, also decoded from integers (0 is false)
+    Bool,
+    Integer,
+    /// Integer serialized as a string beyond the JavaScript safe range,
+    /// whatever `safe_integers` is
+    SafeInteger,
+    Float,
+    Text,
+    /// JSON document stored as text, decoded into a JSON value
+    Json,
+    /// Timestamp stored either as text or as an integer epoch, kept as is
+    Timestamp,
+    /// Binary data, exchanged as a base64 string.
+    /// Also used by operations to bind base64 strings as bytes.
+    Binary,
+}
+
+/// Column decoders of a table, keyed by column name.
+/// Built once when registering a table, then passed to the `*_row_to_json_with_decoders`
+/// functions. Columns without a decoder fall back to the type name heuristic.
+pub type ColumnDecoders = HashMap<String, ColumnDecoder>;
+
+/// Options of a granular operation, passed to the `granular_operation_*_with_options`
+/// functions.
+#[derive(Debug, Clone, Default)]
+pub struct OperationOptions {
+    /// Decoders of the columns whose values need a specific binding
+    pub decoders: ColumnDecoders,
+    /// Columns of the affected rows to return (`RETURNING "id", "title"`),
+    /// or all of them (`RETURNING *`) if `None`
+    pub returning: Option<Vec<String>>,
+}
+
+impl OperationOptions {
+    /// Bind the values of the columns with a declared decoder accordingly
+    pub fn with_decoders(mut self, decoders: ColumnDecoders) -> Self {
+        self.decoders = decoders;
+        self
+    }
+
+    /// Only return the given columns of the affected rows, for instance to leave
+    /// large or sensitive columns out of notifications
+    pub fn with_returning<S: Into<String>>(mut self, columns: impl IntoIterator<Item = S>) -> Self {
+        self.returning = Some(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only return the columns matching the fields of a row struct.
+    /// All the columns are still returned if the fields cannot be determined.
+    pub fn with_returning_fields<T: for<'de> Deserialize<'de>>(mut self) -> Self {
+        if let Some(fields) = struct_fields::<T>() {
+            self = self.with_returning(fields.iter().copied());
+        }
+        self
+    }
+}
+
+/// Warning or notice raised by the database while executing an operation, such as a
+/// truncation warning on MySQL or a `RAISE NOTICE` on PostgreSQL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseNotice {
+    /// Severity of the notice, in lowercase (`warning`, `notice`, `note`...)
+    pub level: String,
+    /// Error code of the notice, when the backend reports one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// Result of a granular operation, with the notices raised by the database meanwhile.
+/// Notices are meant for the caller only, and are not part of the notification.
+#[derive(Debug, Clone)]
+pub struct OperationOutcome<T> {
+    pub notification: Option<OperationNotification<T>>,
+    pub notices: Vec<DatabaseNotice>,
+}
+
+/// Row discarding its columns, for the operations only executed to check that they succeed
+#[derive(Serialize)]
+pub(crate) struct DiscardedRow;
+
+impl<'r, R: Row> FromRow<'r, R> for DiscardedRow {
+    fn from_row(_row: &'r R) -> Result<Self, sqlx::Error> {
+        Ok(DiscardedRow)
+    }
+}
+
+/// Discarded rows have no key, their notifications are keyed by the operation
+impl Identifiable for DiscardedRow {}
+
+/// Decode a column of a row with an explicit decoder.
+/// Returns `None` if the value cannot be decoded (including SQL NULL values).
+pub(crate) fn decode_column<'r, R, I>(
+    row: &'r R,
+    column: I,
+    decoder: ColumnDecoder,
+) -> Option<serde_json::Value>
+where
+    R: Row,
+    I: ColumnIndex<R> + Copy,
+    bool: Decode<'r, R::Database> + Type<R::Database>,
+    i64: Decode<'r, R::Database> + Type<R::Database>,
+    f64: Decode<'r, R::Database> + Type<R::Database>,
+    String: Decode<'r, R::Database> + Type<R::Database>,
+    Vec<u8>: Decode<'r, R::Database> + Type<R::Database>,
+{
+    let integer = || row.try_get::<i64, _>(column).ok();
+    let text = || row.try_get::<String, _>(column).ok();
+
+    match decoder {
+        ColumnDecoder::Bool => row
+            .try_get::<bool, _>(column)
+            .ok()
+            .or_else(|| integer().map(|integer| integer != 0))
+            .map(serde_json::Value::from),
+        ColumnDecoder::Integer => integer().map(integer_to_json),
+        ColumnDecoder::SafeInteger => integer().map(safe_integer_to_json),
+        ColumnDecoder::Float => row
+            .try_get::<f64, _>(column)
+            .ok()
+            .map(serde_json::Value::from),
+        ColumnDecoder::Text => text().map(serde_json::Value::from),
+        // Invalid JSON documents are kept as strings
+        ColumnDecoder::Json => text()
+            .map(|text| serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))),
+        ColumnDecoder::Timestamp => text()
+            .map(serde_json::Value::from)
+            .or_else(|| integer().map(serde_json::Value::from)),
+        ColumnDecoder::Binary => row
+            .try_get::<Vec<u8>, _>(column)
+            .ok()
+            .map(|bytes| serde_json::Value::from(encode_binary(&bytes))),
     }
+}
 
-    (string_query, values)
+/// Reader of the values of a column, to which backends map the type names reported
+/// by their database to serialize rows to JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColumnReader {
+    /// 64-bit integer, serialized depending on `safe_integers`
+    Integer,
+    /// 16-bit integer
+    #[cfg(feature = "postgres")]
+    SmallInteger,
+    /// 32-bit integer
+    #[cfg(feature = "postgres")]
+    MediumInteger,
+    /// Single precision float
+    #[cfg(any(feature = "mysql", feature = "postgres"))]
+    SmallFloat,
+    /// Double precision float
+    Float,
+    Bool,
+    Text,
+    /// Binary data, serialized as a base64 string
+    Binary,
+    /// Date, time or timestamp in a binary format of the database, read with
+    /// `JsonRow::temporal_value`
+    #[cfg(feature = "postgres")]
+    Temporal,
+    /// Value reported without type, read with `JsonRow::untyped_value`
+    Untyped,
+}
+
+/// Row of a database backend, mapping the type names of its columns to their readers
+/// so that rows of all backends are serialized to JSON by `row_to_json`
+pub(crate) trait JsonRow: Row {
+    /// Reader of the columns of a type, `None` for the unsupported types
+    fn column_reader(type_name: &str) -> Option<ColumnReader>;
+
+    /// Value of a column reported without type
+    fn untyped_value(&self, _index: usize) -> Option<serde_json::Value> {
+        Some(serde_json::Value::Null)
+    }
+
+    /// Value of a date, time or timestamp column, as an ISO 8601 string
+    #[cfg(feature = "postgres")]
+    fn temporal_value(&self, _index: usize) -> Option<serde_json::Value> {
+        None
+    }
+}
+
+/// Read the value of a column of a row with a reader.
+/// Returns `None` if the value cannot be read (including SQL NULL values).
+pub(crate) fn read_column<'r, R>(
+    row: &'r R,
+    index: usize,
+    reader: ColumnReader,
+) -> Option<serde_json::Value>
+where
+    R: JsonRow,
+    usize: ColumnIndex<R>,
+    bool: Decode<'r, R::Database> + Type<R::Database>,
+    i16: Decode<'r, R::Database> + Type<R::Database>,
+    i32: Decode<'r, R::Database> + Type<R::Database>,
+    i64: Decode<'r, R::Database> + Type<R::Database>,
+    f32: Decode<'r, R::Database> + Type<R::Database>,
+    f64: Decode<'r, R::Database> + Type<R::Database>,
+    String: Decode<'r, R::Database> + Type<R::Database>,
+    Vec<u8>: Decode<'r, R::Database> + Type<R::Database>,
+{
+    match reader {
+        ColumnReader::Integer => row.try_get::<i64, _>(index).ok().map(integer_to_json),
+        #[cfg(feature = "postgres")]
+        ColumnReader::SmallInteger => row.try_get::<i16, _>(index).ok().map(Into::into),
+        #[cfg(feature = "postgres")]
+        ColumnReader::MediumInteger => row.try_get::<i32, _>(index).ok().map(Into::into),
+        #[cfg(any(feature = "mysql", feature = "postgres"))]
+        ColumnReader::SmallFloat => row.try_get::<f32, _>(index).ok().map(Into::into),
+        ColumnReader::Float => row.try_get::<f64, _>(index).ok().map(Into::into),
+        ColumnReader::Bool => row.try_get::<bool, _>(index).ok().map(Into::into),
+        ColumnReader::Text => row.try_get::<String, _>(index).ok().map(Into::into),
+        ColumnReader::Binary => row
+            .try_get::<Vec<u8>, _>(index)
+            .ok()
+            .map(|bytes| serde_json::Value::from(encode_binary(&bytes))),
+        #[cfg(feature = "postgres")]
+        ColumnReader::Temporal => row.temporal_value(index),
+        ColumnReader::Untyped => row.untyped_value(index),
+    }
+}
+
+/// Convert a row to a JSON object, decoding the columns that have a declared decoder
+/// regardless of the type name reported by the database. The columns of unsupported
+/// types, and the values that cannot be read, are serialized as `null`.
+///
+/// Columns are read by ordinal rather than by name, so that wide rows do not pay
+/// for a name lookup per column.
+pub(crate) fn row_to_json<'r, R>(row: &'r R, decoders: &ColumnDecoders) -> serde_json::Value
+where
+    R: JsonRow,
+    usize: ColumnIndex<R>,
+    bool: Decode<'r, R::Database> + Type<R::Database>,
+    i16: Decode<'r, R::Database> + Type<R::Database>,
+    i32: Decode<'r, R::Database> + Type<R::Database>,
+    i64: Decode<'r, R::Database> + Type<R::Database>,
+    f32: Decode<'r, R::Database> + Type<R::Database>,
+    f64: Decode<'r, R::Database> + Type<R::Database>,
+    String: Decode<'r, R::Database> + Type<R::Database>,
+    Vec<u8>: Decode<'r, R::Database> + Type<R::Database>,
+{
+    let columns = row.columns();
+    let mut json_map = serde_json::Map::with_capacity(columns.len());
+
+    for column in columns {
+        let column_name = column.name();
+        let index = column.ordinal();
+
+        // SQL NULL values are checked first, as some decoders read them as default values
+        // (such as empty strings for SQLite text columns)
+        let is_null = row.try_get_raw(index).map_or(true, |value| value.is_null());
+
+        // Use the declared decoder, or the reader of the type reported by the database
+        let value = match decoders.get(column_name) {
+            _ if is_null => None,
+            Some(decoder) => decode_column(row, index, *decoder),
+            None => R::column_reader(column.type_info().name())
+                .and_then(|reader| read_column(row, index, reader)),
+        };
+
+        json_map.insert(
+            column_name.to_string(),
+            value.unwrap_or(serde_json::Value::Null),
+        );
+    }
+
+    serde_json::Value::Object(json_map)
+}
+
+/// Encode binary column data to a base64 string
+pub(crate) fn encode_binary(bytes: &[u8]) -> String {
+    BASE64.encode(bytes)
+}
+
+/// Decode a base64 string bound to a binary column
+pub(crate) fn decode_binary(encoded: &str) -> Result<Vec<u8>, BindError> {
+    Ok(BASE64.decode(encoded)?)
+}
+
+/// Decode the base64 strings compared to binary columns (declared with
+/// `ColumnDecoder::Binary`) by the equality constraints of a query (`=`, `!=`, `in`,
+/// `not_in`) into bytes, so that they are bound as binary values instead of text.
+/// The engine compares these bytes with the base64 strings of serialized rows.
+/// Subquery conditions are decoded with the same decoders.
+pub fn decode_binary_constraints(
+    query: &QueryTree,
+    decoders: &ColumnDecoders,
+) -> Result<QueryTree, BindError> {
+    fn decode_value(value: &FinalType) -> Result<FinalType, BindError> {
+        match value {
+            FinalType::String(encoded) => Ok(FinalType::Binary(decode_binary(encoded)?)),
+            value => Ok(value.clone()),
+        }
+    }
+
+    fn decode_condition(
+        condition: &Condition,
+        decoders: &ColumnDecoders,
+    ) -> Result<Condition, BindError> {
+        let decode_all = |conditions: &[Condition]| {
+            conditions
+                .iter()
+                .map(|condition| decode_condition(condition, decoders))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(match condition {
+            Condition::And { conditions } => Condition::And {
+                conditions: decode_all(conditions)?,
+            },
+            Condition::Or { conditions } => Condition::Or {
+                conditions: decode_all(conditions)?,
+            },
+            Condition::Not { condition } => Condition::Not {
+                condition: Box::new(decode_condition(condition, decoders)?),
+            },
+            Condition::Exists {
+                subquery,
+                correlate_on,
+            } => Condition::Exists {
+                subquery: Box::new(decode_binary_constraints(subquery, decoders)?),
+                correlate_on: correlate_on.clone(),
+            },
+            Condition::Single { constraint }
+                if constraint.function.is_none()
+                    && matches!(
+                        constraint.operator,
+                        Operator::Equal | Operator::NotEqual | Operator::In | Operator::NotIn
+                    )
+                    && decoders.get(&constraint.column) == Some(&ColumnDecoder::Binary) =>
+            {
+                let value = match &constraint.value {
+                    ConstraintValue::Final(value) => ConstraintValue::Final(decode_value(value)?),
+                    ConstraintValue::List(list) => ConstraintValue::List(
+                        list.iter().map(decode_value).collect::<Result<_, _>>()?,
+                    ),
+                    value => value.clone(),
+                };
+                Condition::Single {
+                    constraint: Constraint {
+                        value,
+                        ..constraint.clone()
+                    },
+                }
+            }
+            condition => condition.clone(),
+        })
+    }
+
+    let condition = query
+        .condition
+        .as_ref()
+        .map(|condition| decode_condition(condition, decoders))
+        .transpose()?;
+
+    Ok(QueryTree {
+        condition,
+        ..query.clone()
+    })
 }
 
 /// Serialize SQL rows to json by mapping them to an intermediate data model structure
@@ -64,76 +947,357 @@ where
     }
 }
 
+/// Conversion of the rows of a table to JSON, registered in a `TypeRegistry`
+pub type RowsSerializer<R> = Box<dyn Fn(&QueryData<R>) -> serde_json::Value + Send + Sync>;
+
+/// Runtime mapping of table names to the data models their rows are serialized with.
+/// Complements the `serialize_rows_static!` macro for schemas only known at runtime.
+pub struct TypeRegistry<R> {
+    serializers: HashMap<String, RowsSerializer<R>>,
+}
+
+impl<R: sqlx::Row> Default for TypeRegistry<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: sqlx::Row> TypeRegistry<R> {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        TypeRegistry {
+            serializers: HashMap::new(),
+        }
+    }
+
+    /// Serialize the rows of a table with a data model
+    pub fn with_table<T>(self, table: &str) -> Self
+    where
+        T: for<'r> FromRow<'r, R> + Serialize + 'static,
+    {
+        self.with_serializer(table, Box::new(serialize_rows::<T, R>))
+    }
+
+    /// Serialize the rows of a table with a custom conversion
+    pub fn with_serializer(mut self, table: &str, serializer: RowsSerializer<R>) -> Self {
+        self.serializers.insert(table.to_string(), serializer);
+        self
+    }
+
+    /// Whether a table is registered
+    pub fn contains(&self, table: &str) -> bool {
+        self.serializers.contains_key(table)
+    }
+}
+
+/// Serialize SQL rows to json with the data model registered for their table at runtime.
+/// Fails with `SnapshotError::UnknownTable` for tables that are not registered.
+pub fn serialize_rows_dynamic<R>(
+    data: &QueryData<R>,
+    table: &str,
+    registry: &TypeRegistry<R>,
+) -> Result<serde_json::Value, SnapshotError> {
+    registry
+        .serializers
+        .get(table)
+        .map(|serializer| serializer(data))
+        .ok_or_else(|| SnapshotError::UnknownTable(table.to_string()))
+}
+
 // ********************************************************************************************* //
 //                                     Query Traversal Functions                                 //
 // ********************************************************************************************* //
 
 /// Trait to normalize the traversal of query constraints and conditions
 trait Traversable {
-    fn traverse(&self) -> (String, Vec<FinalType>);
+    fn traverse(&self, backend: DbKind) -> (String, Vec<FinalType>);
 }
 
 impl Traversable for FinalType {
     /// Traverse a final constraint value
-    fn traverse(&self) -> (String, Vec<FinalType>) {
+    fn traverse(&self, _backend: DbKind) -> (String, Vec<FinalType>) {
         ("?".to_string(), vec![self.clone()])
     }
 }
 
 impl Traversable for ConstraintValue {
     /// Traverse a query constraint value
-    fn traverse(&self) -> (String, Vec<FinalType>) {
+    fn traverse(&self, backend: DbKind) -> (String, Vec<FinalType>) {
         match self {
             ConstraintValue::List(list) => (placeholders(list.len()), list.clone()),
-            ConstraintValue::Final(value) => value.traverse(),
+            ConstraintValue::Final(value) => value.traverse(backend),
+            ConstraintValue::Column { column } => {
+                (format!("\"{}\"", sanitize_identifier(column)), vec![])
+            }
+            ConstraintValue::Json(value) => {
+                ("?".to_string(), vec![FinalType::String(value.to_string())])
+            }
         }
     }
 }
 
 impl Traversable for Constraint {
     /// Traverse a query constraint
-    fn traverse(&self) -> (String, Vec<FinalType>) {
-        let (values_string_query, values) = self.value.traverse();
+    fn traverse(&self, backend: DbKind) -> (String, Vec<FinalType>) {
+        if let Operator::Contains = self.operator {
+            return self.traverse_contains(backend);
+        }
+        if let Some((comparison, all)) = self.operator.quantified() {
+            return self.traverse_quantified(comparison, all, backend);
+        }
+        if let Operator::PrefixMatch = self.operator {
+            return self.traverse_prefix(backend);
+        }
+        // `IN ()` is invalid SQL: an empty list matches nothing with `IN`, and everything
+        // with `NOT IN`, like in the engine
+        if let (Operator::In | Operator::NotIn, ConstraintValue::List(list)) =
+            (&self.operator, &self.value)
+        {
+            let negated = self.operator == Operator::NotIn;
+            if list.is_empty() {
+                let always = if negated { "1 = 1" } else { "1 = 0" };
+                return (always.to_string(), vec![]);
+            }
+            // PostgreSQL binds a list of non-null values of the same type as a single array,
+            // sidestepping the limit of bound parameters per statement
+            if backend == DbKind::Postgres
+                && self.value.is_homogeneous()
+                && !matches!(list[0], FinalType::Null | FinalType::Array(_))
+            {
+                let comparison = if negated { "<> ALL(?)" } else { "= ANY(?)" };
+                return (
+                    format!("{} {comparison}", self.column_expression(backend)),
+                    vec![FinalType::Array(list.clone())],
+                );
+            }
+        }
+        if matches!(self.operator, Operator::IEqual)
+            || matches!(
+                (&self.operator, backend),
+                (Operator::ILike, DbKind::Sqlite | DbKind::MySql)
+            )
+        {
+            return self.traverse_case_insensitive(backend);
+        }
+
+        let (values_string_query, values) = self.value.traverse(backend);
 
         (
             format!(
-                "\"{}\" {} {}",
-                self.column, self.operator, values_string_query
+                "{} {} {}",
+                self.column_expression(backend),
+                self.operator,
+                values_string_query
             ),
             values,
         )
     }
 }
 
-impl Traversable for Condition {
-    /// Traverse a query condition
-    fn traverse(&self) -> (String, Vec<FinalType>) {
+impl ScalarFunction {
+    /// Name of the function in the SQL dialect of a backend. MySQL `LENGTH`
+    /// counts bytes, `CHAR_LENGTH` counts characters like on the other backends.
+    fn sql_name(&self, backend: DbKind) -> &'static str {
+        match (self, backend) {
+            (ScalarFunction::Length, DbKind::MySql) => "CHAR_LENGTH",
+            (ScalarFunction::Length, _) => "LENGTH",
+            (ScalarFunction::Lower, _) => "LOWER",
+            (ScalarFunction::Upper, _) => "UPPER",
+            (ScalarFunction::Abs, _) => "ABS",
+        }
+    }
+}
+
+impl Constraint {
+    /// Left side of the constraint: its column, wrapped in its scalar function if any
+    fn column_expression(&self, backend: DbKind) -> String {
+        let column = format!("\"{}\"", sanitize_identifier(&self.column));
+        match &self.function {
+            Some(function) => format!("{}({column})", function.sql_name(backend)),
+            None => column,
+        }
+    }
+
+    /// Traverse a quantified comparison with a list of values. SQLite has no `ANY` and
+    /// `ALL` quantifiers, and the other backends only apply them to arrays or subqueries:
+    /// the comparison is expanded with each value instead, joined by `AND` for `ALL` and
+    /// by `OR` for `ANY`. This gives the same results, NULL values included (a comparison
+    /// with NULL is unknown in both forms). An empty list is always true with `ALL`
+    /// and always false with `ANY`, like an empty subquery.
+    fn traverse_quantified(
+        &self,
+        comparison: Operator,
+        all: bool,
+        backend: DbKind,
+    ) -> (String, Vec<FinalType>) {
+        // Other values are rejected by validation: they are compared as a single value
+        let ConstraintValue::List(list) = &self.value else {
+            let (value, values) = self.value.traverse(backend);
+            return (
+                format!("{} {comparison} {value}", self.column_expression(backend)),
+                values,
+            );
+        };
+
+        if list.is_empty() {
+            let query = if all { "1 = 1" } else { "1 = 0" };
+            return (query.to_string(), vec![]);
+        }
+
+        let column = self.column_expression(backend);
+        let comparisons = vec![format!("{column} {comparison} ?"); list.len()];
+        let separator = if all { " AND " } else { " OR " };
+
+        (format!("({})", comparisons.join(separator)), list.clone())
+    }
+
+    /// Traverse a prefix constraint into a half-open range, binding the prefix as its
+    /// lower bound and the prefix with its last code point incremented as its upper bound.
+    /// Without upper bound (see `prefix_upper_bound`), only the lower bound is checked.
+    fn traverse_prefix(&self, backend: DbKind) -> (String, Vec<FinalType>) {
+        // Other values are rejected by validation: no row matches them, as in the engine
+        let ConstraintValue::Final(FinalType::String(prefix)) = &self.value else {
+            return ("1 = 0".to_string(), vec![]);
+        };
+
+        let column = self.column_expression(backend);
+        let lower = FinalType::String(prefix.clone());
+
+        match prefix_upper_bound(prefix) {
+            Some(upper) => (
+                format!("({column} >= ? AND {column} < ?)"),
+                vec![lower, FinalType::String(upper)],
+            ),
+            None => (format!("{column} >= ?"), vec![lower]),
+        }
+    }
+
+    /// Traverse a case-insensitive comparison by lowering both sides. Only PostgreSQL has
+    /// an `ILIKE` operator, and the case sensitivity of `LIKE` depends on the pragmas
+    /// (SQLite) or the collation of the column (MySQL) on the other backends.
+    fn traverse_case_insensitive(&self, backend: DbKind) -> (String, Vec<FinalType>) {
+        let (value, values) = self.value.traverse(backend);
+        let operator = match self.operator {
+            Operator::ILike => "LIKE",
+            _ => "=",
+        };
+
+        (
+            format!(
+                "LOWER({}) {operator} LOWER({value})",
+                self.column_expression(backend)
+            ),
+            values,
+        )
+    }
+
+    /// Traverse a JSON containment constraint. The value is bound as JSON text,
+    /// and the containment is checked with the native operator of each backend.
+    ///
+    /// SQLite has no such operator: the top-level elements (or key-value pairs) of
+    /// the value are looked up in the column with `json_each`, so that nested
+    /// arrays and objects must be equal instead of contained.
+    fn traverse_contains(&self, backend: DbKind) -> (String, Vec<FinalType>) {
+        let column = format!("\"{}\"", sanitize_identifier(&self.column));
+        let (value, values) = match &self.value {
+            ConstraintValue::Column { .. } => self.value.traverse(backend),
+            value => (
+                "?".to_string(),
+                vec![FinalType::String(value.to_json().to_string())],
+            ),
+        };
+
+        let query = match backend {
+            DbKind::Postgres => format!("CAST({column} AS jsonb) @> CAST({value} AS jsonb)"),
+            DbKind::MySql => format!("JSON_CONTAINS({column}, {value})"),
+            DbKind::Sqlite => format!(
+                "NOT EXISTS (SELECT 1 FROM json_each({value}) AS candidate \
+                WHERE NOT EXISTS (SELECT 1 FROM json_each({column}) AS target \
+                WHERE target.type = candidate.type AND target.value = candidate.value \
+                AND (json_type({column}) != 'object' OR target.key = candidate.key)))"
+            ),
+        };
+
+        (query, values)
+    }
+}
+
+impl Condition {
+    /// Traverse a query condition on a table. The table is needed to qualify the
+    /// outer column of correlated subqueries.
+    fn traverse(&self, table: &str, backend: DbKind) -> (String, Vec<FinalType>) {
         match self {
-            Condition::Single { constraint } => constraint.traverse(),
-            Condition::Or { conditions } => reduce_constraints_list(conditions, " OR "),
-            Condition::And { conditions } => reduce_constraints_list(conditions, " AND "),
+            Condition::Single { constraint } => constraint.traverse(backend),
+            Condition::Or { conditions } => {
+                reduce_constraints_list(conditions, " OR ", table, backend)
+            }
+            Condition::And { conditions } => {
+                reduce_constraints_list(conditions, " AND ", table, backend)
+            }
+            Condition::Not { condition } => {
+                let (query, values) = condition.traverse(table, backend);
+                (format!("NOT ({query})"), values)
+            }
+            Condition::Exists {
+                subquery,
+                correlate_on: (inner, outer),
+            } => {
+                let subtable = sanitize_identifier(&subquery.table);
+                let mut query = format!(
+                    "EXISTS (SELECT 1 FROM {subtable} WHERE {subtable}.\"{}\" = {}.\"{}\"",
+                    sanitize_identifier(inner),
+                    sanitize_identifier(table),
+                    sanitize_identifier(outer)
+                );
+                let mut values = vec![];
+
+                // Unqualified columns of the subquery condition refer to the subquery table
+                if let Some(condition) = &subquery.condition {
+                    let (placeholders, args) = condition.traverse(&subquery.table, backend);
+                    query.push_str(&format!(" AND ({placeholders})"));
+                    values.extend(args);
+                }
+                query.push(')');
+
+                (query, values)
+            }
         }
     }
 }
 
 impl Traversable for PaginateOptions {
     /// Traverse a query pagination options
-    fn traverse(&self) -> (String, Vec<FinalType>) {
+    fn traverse(&self, _backend: DbKind) -> (String, Vec<FinalType>) {
+        self.traverse_distinct_on(&[])
+    }
+}
+
+impl PaginateOptions {
+    /// Traverse the keyset pagination cursor, if any, into a constraint
+    /// keeping the rows strictly after it in the pagination order
+    fn traverse_cursor(&self) -> Option<(String, FinalType)> {
+        let cursor = self.cursor.clone()?;
+        let (column, descending) = self.ordering();
+        let operator = if descending { "<" } else { ">" };
+
+        Some((
+            format!("\"{}\" {operator} ?", sanitize_identifier(column)),
+            cursor,
+        ))
+    }
+
+    /// Traverse a query pagination options, ordering first by the given sanitized
+    /// `DISTINCT ON` columns as required by PostgreSQL. The pagination ordering
+    /// follows them, and sets the direction of a `DISTINCT ON` column it targets.
+    fn traverse_distinct_on(&self, distinct_on: &[String]) -> (String, Vec<FinalType>) {
         let mut query_string = "".to_string();
         let mut values: Vec<FinalType> = vec![];
 
-        if let Some(order) = &self.order_by {
-            query_string.push_str(
-                match order {
-                    OrderBy::Asc(col) => format!("ORDER BY {} ASC ", sanitize_identifier(col)),
-                    OrderBy::Desc(col) => format!("ORDER BY {} DESC ", sanitize_identifier(col)),
-                }
-                .as_str(),
-            );
-        } else {
-            // By default, if paginate options are present, order by ID descending
-            query_string.push_str("ORDER BY id DESC ");
-        }
+        let (column, descending) = self.ordering();
+        query_string.push_str(&format!(
+            "ORDER BY {} ",
+            ordering_clause(distinct_on, column, descending)
+        ));
 
         query_string.push_str("LIMIT ? ");
         values.push(FinalType::Number(self.per_page.into()));
@@ -147,14 +1311,44 @@ impl Traversable for PaginateOptions {
     }
 }
 
+/// Columns of an `ORDER BY` clause, ordering first by the given sanitized `DISTINCT ON`
+/// columns as required by PostgreSQL. The ordering column follows them, and sets the
+/// direction of a `DISTINCT ON` column it targets.
+fn ordering_clause(distinct_on: &[String], column: &str, descending: bool) -> String {
+    let column = sanitize_identifier(column);
+    let direction = if descending { "DESC" } else { "ASC" };
+
+    let mut ordering: Vec<String> = distinct_on
+        .iter()
+        .map(|distinct| {
+            if distinct == &column {
+                format!("{distinct} {direction}")
+            } else {
+                format!("{distinct} ASC")
+            }
+        })
+        .collect();
+
+    if !distinct_on.contains(&column) {
+        ordering.push(format!("{column} {direction}"));
+    }
+
+    ordering.join(", ")
+}
+
 /// Create a list of string queries and constraint values vectors from a list of
 /// conditions
-fn reduce_constraints_list(conditions: &[Condition], sep: &str) -> (String, Vec<FinalType>) {
+fn reduce_constraints_list(
+    conditions: &[Condition],
+    sep: &str,
+    table: &str,
+    backend: DbKind,
+) -> (String, Vec<FinalType>) {
     let mut placeholder_strings: Vec<String> = vec![];
     let mut total_values: Vec<FinalType> = vec![];
 
     conditions.iter().for_each(|condition| {
-        let (string_query, values) = condition.traverse();
+        let (string_query, values) = condition.traverse(table, backend);
         placeholder_strings.push(string_query);
         total_values.extend(values);
     });
@@ -1,117 +1,373 @@
 //! Particularized MySQL implementations.
 
+use std::time::Duration;
+
+use serde::Serialize;
 use sqlx::{
-    mysql::{MySqlArguments, MySqlRow},
+    mysql::{MySqlArguments, MySqlConnection, MySqlDatabaseError, MySqlPool, MySqlRow},
     query::Query,
-    Column, Executor, FromRow, MySql, Row, TypeInfo,
+    Acquire, Connection, Executor, FromRow, MySql, Row,
 };
 
 use crate::{
-    operations::serialize::{GranularOperation, OperationNotification},
-    queries::serialize::{FinalType, QueryData, QueryTree, ReturnType},
+    cancellation::CancellationToken,
+    error::{BindError, OperationError, QueryError},
+    operations::serialize::{
+        ConflictPolicy, GranularOperation, JsonObject, OperationNotification, PartialCreateMany,
+        SyncSet,
+    },
+    queries::serialize::{AdaptivePage, FinalType, Page, QueryData, QueryTree, ReturnType},
+    snapshots::Identifiable,
     utils::{
-        delete_statement, insert_many_statement, insert_statement, ordered_keys, update_statement,
+        delete_statement, insert_statement_on_conflict, ordered_keys, select_by_id_statement,
+        to_backtick_identifiers, update_statement,
     },
 };
 
-use super::prepare_sqlx_query;
+use super::{
+    acquire_connection, adaptive_query, adaptive_rows, cached_query, check_query_support,
+    decode_binary, decode_binary_constraints, describe_bindings, fetch_many_with, no_row_inserted,
+    page_query, paginate_rows, prepare_sqlx_query, row_to_json, statement_error, timeout_result,
+    with_server_cancel, ColumnDecoder, ColumnDecoders, ColumnReader, DatabaseNotice, DbKind,
+    DiscardedRow, JsonRow, OperationOptions, OperationOutcome,
+};
+
+/// MySQL error number for statements interrupted by `MAX_EXECUTION_TIME`
+const ER_QUERY_TIMEOUT: u16 = 3024;
+
+/// MySQL error number for statements interrupted by `KILL QUERY` (`ER_QUERY_INTERRUPTED`)
+const ER_QUERY_INTERRUPTED: u16 = 1317;
 
-/// Bind a native value to a MySQL query
+/// Bind a native value to a MySQL query.
+/// Fails if a number cannot be represented by any bindable type.
 #[inline]
 pub fn bind_mysql_value<'q>(
     query: Query<'q, MySql, MySqlArguments>,
     value: FinalType,
-) -> Query<'q, MySql, MySqlArguments> {
-    match value {
+) -> Result<Query<'q, MySql, MySqlArguments>, BindError> {
+    let query = match value {
         FinalType::Null => query.bind(None::<String>),
         FinalType::Number(number) => {
-            if number.is_f64() {
-                query.bind(number.as_f64().unwrap())
+            if let Some(integer) = number.as_i64() {
+                query.bind(integer)
+            } else if let Some(integer) = number.as_u64() {
+                query.bind(integer)
+            } else if let Some(float) = number.as_f64() {
+                query.bind(float)
             } else {
-                query.bind(number.as_i64().unwrap())
+                return Err(BindError::UnsupportedNumber(number));
             }
         }
         FinalType::String(string) => query.bind(string),
         FinalType::Bool(bool) => query.bind(bool),
+        FinalType::Array(list) => return Err(BindError::UnsupportedArray(list)),
+        FinalType::Binary(bytes) => query.bind(bytes),
+    };
+
+    Ok(query)
+}
+
+/// Bind a value to a query for a column, decoding the base64 strings
+/// bound to binary columns into bytes
+fn bind_mysql_column<'q>(
+    query: Query<'q, MySql, MySqlArguments>,
+    value: FinalType,
+    decoder: Option<&ColumnDecoder>,
+) -> Result<Query<'q, MySql, MySqlArguments>, BindError> {
+    match (decoder, value) {
+        (Some(ColumnDecoder::Binary), FinalType::String(encoded)) => {
+            Ok(query.bind(decode_binary(&encoded)?))
+        }
+        (_, value) => bind_mysql_value(query, value),
     }
 }
 
-/// Fetch data using a serialized query tree from a MySQL database
-pub async fn fetch_mysql_query<'a, E>(query: &QueryTree, executor: E) -> QueryData<MySqlRow>
+/// Fetch data using a serialized query tree from a MySQL database.
+/// Queries that fail validation (see `QueryTree::validate`) are rejected before any SQL is built.
+pub async fn fetch_mysql_query<'a, E>(
+    query: &QueryTree,
+    executor: E,
+) -> Result<QueryData<MySqlRow>, QueryError>
+where
+    E: Executor<'a, Database = MySql>,
+{
+    fetch_mysql_query_with_max_execution_time(query, executor, None).await
+}
+
+/// Fetch data using a serialized query tree from a MySQL database, binding the
+/// base64 strings compared to binary columns as bytes (see `decode_binary_constraints`)
+pub async fn fetch_mysql_query_with_decoders<'a, E>(
+    query: &QueryTree,
+    executor: E,
+    decoders: &ColumnDecoders,
+) -> Result<QueryData<MySqlRow>, QueryError>
+where
+    E: Executor<'a, Database = MySql>,
+{
+    let query = decode_binary_constraints(query, decoders)?;
+    fetch_mysql_query_with_max_execution_time(&query, executor, None).await
+}
+
+/// Fetch independent queries concurrently on a MySQL pool, at most `concurrency`
+/// at a time. Results are returned in the order of the queries, and a failing query
+/// does not fail the others.
+pub async fn fetch_many_mysql_queries(
+    queries: Vec<QueryTree>,
+    pool: &MySqlPool,
+    concurrency: usize,
+) -> Vec<Result<QueryData<MySqlRow>, QueryError>> {
+    fetch_many_mysql_queries_with_acquire_timeout(queries, pool, concurrency, None).await
+}
+
+/// Same as `fetch_many_mysql_queries`, failing the queries that wait for a pooled
+/// connection longer than `acquire_timeout` with `QueryError::PoolExhausted`
+pub async fn fetch_many_mysql_queries_with_acquire_timeout(
+    queries: Vec<QueryTree>,
+    pool: &MySqlPool,
+    concurrency: usize,
+    acquire_timeout: Option<Duration>,
+) -> Vec<Result<QueryData<MySqlRow>, QueryError>> {
+    fetch_many_with(queries, concurrency, |query| {
+        let pool = pool.clone();
+        async move {
+            let mut conn = acquire_connection(&pool, acquire_timeout).await?;
+            fetch_mysql_query_with_max_execution_time(&query, &mut *conn, None).await
+        }
+    })
+    .await
+}
+
+/// Fetch a keyset page of rows using a serialized query tree from a MySQL database,
+/// along with the cursor of the next page
+pub async fn fetch_mysql_page<'a, E, T>(
+    query: &QueryTree,
+    executor: E,
+) -> Result<Page<T>, QueryError>
+where
+    E: Executor<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow> + Serialize,
+{
+    let rows = fetch_mysql_query(&page_query(query), executor)
+        .await?
+        .unwrap_many()
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<_, _>>()?;
+
+    Ok(paginate_rows(rows, query))
+}
+
+/// Fetch all the rows of a query from a MySQL database if there are at most `threshold`
+/// of them, or their first keyset page with a `paginated` flag otherwise
+pub async fn fetch_mysql_adaptive_page<'a, E, T>(
+    query: &QueryTree,
+    executor: E,
+    threshold: u64,
+) -> Result<AdaptivePage<T>, QueryError>
 where
     E: Executor<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow> + Serialize,
 {
-    // Prepare the query
-    let (sql, values) = prepare_sqlx_query(&query);
+    let rows = fetch_mysql_query(&adaptive_query(query, threshold), executor)
+        .await?
+        .unwrap_many()
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<_, _>>()?;
+
+    Ok(adaptive_rows(rows, query, threshold))
+}
 
-    let mut sqlx_query = sqlx::query(&sql);
+/// Fetch data using a serialized query tree from a MySQL database,
+/// failing with `QueryError::Timeout` if the query runs for longer than `timeout`.
+///
+/// The statement carries a `MAX_EXECUTION_TIME` optimizer hint, which only applies to it
+/// and leaves the session variables of the pooled connection untouched. If the client-side
+/// timeout fires first, SQLx drains the pending result before reusing the connection.
+pub async fn fetch_mysql_query_with_timeout<'a, E>(
+    query: &QueryTree,
+    executor: E,
+    timeout: Duration,
+) -> Result<QueryData<MySqlRow>, QueryError>
+where
+    E: Executor<'a, Database = MySql>,
+{
+    let result = tokio::time::timeout(
+        timeout,
+        fetch_mysql_query_with_max_execution_time(query, executor, Some(timeout)),
+    )
+    .await;
+
+    timeout_result(result, timeout, |err| {
+        err.as_database_error()
+            .and_then(|err| err.try_downcast_ref::<MySqlDatabaseError>())
+            .is_some_and(|err| err.number() == ER_QUERY_TIMEOUT)
+    })
+}
+
+/// Fetch data using a serialized query tree from a MySQL database,
+/// failing with `QueryError::Cancelled` if the token is cancelled before the query completes
+/// (for instance when the client waiting for the rows disconnects).
+///
+/// Dropping the query future would not stop the statement on the server: on cancellation,
+/// `KILL QUERY` is run from a separate connection (so that it also works with exhausted
+/// pools), and the aborted statement is awaited before the connection is released.
+pub async fn fetch_mysql_query_cancellable(
+    query: &QueryTree,
+    pool: &MySqlPool,
+    token: &CancellationToken,
+) -> Result<QueryData<MySqlRow>, QueryError> {
+    fetch_mysql_query_cancellable_with_acquire_timeout(query, pool, token, None).await
+}
+
+/// Same as `fetch_mysql_query_cancellable`, failing with `QueryError::PoolExhausted`
+/// if no pooled connection is available within `acquire_timeout`
+pub async fn fetch_mysql_query_cancellable_with_acquire_timeout(
+    query: &QueryTree,
+    pool: &MySqlPool,
+    token: &CancellationToken,
+    acquire_timeout: Option<Duration>,
+) -> Result<QueryData<MySqlRow>, QueryError> {
+    let mut conn = acquire_connection(pool, acquire_timeout).await?;
+    let (connection_id,): (u64,) = sqlx::query_as("SELECT CONNECTION_ID()")
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let result = with_server_cancel(
+        fetch_mysql_query_with_max_execution_time(query, &mut *conn, None),
+        token,
+        || async {
+            let mut canceller = MySqlConnection::connect_with(&pool.connect_options()).await?;
+            sqlx::query(&format!("KILL QUERY {connection_id}"))
+                .execute(&mut canceller)
+                .await?;
+            canceller.close().await
+        },
+    )
+    .await;
+
+    match result {
+        Err(err)
+            if err
+                .database_error()
+                .and_then(|err| err.as_database_error())
+                .and_then(|err| err.try_downcast_ref::<MySqlDatabaseError>())
+                .is_some_and(|err| err.number() == ER_QUERY_INTERRUPTED) =>
+        {
+            Err(QueryError::Cancelled)
+        }
+        result => result,
+    }
+}
+
+/// Check that a MySQL database is reachable by running a trivial `SELECT 1`,
+/// for liveness and readiness probes. Fails with `sqlx::Error::PoolClosed` on a closed pool.
+pub async fn check_mysql_connection<'a, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = MySql>,
+{
+    executor.execute("SELECT 1").await?;
+    Ok(())
+}
+
+/// Fetch data using a serialized query tree, with an optional execution time limit
+/// enforced by the server
+async fn fetch_mysql_query_with_max_execution_time<'a, E>(
+    query: &QueryTree,
+    executor: E,
+    max_execution_time: Option<Duration>,
+) -> Result<QueryData<MySqlRow>, QueryError>
+where
+    E: Executor<'a, Database = MySql>,
+{
+    // Validate and prepare the query
+    check_query_support(query, DbKind::MySql)?;
+    let (sql, values) = prepare_sqlx_query(query, DbKind::MySql)?;
+    let mut with_backticks = to_backtick_identifiers(&sql);
+
+    if let Some(timeout) = max_execution_time {
+        let hint = format!(
+            "SELECT /*+ MAX_EXECUTION_TIME({}) */",
+            timeout.as_millis().max(1)
+        );
+        with_backticks = with_backticks.replacen("SELECT", &hint, 1);
+    }
+
+    let mut sqlx_query = cached_query(&with_backticks);
 
     // Bind the values
+    let bindings = describe_bindings(&values);
     for value in values {
-        sqlx_query = bind_mysql_value(sqlx_query, value);
+        sqlx_query = bind_mysql_value(sqlx_query, value)?;
     }
 
     // Fetch one or many rows depending on the query
     match query.return_type {
         ReturnType::Single => {
-            let row = sqlx_query.fetch_optional(executor).await.unwrap();
-            return QueryData::Single(row);
+            let row = sqlx_query
+                .fetch_optional(executor)
+                .await
+                .map_err(statement_error(&with_backticks, bindings))?;
+            Ok(QueryData::Single(row))
         }
         ReturnType::Many => {
-            let rows = sqlx_query.fetch_all(executor).await.unwrap();
-            return QueryData::Many(rows);
+            let rows = sqlx_query
+                .fetch_all(executor)
+                .await
+                .map_err(statement_error(&with_backticks, bindings))?;
+            Ok(QueryData::Many(rows))
         }
     }
 }
 
 /// Convert a MySQL row to a JSON object
 pub fn mysql_row_to_json(row: &MySqlRow) -> serde_json::Value {
-    let mut json_map = serde_json::Map::new();
+    mysql_row_to_json_with_decoders(row, &ColumnDecoders::new())
+}
 
-    for column in row.columns() {
-        let column_name = column.name();
-        let column_type = column.type_info().name();
+/// Convert a MySQL row to a JSON object, decoding the columns that have a declared
+/// decoder regardless of the type name reported by the database
+pub fn mysql_row_to_json_with_decoders(
+    row: &MySqlRow,
+    decoders: &ColumnDecoders,
+) -> serde_json::Value {
+    row_to_json(row, decoders)
+}
 
-        // Dynamically match the type and insert it into the JSON map
-        let value = match column_type {
-            "INTEGER" => row
-                .try_get::<i64, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "REAL" | "NUMERIC" => row
-                .try_get::<f64, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "BOOLEAN" => row
-                .try_get::<bool, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "TEXT" | "DATE" | "TIME" | "DATETIME" => row
-                .try_get::<String, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "NULL" => Some(serde_json::Value::Null),
-            "BLOB" => None, // Skip BLOB columns
-            _ => None,      // Handle other types as needed
-        };
-
-        // Add to JSON map if value is present
-        if let Some(v) = value {
-            json_map.insert(column_name.to_string(), v);
-        } else {
-            json_map.insert(column_name.to_string(), serde_json::Value::Null);
+impl JsonRow for MySqlRow {
+    fn column_reader(type_name: &str) -> Option<ColumnReader> {
+        match type_name {
+            "INTEGER" | "BIGINT" | "INT" | "MEDIUMINT" | "SMALLINT" | "TINYINT" => {
+                Some(ColumnReader::Integer)
+            }
+            "FLOAT" => Some(ColumnReader::SmallFloat),
+            "REAL" | "NUMERIC" | "DOUBLE" => Some(ColumnReader::Float),
+            "BOOLEAN" => Some(ColumnReader::Bool),
+            "TEXT" | "CHAR" | "VARCHAR" | "TINYTEXT" | "MEDIUMTEXT" | "LONGTEXT" | "DATE"
+            | "TIME" | "DATETIME" => Some(ColumnReader::Text),
+            "NULL" => Some(ColumnReader::Untyped),
+            "BLOB" | "TINYBLOB" | "MEDIUMBLOB" | "LONGBLOB" | "BINARY" | "VARBINARY" => {
+                Some(ColumnReader::Binary)
+            }
+            _ => None,
         }
     }
-
-    serde_json::Value::Object(json_map)
 }
 
 /// Convert a vector of MySQL rows to a JSON array
 pub fn mysql_rows_to_json(rows: &[MySqlRow]) -> serde_json::Value {
+    mysql_rows_to_json_with_decoders(rows, &ColumnDecoders::new())
+}
+
+/// Convert a vector of MySQL rows to a JSON array, using the declared column decoders
+pub fn mysql_rows_to_json_with_decoders(
+    rows: &[MySqlRow],
+    decoders: &ColumnDecoders,
+) -> serde_json::Value {
     let mut json_array = Vec::new();
 
     for row in rows {
-        json_array.push(mysql_row_to_json(row));
+        json_array.push(mysql_row_to_json_with_decoders(row, decoders));
     }
 
     serde_json::Value::Array(json_array)
@@ -122,71 +378,253 @@ pub fn mysql_rows_to_json(rows: &[MySqlRow]) -> serde_json::Value {
 /// and `Serialize` depending on the table name.
 pub type SerializeRowsMapped = fn(&QueryData<MySqlRow>, table: &str) -> serde_json::Value;
 
+/// Select a single row by id from a MySQL table
+async fn select_mysql_row_by_id(
+    table: &str,
+    id: FinalType,
+    columns: Option<&[String]>,
+    conn: &mut MySqlConnection,
+) -> Result<Option<MySqlRow>, QueryError> {
+    let string_query = to_backtick_identifiers(&select_by_id_statement(table, columns));
+    let sqlx_query = bind_mysql_value(cached_query(&string_query), id)?;
+
+    Ok(sqlx_query.fetch_optional(conn).await?)
+}
+
+/// Insert a single row in a MySQL table and return it, or `None` if it was skipped
+/// because of a conflict, forwarding conversion and database errors. MySQL does not
+/// support `RETURNING` clauses, so the row is selected back using its explicit id
+/// if provided, or the auto-generated id otherwise.
+async fn try_insert_mysql_row(
+    table: &str,
+    mut data: JsonObject,
+    on_conflict: ConflictPolicy,
+    options: &OperationOptions,
+    conn: &mut MySqlConnection,
+    notices: &mut Option<Vec<DatabaseNotice>>,
+) -> Result<Option<MySqlRow>, QueryError> {
+    // Fix the order of the keys for later iterations
+    let keys = ordered_keys(&data);
+    let explicit_id = data.get("id").cloned();
+
+    // Produce the SQL query string
+    let string_query = to_backtick_identifiers(&insert_statement_on_conflict(
+        table,
+        &keys,
+        on_conflict,
+        DbKind::MySql,
+    ));
+    let mut sqlx_query = cached_query(&string_query);
+
+    // Bind the values in the order of the keys
+    for key in keys.iter() {
+        // Consume the value and convert it to a NativeType for proper binding
+        let value = data.remove(key).unwrap();
+        sqlx_query = bind_mysql_column(
+            sqlx_query,
+            FinalType::try_from(value)?,
+            options.decoders.get(key),
+        )?;
+    }
+
+    let result = sqlx_query.execute(&mut *conn).await?;
+    collect_mysql_warnings(&mut *conn, notices).await?;
+
+    // `INSERT IGNORE` does not affect any row on conflict
+    if result.rows_affected() == 0 {
+        no_row_inserted(table, on_conflict)?;
+        return Ok(None);
+    }
+
+    let id = match explicit_id {
+        Some(id) => FinalType::try_from(id)?,
+        None => FinalType::Number(result.last_insert_id().into()),
+    };
+
+    let select_query =
+        to_backtick_identifiers(&select_by_id_statement(table, options.returning.as_deref()));
+    Ok(Some(
+        bind_mysql_value(cached_query(&select_query), id)?
+            .fetch_one(conn)
+            .await?,
+    ))
+}
+
 /// Perform a granular operation on a MySQL database.
+/// Returns a notification to be sent to clients, or the conversion or database error
+/// of the operation (such as a constraint violation, or a row not buildable into `T`).
+///
+/// Because MySQL does not support `RETURNING` clauses, the affected rows are
+/// selected separately within the same transaction as the operation.
+pub async fn granular_operation_mysql<'a, A, T>(
+    operation: GranularOperation,
+    connection: A,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    A: Acquire<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    granular_operation_mysql_with_options(operation, connection, &OperationOptions::default()).await
+}
+
+/// Perform a granular operation on a MySql database with options: the values of the
+/// columns that have a declared decoder are bound accordingly (base64 strings for binary
+/// columns), and only the requested columns are selected back, from which `T` must be buildable.
 /// Returns a notification to be sent to clients.
-pub async fn granular_operation_mysql<'a, E, T>(
+pub async fn granular_operation_mysql_with_options<'a, A, T>(
     operation: GranularOperation,
-    executor: E,
-) -> Option<OperationNotification<T>>
+    connection: A,
+    options: &OperationOptions,
+) -> Result<Option<OperationNotification<T>>, OperationError>
 where
-    E: Executor<'a, Database = MySql>,
-    T: for<'r> FromRow<'r, MySqlRow>,
+    A: Acquire<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
 {
-    match operation {
-        GranularOperation::Create { table, mut data } => {
-            // Fix the order of the keys for later iterations
-            let keys = ordered_keys(&data);
+    let mut conn = connection.acquire().await?;
+    execute_operation_mysql(operation, &mut conn, options).await
+}
 
-            // Produce the SQL query string
-            let string_query = insert_statement(&table, &keys);
-            let mut sqlx_query = sqlx::query(&string_query);
+/// Perform a granular operation on a MySQL database with options, and return the
+/// warnings raised by its statements (such as truncations) along with the notification.
+pub async fn granular_operation_mysql_with_notices<'a, A, T>(
+    operation: GranularOperation,
+    connection: A,
+    options: &OperationOptions,
+) -> Result<OperationOutcome<T>, OperationError>
+where
+    A: Acquire<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_operation_mysql_with_notices(operation, &mut conn, options).await
+}
 
-            // Bind the values in the order of the keys
-            for key in keys.iter() {
-                // Consume the value and convert it to a NativeType for proper binding
-                let value = data.remove(key).unwrap();
-                let native_value = FinalType::try_from(value).unwrap();
-                sqlx_query = bind_mysql_value(sqlx_query, native_value);
-            }
+/// Same as `granular_operation_mysql_with_notices`, on a connection
+pub async fn execute_operation_mysql_with_notices<T>(
+    operation: GranularOperation,
+    conn: &mut MySqlConnection,
+    options: &OperationOptions,
+) -> Result<OperationOutcome<T>, OperationError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    let mut notices = Some(vec![]);
+    let notification = run_operation_mysql(operation, conn, options, &mut notices).await?;
+
+    Ok(OperationOutcome {
+        notification,
+        notices: notices.unwrap_or_default(),
+    })
+}
+
+/// Append the warnings of the last statement executed on a connection to the collected
+/// notices, if they are collected. MySQL clears them with the next statement.
+async fn collect_mysql_warnings(
+    conn: &mut MySqlConnection,
+    notices: &mut Option<Vec<DatabaseNotice>>,
+) -> Result<(), QueryError> {
+    let Some(notices) = notices else {
+        return Ok(());
+    };
+
+    let rows = sqlx::query("SHOW WARNINGS").fetch_all(conn).await?;
+    notices.extend(rows.iter().map(|row| {
+        DatabaseNotice {
+            level: row
+                .try_get::<String, _>("Level")
+                .unwrap_or_default()
+                .to_lowercase(),
+            code: row
+                .try_get::<u32, _>("Code")
+                .ok()
+                .map(|code| code.to_string()),
+            message: row.try_get("Message").unwrap_or_default(),
+        }
+    }));
+    Ok(())
+}
 
-            let result = sqlx_query.fetch_one(executor).await.unwrap();
-            let data = T::from_row(&result).unwrap();
+/// Same as `granular_operation_mysql_with_options`, executed on a connection rather than
+/// on any `Acquire` implementation, so that its future stays `Send` when given a transaction
+/// or a borrowed pool connection (such as one acquired with `acquire_connection`)
+pub async fn execute_operation_mysql<T>(
+    operation: GranularOperation,
+    conn: &mut MySqlConnection,
+    options: &OperationOptions,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    run_operation_mysql(operation, conn, options, &mut None).await
+}
+
+/// Body of the granular operations, collecting the warnings raised by its statements
+/// into `notices` if they are collected
+async fn run_operation_mysql<T>(
+    operation: GranularOperation,
+    conn: &mut MySqlConnection,
+    options: &OperationOptions,
+    notices: &mut Option<Vec<DatabaseNotice>>,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    let decoders = &options.decoders;
+    let returning = options.returning.as_deref();
+    let mut tx = Connection::begin(&mut *conn).await?;
+
+    let notification = match operation {
+        GranularOperation::Create {
+            table,
+            data,
+            on_conflict,
+            client_ref,
+        } => {
+            // Nothing to commit nor notify if the row was skipped because of a conflict
+            let Some(result) =
+                try_insert_mysql_row(&table, data, on_conflict, options, &mut tx, notices).await?
+            else {
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
             // Produce the creation notification
-            Some(OperationNotification::Create {
+            OperationNotification::Create {
                 table: table.to_string(),
                 data,
-            })
+                client_ref,
+            }
         }
-        GranularOperation::CreateMany { table, mut data } => {
-            // Fix the order of the keys for later iterations
-            let keys = ordered_keys(&data[0]);
-
-            // Produce the SQL query string
-            let string_query = insert_many_statement(&table, &keys, data.len());
-            let mut sqlx_query = sqlx::query(&string_query);
-
-            // Bind all values in order of the keys
-            for entry in data.iter_mut() {
-                for key in keys.iter() {
-                    // Consume the value and convert it to a NativeType for proper binding
-                    let value = entry.remove(key).unwrap();
-                    let native_value = FinalType::try_from(value).unwrap();
-                    sqlx_query = bind_mysql_value(sqlx_query, native_value);
+        GranularOperation::CreateMany {
+            table,
+            data,
+            on_conflict,
+            client_ref,
+        } => {
+            // Rows are inserted one by one so that the returned data always
+            // matches the order of the input rows.
+            let mut created: Vec<T> = Vec::with_capacity(data.len());
+
+            for entry in data {
+                // Rows skipped because of a conflict are not notified
+                if let Some(result) =
+                    try_insert_mysql_row(&table, entry, on_conflict, options, &mut tx, notices)
+                        .await?
+                {
+                    created.push(T::from_row(&result).map_err(OperationError::FromRow)?);
                 }
             }
 
-            let results = sqlx_query.fetch_all(executor).await.unwrap();
-            let data: Vec<T> = results
-                .into_iter()
-                .map(|row| T::from_row(&row).unwrap())
-                .collect();
+            if created.is_empty() && on_conflict == ConflictPolicy::Ignore {
+                return Ok(None);
+            }
 
             // Produce the operation notification
-            Some(OperationNotification::CreateMany {
+            OperationNotification::CreateMany {
                 table: table.to_string(),
-                data,
-            })
+                data: created,
+                client_ref,
+            }
         }
         GranularOperation::Update {
             table,
@@ -197,55 +635,208 @@ where
             let keys = ordered_keys(&data);
 
             // Produce the SQL query string
-            let string_query = update_statement(&table, &keys);
-            let mut sqlx_query = sqlx::query(&string_query);
+            let string_query = to_backtick_identifiers(&update_statement(&table, &keys));
+            let mut sqlx_query = cached_query(&string_query);
 
             // Bind the values in the order of the keys
             for key in keys.iter() {
                 // Consume the value and convert it to a NativeType for proper binding
                 let value = data.remove(key).unwrap();
-                let native_value = FinalType::try_from(value).unwrap();
-                sqlx_query = bind_mysql_value(sqlx_query, native_value);
+                let native_value = FinalType::try_from(value)?;
+                sqlx_query = bind_mysql_column(sqlx_query, native_value, decoders.get(key))?;
             }
 
             // Bind the ID
-            sqlx_query = bind_mysql_value(sqlx_query, id.clone());
-
-            let result = sqlx_query.fetch_optional(executor).await.unwrap();
-
-            if result.is_none() {
-                return None;
-            }
-
-            let data = T::from_row(&result.unwrap()).unwrap();
+            sqlx_query = bind_mysql_value(sqlx_query, id.clone())?;
+
+            // Select the row before it is updated
+            let Some(previous) =
+                select_mysql_row_by_id(&table, id.clone(), returning, &mut tx).await?
+            else {
+                return Ok(None);
+            };
+            sqlx_query.execute(&mut *tx).await?;
+            collect_mysql_warnings(&mut tx, notices).await?;
+
+            let Some(result) =
+                select_mysql_row_by_id(&table, id.clone(), returning, &mut tx).await?
+            else {
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
             // Produce the creation notification
-            Some(OperationNotification::Update {
-                table: table.to_string(),
-                id: id.clone(),
+            OperationNotification::update(
+                &table,
+                id.clone(),
                 data,
-            })
+                Some(T::from_row(&previous).map_err(OperationError::FromRow)?),
+            )
         }
         GranularOperation::Delete { table, id } => {
+            // Select the row before it is deleted
+            let Some(result) =
+                select_mysql_row_by_id(&table, id.clone(), returning, &mut tx).await?
+            else {
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
+
             let string_query = delete_statement(&table);
-            let mut sqlx_query = sqlx::query(&string_query);
+            let mut sqlx_query = cached_query(&string_query);
 
             // Bind the ID
-            sqlx_query = bind_mysql_value(sqlx_query, id.clone());
+            sqlx_query = bind_mysql_value(sqlx_query, id.clone())?;
+            sqlx_query.execute(&mut *tx).await?;
+            collect_mysql_warnings(&mut tx, notices).await?;
 
-            let result = sqlx_query.fetch_optional(executor).await.unwrap();
+            OperationNotification::delete(&table, id.clone(), data)
+        }
+    };
 
-            if result.is_none() {
-                return None;
-            }
+    tx.commit().await?;
 
-            let data = T::from_row(&result.unwrap()).unwrap();
+    Ok(Some(notification))
+}
 
-            Some(OperationNotification::Delete {
-                table: table.to_string(),
-                id: id.clone(),
-                data,
-            })
+/// Bring the rows of a MySQL table matching a condition to a desired set, atomically:
+/// the current rows are selected and the resulting creations, updates and deletions
+/// executed in a single transaction. Returns the per-row notifications, in execution order.
+pub async fn sync_set_mysql<'a, A, T>(
+    sync: SyncSet,
+    connection: A,
+    options: &OperationOptions,
+) -> Result<Vec<OperationNotification<T>>, OperationError>
+where
+    A: Acquire<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_sync_set_mysql(sync, &mut conn, options).await
+}
+
+/// Same as `sync_set_mysql`, on a connection
+pub async fn execute_sync_set_mysql<T>(
+    sync: SyncSet,
+    conn: &mut MySqlConnection,
+    options: &OperationOptions,
+) -> Result<Vec<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+{
+    // The condition comes from the client: reject it before opening the transaction
+    let query = sync.query();
+    query.validate()?;
+
+    let mut tx = Connection::begin(conn).await?;
+
+    let current = fetch_mysql_query(&query, &mut *tx).await?.unwrap_many();
+    let current: Vec<serde_json::Value> = current.iter().map(mysql_row_to_json).collect();
+
+    let mut notifications = vec![];
+    for operation in sync.operations(&current)? {
+        if let Some(notification) =
+            run_operation_mysql(operation, &mut tx, options, &mut None).await?
+        {
+            notifications.push(notification);
         }
     }
+
+    tx.commit().await?;
+    Ok(notifications)
+}
+
+/// Dry run of a granular operation on a MySQL database: the operation is executed
+/// in a transaction that is always rolled back, so that conversion errors and constraint
+/// violations are reported without writing anything.
+///
+/// Auto-increment values consumed by the rolled back insertions are not reused.
+pub async fn validate_operation_mysql<'a, A>(
+    operation: GranularOperation,
+    connection: A,
+) -> Result<(), OperationError>
+where
+    A: Acquire<'a, Database = MySql>,
+{
+    let mut conn = connection.acquire().await?;
+    execute_validate_operation_mysql(operation, &mut conn).await
+}
+
+/// Same as `validate_operation_mysql`, on a connection
+pub async fn execute_validate_operation_mysql(
+    operation: GranularOperation,
+    conn: &mut MySqlConnection,
+) -> Result<(), OperationError> {
+    let mut tx = Connection::begin(conn).await?;
+    run_operation_mysql::<DiscardedRow>(
+        operation,
+        &mut tx,
+        &OperationOptions::default(),
+        &mut None,
+    )
+    .await?;
+    tx.rollback().await?;
+
+    Ok(())
+}
+
+/// Perform a non-atomic `CreateMany` operation on a MySQL database.
+/// Rows are inserted individually outside of any transaction: the rows that fail
+/// are reported by index, and the others are created regardless. Only the failure
+/// to acquire a connection fails the whole operation.
+pub async fn create_many_partial_mysql<'a, A, T>(
+    table: &str,
+    data: Vec<JsonObject>,
+    connection: A,
+) -> Result<PartialCreateMany<T>, QueryError>
+where
+    A: Acquire<'a, Database = MySql>,
+    T: for<'r> FromRow<'r, MySqlRow>,
+{
+    let mut conn = connection.acquire().await?;
+    execute_create_many_partial_mysql(table, data, &mut conn).await
+}
+
+/// Same as `create_many_partial_mysql`, on a connection
+pub async fn execute_create_many_partial_mysql<T>(
+    table: &str,
+    data: Vec<JsonObject>,
+    conn: &mut MySqlConnection,
+) -> Result<PartialCreateMany<T>, QueryError>
+where
+    T: for<'r> FromRow<'r, MySqlRow>,
+{
+    let mut result = PartialCreateMany {
+        table: table.to_string(),
+        created: Default::default(),
+        errors: Default::default(),
+    };
+
+    for (index, entry) in data.into_iter().enumerate() {
+        // Without conflict policy, inserted rows are never skipped
+        let row = try_insert_mysql_row(
+            table,
+            entry,
+            ConflictPolicy::Error,
+            &OperationOptions::default(),
+            conn,
+            &mut None,
+        )
+        .await
+        .and_then(|row| {
+            let row = row.ok_or_else(|| QueryError::NoRowInserted(table.to_string()))?;
+            Ok(T::from_row(&row)?)
+        });
+
+        match row {
+            Ok(row) => {
+                result.created.insert(index, row);
+            }
+            Err(err) => {
+                result.errors.insert(index, err.to_string());
+            }
+        }
+    }
+
+    Ok(result)
 }
@@ -1,118 +1,507 @@
 //! Particularized PostgreSQL implementations.
 
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
 use sqlx::{
-    postgres::{PgArguments, PgRow},
+    postgres::{PgArguments, PgConnection, PgPool, PgRow, PgValueFormat},
     query::Query,
-    Column, Executor, FromRow, Postgres, Row, TypeInfo,
+    Acquire, Connection, Executor, FromRow, Postgres, Row, TypeInfo, ValueRef,
+};
+use tracing::{
+    field::{Field, Visit},
+    instrument::WithSubscriber,
+    span, Dispatch, Event, Level, Metadata, Subscriber,
 };
 
 use crate::{
-    operations::serialize::{GranularOperation, OperationNotification},
-    queries::serialize::{FinalType, QueryData, QueryTree, ReturnType},
+    cancellation::CancellationToken,
+    error::{BindError, OperationError, QueryError},
+    operations::serialize::{
+        ConflictPolicy, GranularOperation, JsonObject, OperationNotification, PartialCreateMany,
+        SyncSet,
+    },
+    queries::serialize::{AdaptivePage, FinalType, Page, QueryData, QueryTree, ReturnType},
+    snapshots::Identifiable,
     utils::{
-        delete_statement, insert_many_statement, insert_statement, ordered_keys,
-        to_numbered_placeholders, update_statement,
+        delete_statement, insert_statement, insert_statement_on_conflict, ordered_keys,
+        select_by_id_statement, to_numbered_placeholders, update_statement, with_returning,
     },
 };
 
-use super::prepare_sqlx_query;
+use super::{
+    acquire_connection, adaptive_query, adaptive_rows, cached_query, check_query_support,
+    decode_binary, decode_binary_constraints, describe_bindings, fetch_many_with, no_row_inserted,
+    page_query, paginate_rows, prepare_sqlx_query, row_to_json, statement_error, timeout_result,
+    with_server_cancel, ColumnDecoder, ColumnDecoders, ColumnReader, DatabaseNotice, DbKind,
+    DiscardedRow, JsonRow, OperationOptions, OperationOutcome,
+};
+
+/// PostgreSQL error code for statements cancelled by `statement_timeout` (`query_canceled`)
+const QUERY_CANCELED: &str = "57014";
 
-/// Bind a native value to a Postgres query
+/// Bind a native value to a Postgres query.
+/// Fails if a number cannot be represented by any bindable type, including the
+/// integers beyond the i64 range.
 #[inline]
 pub fn bind_postgres_value<'q>(
     query: Query<'q, Postgres, PgArguments>,
     value: FinalType,
-) -> Query<'q, Postgres, PgArguments> {
-    match value {
+) -> Result<Query<'q, Postgres, PgArguments>, BindError> {
+    let query = match value {
         FinalType::Null => query.bind(None::<String>),
         FinalType::Number(number) => {
-            if number.is_f64() {
-                query.bind(number.as_f64().unwrap())
+            if let Some(integer) = number.as_i64() {
+                query.bind(integer)
+            } else if number.is_u64() {
+                // PostgreSQL has no unsigned 64-bit integer type, and binding integers
+                // beyond the i64 range as floats would lose their precision
+                return Err(BindError::UnsupportedNumber(number));
+            } else if let Some(float) = number.as_f64() {
+                query.bind(float)
             } else {
-                query.bind(number.as_i64().unwrap())
+                return Err(BindError::UnsupportedNumber(number));
             }
         }
         FinalType::String(string) => query.bind(string),
         FinalType::Bool(bool) => query.bind(bool),
+        FinalType::Array(list) => bind_postgres_array(query, list)?,
+        FinalType::Binary(bytes) => query.bind(bytes),
+    };
+
+    Ok(query)
+}
+
+/// Bind a list of values of the same type as a single array parameter: integers as
+/// `INT8[]` (or `FLOAT8[]` if one of them is not an `i64`), floats as `FLOAT8[]`,
+/// strings as `TEXT[]` and booleans as `BOOL[]`. PostgreSQL casts the array elements
+/// to the type of the compared column like it does for scalar parameters.
+fn bind_postgres_array<'q>(
+    query: Query<'q, Postgres, PgArguments>,
+    list: Vec<FinalType>,
+) -> Result<Query<'q, Postgres, PgArguments>, BindError> {
+    let numbers: Option<Vec<&serde_json::Number>> = list
+        .iter()
+        .map(|value| match value {
+            FinalType::Number(number) => Some(number),
+            _ => None,
+        })
+        .collect();
+    if let Some(numbers) = numbers {
+        let integers: Option<Vec<i64>> = numbers.iter().map(|number| number.as_i64()).collect();
+        if let Some(integers) = integers {
+            return Ok(query.bind(integers));
+        }
+        let floats: Option<Vec<f64>> = numbers.iter().map(|number| number.as_f64()).collect();
+        if let Some(floats) = floats {
+            return Ok(query.bind(floats));
+        }
+        return Err(BindError::UnsupportedArray(list));
+    }
+
+    let strings: Option<Vec<String>> = list
+        .iter()
+        .map(|value| match value {
+            FinalType::String(string) => Some(string.clone()),
+            _ => None,
+        })
+        .collect();
+    if let Some(strings) = strings {
+        return Ok(query.bind(strings));
+    }
+
+    let binaries: Option<Vec<Vec<u8>>> = list
+        .iter()
+        .map(|value| match value {
+            FinalType::Binary(bytes) => Some(bytes.clone()),
+            _ => None,
+        })
+        .collect();
+    if let Some(binaries) = binaries {
+        return Ok(query.bind(binaries));
+    }
+
+    let bools: Option<Vec<bool>> = list
+        .iter()
+        .map(|value| match value {
+            FinalType::Bool(bool) => Some(*bool),
+            _ => None,
+        })
+        .collect();
+    match bools {
+        Some(bools) => Ok(query.bind(bools)),
+        None => Err(BindError::UnsupportedArray(list)),
+    }
+}
+
+/// Bind a value to a query for a column, decoding the base64 strings
+/// bound to binary columns into bytes
+fn bind_postgres_column<'q>(
+    query: Query<'q, Postgres, PgArguments>,
+    value: FinalType,
+    decoder: Option<&ColumnDecoder>,
+) -> Result<Query<'q, Postgres, PgArguments>, BindError> {
+    match (decoder, value) {
+        (Some(ColumnDecoder::Binary), FinalType::String(encoded)) => {
+            Ok(query.bind(decode_binary(&encoded)?))
+        }
+        (_, value) => bind_postgres_value(query, value),
     }
 }
 
-/// Fetch data using a serialized query tree from a PostgreSQL database
-pub async fn fetch_postgres_query<'a, E>(query: &QueryTree, executor: E) -> QueryData<PgRow>
+/// Fetch data using a serialized query tree from a PostgreSQL database.
+/// Queries that fail validation (see `QueryTree::validate`) are rejected before any SQL is built.
+pub async fn fetch_postgres_query<'a, E>(
+    query: &QueryTree,
+    executor: E,
+) -> Result<QueryData<PgRow>, QueryError>
 where
     E: Executor<'a, Database = Postgres>,
 {
-    // Prepare the query
-    let (sql, values) = prepare_sqlx_query(&query);
-    let with_placeholders = to_numbered_placeholders(&sql);
-    let mut sqlx_query = sqlx::query(&with_placeholders);
+    // Validate and prepare the query
+    check_query_support(query, DbKind::Postgres)?;
+    let (with_placeholders, values) = prepare_sqlx_query(query, DbKind::Postgres)?;
+    let mut sqlx_query = cached_query(&with_placeholders);
 
     // Bind the values
+    let bindings = describe_bindings(&values);
     for value in values {
-        sqlx_query = bind_postgres_value(sqlx_query, value);
+        sqlx_query = bind_postgres_value(sqlx_query, value)?;
     }
 
     // Fetch one or many rows depending on the query
     match query.return_type {
         ReturnType::Single => {
-            let row = sqlx_query.fetch_optional(executor).await.unwrap();
-            return QueryData::Single(row);
+            let row = sqlx_query
+                .fetch_optional(executor)
+                .await
+                .map_err(statement_error(&with_placeholders, bindings))?;
+            Ok(QueryData::Single(row))
         }
         ReturnType::Many => {
-            let rows = sqlx_query.fetch_all(executor).await.unwrap();
-            return QueryData::Many(rows);
+            let rows = sqlx_query
+                .fetch_all(executor)
+                .await
+                .map_err(statement_error(&with_placeholders, bindings))?;
+            Ok(QueryData::Many(rows))
         }
     }
 }
 
+/// Fetch data using a serialized query tree from a PostgreSQL database, binding the
+/// base64 strings compared to binary columns as bytes (see `decode_binary_constraints`)
+pub async fn fetch_postgres_query_with_decoders<'a, E>(
+    query: &QueryTree,
+    executor: E,
+    decoders: &ColumnDecoders,
+) -> Result<QueryData<PgRow>, QueryError>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    let query = decode_binary_constraints(query, decoders)?;
+    fetch_postgres_query(&query, executor).await
+}
+
+/// Fetch independent queries concurrently on a PostgreSQL pool, at most `concurrency`
+/// at a time. Results are returned in the order of the queries, and a failing query
+/// does not fail the others.
+pub async fn fetch_many_postgres_queries(
+    queries: Vec<QueryTree>,
+    pool: &PgPool,
+    concurrency: usize,
+) -> Vec<Result<QueryData<PgRow>, QueryError>> {
+    fetch_many_postgres_queries_with_acquire_timeout(queries, pool, concurrency, None).await
+}
+
+/// Same as `fetch_many_postgres_queries`, failing the queries that wait for a pooled
+/// connection longer than `acquire_timeout` with `QueryError::PoolExhausted`
+pub async fn fetch_many_postgres_queries_with_acquire_timeout(
+    queries: Vec<QueryTree>,
+    pool: &PgPool,
+    concurrency: usize,
+    acquire_timeout: Option<Duration>,
+) -> Vec<Result<QueryData<PgRow>, QueryError>> {
+    fetch_many_with(queries, concurrency, |query| {
+        let pool = pool.clone();
+        async move {
+            let mut conn = acquire_connection(&pool, acquire_timeout).await?;
+            fetch_postgres_query(&query, &mut *conn).await
+        }
+    })
+    .await
+}
+
+/// Fetch a keyset page of rows using a serialized query tree from a PostgreSQL database,
+/// along with the cursor of the next page
+pub async fn fetch_postgres_page<'a, E, T>(
+    query: &QueryTree,
+    executor: E,
+) -> Result<Page<T>, QueryError>
+where
+    E: Executor<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow> + Serialize,
+{
+    let rows = fetch_postgres_query(&page_query(query), executor)
+        .await?
+        .unwrap_many()
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<_, _>>()?;
+
+    Ok(paginate_rows(rows, query))
+}
+
+/// Fetch all the rows of a query from a PostgreSQL database if there are at most `threshold`
+/// of them, or their first keyset page with a `paginated` flag otherwise
+pub async fn fetch_postgres_adaptive_page<'a, E, T>(
+    query: &QueryTree,
+    executor: E,
+    threshold: u64,
+) -> Result<AdaptivePage<T>, QueryError>
+where
+    E: Executor<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow> + Serialize,
+{
+    let rows = fetch_postgres_query(&adaptive_query(query, threshold), executor)
+        .await?
+        .unwrap_many()
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<_, _>>()?;
+
+    Ok(adaptive_rows(rows, query, threshold))
+}
+
+/// Fetch data using a serialized query tree from a PostgreSQL database,
+/// failing with `QueryError::Timeout` if the query runs for longer than `timeout`.
+///
+/// The query runs in a transaction with `SET LOCAL statement_timeout`, so that the server
+/// cancels it by itself and the setting does not leak to the pooled connection once the
+/// transaction ends. If the client-side timeout fires first, the transaction is dropped and
+/// SQLx rolls it back and waits for the pending statement before reusing the connection.
+pub async fn fetch_postgres_query_with_timeout<'a, A>(
+    query: &QueryTree,
+    connection: A,
+    timeout: Duration,
+) -> Result<QueryData<PgRow>, QueryError>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tx = connection.begin().await?;
+
+    // A `statement_timeout` of 0 disables the timeout
+    let millis = timeout.as_millis().max(1);
+    sqlx::query(&format!("SET LOCAL statement_timeout = {millis}"))
+        .execute(&mut *tx)
+        .await?;
+
+    let result = tokio::time::timeout(timeout, fetch_postgres_query(query, &mut *tx)).await;
+    let data = timeout_result(result, timeout, |err| {
+        err.as_database_error()
+            .and_then(|err| err.code())
+            .is_some_and(|code| code == QUERY_CANCELED)
+    })?;
+
+    tx.commit().await?;
+
+    Ok(data)
+}
+
+/// Fetch data using a serialized query tree from a PostgreSQL database,
+/// failing with `QueryError::Cancelled` if the token is cancelled before the query completes
+/// (for instance when the client waiting for the rows disconnects).
+///
+/// Dropping the query future would not stop the statement on the server: on cancellation,
+/// `pg_cancel_backend` is called from a separate connection (so that it also works with
+/// exhausted pools), and the aborted statement is awaited before the connection is released.
+pub async fn fetch_postgres_query_cancellable(
+    query: &QueryTree,
+    pool: &PgPool,
+    token: &CancellationToken,
+) -> Result<QueryData<PgRow>, QueryError> {
+    fetch_postgres_query_cancellable_with_acquire_timeout(query, pool, token, None).await
+}
+
+/// Same as `fetch_postgres_query_cancellable`, failing with `QueryError::PoolExhausted`
+/// if no pooled connection is available within `acquire_timeout`
+pub async fn fetch_postgres_query_cancellable_with_acquire_timeout(
+    query: &QueryTree,
+    pool: &PgPool,
+    token: &CancellationToken,
+    acquire_timeout: Option<Duration>,
+) -> Result<QueryData<PgRow>, QueryError> {
+    let mut conn = acquire_connection(pool, acquire_timeout).await?;
+    let (pid,): (i32,) = sqlx::query_as("SELECT pg_backend_pid()")
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let result = with_server_cancel(fetch_postgres_query(query, &mut *conn), token, || async {
+        let mut canceller = PgConnection::connect_with(&pool.connect_options()).await?;
+        sqlx::query("SELECT pg_cancel_backend($1)")
+            .bind(pid)
+            .execute(&mut canceller)
+            .await?;
+        canceller.close().await
+    })
+    .await;
+
+    match result {
+        Err(err)
+            if err
+                .database_error()
+                .and_then(|err| err.as_database_error())
+                .and_then(|err| err.code())
+                .is_some_and(|code| code == QUERY_CANCELED) =>
+        {
+            Err(QueryError::Cancelled)
+        }
+        result => result,
+    }
+}
+
+/// Check that a PostgreSQL database is reachable by running a trivial `SELECT 1`,
+/// for liveness and readiness probes. Fails with `sqlx::Error::PoolClosed` on a closed pool.
+pub async fn check_postgres_connection<'a, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    executor.execute("SELECT 1").await?;
+    Ok(())
+}
+
 /// Convert a PostgreSQL row to a JSON object
 pub fn postgres_row_to_json(row: &PgRow) -> serde_json::Value {
-    let mut json_map = serde_json::Map::new();
-
-    for column in row.columns() {
-        let column_name = column.name();
-        let column_type = column.type_info().name();
-
-        // Dynamically match the type and insert it into the JSON map
-        let value = match column_type {
-            "INTEGER" => row
-                .try_get::<i64, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "REAL" | "NUMERIC" => row
-                .try_get::<f64, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "BOOLEAN" => row
-                .try_get::<bool, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "TEXT" | "DATE" | "TIME" | "DATETIME" => row
-                .try_get::<String, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "NULL" => Some(serde_json::Value::Null),
-            "BLOB" => None, // Skip BLOB columns
-            _ => None,      // Handle other types as needed
-        };
+    postgres_row_to_json_with_decoders(row, &ColumnDecoders::new())
+}
 
-        // Add to JSON map if value is present
-        if let Some(v) = value {
-            json_map.insert(column_name.to_string(), v);
-        } else {
-            json_map.insert(column_name.to_string(), serde_json::Value::Null);
+/// Convert a PostgreSQL row to a JSON object, decoding the columns that have a declared
+/// decoder regardless of the type name reported by the database
+pub fn postgres_row_to_json_with_decoders(
+    row: &PgRow,
+    decoders: &ColumnDecoders,
+) -> serde_json::Value {
+    row_to_json(row, decoders)
+}
+
+impl JsonRow for PgRow {
+    fn column_reader(type_name: &str) -> Option<ColumnReader> {
+        match type_name {
+            "INTEGER" | "INT8" => Some(ColumnReader::Integer),
+            "INT2" => Some(ColumnReader::SmallInteger),
+            "INT4" => Some(ColumnReader::MediumInteger),
+            "FLOAT4" => Some(ColumnReader::SmallFloat),
+            "REAL" | "NUMERIC" | "FLOAT8" => Some(ColumnReader::Float),
+            "BOOLEAN" | "BOOL" => Some(ColumnReader::Bool),
+            "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => Some(ColumnReader::Text),
+            "DATE" | "TIME" | "TIMESTAMP" | "TIMESTAMPTZ" => Some(ColumnReader::Temporal),
+            "NULL" => Some(ColumnReader::Untyped),
+            "BYTEA" => Some(ColumnReader::Binary),
+            _ => None,
         }
     }
 
-    serde_json::Value::Object(json_map)
+    /// Dates and times are read from their binary format without date-time library:
+    /// days (`DATE`) or microseconds (`TIME`, `TIMESTAMP`, `TIMESTAMPTZ` in UTC) since
+    /// 2000-01-01 or midnight. Values in the text format are kept as is.
+    fn temporal_value(&self, index: usize) -> Option<serde_json::Value> {
+        let value = self.try_get_raw(index).ok()?;
+        if value.format() == PgValueFormat::Text {
+            return value.as_str().ok().map(Into::into);
+        }
+
+        let type_name = value.type_info().name().to_string();
+        let bytes = value.as_bytes().ok()?;
+        let formatted = match (type_name.as_str(), bytes.len()) {
+            ("DATE", 4) => match i32::from_be_bytes(bytes.try_into().ok()?) {
+                i32::MAX => "infinity".to_string(),
+                i32::MIN => "-infinity".to_string(),
+                days => format_pg_date(days.into()),
+            },
+            ("TIME", 8) => format_pg_time(i64::from_be_bytes(bytes.try_into().ok()?)),
+            ("TIMESTAMP", 8) => format_pg_timestamp(i64::from_be_bytes(bytes.try_into().ok()?), ""),
+            ("TIMESTAMPTZ", 8) => {
+                format_pg_timestamp(i64::from_be_bytes(bytes.try_into().ok()?), "Z")
+            }
+            _ => return None,
+        };
+
+        Some(formatted.into())
+    }
+}
+
+/// Microseconds in a day
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+/// Format a number of days since 2000-01-01 as a `YYYY-MM-DD` date, with the
+/// proleptic Gregorian calendar (`civil_from_days` of Howard Hinnant)
+fn format_pg_date(days: i64) -> String {
+    // Days since 0000-03-01, so that leap days end the years
+    let days = days + 730_425;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Format a number of microseconds since midnight as a `HH:MM:SS` time, with the
+/// significant digits of its fractional seconds
+fn format_pg_time(micros: i64) -> String {
+    let seconds = micros / 1_000_000;
+    let mut time = format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+    let fraction = micros % 1_000_000;
+    if fraction != 0 {
+        time.push_str(format!(".{fraction:06}").trim_end_matches('0'));
+    }
+    time
+}
+
+/// Format a number of microseconds since 2000-01-01 as a `YYYY-MM-DDTHH:MM:SS` timestamp,
+/// followed by a time zone designator
+fn format_pg_timestamp(micros: i64, zone: &str) -> String {
+    match micros {
+        i64::MAX => "infinity".to_string(),
+        i64::MIN => "-infinity".to_string(),
+        micros => format!(
+            "{}T{}{zone}",
+            format_pg_date(micros.div_euclid(MICROS_PER_DAY)),
+            format_pg_time(micros.rem_euclid(MICROS_PER_DAY))
+        ),
+    }
 }
 
 /// Convert a vector of Postgres rows to a JSON array
 pub fn postgres_rows_to_json(rows: &[PgRow]) -> serde_json::Value {
+    postgres_rows_to_json_with_decoders(rows, &ColumnDecoders::new())
+}
+
+/// Convert a vector of Postgres rows to a JSON array, using the declared column decoders
+pub fn postgres_rows_to_json_with_decoders(
+    rows: &[PgRow],
+    decoders: &ColumnDecoders,
+) -> serde_json::Value {
     let mut json_array = Vec::new();
 
     for row in rows {
-        json_array.push(postgres_row_to_json(row));
+        json_array.push(postgres_row_to_json_with_decoders(row, decoders));
     }
 
     serde_json::Value::Array(json_array)
@@ -124,74 +513,146 @@ pub fn postgres_rows_to_json(rows: &[PgRow]) -> serde_json::Value {
 pub type SerializeRowsMapped = fn(&QueryData<PgRow>, table: &str) -> serde_json::Value;
 
 /// Perform a granular operation on a Postgres database.
+/// Returns a notification to be sent to clients, or the conversion or database error
+/// of the operation (such as a constraint violation, or a row not buildable into `T`).
+pub async fn granular_operation_postgres<'a, A, T>(
+    operation: GranularOperation,
+    connection: A,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    A: Acquire<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
+{
+    granular_operation_postgres_with_options(operation, connection, &OperationOptions::default())
+        .await
+}
+
+/// Perform a granular operation on a Postgres database with options: the values of the
+/// columns that have a declared decoder are bound accordingly (base64 strings for binary
+/// columns), and only the requested columns are returned, from which `T` must be buildable.
 /// Returns a notification to be sent to clients.
-pub async fn granular_operation_postgres<'a, E, T>(
+pub async fn granular_operation_postgres_with_options<'a, A, T>(
     operation: GranularOperation,
-    executor: E,
-) -> Option<OperationNotification<T>>
+    connection: A,
+    options: &OperationOptions,
+) -> Result<Option<OperationNotification<T>>, OperationError>
 where
-    E: Executor<'a, Database = Postgres>,
-    T: for<'r> FromRow<'r, PgRow>,
+    A: Acquire<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
 {
+    let mut conn = connection.acquire().await?;
+    execute_operation_postgres(operation, &mut conn, options).await
+}
+
+/// Same as `granular_operation_postgres_with_options`, executed on a connection rather than
+/// on any `Acquire` implementation, so that its future stays `Send` when given a transaction
+/// or a borrowed pool connection (such as one acquired with `acquire_connection`)
+pub async fn execute_operation_postgres<T>(
+    operation: GranularOperation,
+    conn: &mut PgConnection,
+    options: &OperationOptions,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
+{
+    let decoders = &options.decoders;
+    let returning = options.returning.as_deref();
+
     match operation {
-        GranularOperation::Create { table, mut data } => {
+        GranularOperation::Create {
+            table,
+            mut data,
+            on_conflict,
+            client_ref,
+        } => {
             // Fix the order of the keys for later iterations
             let keys = ordered_keys(&data);
 
             // Produce the SQL query string
-            let string_query = insert_statement(&table, &keys);
+            let string_query = with_returning(
+                insert_statement_on_conflict(&table, &keys, on_conflict, DbKind::Postgres),
+                returning,
+            );
             let numbered_query = to_numbered_placeholders(&string_query);
 
-            let mut sqlx_query = sqlx::query(&numbered_query);
+            let mut sqlx_query = cached_query(&numbered_query);
 
             // Bind the values in the order of the keys
             for key in keys.iter() {
                 // Consume the value and convert it to a NativeType for proper binding
                 let value = data.remove(key).unwrap();
-                let native_value = FinalType::try_from(value).unwrap();
-                sqlx_query = bind_postgres_value(sqlx_query, native_value);
+                let native_value = FinalType::try_from(value)?;
+                sqlx_query = bind_postgres_column(sqlx_query, native_value, decoders.get(key))?;
             }
 
-            let result = sqlx_query.fetch_one(executor).await.unwrap();
-            let data = T::from_row(&result).unwrap();
+            // No row is returned if it was skipped because of a conflict
+            let Some(result) = sqlx_query.fetch_optional(&mut *conn).await? else {
+                no_row_inserted(&table, on_conflict)?;
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
             // Produce the creation notification
-            Some(OperationNotification::Create {
+            Ok(Some(OperationNotification::Create {
                 table: table.to_string(),
                 data,
-            })
+                client_ref,
+            }))
         }
-        GranularOperation::CreateMany { table, mut data } => {
-            // Fix the order of the keys for later iterations
-            let keys = ordered_keys(&data[0]);
-
-            // Produce the SQL query string
-            let string_query = insert_many_statement(&table, &keys, data.len());
-            let numbered_query = to_numbered_placeholders(&string_query);
-
-            let mut sqlx_query = sqlx::query(&numbered_query);
-
-            // Bind all values in order of the keys
-            for entry in data.iter_mut() {
+        GranularOperation::CreateMany {
+            table,
+            data,
+            on_conflict,
+            client_ref,
+        } => {
+            // The order of the rows returned by a multi-row `INSERT ... RETURNING *`
+            // is not guaranteed. Rows are inserted one by one in a transaction so that
+            // the returned data always matches the order of the input rows.
+            let mut tx = Connection::begin(&mut *conn).await?;
+            let mut created: Vec<T> = Vec::with_capacity(data.len());
+
+            for mut entry in data {
+                // Fix the order of the keys for later iterations
+                let keys = ordered_keys(&entry);
+
+                // Produce the SQL query string
+                let string_query = with_returning(
+                    insert_statement_on_conflict(&table, &keys, on_conflict, DbKind::Postgres),
+                    returning,
+                );
+                let numbered_query = to_numbered_placeholders(&string_query);
+
+                let mut sqlx_query = cached_query(&numbered_query);
+
+                // Bind the values in the order of the keys
                 for key in keys.iter() {
                     // Consume the value and convert it to a NativeType for proper binding
                     let value = entry.remove(key).unwrap();
-                    let native_value = FinalType::try_from(value).unwrap();
-                    sqlx_query = bind_postgres_value(sqlx_query, native_value);
+                    let native_value = FinalType::try_from(value)?;
+                    sqlx_query = bind_postgres_column(sqlx_query, native_value, decoders.get(key))?;
+                }
+
+                // Rows skipped because of a conflict are not notified
+                match sqlx_query.fetch_optional(&mut *tx).await? {
+                    Some(result) => {
+                        created.push(T::from_row(&result).map_err(OperationError::FromRow)?)
+                    }
+                    None => no_row_inserted(&table, on_conflict)?,
                 }
             }
 
-            let results = sqlx_query.fetch_all(executor).await.unwrap();
-            let data: Vec<T> = results
-                .into_iter()
-                .map(|row| T::from_row(&row).unwrap())
-                .collect();
+            tx.commit().await?;
+
+            if created.is_empty() && on_conflict == ConflictPolicy::Ignore {
+                return Ok(None);
+            }
 
             // Produce the operation notification
-            Some(OperationNotification::CreateMany {
+            Ok(Some(OperationNotification::CreateMany {
                 table: table.to_string(),
-                data,
-            })
+                data: created,
+                client_ref,
+            }))
         }
         GranularOperation::Update {
             table,
@@ -202,59 +663,350 @@ where
             let keys = ordered_keys(&data);
 
             // Produce the SQL query string
-            let string_query = update_statement(&table, &keys);
+            let string_query = with_returning(update_statement(&table, &keys), returning);
             let numbered_query = to_numbered_placeholders(&string_query);
 
-            let mut sqlx_query = sqlx::query(&numbered_query);
+            let mut sqlx_query = cached_query(&numbered_query);
 
             // Bind the values in the order of the keys
             for key in keys.iter() {
                 // Consume the value and convert it to a NativeType for proper binding
                 let value = data.remove(key).unwrap();
-                let native_value = FinalType::try_from(value).unwrap();
-                sqlx_query = bind_postgres_value(sqlx_query, native_value);
+                let native_value = FinalType::try_from(value)?;
+                sqlx_query = bind_postgres_column(sqlx_query, native_value, decoders.get(key))?;
             }
 
             // Bind the ID
-            sqlx_query = bind_postgres_value(sqlx_query, id.clone());
-
-            let result = sqlx_query.fetch_optional(executor).await.unwrap();
-
-            if result.is_none() {
-                return None;
-            }
-
-            let data = T::from_row(&result.unwrap()).unwrap();
+            sqlx_query = bind_postgres_value(sqlx_query, id.clone())?;
+
+            // Select the row before it is updated, in the same transaction
+            let mut tx = Connection::begin(&mut *conn).await?;
+            let select_query = to_numbered_placeholders(&select_by_id_statement(&table, returning));
+            let Some(previous) = bind_postgres_value(cached_query(&select_query), id.clone())?
+                .fetch_optional(&mut *tx)
+                .await?
+            else {
+                return Ok(None);
+            };
+
+            let Some(result) = sqlx_query.fetch_optional(&mut *tx).await? else {
+                return Ok(None);
+            };
+            tx.commit().await?;
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
             // Produce the creation notification
-            Some(OperationNotification::Update {
-                table: table.to_string(),
-                id: id.clone(),
+            Ok(Some(OperationNotification::update(
+                &table,
+                id.clone(),
                 data,
-            })
+                Some(T::from_row(&previous).map_err(OperationError::FromRow)?),
+            )))
         }
         GranularOperation::Delete { table, id } => {
-            let string_query = delete_statement(&table);
+            let string_query = with_returning(delete_statement(&table), returning);
             let numbered_query = to_numbered_placeholders(&string_query);
 
-            let mut sqlx_query = sqlx::query(&numbered_query);
+            let mut sqlx_query = cached_query(&numbered_query);
 
             // Bind the ID
-            sqlx_query = bind_postgres_value(sqlx_query, id.clone());
+            sqlx_query = bind_postgres_value(sqlx_query, id.clone())?;
 
-            let result = sqlx_query.fetch_optional(executor).await.unwrap();
+            let Some(result) = sqlx_query.fetch_optional(&mut *conn).await? else {
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
-            if result.is_none() {
-                return None;
-            }
+            Ok(Some(OperationNotification::delete(
+                &table,
+                id.clone(),
+                data,
+            )))
+        }
+    }
+}
 
-            let data = T::from_row(&result.unwrap()).unwrap();
+/// Perform a granular operation on a PostgreSQL database with options, and return the
+/// notices raised meanwhile (for instance with `RAISE NOTICE` in triggers) along with
+/// the notification.
+pub async fn granular_operation_postgres_with_notices<'a, A, T>(
+    operation: GranularOperation,
+    connection: A,
+    options: &OperationOptions,
+) -> Result<OperationOutcome<T>, OperationError>
+where
+    A: Acquire<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_operation_postgres_with_notices(operation, &mut conn, options).await
+}
 
-            Some(OperationNotification::Delete {
-                table: table.to_string(),
-                id: id.clone(),
-                data,
-            })
+/// Same as `granular_operation_postgres_with_notices`, on a connection
+pub async fn execute_operation_postgres_with_notices<T>(
+    operation: GranularOperation,
+    conn: &mut PgConnection,
+    options: &OperationOptions,
+) -> Result<OperationOutcome<T>, OperationError>
+where
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
+{
+    let collector = NoticeCollector::new();
+    let notices = collector.notices.clone();
+
+    let notification = execute_operation_postgres(operation, conn, options)
+        .with_subscriber(Dispatch::new(collector))
+        .await?;
+    let notices = std::mem::take(&mut *notices.lock().unwrap());
+
+    Ok(OperationOutcome {
+        notification,
+        notices,
+    })
+}
+
+/// Target of the events through which SQLx reports the notices of PostgreSQL
+const NOTICE_TARGET: &str = "sqlx::postgres::notice";
+
+/// Tracing subscriber collecting the notices reported by SQLx while an operation runs.
+/// SQLx only reports notices as tracing events: the collector records them, and forwards
+/// every event and span to the subscriber that was in place, so that logging is unaffected.
+struct NoticeCollector {
+    notices: Arc<Mutex<Vec<DatabaseNotice>>>,
+    inner: Dispatch,
+}
+
+impl NoticeCollector {
+    /// Create a collector forwarding to the current subscriber
+    fn new() -> Self {
+        NoticeCollector {
+            notices: Arc::default(),
+            inner: tracing::dispatcher::get_default(Dispatch::clone),
+        }
+    }
+}
+
+/// Visitor extracting the message of a notice event
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0 = value.to_string();
         }
     }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+impl Subscriber for NoticeCollector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == NOTICE_TARGET || self.inner.enabled(metadata)
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        self.inner.new_span(span)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.inner.record(span, values)
+    }
+
+    fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+        self.inner.record_follows_from(span, follows)
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        if event.metadata().target() == NOTICE_TARGET {
+            let mut visitor = MessageVisitor::default();
+            event.record(&mut visitor);
+
+            // Levels of the PostgreSQL severities, as mapped by SQLx
+            let level = match *event.metadata().level() {
+                Level::ERROR => "error",
+                Level::WARN => "warning",
+                Level::INFO => "notice",
+                Level::DEBUG => "debug",
+                Level::TRACE => "info",
+            };
+            self.notices.lock().unwrap().push(DatabaseNotice {
+                level: level.to_string(),
+                code: None,
+                message: visitor.0,
+            });
+        }
+        if self.inner.enabled(event.metadata()) {
+            self.inner.event(event);
+        }
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.inner.enter(span)
+    }
+
+    fn exit(&self, span: &span::Id) {
+        self.inner.exit(span)
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        self.inner.clone_span(id)
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        self.inner.try_close(id)
+    }
+}
+
+/// Bring the rows of a PostgreSQL table matching a condition to a desired set, atomically:
+/// the current rows are selected and the resulting creations, updates and deletions
+/// executed in a single transaction. Returns the per-row notifications, in execution order.
+pub async fn sync_set_postgres<'a, A, T>(
+    sync: SyncSet,
+    connection: A,
+    options: &OperationOptions,
+) -> Result<Vec<OperationNotification<T>>, OperationError>
+where
+    A: Acquire<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_sync_set_postgres(sync, &mut conn, options).await
+}
+
+/// Same as `sync_set_postgres`, on a connection
+pub async fn execute_sync_set_postgres<T>(
+    sync: SyncSet,
+    conn: &mut PgConnection,
+    options: &OperationOptions,
+) -> Result<Vec<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, PgRow> + Identifiable,
+{
+    // The condition comes from the client: reject it before opening the transaction
+    let query = sync.query();
+    query.validate()?;
+
+    let mut tx = Connection::begin(conn).await?;
+
+    let current = fetch_postgres_query(&query, &mut *tx).await?.unwrap_many();
+    let current: Vec<serde_json::Value> = current.iter().map(postgres_row_to_json).collect();
+
+    let mut notifications = vec![];
+    for operation in sync.operations(&current)? {
+        if let Some(notification) = execute_operation_postgres(operation, &mut tx, options).await? {
+            notifications.push(notification);
+        }
+    }
+
+    tx.commit().await?;
+    Ok(notifications)
+}
+
+/// Dry run of a granular operation on a PostgreSQL database: the operation is executed
+/// in a transaction that is always rolled back, so that conversion errors and constraint
+/// violations are reported without writing anything.
+pub async fn validate_operation_postgres<'a, A>(
+    operation: GranularOperation,
+    connection: A,
+) -> Result<(), OperationError>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut conn = connection.acquire().await?;
+    execute_validate_operation_postgres(operation, &mut conn).await
+}
+
+/// Same as `validate_operation_postgres`, on a connection
+pub async fn execute_validate_operation_postgres(
+    operation: GranularOperation,
+    conn: &mut PgConnection,
+) -> Result<(), OperationError> {
+    let mut tx = Connection::begin(conn).await?;
+    execute_operation_postgres::<DiscardedRow>(operation, &mut tx, &OperationOptions::default())
+        .await?;
+    tx.rollback().await?;
+
+    Ok(())
+}
+
+/// Insert a single row and return it, forwarding conversion and database errors
+async fn try_insert_postgres_row<'a, E>(
+    table: &str,
+    mut data: JsonObject,
+    executor: E,
+) -> Result<PgRow, QueryError>
+where
+    E: Executor<'a, Database = Postgres>,
+{
+    // Fix the order of the keys for later iterations
+    let keys = ordered_keys(&data);
+
+    // Produce the SQL query string
+    let string_query = with_returning(insert_statement(table, &keys), None);
+    let numbered_query = to_numbered_placeholders(&string_query);
+
+    let mut sqlx_query = cached_query(&numbered_query);
+
+    // Bind the values in the order of the keys
+    for key in keys.iter() {
+        let value = data.remove(key).unwrap();
+        sqlx_query = bind_postgres_value(sqlx_query, FinalType::try_from(value)?)?;
+    }
+
+    Ok(sqlx_query.fetch_one(executor).await?)
+}
+
+/// Perform a non-atomic `CreateMany` operation on a Postgres database.
+/// Rows are inserted individually outside of any transaction: the rows that fail
+/// are reported by index, and the others are created regardless. Only the failure
+/// to acquire a connection fails the whole operation.
+pub async fn create_many_partial_postgres<'a, A, T>(
+    table: &str,
+    data: Vec<JsonObject>,
+    connection: A,
+) -> Result<PartialCreateMany<T>, QueryError>
+where
+    A: Acquire<'a, Database = Postgres>,
+    T: for<'r> FromRow<'r, PgRow>,
+{
+    let mut conn = connection.acquire().await?;
+    execute_create_many_partial_postgres(table, data, &mut conn).await
+}
+
+/// Same as `create_many_partial_postgres`, on a connection
+pub async fn execute_create_many_partial_postgres<T>(
+    table: &str,
+    data: Vec<JsonObject>,
+    conn: &mut PgConnection,
+) -> Result<PartialCreateMany<T>, QueryError>
+where
+    T: for<'r> FromRow<'r, PgRow>,
+{
+    let mut result = PartialCreateMany {
+        table: table.to_string(),
+        created: Default::default(),
+        errors: Default::default(),
+    };
+
+    for (index, entry) in data.into_iter().enumerate() {
+        let row = try_insert_postgres_row(table, entry, &mut *conn)
+            .await
+            .and_then(|row| Ok(T::from_row(&row)?));
+
+        match row {
+            Ok(row) => {
+                result.created.insert(index, row);
+            }
+            Err(err) => {
+                result.errors.insert(index, err.to_string());
+            }
+        }
+    }
+
+    Ok(result)
 }
@@ -1,121 +1,383 @@
 //! Particularized SQLite implementations.
 
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
 use sqlx::{
     query::Query,
-    sqlite::{SqliteArguments, SqliteRow},
-    Column, Executor, FromRow, Row, Sqlite, TypeInfo,
+    sqlite::{SqliteArguments, SqliteConnection, SqlitePool, SqliteRow},
+    Acquire, Connection, Executor, FromRow, Row, Sqlite, TypeInfo, ValueRef,
 };
 
 use crate::{
-    operations::serialize::{GranularOperation, OperationNotification},
-    queries::serialize::{FinalType, QueryData, QueryTree, ReturnType},
+    cancellation::CancellationToken,
+    error::{BindError, OperationError, QueryError},
+    operations::serialize::{
+        ConflictPolicy, GranularOperation, JsonObject, OperationNotification, PartialCreateMany,
+        SyncSet,
+    },
+    queries::serialize::{AdaptivePage, FinalType, Page, QueryData, QueryTree, ReturnType},
+    snapshots::Identifiable,
     utils::{
-        delete_statement, insert_many_statement, insert_statement, ordered_keys,
-        to_numbered_placeholders, update_statement,
+        delete_statement, insert_statement, insert_statement_on_conflict, ordered_keys,
+        select_by_id_statement, to_numbered_placeholders, update_statement, with_returning,
     },
 };
 
-use super::prepare_sqlx_query;
+use super::{
+    acquire_connection, adaptive_query, adaptive_rows, cached_query, cancellable,
+    check_query_support, decode_binary, decode_binary_constraints, describe_bindings,
+    fetch_many_with, no_row_inserted, page_query, paginate_rows, prepare_sqlx_query, read_column,
+    row_to_json, statement_error, timeout_result, ColumnDecoder, ColumnDecoders, ColumnReader,
+    DbKind, DiscardedRow, JsonRow, OperationOptions, OperationOutcome,
+};
+
+/// Number of SQLite virtual machine instructions between two deadline checks
+const PROGRESS_HANDLER_OPS: i32 = 1000;
+
+/// SQLite result code for interrupted statements (`SQLITE_INTERRUPT`)
+const SQLITE_INTERRUPT: &str = "9";
 
-/// Bind a native value to a Sqlite query
+/// Bind a native value to a Sqlite query.
+/// Fails if a number cannot be represented by any bindable type, including the
+/// integers beyond the i64 range.
 #[inline]
 pub fn bind_sqlite_value<'q>(
     query: Query<'q, Sqlite, SqliteArguments<'q>>,
     value: FinalType,
-) -> Query<'q, Sqlite, SqliteArguments<'q>> {
-    match value {
+) -> Result<Query<'q, Sqlite, SqliteArguments<'q>>, BindError> {
+    let query = match value {
         FinalType::Null => query.bind(None::<String>),
         FinalType::Number(number) => {
-            if number.is_f64() {
-                query.bind(number.as_f64().unwrap())
+            if let Some(integer) = number.as_i64() {
+                query.bind(integer)
+            } else if number.is_u64() {
+                // SQLite has no unsigned 64-bit integer type, and binding integers
+                // beyond the i64 range as floats would lose their precision
+                return Err(BindError::UnsupportedNumber(number));
+            } else if let Some(float) = number.as_f64() {
+                query.bind(float)
             } else {
-                query.bind(number.as_i64().unwrap())
+                return Err(BindError::UnsupportedNumber(number));
             }
         }
         FinalType::String(string) => query.bind(string),
         FinalType::Bool(bool) => query.bind(bool),
+        FinalType::Array(list) => return Err(BindError::UnsupportedArray(list)),
+        FinalType::Binary(bytes) => query.bind(bytes),
+    };
+
+    Ok(query)
+}
+
+/// Bind a value to a query for a column, decoding the base64 strings
+/// bound to binary columns into bytes
+fn bind_sqlite_column<'q>(
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    value: FinalType,
+    decoder: Option<&ColumnDecoder>,
+) -> Result<Query<'q, Sqlite, SqliteArguments<'q>>, BindError> {
+    match (decoder, value) {
+        (Some(ColumnDecoder::Binary), FinalType::String(encoded)) => {
+            Ok(query.bind(decode_binary(&encoded)?))
+        }
+        (_, value) => bind_sqlite_value(query, value),
     }
 }
 
-/// Fetch data using a serialized query tree from a SQLite database
-pub async fn fetch_sqlite_query<'a, E>(query: &QueryTree, executor: E) -> QueryData<SqliteRow>
+/// Fetch data using a serialized query tree from a SQLite database.
+/// Queries that fail validation (see `QueryTree::validate`) are rejected before any SQL is built.
+pub async fn fetch_sqlite_query<'a, E>(
+    query: &QueryTree,
+    executor: E,
+) -> Result<QueryData<SqliteRow>, QueryError>
 where
     E: Executor<'a, Database = Sqlite>,
 {
-    // Prepare the query
-    let (sql, values) = prepare_sqlx_query(&query);
+    // Validate and prepare the query
+    check_query_support(query, DbKind::Sqlite)?;
+    let (sql, values) = prepare_sqlx_query(query, DbKind::Sqlite)?;
     let with_placeholders = to_numbered_placeholders(&sql);
-    let mut sqlx_query = sqlx::query(&with_placeholders);
+    let mut sqlx_query = cached_query(&with_placeholders);
 
     // Bind the values
+    let bindings = describe_bindings(&values);
     for value in values {
-        sqlx_query = bind_sqlite_value(sqlx_query, value);
+        sqlx_query = bind_sqlite_value(sqlx_query, value)?;
     }
 
     // Fetch one or many rows depending on the query
     match query.return_type {
         ReturnType::Single => {
-            let row = sqlx_query.fetch_optional(executor).await.unwrap();
-            return QueryData::Single(row);
+            let row = sqlx_query
+                .fetch_optional(executor)
+                .await
+                .map_err(statement_error(&with_placeholders, bindings))?;
+            Ok(QueryData::Single(row))
         }
         ReturnType::Many => {
-            let rows = sqlx_query.fetch_all(executor).await.unwrap();
-            return QueryData::Many(rows);
+            let rows = sqlx_query
+                .fetch_all(executor)
+                .await
+                .map_err(statement_error(&with_placeholders, bindings))?;
+            Ok(QueryData::Many(rows))
         }
     }
 }
 
+/// Fetch data using a serialized query tree from a SQLite database, binding the
+/// base64 strings compared to binary columns as bytes (see `decode_binary_constraints`)
+pub async fn fetch_sqlite_query_with_decoders<'a, E>(
+    query: &QueryTree,
+    executor: E,
+    decoders: &ColumnDecoders,
+) -> Result<QueryData<SqliteRow>, QueryError>
+where
+    E: Executor<'a, Database = Sqlite>,
+{
+    let query = decode_binary_constraints(query, decoders)?;
+    fetch_sqlite_query(&query, executor).await
+}
+
+/// Fetch independent queries concurrently on a SQLite pool, at most `concurrency`
+/// at a time. Results are returned in the order of the queries, and a failing query
+/// does not fail the others.
+pub async fn fetch_many_sqlite_queries(
+    queries: Vec<QueryTree>,
+    pool: &SqlitePool,
+    concurrency: usize,
+) -> Vec<Result<QueryData<SqliteRow>, QueryError>> {
+    fetch_many_sqlite_queries_with_acquire_timeout(queries, pool, concurrency, None).await
+}
+
+/// Same as `fetch_many_sqlite_queries`, failing the queries that wait for a pooled
+/// connection longer than `acquire_timeout` with `QueryError::PoolExhausted`
+pub async fn fetch_many_sqlite_queries_with_acquire_timeout(
+    queries: Vec<QueryTree>,
+    pool: &SqlitePool,
+    concurrency: usize,
+    acquire_timeout: Option<Duration>,
+) -> Vec<Result<QueryData<SqliteRow>, QueryError>> {
+    fetch_many_with(queries, concurrency, |query| {
+        let pool = pool.clone();
+        async move {
+            let mut conn = acquire_connection(&pool, acquire_timeout).await?;
+            fetch_sqlite_query(&query, &mut *conn).await
+        }
+    })
+    .await
+}
+
+/// Fetch a keyset page of rows using a serialized query tree from a SQLite database,
+/// along with the cursor of the next page
+pub async fn fetch_sqlite_page<'a, E, T>(
+    query: &QueryTree,
+    executor: E,
+) -> Result<Page<T>, QueryError>
+where
+    E: Executor<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow> + Serialize,
+{
+    let rows = fetch_sqlite_query(&page_query(query), executor)
+        .await?
+        .unwrap_many()
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<_, _>>()?;
+
+    Ok(paginate_rows(rows, query))
+}
+
+/// Fetch all the rows of a query from a SQLite database if there are at most `threshold`
+/// of them, or their first keyset page with a `paginated` flag otherwise
+pub async fn fetch_sqlite_adaptive_page<'a, E, T>(
+    query: &QueryTree,
+    executor: E,
+    threshold: u64,
+) -> Result<AdaptivePage<T>, QueryError>
+where
+    E: Executor<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow> + Serialize,
+{
+    let rows = fetch_sqlite_query(&adaptive_query(query, threshold), executor)
+        .await?
+        .unwrap_many()
+        .iter()
+        .map(T::from_row)
+        .collect::<Result<_, _>>()?;
+
+    Ok(adaptive_rows(rows, query, threshold))
+}
+
+/// Fetch data using a serialized query tree from a SQLite database,
+/// failing with `QueryError::Timeout` if the query runs for longer than `timeout`.
+///
+/// SQLite has no statement timeout: a progress handler interrupts the statement
+/// once the deadline has passed, so that the connection is not kept busy by an
+/// abandoned query. The handler is removed before the connection is released.
+pub async fn fetch_sqlite_query_with_timeout<'a, A>(
+    query: &QueryTree,
+    connection: A,
+    timeout: Duration,
+) -> Result<QueryData<SqliteRow>, QueryError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+{
+    let mut conn = connection.acquire().await?;
+
+    let deadline = Instant::now() + timeout;
+    conn.lock_handle()
+        .await?
+        .set_progress_handler(PROGRESS_HANDLER_OPS, move || Instant::now() < deadline);
+
+    let result = tokio::time::timeout(timeout, fetch_sqlite_query(query, &mut *conn)).await;
+
+    // Waits for the interrupted statement to stop if the client-side timeout fired first
+    conn.lock_handle().await?.remove_progress_handler();
+
+    timeout_result(result, timeout, |err| {
+        err.as_database_error()
+            .and_then(|err| err.code())
+            .is_some_and(|code| code == SQLITE_INTERRUPT)
+    })
+}
+
+/// Fetch data using a serialized query tree from a SQLite database,
+/// failing with `QueryError::Cancelled` if the token is cancelled before the query completes
+/// (for instance when the client waiting for the rows disconnects).
+///
+/// Like timeouts, cancellation interrupts the running statement through a progress handler,
+/// instead of letting it run to completion on an abandoned connection.
+pub async fn fetch_sqlite_query_cancellable<'a, A>(
+    query: &QueryTree,
+    connection: A,
+    token: &CancellationToken,
+) -> Result<QueryData<SqliteRow>, QueryError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+{
+    let mut conn = connection.acquire().await?;
+    fetch_connection_cancellable(query, &mut conn, token).await
+}
+
+/// Body of the cancellable fetches, executed on a connection so that its future
+/// stays `Send` when given a borrowed pool connection
+async fn fetch_connection_cancellable(
+    query: &QueryTree,
+    conn: &mut SqliteConnection,
+    token: &CancellationToken,
+) -> Result<QueryData<SqliteRow>, QueryError> {
+    let handler_token = token.clone();
+    conn.lock_handle()
+        .await?
+        .set_progress_handler(PROGRESS_HANDLER_OPS, move || !handler_token.is_cancelled());
+
+    let result = cancellable(fetch_sqlite_query(query, &mut *conn), token).await;
+
+    // Waits for the interrupted statement to stop if the fetch was dropped first
+    conn.lock_handle().await?.remove_progress_handler();
+
+    match result {
+        Err(err)
+            if err
+                .database_error()
+                .and_then(|err| err.as_database_error())
+                .and_then(|err| err.code())
+                .is_some_and(|code| code == SQLITE_INTERRUPT) =>
+        {
+            Err(QueryError::Cancelled)
+        }
+        result => result,
+    }
+}
+
+/// Same as `fetch_sqlite_query_cancellable` on a pool, failing with
+/// `QueryError::PoolExhausted` if no pooled connection is available within `acquire_timeout`
+pub async fn fetch_sqlite_query_cancellable_with_acquire_timeout(
+    query: &QueryTree,
+    pool: &SqlitePool,
+    token: &CancellationToken,
+    acquire_timeout: Option<Duration>,
+) -> Result<QueryData<SqliteRow>, QueryError> {
+    let mut conn = acquire_connection(pool, acquire_timeout).await?;
+    fetch_connection_cancellable(query, &mut conn, token).await
+}
+
+/// Check that a SQLite database is reachable by running a trivial `SELECT 1`,
+/// for liveness and readiness probes. Fails with `sqlx::Error::PoolClosed` on a closed pool.
+pub async fn check_sqlite_connection<'a, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Executor<'a, Database = Sqlite>,
+{
+    executor.execute("SELECT 1").await?;
+    Ok(())
+}
+
 /// Convert a SQLite row to a JSON object
 pub fn sqlite_row_to_json(row: &SqliteRow) -> serde_json::Value {
-    let mut json_map = serde_json::Map::new();
-
-    for column in row.columns() {
-        let column_name = column.name();
-        let column_type = column.type_info().name();
-
-        // Dynamically match the type and insert it into the JSON map
-        let value = match column_type {
-            "INTEGER" => row
-                .try_get::<i64, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "REAL" | "NUMERIC" => row
-                .try_get::<f64, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "BOOLEAN" => row
-                .try_get::<bool, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "TEXT" | "DATE" | "TIME" | "DATETIME" => row
-                .try_get::<String, _>(column_name)
-                .ok()
-                .map(serde_json::Value::from),
-            "NULL" => Some(serde_json::Value::Null),
-            "BLOB" => None, // Skip BLOB columns
-            _ => None,      // Handle other types as needed
-        };
+    sqlite_row_to_json_with_decoders(row, &ColumnDecoders::new())
+}
 
-        // Add to JSON map if value is present
-        if let Some(v) = value {
-            json_map.insert(column_name.to_string(), v);
-        } else {
-            json_map.insert(column_name.to_string(), serde_json::Value::Null);
+/// Convert a SQLite row to a JSON object, decoding the columns that have a declared
+/// decoder regardless of the type name reported by the database
+pub fn sqlite_row_to_json_with_decoders(
+    row: &SqliteRow,
+    decoders: &ColumnDecoders,
+) -> serde_json::Value {
+    row_to_json(row, decoders)
+}
+
+impl JsonRow for SqliteRow {
+    fn column_reader(type_name: &str) -> Option<ColumnReader> {
+        match type_name {
+            "INTEGER" => Some(ColumnReader::Integer),
+            "REAL" | "NUMERIC" => Some(ColumnReader::Float),
+            "BOOLEAN" => Some(ColumnReader::Bool),
+            "TEXT" | "DATE" | "TIME" | "DATETIME" => Some(ColumnReader::Text),
+            "NULL" => Some(ColumnReader::Untyped),
+            "BLOB" => Some(ColumnReader::Binary),
+            _ => None,
         }
     }
 
-    serde_json::Value::Object(json_map)
+    /// Convert the value of a column reported without type to JSON, from its storage class.
+    /// SQLite reports no type for expressions, columns declared without type, and columns
+    /// with the `NUMERIC` affinity: integers and floats are kept apart as they are stored.
+    ///
+    /// Columns declared `REAL` always store floats, integral values included, and are
+    /// serialized as floats (`3.0`) by their declared type.
+    fn untyped_value(&self, index: usize) -> Option<serde_json::Value> {
+        let value = self.try_get_raw(index).ok()?;
+        if value.is_null() {
+            return Some(serde_json::Value::Null);
+        }
+
+        let reader = match value.type_info().name() {
+            "INTEGER" => ColumnReader::Integer,
+            "REAL" => ColumnReader::Float,
+            "TEXT" => ColumnReader::Text,
+            "BLOB" => ColumnReader::Binary,
+            _ => return None,
+        };
+        read_column(self, index, reader)
+    }
 }
 
 /// Convert a vector of SQLite rows to a JSON array
 pub fn sqlite_rows_to_json(rows: &[SqliteRow]) -> serde_json::Value {
-    let mut json_array = Vec::new();
-
-    for row in rows {
-        json_array.push(sqlite_row_to_json(row));
-    }
+    sqlite_rows_to_json_with_decoders(rows, &ColumnDecoders::new())
+}
 
-    serde_json::Value::Array(json_array)
+/// Convert a vector of SQLite rows to a JSON array, using the declared column decoders
+pub fn sqlite_rows_to_json_with_decoders(
+    rows: &[SqliteRow],
+    decoders: &ColumnDecoders,
+) -> serde_json::Value {
+    serde_json::Value::Array(
+        rows.iter()
+            .map(|row| sqlite_row_to_json_with_decoders(row, decoders))
+            .collect(),
+    )
 }
 
 /// Helper function signature for serializing SQLite rows to JSON
@@ -124,74 +386,176 @@ pub fn sqlite_rows_to_json(rows: &[SqliteRow]) -> serde_json::Value {
 pub type SerializeRowsMapped = fn(&QueryData<SqliteRow>, table: &str) -> serde_json::Value;
 
 /// Perform a granular operation on a SQLite database.
+/// Returns a notification to be sent to clients, or the conversion or database error
+/// of the operation (such as a constraint violation, or a row not buildable into `T`).
+pub async fn granular_operation_sqlite<'a, A, T>(
+    operation: GranularOperation,
+    connection: A,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+{
+    granular_operation_sqlite_with_options(operation, connection, &OperationOptions::default())
+        .await
+}
+
+/// Perform a granular operation on a Sqlite database with options: the values of the
+/// columns that have a declared decoder are bound accordingly (base64 strings for binary
+/// columns), and only the requested columns are returned, from which `T` must be buildable.
 /// Returns a notification to be sent to clients.
-pub async fn granular_operation_sqlite<'a, E, T>(
+pub async fn granular_operation_sqlite_with_options<'a, A, T>(
     operation: GranularOperation,
-    executor: E,
-) -> Option<OperationNotification<T>>
+    connection: A,
+    options: &OperationOptions,
+) -> Result<Option<OperationNotification<T>>, OperationError>
 where
-    E: Executor<'a, Database = Sqlite>,
-    T: for<'r> FromRow<'r, SqliteRow>,
+    A: Acquire<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_operation_sqlite(operation, &mut conn, options).await
+}
+
+/// Same as `granular_operation_sqlite_with_options`, in the shape of the functions
+/// returning the notices of the other backends. SQLite raises no notices.
+pub async fn granular_operation_sqlite_with_notices<'a, A, T>(
+    operation: GranularOperation,
+    connection: A,
+    options: &OperationOptions,
+) -> Result<OperationOutcome<T>, OperationError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_operation_sqlite_with_notices(operation, &mut conn, options).await
+}
+
+/// Same as `granular_operation_sqlite_with_notices`, on a connection
+pub async fn execute_operation_sqlite_with_notices<T>(
+    operation: GranularOperation,
+    conn: &mut SqliteConnection,
+    options: &OperationOptions,
+) -> Result<OperationOutcome<T>, OperationError>
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
 {
+    Ok(OperationOutcome {
+        notification: execute_operation_sqlite(operation, conn, options).await?,
+        notices: vec![],
+    })
+}
+
+/// Same as `granular_operation_sqlite_with_options`, executed on a connection rather than
+/// on any `Acquire` implementation, so that its future stays `Send` when given a transaction
+/// or a borrowed pool connection (such as one acquired with `acquire_connection`)
+pub async fn execute_operation_sqlite<T>(
+    operation: GranularOperation,
+    conn: &mut SqliteConnection,
+    options: &OperationOptions,
+) -> Result<Option<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+{
+    let decoders = &options.decoders;
+    let returning = options.returning.as_deref();
+
     match operation {
-        GranularOperation::Create { table, mut data } => {
+        GranularOperation::Create {
+            table,
+            mut data,
+            on_conflict,
+            client_ref,
+        } => {
             // Fix the order of the keys for later iterations
             let keys = ordered_keys(&data);
 
             // Produce the SQL query string
-            let string_query = insert_statement(&table, &keys);
+            let string_query = with_returning(
+                insert_statement_on_conflict(&table, &keys, on_conflict, DbKind::Sqlite),
+                returning,
+            );
             let numbered_query = to_numbered_placeholders(&string_query);
 
-            let mut sqlx_query = sqlx::query(&numbered_query);
+            let mut sqlx_query = cached_query(&numbered_query);
 
             // Bind the values in the order of the keys
             for key in keys.iter() {
                 // Consume the value and convert it to a NativeType for proper binding
                 let value = data.remove(key).unwrap();
-                let native_value = FinalType::try_from(value).unwrap();
-                sqlx_query = bind_sqlite_value(sqlx_query, native_value);
+                let native_value = FinalType::try_from(value)?;
+                sqlx_query = bind_sqlite_column(sqlx_query, native_value, decoders.get(key))?;
             }
 
-            let result = sqlx_query.fetch_one(executor).await.unwrap();
-            let data = T::from_row(&result).unwrap();
+            // No row is returned if it was skipped because of a conflict
+            let Some(result) = sqlx_query.fetch_optional(&mut *conn).await? else {
+                no_row_inserted(&table, on_conflict)?;
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
             // Produce the creation notification
-            Some(OperationNotification::Create {
+            Ok(Some(OperationNotification::Create {
                 table: table.to_string(),
                 data,
-            })
+                client_ref,
+            }))
         }
-        GranularOperation::CreateMany { table, mut data } => {
-            // Fix the order of the keys for later iterations
-            let keys = ordered_keys(&data[0]);
-
-            // Produce the SQL query string
-            let string_query = insert_many_statement(&table, &keys, data.len());
-            let numbered_query = to_numbered_placeholders(&string_query);
-
-            let mut sqlx_query = sqlx::query(&numbered_query);
-
-            // Bind all values in order of the keys
-            for entry in data.iter_mut() {
+        GranularOperation::CreateMany {
+            table,
+            data,
+            on_conflict,
+            client_ref,
+        } => {
+            // The order of the rows returned by a multi-row `INSERT ... RETURNING *`
+            // is not guaranteed. Rows are inserted one by one in a transaction so that
+            // the returned data always matches the order of the input rows.
+            let mut tx = Connection::begin(&mut *conn).await?;
+            let mut created: Vec<T> = Vec::with_capacity(data.len());
+
+            for mut entry in data {
+                // Fix the order of the keys for later iterations
+                let keys = ordered_keys(&entry);
+
+                // Produce the SQL query string
+                let string_query = with_returning(
+                    insert_statement_on_conflict(&table, &keys, on_conflict, DbKind::Sqlite),
+                    returning,
+                );
+                let numbered_query = to_numbered_placeholders(&string_query);
+
+                let mut sqlx_query = cached_query(&numbered_query);
+
+                // Bind the values in the order of the keys
                 for key in keys.iter() {
                     // Consume the value and convert it to a NativeType for proper binding
                     let value = entry.remove(key).unwrap();
-                    let native_value = FinalType::try_from(value).unwrap();
-                    sqlx_query = bind_sqlite_value(sqlx_query, native_value);
+                    let native_value = FinalType::try_from(value)?;
+                    sqlx_query = bind_sqlite_column(sqlx_query, native_value, decoders.get(key))?;
+                }
+
+                // Rows skipped because of a conflict are not notified
+                match sqlx_query.fetch_optional(&mut *tx).await? {
+                    Some(result) => {
+                        created.push(T::from_row(&result).map_err(OperationError::FromRow)?)
+                    }
+                    None => no_row_inserted(&table, on_conflict)?,
                 }
             }
 
-            let results = sqlx_query.fetch_all(executor).await.unwrap();
-            let data: Vec<T> = results
-                .into_iter()
-                .map(|row| T::from_row(&row).unwrap())
-                .collect();
+            tx.commit().await?;
+
+            if created.is_empty() && on_conflict == ConflictPolicy::Ignore {
+                return Ok(None);
+            }
 
             // Produce the operation notification
-            Some(OperationNotification::CreateMany {
+            Ok(Some(OperationNotification::CreateMany {
                 table: table.to_string(),
-                data,
-            })
+                data: created,
+                client_ref,
+            }))
         }
         GranularOperation::Update {
             table,
@@ -202,59 +566,298 @@ where
             let keys = ordered_keys(&data);
 
             // Produce the SQL query string
-            let string_query = update_statement(&table, &keys);
+            let string_query = with_returning(update_statement(&table, &keys), returning);
             let numbered_query = to_numbered_placeholders(&string_query);
 
-            let mut sqlx_query = sqlx::query(&numbered_query);
+            let mut sqlx_query = cached_query(&numbered_query);
 
             // Bind the values in the order of the keys
             for key in keys.iter() {
                 // Consume the value and convert it to a NativeType for proper binding
                 let value = data.remove(key).unwrap();
-                let native_value = FinalType::try_from(value).unwrap();
-                sqlx_query = bind_sqlite_value(sqlx_query, native_value);
+                let native_value = FinalType::try_from(value)?;
+                sqlx_query = bind_sqlite_column(sqlx_query, native_value, decoders.get(key))?;
             }
 
             // Bind the ID
-            sqlx_query = bind_sqlite_value(sqlx_query, id.clone());
-
-            let result = sqlx_query.fetch_optional(executor).await.unwrap();
-
-            if result.is_none() {
-                return None;
-            }
-
-            let data = T::from_row(&result.unwrap()).unwrap();
+            sqlx_query = bind_sqlite_value(sqlx_query, id.clone())?;
+
+            // Select the row before it is updated, in the same transaction
+            let mut tx = Connection::begin(&mut *conn).await?;
+            let select_query = to_numbered_placeholders(&select_by_id_statement(&table, returning));
+            let Some(previous) = bind_sqlite_value(cached_query(&select_query), id.clone())?
+                .fetch_optional(&mut *tx)
+                .await?
+            else {
+                return Ok(None);
+            };
+
+            let Some(result) = sqlx_query.fetch_optional(&mut *tx).await? else {
+                return Ok(None);
+            };
+            tx.commit().await?;
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
 
             // Produce the creation notification
-            Some(OperationNotification::Update {
-                table: table.to_string(),
-                id: id.clone(),
+            Ok(Some(OperationNotification::update(
+                &table,
+                id.clone(),
                 data,
-            })
+                Some(T::from_row(&previous).map_err(OperationError::FromRow)?),
+            )))
         }
         GranularOperation::Delete { table, id } => {
-            let string_query = delete_statement(&table);
+            let string_query = with_returning(delete_statement(&table), returning);
             let numbered_query = to_numbered_placeholders(&string_query);
 
-            let mut sqlx_query = sqlx::query(&numbered_query);
+            let mut sqlx_query = cached_query(&numbered_query);
 
             // Bind the ID
-            sqlx_query = bind_sqlite_value(sqlx_query, id.clone());
+            sqlx_query = bind_sqlite_value(sqlx_query, id.clone())?;
+
+            let Some(result) = sqlx_query.fetch_optional(&mut *conn).await? else {
+                return Ok(None);
+            };
+            let data = T::from_row(&result).map_err(OperationError::FromRow)?;
+
+            Ok(Some(OperationNotification::delete(
+                &table,
+                id.clone(),
+                data,
+            )))
+        }
+    }
+}
 
-            let result = sqlx_query.fetch_optional(executor).await.unwrap();
+/// Bring the rows of a SQLite table matching a condition to a desired set, atomically:
+/// the current rows are selected and the resulting creations, updates and deletions
+/// executed in a single transaction. Returns the per-row notifications, in execution order.
+pub async fn sync_set_sqlite<'a, A, T>(
+    sync: SyncSet,
+    connection: A,
+    options: &OperationOptions,
+) -> Result<Vec<OperationNotification<T>>, OperationError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+{
+    let mut conn = connection.acquire().await?;
+    execute_sync_set_sqlite(sync, &mut conn, options).await
+}
+
+/// Same as `sync_set_sqlite`, on a connection
+pub async fn execute_sync_set_sqlite<T>(
+    sync: SyncSet,
+    conn: &mut SqliteConnection,
+    options: &OperationOptions,
+) -> Result<Vec<OperationNotification<T>>, OperationError>
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+{
+    // The condition comes from the client: reject it before opening the transaction
+    let query = sync.query();
+    query.validate()?;
+
+    let mut tx = Connection::begin(conn).await?;
+
+    let current = fetch_sqlite_query(&query, &mut *tx).await?.unwrap_many();
+    let current: Vec<serde_json::Value> = current.iter().map(sqlite_row_to_json).collect();
+
+    let mut notifications = vec![];
+    for operation in sync.operations(&current)? {
+        if let Some(notification) = execute_operation_sqlite(operation, &mut tx, options).await? {
+            notifications.push(notification);
+        }
+    }
 
-            if result.is_none() {
-                return None;
+    tx.commit().await?;
+    Ok(notifications)
+}
+
+/// Dry run of a granular operation on a SQLite database: the operation is executed
+/// in a transaction that is always rolled back, so that conversion errors and constraint
+/// violations are reported without writing anything.
+pub async fn validate_operation_sqlite<'a, A>(
+    operation: GranularOperation,
+    connection: A,
+) -> Result<(), OperationError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+{
+    let mut conn = connection.acquire().await?;
+    execute_validate_operation_sqlite(operation, &mut conn).await
+}
+
+/// Same as `validate_operation_sqlite`, on a connection
+pub async fn execute_validate_operation_sqlite(
+    operation: GranularOperation,
+    conn: &mut SqliteConnection,
+) -> Result<(), OperationError> {
+    let mut tx = Connection::begin(conn).await?;
+    execute_operation_sqlite::<DiscardedRow>(operation, &mut tx, &OperationOptions::default())
+        .await?;
+    tx.rollback().await?;
+
+    Ok(())
+}
+
+/// Maximum number of values bound by each statement of `seed_sqlite`,
+/// below the default SQLite limit of bound parameters
+#[cfg(feature = "test-support")]
+const SEED_MAX_VALUES: usize = 999;
+
+/// Create a table in a SQLite database and insert JSON rows into it, for tests.
+///
+/// The columns and their types are inferred from the first row: integers are `INTEGER`
+/// (an integer `id` column being the primary key), other numbers `REAL`, booleans
+/// `BOOLEAN`, strings `TEXT`, and arrays and objects are stored as JSON `TEXT`.
+/// `null` values leave the column without declared type. Keys missing from the other
+/// rows are inserted as `NULL`, and their keys absent from the first row are ignored.
+#[cfg(feature = "test-support")]
+pub async fn seed_sqlite<'a, E>(
+    executor: E,
+    table: &str,
+    rows: &[JsonObject],
+) -> Result<(), QueryError>
+where
+    E: Acquire<'a, Database = Sqlite>,
+{
+    let Some(first) = rows.first() else {
+        return Ok(());
+    };
+    let keys = ordered_keys(first);
+
+    let columns = keys
+        .iter()
+        .map(|key| {
+            let column_type = match &first[key] {
+                serde_json::Value::Number(number) if number.is_i64() || number.is_u64() => {
+                    if key == "id" {
+                        "INTEGER PRIMARY KEY"
+                    } else {
+                        "INTEGER"
+                    }
+                }
+                serde_json::Value::Number(_) => "REAL",
+                serde_json::Value::Bool(_) => "BOOLEAN",
+                serde_json::Value::String(_)
+                | serde_json::Value::Array(_)
+                | serde_json::Value::Object(_) => "TEXT",
+                serde_json::Value::Null => "",
+            };
+            format!(
+                "\"{}\" {column_type}",
+                crate::utils::sanitize_identifier(key)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut tx = executor.begin().await?;
+    sqlx::query(&format!(
+        "CREATE TABLE {} ({columns})",
+        crate::utils::sanitize_identifier(table)
+    ))
+    .execute(&mut *tx)
+    .await?;
+
+    for chunk in rows.chunks((SEED_MAX_VALUES / keys.len()).max(1)) {
+        let statement = crate::utils::insert_many_statement(table, &keys, chunk.len());
+        let mut sqlx_query = sqlx::query(&statement);
+
+        for row in chunk {
+            for key in keys.iter() {
+                let value = match row.get(key) {
+                    None => FinalType::Null,
+                    Some(value @ (serde_json::Value::Array(_) | serde_json::Value::Object(_))) => {
+                        FinalType::String(value.to_string())
+                    }
+                    Some(value) => FinalType::try_from(value.clone())?,
+                };
+                sqlx_query = bind_sqlite_value(sqlx_query, value)?;
             }
+        }
 
-            let data = T::from_row(&result.unwrap()).unwrap();
+        sqlx_query.execute(&mut *tx).await?;
+    }
 
-            Some(OperationNotification::Delete {
-                table: table.to_string(),
-                id: id.clone(),
-                data,
-            })
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Insert a single row and return it, forwarding conversion and database errors
+async fn try_insert_sqlite_row<'a, E>(
+    table: &str,
+    mut data: JsonObject,
+    executor: E,
+) -> Result<SqliteRow, QueryError>
+where
+    E: Executor<'a, Database = Sqlite>,
+{
+    // Fix the order of the keys for later iterations
+    let keys = ordered_keys(&data);
+
+    // Produce the SQL query string
+    let string_query = with_returning(insert_statement(table, &keys), None);
+    let numbered_query = to_numbered_placeholders(&string_query);
+
+    let mut sqlx_query = cached_query(&numbered_query);
+
+    // Bind the values in the order of the keys
+    for key in keys.iter() {
+        let value = data.remove(key).unwrap();
+        sqlx_query = bind_sqlite_value(sqlx_query, FinalType::try_from(value)?)?;
+    }
+
+    Ok(sqlx_query.fetch_one(executor).await?)
+}
+
+/// Perform a non-atomic `CreateMany` operation on a SQLite database.
+/// Rows are inserted individually outside of any transaction: the rows that fail
+/// are reported by index, and the others are created regardless. Only the failure
+/// to acquire a connection fails the whole operation.
+pub async fn create_many_partial_sqlite<'a, A, T>(
+    table: &str,
+    data: Vec<JsonObject>,
+    connection: A,
+) -> Result<PartialCreateMany<T>, QueryError>
+where
+    A: Acquire<'a, Database = Sqlite>,
+    T: for<'r> FromRow<'r, SqliteRow>,
+{
+    let mut conn = connection.acquire().await?;
+    execute_create_many_partial_sqlite(table, data, &mut conn).await
+}
+
+/// Same as `create_many_partial_sqlite`, on a connection
+pub async fn execute_create_many_partial_sqlite<T>(
+    table: &str,
+    data: Vec<JsonObject>,
+    conn: &mut SqliteConnection,
+) -> Result<PartialCreateMany<T>, QueryError>
+where
+    T: for<'r> FromRow<'r, SqliteRow>,
+{
+    let mut result = PartialCreateMany {
+        table: table.to_string(),
+        created: Default::default(),
+        errors: Default::default(),
+    };
+
+    for (index, entry) in data.into_iter().enumerate() {
+        let row = try_insert_sqlite_row(table, entry, &mut *conn)
+            .await
+            .and_then(|row| Ok(T::from_row(&row)?));
+
+        match row {
+            Ok(row) => {
+                result.created.insert(index, row);
+            }
+            Err(err) => {
+                result.errors.insert(index, err.to_string());
+            }
         }
     }
+
+    Ok(result)
 }
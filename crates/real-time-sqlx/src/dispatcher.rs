@@ -0,0 +1,1972 @@
+//! Backend-agnostic subscriptions and operation notifications fan-out.
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::{sync::RwLock, task::JoinHandle};
+
+use crate::{
+    enums::EnumColumns,
+    error::{ChannelError, OperationError, PolicyError},
+    liveness::{Liveness, StalePruning},
+    notification_log::NotificationLog,
+    operations::serialize::{
+        field_diff, object_array_from_value, object_from_value, GranularOperation, JsonObject,
+        OperationNotification, Tabled,
+    },
+    operators::OperatorPolicy,
+    queries::{
+        serialize::{FinalType, OrderBy, QueryTree, ReturnType},
+        Checkable, PatternCache,
+    },
+    utils::tag_request_id,
+};
+
+/// A channel to which serialized notifications can be pushed.
+/// Implemented by the transport of each backend.
+pub trait NotificationChannel {
+    /// Send a serialized notification through the channel.
+    /// An error means that the channel is closed and should be pruned.
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError>;
+
+    /// Send a notification already serialized to JSON, shared with the other channels
+    /// receiving the same payload (see `TableSubscriptions::set_shared_payloads`).
+    /// Transports writing text, such as websocket servers, should override it to skip the
+    /// `serde_json::Value` intermediate: the default implementation parses the payload back.
+    fn send_serialized(&self, payload: Arc<str>) -> Result<(), ChannelError> {
+        let value = serde_json::from_str(&payload).expect("Shared payloads are valid JSON");
+        self.send_value(value)
+    }
+
+    /// Check that the channel is alive, for the pruning of stale channels.
+    /// By default, a `ping` message is sent and the channel is dead if it fails:
+    /// transports able to tell without sending anything should override it.
+    fn probe(&self) -> bool {
+        self.send_value(ping_message()).is_ok()
+    }
+}
+
+/// An emitter of named global events, such as the event system of Tauri applications.
+/// Implemented by the transport of each backend that has one.
+pub trait EventEmitter {
+    /// Emit a serialized payload as a global event.
+    /// An error means that the event could not be delivered.
+    fn emit_value(&self, event: &str, payload: serde_json::Value) -> Result<(), ChannelError>;
+}
+
+/// Name of the global event carrying the notifications of a table
+pub fn table_event_name(table: &str) -> String {
+    format!("real-time-sqlx:{table}")
+}
+
+/// A notification channel delivering notifications as global events named after the
+/// table of its subscription (see `table_event_name`), instead of through a dedicated
+/// channel. Notifications carry the channel id of the subscription in a `channelId`
+/// field, for clients to route them.
+#[derive(Debug, Clone)]
+pub struct EventChannel<E> {
+    emitter: E,
+    event: String,
+    channel_id: String,
+}
+
+impl<E: EventEmitter> EventChannel<E> {
+    /// Create an event channel for the subscription of a channel id to a table
+    pub fn new(emitter: E, table: &str, channel_id: &str) -> Self {
+        EventChannel {
+            emitter,
+            event: table_event_name(table),
+            channel_id: channel_id.to_string(),
+        }
+    }
+}
+
+impl<E: EventEmitter> NotificationChannel for EventChannel<E> {
+    fn send_value(&self, mut value: serde_json::Value) -> Result<(), ChannelError> {
+        if let serde_json::Value::Object(object) = &mut value {
+            object.insert("channelId".to_string(), self.channel_id.clone().into());
+        }
+        self.emitter.emit_value(&self.event, value)
+    }
+}
+
+/// Per-table transformation applied to every serialized row sent to clients,
+/// for instance to add derived fields that are not stored columns.
+pub type Projection = fn(&mut serde_json::Value);
+
+/// Apply a projection to the rows of a serialized snapshot or notification,
+/// whether its `data` field holds a single row or a list of rows.
+/// The `previous` row of update notifications and the `created` rows of
+/// non-atomic `CreateMany` results are projected as well.
+pub fn project_data<F>(value: &mut serde_json::Value, mut projection: F)
+where
+    F: FnMut(&mut serde_json::Value),
+{
+    match value.get_mut("data") {
+        Some(serde_json::Value::Array(rows)) => rows.iter_mut().for_each(&mut projection),
+        Some(row @ serde_json::Value::Object(_)) => projection(row),
+        _ => {}
+    }
+
+    if let Some(serde_json::Value::Object(rows)) = value.get_mut("created") {
+        rows.values_mut().for_each(&mut projection);
+    }
+
+    if let Some(row @ serde_json::Value::Object(_)) = value.get_mut("previous") {
+        projection(row);
+    }
+}
+
+/// Keep only some columns of the rows of a serialized snapshot or notification
+/// (see `project_data` for the rows concerned), and of the field diff of an update
+pub fn select_columns(value: &mut serde_json::Value, columns: &[String]) {
+    project_data(value, |row| {
+        if let serde_json::Value::Object(row) = row {
+            row.retain(|column, _| columns.contains(column));
+        }
+    });
+
+    if let Some(serde_json::Value::Object(diff)) = value.get_mut("diff") {
+        diff.retain(|column, _| columns.contains(column));
+    }
+}
+
+/// Sort key of the rows of a serialized creation notification for an ordered `Many` query:
+/// `{ "column", "order", "value" }` for a `create`, with the `values` of the rows in their
+/// order for a `create_many`. Clients can insert the new rows at their position from it,
+/// even when the ordering column is not among the selected columns.
+pub fn sort_key(query: &QueryTree, value: &serde_json::Value) -> Option<serde_json::Value> {
+    if !matches!(query.return_type, ReturnType::Many) {
+        return None;
+    }
+    let (column, descending) = query.ordering()?;
+    let order = if descending { "desc" } else { "asc" };
+    let key_of = |row: &serde_json::Value| row.get(column).cloned().unwrap_or_default();
+
+    match (value.get("type")?.as_str()?, value.get("data")?) {
+        ("create", row) => Some(serde_json::json!({
+            "column": column,
+            "order": order,
+            "value": key_of(row),
+        })),
+        ("create_many", serde_json::Value::Array(rows)) => Some(serde_json::json!({
+            "column": column,
+            "order": order,
+            "values": rows.iter().map(key_of).collect::<Vec<_>>(),
+        })),
+        _ => None,
+    }
+}
+
+/// Primary keys of the rows of a serialized snapshot (`{ "type", "data" }`), as held
+/// by the client that fetched it, given the key columns of the table if not keyed by `id`
+pub fn snapshot_ids(
+    snapshot: &serde_json::Value,
+    key_columns: Option<&[String]>,
+) -> Vec<serde_json::Value> {
+    let id = |row: &serde_json::Value| {
+        row.as_object()
+            .map(|object| row_key_or_id(object, key_columns))
+            .filter(|key| !key.is_null())
+    };
+    match snapshot.get("data") {
+        Some(serde_json::Value::Array(rows)) => rows.iter().filter_map(id).collect(),
+        Some(row) => id(row).into_iter().collect(),
+        None => vec![],
+    }
+}
+
+/// Rows held by the client of a tail subscription, by primary key: the rows of its
+/// initial snapshot and the rows created since
+#[derive(Debug, Clone, Default)]
+struct HeldRows(Arc<Mutex<HashSet<String>>>);
+
+impl HeldRows {
+    fn new(ids: impl IntoIterator<Item = serde_json::Value>) -> Self {
+        HeldRows(Arc::new(Mutex::new(
+            ids.into_iter().map(|id| id.to_string()).collect(),
+        )))
+    }
+
+    /// Restrict the effect of an operation on a row, given its primary key, to the
+    /// creation of new rows and to the changes of held rows, keeping track of the held rows
+    fn track(&self, outcome: MatchOutcome, key: serde_json::Value) -> MatchOutcome {
+        if key.is_null() {
+            return outcome;
+        }
+        let key = key.to_string();
+        let mut held = self.0.lock().unwrap();
+
+        match outcome {
+            MatchOutcome::Insert => {
+                held.insert(key);
+                MatchOutcome::Insert
+            }
+            MatchOutcome::Update if held.contains(&key) => MatchOutcome::Update,
+            MatchOutcome::Leave if held.remove(&key) => MatchOutcome::Leave,
+            MatchOutcome::Refetch => MatchOutcome::Refetch,
+            // Older rows entering the query results are not forwarded
+            _ => MatchOutcome::NoMatch,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+/// Coalescing of the refetches of a subscription: the refetches triggered within a window
+/// are collapsed into a single one, sent once the window has elapsed by
+/// `TableSubscriptions::flush_refetches`. The refetch is always sent after the operations
+/// that triggered it, so that the client converges to the latest state.
+#[derive(Debug, Clone)]
+struct RefetchThrottle {
+    window: Duration,
+    /// Deadline of the pending refetch, if any
+    deadline: Arc<Mutex<Option<Instant>>>,
+}
+
+impl RefetchThrottle {
+    fn new(window: Duration) -> Self {
+        RefetchThrottle {
+            window,
+            deadline: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Schedule a refetch at the end of the current window, opening one if needed
+    fn defer(&self) {
+        self.deadline
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| Instant::now() + self.window);
+    }
+
+    /// Whether the pending refetch is due, clearing it if so
+    fn take_due(&self, now: Instant) -> bool {
+        let mut deadline = self.deadline.lock().unwrap();
+        if deadline.is_some_and(|deadline| deadline <= now) {
+            *deadline = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Configuration of the periodic sending of the deferred refetches of the throttled
+/// subscriptions (see `Subscription::with_refetch_window`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefetchFlushing {
+    /// Delay between two flushes, bounding the delay of the refetches beyond their window
+    pub interval: Duration,
+}
+
+impl RefetchFlushing {
+    /// Send the deferred refetches at the given interval
+    pub fn new(interval: Duration) -> Self {
+        RefetchFlushing { interval }
+    }
+
+    /// Wait until the next flush
+    pub async fn tick(&self) {
+        tokio::time::sleep(self.interval).await;
+    }
+}
+
+/// A real-time query subscription, linking a query to the channel
+/// that its notifications are sent to.
+#[derive(Debug, Clone)]
+pub struct Subscription<C> {
+    pub query: QueryTree,
+    pub channel: C,
+    /// Optional client-supplied request id, echoed in every notification
+    pub request_id: Option<String>,
+    /// Optional columns to watch: update notifications are only forwarded
+    /// if one of them changed
+    pub watch_columns: Option<Vec<String>>,
+    /// Whether update notifications carry the row before the update in a `previous` field
+    pub include_previous: bool,
+    /// Whether update notifications carry the changed fields with their old and new
+    /// values in a `diff` field
+    pub include_diff: bool,
+    /// Whether updated rows that do not match the query are signaled with a `Delete`
+    /// notification, or ignored
+    pub emit_leave_events: bool,
+    /// Optional identifier of the client connection. Identical queries subscribed by the
+    /// same client are coalesced into a single subscription.
+    pub client_id: Option<String>,
+    /// Optional columns sent to the channel. Queries are matched against full rows,
+    /// and only the rows of outgoing notifications are reduced to these columns.
+    pub columns: Option<Vec<String>>,
+    /// Optional opaque label supplied by the client (such as the name of the component
+    /// that subscribed), exposed by `TableSubscriptions::inspect` to diagnose leaks
+    pub label: Option<String>,
+    /// Whether `CreateMany` notifications are delivered as one `Create` notification per row
+    pub split_batches: bool,
+    /// Whether the channel receives a `subscribed` message once the subscription is registered
+    pub ack: bool,
+    /// Patterns of the query, compiled once when subscribing
+    patterns: PatternCache,
+    /// Primary key of the watched row, for subscriptions to a single row
+    row_id: Option<FinalType>,
+    /// Whether the query can never match given the enum columns of its table
+    unsatisfiable: bool,
+    /// Number of rows matching the query, for subscriptions streaming it instead of the rows
+    count: Option<Arc<AtomicU64>>,
+    /// Last activity of the client: the subscription, or the last `touch`
+    last_activity: Instant,
+    /// Rows held by the client, for tail subscriptions
+    tail: Option<HeldRows>,
+    /// Primary key columns of the table, for tables not keyed by `id`
+    key_columns: Option<Vec<String>>,
+    /// Initial snapshot of the subscription, cached when it is registered
+    snapshot: Option<serde_json::Value>,
+    /// Name of the subscription group and index of the query in the group, for the
+    /// queries subscribed together by `subscribe_group` (see `group_channel_id`)
+    group: Option<(String, usize)>,
+    /// Coalescing of the refetches of the subscription, if throttled
+    refetch_throttle: Option<RefetchThrottle>,
+}
+
+impl<C: NotificationChannel> Subscription<C> {
+    /// Create a new subscription with default options
+    pub fn new(query: QueryTree, channel: C) -> Self {
+        Subscription {
+            patterns: PatternCache::new(&query),
+            query,
+            channel,
+            request_id: None,
+            watch_columns: None,
+            include_previous: false,
+            include_diff: false,
+            emit_leave_events: true,
+            client_id: None,
+            columns: None,
+            label: None,
+            ack: false,
+            split_batches: false,
+            row_id: None,
+            unsatisfiable: false,
+            count: None,
+            last_activity: Instant::now(),
+            tail: None,
+            key_columns: None,
+            snapshot: None,
+            group: None,
+            refetch_throttle: None,
+        }
+    }
+
+    /// Create a subscription to a single row by primary key.
+    /// Its channel receives the creation, the updates and the deletion of this row only.
+    pub fn by_id(table: &str, id: FinalType, channel: C) -> Self {
+        Subscription {
+            row_id: Some(id.clone()),
+            ..Subscription::new(QueryTree::by_id(table, id), channel)
+        }
+    }
+
+    /// Primary key of the watched row, for subscriptions to a single row
+    pub fn row_id(&self) -> Option<&FinalType> {
+        self.row_id.as_ref()
+    }
+
+    /// Stream the number of rows matching the query instead of the rows, starting from
+    /// the given count. The channel is sent a `count` message whenever it changes.
+    pub fn with_count(mut self, count: u64) -> Self {
+        self.count = Some(Arc::new(AtomicU64::new(count)));
+        self
+    }
+
+    /// Only forward the creation of new rows and the changes of the rows held by the
+    /// client, given the primary keys of the rows of its initial snapshot (see
+    /// `snapshot_ids`). Suited to feeds fetching the latest rows and prepending new ones:
+    /// changes of older rows entering the query results are ignored.
+    pub fn with_tail(mut self, ids: impl IntoIterator<Item = serde_json::Value>) -> Self {
+        self.tail = Some(HeldRows::new(ids));
+        self
+    }
+
+    /// Set the initial snapshot of the subscription, cached once it is registered so that
+    /// `TableSubscriptions::refetch_with_soft_timeout` can serve it before any refetch
+    /// completes. It must be projected and reduced to the columns of the subscription.
+    pub fn with_snapshot(mut self, snapshot: serde_json::Value) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Collapse the refetches triggered within `window` into a single one, sent once the
+    /// window has elapsed, for queries that cannot be evaluated locally on frequently
+    /// written tables. Deferred refetches are sent by `TableSubscriptions::flush_refetches`.
+    pub fn with_refetch_window(mut self, window: Option<Duration>) -> Self {
+        self.refetch_throttle = window.map(RefetchThrottle::new);
+        self
+    }
+
+    /// Window within which refetches are collapsed, if throttled
+    pub fn refetch_window(&self) -> Option<Duration> {
+        self.refetch_throttle
+            .as_ref()
+            .map(|throttle| throttle.window)
+    }
+
+    /// Number of rows held by the client, for tail subscriptions
+    pub fn held_rows(&self) -> Option<usize> {
+        self.tail.as_ref().map(HeldRows::len)
+    }
+
+    /// Current number of matching rows, for subscriptions streaming it
+    pub fn count(&self) -> Option<u64> {
+        self.count
+            .as_ref()
+            .map(|count| count.load(Ordering::SeqCst))
+    }
+
+    /// Change of the number of rows matching the query after an operation, from the
+    /// `Insert` and `Leave` outcomes of its rows. Returns `None` when it cannot be known
+    /// locally: for updates whose rows before the update are unknown.
+    fn count_delta<T: Serialize>(
+        &self,
+        operation: &OperationNotification<T>,
+        data: &serde_json::Value,
+    ) -> Option<i64> {
+        let matches = |object: &JsonObject| {
+            !self.unsatisfiable && self.query.check_with(object, &self.patterns)
+        };
+
+        match operation {
+            OperationNotification::Create { .. } | OperationNotification::Delete { .. } => {
+                let object = object_from_value(data.clone()).unwrap();
+                match self.outcome(operation, &object) {
+                    MatchOutcome::Insert => Some(1),
+                    MatchOutcome::Leave => Some(-1),
+                    _ => Some(0),
+                }
+            }
+            // Rows updated in or out of the query results
+            OperationNotification::Update {
+                previous: Some(previous),
+                ..
+            } => {
+                let object = object_from_value(data.clone()).unwrap();
+                let previous = object_from_value(serde_json::to_value(previous).unwrap()).unwrap();
+                let is_in = self.outcome(operation, &object) == MatchOutcome::Update;
+                Some(is_in as i64 - matches(&previous) as i64)
+            }
+            OperationNotification::CreateMany { .. } | OperationNotification::DeleteMany { .. } => {
+                let objects = object_array_from_value(data.clone()).unwrap();
+                let matching = objects.iter().filter(|object| matches(object)).count() as i64;
+                match operation {
+                    OperationNotification::CreateMany { .. } => Some(matching),
+                    _ => Some(-matching),
+                }
+            }
+            OperationNotification::Update { previous: None, .. }
+            | OperationNotification::UpdateMany { .. } => None,
+        }
+    }
+
+    /// Effect of a single-row operation on the subscription, given its serialized row
+    fn outcome<T>(
+        &self,
+        notification: &OperationNotification<T>,
+        object: &JsonObject,
+    ) -> MatchOutcome {
+        // Short-circuit the queries that compare enum columns to impossible values
+        if self.unsatisfiable {
+            return MatchOutcome::NoMatch;
+        }
+
+        let Some(row_id) = &self.row_id else {
+            let outcome = row_outcome(&self.query, &self.patterns, notification, object);
+            return self.restrict(outcome, object);
+        };
+
+        let is_row = FinalType::try_from(row_key_or_id(object, self.key_columns.as_deref()))
+            .is_ok_and(|id| id.equals(row_id));
+
+        // The watched row cannot stop matching, and other rows never concern the subscription
+        match (notification, is_row) {
+            (OperationNotification::Create { .. }, true) => MatchOutcome::Insert,
+            (OperationNotification::Update { .. }, true) => MatchOutcome::Update,
+            (OperationNotification::Delete { .. }, true) => MatchOutcome::Leave,
+            _ => MatchOutcome::NoMatch,
+        }
+    }
+
+    /// Restrict the effect of an operation on a row to the rows concerning the client:
+    /// new and held rows for tail subscriptions, any row otherwise
+    fn restrict(&self, outcome: MatchOutcome, object: &JsonObject) -> MatchOutcome {
+        match &self.tail {
+            Some(tail) => tail.track(outcome, row_key_or_id(object, self.key_columns.as_deref())),
+            None => outcome,
+        }
+    }
+
+    /// Make the subscription the query at `index` of a subscription group. Its
+    /// notifications carry the `group` name and the `queryIndex`, and the whole group
+    /// is torn down by `TableSubscriptions::unsubscribe_group`.
+    pub fn with_group(mut self, group: &str, index: usize) -> Self {
+        self.group = Some((group.to_string(), index));
+        self
+    }
+
+    /// Name of the subscription group and index of the query in the group, if any
+    pub fn group(&self) -> Option<(&str, usize)> {
+        self.group
+            .as_ref()
+            .map(|(group, index)| (group.as_str(), *index))
+    }
+
+    /// Set the request id echoed in the subscription notifications
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    /// Only forward update notifications that change one of the given columns
+    pub fn with_watch_columns(mut self, watch_columns: Option<Vec<String>>) -> Self {
+        self.watch_columns = watch_columns;
+        self
+    }
+
+    /// Include the row before the update in update notifications, when known
+    pub fn with_previous(mut self, include_previous: bool) -> Self {
+        self.include_previous = include_previous;
+        self
+    }
+
+    /// Include the changed fields of the row, with their old and new values,
+    /// in update notifications, when the row before the update is known
+    pub fn with_diff(mut self, include_diff: bool) -> Self {
+        self.include_diff = include_diff;
+        self
+    }
+
+    /// Signal updated rows that do not match the query with a `Delete` notification
+    /// (the default), or ignore them for clients that never had these rows
+    pub fn with_leave_events(mut self, emit_leave_events: bool) -> Self {
+        self.emit_leave_events = emit_leave_events;
+        self
+    }
+
+    /// Only send the given columns of the rows to the channel
+    pub fn with_columns(mut self, columns: Option<Vec<String>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Reduce the rows of a serialized snapshot or notification to the columns
+    /// of the subscription, if any
+    pub fn select_columns(&self, value: &mut serde_json::Value) {
+        if let Some(columns) = &self.columns {
+            select_columns(value, columns);
+        }
+    }
+
+    /// Attach an opaque label to the subscription, for inspection
+    pub fn with_label(mut self, label: Option<String>) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Deliver `CreateMany` notifications as one `Create` notification per matching row,
+    /// for client stores that only handle single-row creations
+    pub fn with_split_batches(mut self, split_batches: bool) -> Self {
+        self.split_batches = split_batches;
+        self
+    }
+
+    /// Acknowledge the registration of the subscription with a `subscribed` message,
+    /// so that clients can tell an empty subscription from a pending one
+    pub fn with_ack(mut self, ack: bool) -> Self {
+        self.ack = ack;
+        self
+    }
+
+    /// Set the client identifier used to coalesce duplicate subscriptions
+    pub fn with_client_id(mut self, client_id: Option<String>) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    /// Whether an update from `previous` to `current` changes a watched column.
+    /// Always true for subscriptions without watched columns.
+    pub fn is_watched_change(&self, previous: &JsonObject, current: &JsonObject) -> bool {
+        match &self.watch_columns {
+            Some(columns) => columns
+                .iter()
+                .any(|column| previous.get(column) != current.get(column)),
+            None => true,
+        }
+    }
+
+    /// Send a serialized notification to the subscription channel
+    pub fn send(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        self.channel.send_value(self.personalize(value))
+    }
+
+    /// Serialize a notification to the JSON text sent to the subscription channel,
+    /// identical to the one `send` would deliver
+    pub fn serialize(&self, value: serde_json::Value) -> Arc<str> {
+        serde_json::to_string(&self.personalize(value))
+            .unwrap()
+            .into()
+    }
+
+    /// Attach the sort key of created rows for ordered queries, reduce a notification
+    /// to the selected columns and tag it with the group and the request id
+    fn personalize(&self, mut value: serde_json::Value) -> serde_json::Value {
+        if let Some(sort_key) = sort_key(&self.query, &value) {
+            value["sortKey"] = sort_key;
+        }
+        self.select_columns(&mut value);
+        if let (Some((group, index)), serde_json::Value::Object(object)) = (&self.group, &mut value)
+        {
+            object.insert("group".to_string(), group.as_str().into());
+            object.insert("queryIndex".to_string(), (*index).into());
+        }
+        tag_request_id(value, self.request_id.as_deref())
+    }
+}
+
+/// Process a database operation notification and notify the relevant
+/// channels about the change that occured.
+///
+/// Returns a list of channel uuid identifiers that errored out and should be pruned.
+pub fn process_channel_event<'a, T, C>(
+    channels: &'a HashMap<String, Subscription<C>>,
+    operation: &OperationNotification<T>,
+) -> Vec<&'a str>
+where
+    T: Clone + Serialize,
+    C: NotificationChannel,
+{
+    process_projected_channel_event(channels, operation, None)
+}
+
+/// Same as `process_channel_event`, applying an optional projection to the rows
+/// of every notification sent. Rows are matched against the queries before projection.
+pub fn process_projected_channel_event<'a, T, C>(
+    channels: &'a HashMap<String, Subscription<C>>,
+    operation: &OperationNotification<T>,
+    projection: Option<Projection>,
+) -> Vec<&'a str>
+where
+    T: Clone + Serialize,
+    C: NotificationChannel,
+{
+    process_sequenced_channel_event(channels, operation, projection, None, None, false).failing
+}
+
+/// Index of a message shared between the subscriptions of a fan-out
+#[derive(Debug, Clone, Copy)]
+struct MessageId(usize);
+
+/// Serialized shared messages are reused between the subscriptions with the same
+/// request id, selected columns, ordering and group (name and query index),
+/// which personalize them
+type PayloadKey<'a> = (
+    usize,
+    Option<&'a str>,
+    Option<&'a [String]>,
+    Option<(&'a str, bool)>,
+    Option<(&'a str, usize)>,
+);
+
+/// Outcome of sending a notification to the subscriptions of a table
+#[derive(Default)]
+struct FanOut<'a> {
+    /// Channels that errored out, scheduled for pruning
+    failing: Vec<&'a str>,
+    /// Number of channels that received a message
+    notified: usize,
+    /// Whether shared messages are serialized once per distinct payload
+    shared: bool,
+    /// Messages shared between subscriptions
+    messages: Vec<serde_json::Value>,
+    /// Serialized shared messages
+    payloads: HashMap<PayloadKey<'a>, Arc<str>>,
+}
+
+impl<'a> FanOut<'a> {
+    /// Create a fan-out, serializing shared messages once per distinct payload if `shared`
+    fn new(shared: bool) -> Self {
+        FanOut {
+            shared,
+            ..FanOut::default()
+        }
+    }
+
+    /// Register a message sent as-is to several subscriptions
+    fn share(&mut self, value: serde_json::Value) -> MessageId {
+        self.messages.push(value);
+        MessageId(self.messages.len() - 1)
+    }
+
+    /// Count a sent message, or schedule the channel for pruning
+    fn record(&mut self, key: &'a str, result: Result<(), ChannelError>) {
+        match result {
+            Ok(()) => self.notified += 1,
+            Err(_) => self.failing.push(key),
+        }
+    }
+
+    /// Send a message to a subscription, counting it or scheduling its channel for pruning
+    fn send<C: NotificationChannel>(
+        &mut self,
+        key: &'a str,
+        subscription: &Subscription<C>,
+        value: serde_json::Value,
+    ) {
+        self.record(key, subscription.send(value));
+    }
+
+    /// Send a shared message to a subscription. With shared payloads, the message is
+    /// serialized once for all the subscriptions with the same request id, columns,
+    /// ordering and group.
+    fn send_shared<C: NotificationChannel>(
+        &mut self,
+        key: &'a str,
+        subscription: &'a Subscription<C>,
+        message: MessageId,
+    ) {
+        let value = &self.messages[message.0];
+        let result = if self.shared {
+            let payload_key = (
+                message.0,
+                subscription.request_id.as_deref(),
+                subscription.columns.as_deref(),
+                subscription.query.ordering(),
+                subscription
+                    .group
+                    .as_ref()
+                    .map(|(group, index)| (group.as_str(), *index)),
+            );
+            let payload = self
+                .payloads
+                .entry(payload_key)
+                .or_insert_with(|| subscription.serialize(value.clone()))
+                .clone();
+            subscription.channel.send_serialized(payload)
+        } else {
+            subscription.send(value.clone())
+        };
+        self.record(key, result);
+    }
+
+    /// Send a shared refetch message to a subscription, or defer it to the end of the
+    /// refetch window of throttled subscriptions
+    fn send_refetch<C: NotificationChannel>(
+        &mut self,
+        key: &'a str,
+        subscription: &'a Subscription<C>,
+        refetch: MessageId,
+    ) {
+        match &subscription.refetch_throttle {
+            Some(throttle) => throttle.defer(),
+            None => self.send_shared(key, subscription, refetch),
+        }
+    }
+
+    /// Send several messages to a subscription, counting it once.
+    /// Stops at the first error, scheduling its channel for pruning.
+    fn send_all<C: NotificationChannel>(
+        &mut self,
+        key: &'a str,
+        subscription: &Subscription<C>,
+        values: impl IntoIterator<Item = serde_json::Value>,
+    ) {
+        let result = values
+            .into_iter()
+            .try_for_each(|value| subscription.send(value));
+        self.record(key, result);
+    }
+}
+
+/// Key of a row, identifying it in the synthetic `Delete` notifications sent when it
+/// leaves the results of a query: the value of the key column for single-column keys,
+/// or an object of the key columns values for composite keys.
+fn row_key(object: &JsonObject, key_columns: &[String]) -> serde_json::Value {
+    match key_columns {
+        [column] => object
+            .get(column)
+            .cloned()
+            .unwrap_or(serde_json::Value::Null),
+        columns => serde_json::Value::Object(
+            columns
+                .iter()
+                .map(|column| {
+                    let value = object.get(column).cloned();
+                    (column.clone(), value.unwrap_or(serde_json::Value::Null))
+                })
+                .collect(),
+        ),
+    }
+}
+
+/// Key of a row given the key columns of its table, or its `id` for tables keyed by `id`
+fn row_key_or_id(object: &JsonObject, key_columns: Option<&[String]>) -> serde_json::Value {
+    match key_columns {
+        Some(key_columns) => row_key(object, key_columns),
+        None => object.get("id").cloned().unwrap_or(serde_json::Value::Null),
+    }
+}
+
+/// Same as `process_projected_channel_event`, adding the sequence number of the
+/// notification in the notification log, if any, to every notification sent.
+/// The synthetic `Delete` notifications identify rows by their key columns if given,
+/// and by the `id` of the operation otherwise.
+/// With `shared` payloads, the notifications sent to several channels are serialized once.
+fn process_sequenced_channel_event<'a, T, C>(
+    channels: &'a HashMap<String, Subscription<C>>,
+    operation: &OperationNotification<T>,
+    projection: Option<Projection>,
+    sequence: Option<u64>,
+    key_columns: Option<&[String]>,
+    shared: bool,
+) -> FanOut<'a>
+where
+    T: Clone + Serialize,
+    C: NotificationChannel,
+{
+    let project = |mut value: serde_json::Value| {
+        if let Some(projection) = projection {
+            project_data(&mut value, projection);
+        }
+        if let (Some(sequence), serde_json::Value::Object(object)) = (sequence, &mut value) {
+            object.insert("sequence".to_string(), sequence.into());
+        }
+        value
+    };
+
+    let serialized_operation = serde_json::to_value(operation).unwrap();
+    let data = serialized_operation.get("data").unwrap();
+
+    let mut fan_out = FanOut::new(shared);
+    let refetch = fan_out.share(refetch_message());
+
+    // Queries that cannot be evaluated locally are refetched on every operation
+    let (channels, refetched): (Vec<_>, Vec<_>) = channels
+        .iter()
+        .partition(|(_, subscription)| subscription.query.is_locally_evaluable());
+
+    for (key, subscription) in refetched {
+        fan_out.send_refetch(key, subscription, refetch);
+    }
+
+    // Subscriptions streaming a count are sent its new value when it changes
+    let (counted, channels): (Vec<_>, Vec<_>) = channels
+        .into_iter()
+        .partition(|(_, subscription)| subscription.count.is_some());
+
+    for (key, subscription) in counted {
+        let counter = subscription.count.as_ref().unwrap();
+        match subscription.count_delta(operation, data) {
+            Some(0) => {}
+            Some(delta) => {
+                let count = counter
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                        Some(count.saturating_add_signed(delta))
+                    })
+                    .unwrap()
+                    .saturating_add_signed(delta);
+                fan_out.send(key, subscription, project(count_message(count)));
+            }
+            None => fan_out.send_refetch(key, subscription, refetch),
+        }
+    }
+
+    match operation {
+        // For single-row operations, we simply push the operation to the channel
+        // if the query matches
+        OperationNotification::Create { .. } | OperationNotification::Delete { .. } => {
+            let object = object_from_value(data.clone()).unwrap();
+            let mut message = None;
+
+            for (key, subscription) in channels.iter().copied() {
+                if subscription.outcome(operation, &object) != MatchOutcome::NoMatch {
+                    // Send an item to the channel, or schedule the channel for deletion
+                    let message = *message.get_or_insert_with(|| {
+                        fan_out.share(project(serialized_operation.clone()))
+                    });
+                    fan_out.send_shared(key, subscription, message);
+                }
+            }
+        }
+        OperationNotification::Update {
+            table,
+            data: notif_data,
+            id,
+            previous,
+        } => {
+            let object = object_from_value(data.clone()).unwrap();
+            let previous = previous.as_ref().map(|previous| {
+                object_from_value(serde_json::to_value(previous).unwrap()).unwrap()
+            });
+
+            // Update notification carrying the previous row, for the subscriptions asking for it
+            let with_previous = previous.as_ref().map(|previous| {
+                let mut operation = serialized_operation.clone();
+                operation["previous"] = serde_json::Value::Object(previous.clone());
+                operation
+            });
+
+            // Changed fields of the row, for the subscriptions asking for them
+            let diff = previous
+                .as_ref()
+                .map(|previous| serde_json::Value::Object(field_diff(previous, &object)));
+
+            // Shared messages, by whether they carry the previous row and the diff
+            let mut updates: HashMap<(bool, bool), MessageId> = HashMap::new();
+            let mut leave = None;
+
+            for (key, subscription) in channels.iter().copied() {
+                // Skip the subscriptions whose watched columns did not change
+                if let Some(previous) = &previous {
+                    if !subscription.is_watched_change(previous, &object) {
+                        continue;
+                    }
+                }
+
+                match subscription.outcome(operation, &object) {
+                    MatchOutcome::Update => {
+                        let variant = (
+                            subscription.include_previous && with_previous.is_some(),
+                            subscription.include_diff && diff.is_some(),
+                        );
+                        let message = *updates.entry(variant).or_insert_with(|| {
+                            let mut operation = match &with_previous {
+                                Some(operation) if variant.0 => operation.clone(),
+                                _ => serialized_operation.clone(),
+                            };
+                            if let (Some(diff), true) = (&diff, variant.1) {
+                                operation["diff"] = diff.clone();
+                            }
+                            fan_out.share(project(operation))
+                        });
+
+                        fan_out.send_shared(key, subscription, message);
+                    }
+                    MatchOutcome::Leave if subscription.emit_leave_events => {
+                        // The row leaves the query results: we send a false `Delete` operation
+                        let message = *leave.get_or_insert_with(|| {
+                            let mut delete_operation =
+                                serde_json::to_value(OperationNotification::Delete {
+                                    table: table.clone(),
+                                    data: notif_data.clone(),
+                                    id: id.clone(),
+                                })
+                                .unwrap();
+                            if let Some(key_columns) = key_columns {
+                                delete_operation["id"] = row_key(&object, key_columns);
+                            }
+                            fan_out.share(project(delete_operation))
+                        });
+
+                        fan_out.send_shared(key, subscription, message);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // For multiple-row operations, we check each row individually for matches against
+        // the query. We build per-query personalized vectors of matching objects and send
+        // them to the corresponding channels
+        OperationNotification::CreateMany {
+            table,
+            data: unserialized_data,
+            client_ref,
+        } => {
+            let objects = object_array_from_value(data.clone()).unwrap();
+
+            for (key, subscription) in channels.iter().copied() {
+                let mut matching_objects: Vec<T> = Vec::new();
+                for (index, object) in objects.iter().enumerate() {
+                    if subscription
+                        .query
+                        .check_with(object, &subscription.patterns)
+                        && subscription.restrict(MatchOutcome::Insert, object)
+                            == MatchOutcome::Insert
+                    {
+                        matching_objects.push(unserialized_data[index].clone());
+                    }
+                }
+
+                if matching_objects.is_empty() {
+                    continue;
+                }
+
+                if subscription.split_batches {
+                    let creations = matching_objects.into_iter().map(|data| {
+                        project(
+                            serde_json::to_value(OperationNotification::Create {
+                                table: table.clone(),
+                                data,
+                                client_ref: client_ref.clone(),
+                            })
+                            .unwrap(),
+                        )
+                    });
+                    fan_out.send_all(key, subscription, creations);
+                } else {
+                    let serialized_operation =
+                        serde_json::to_value(OperationNotification::CreateMany {
+                            table: table.clone(),
+                            data: matching_objects,
+                            client_ref: client_ref.clone(),
+                        })
+                        .unwrap();
+                    fan_out.send(key, subscription, project(serialized_operation));
+                }
+            }
+        }
+        // Bulk updates and deletions are split the same way: each subscription only learns
+        // about the rows matching its own query. Updated rows that do not match anymore
+        // leave the query results, and are sent as a `DeleteMany` operation.
+        OperationNotification::UpdateMany {
+            table,
+            data: unserialized_data,
+        }
+        | OperationNotification::DeleteMany {
+            table,
+            data: unserialized_data,
+        } => {
+            let is_update = matches!(operation, OperationNotification::UpdateMany { .. });
+            let objects = object_array_from_value(data.clone()).unwrap();
+
+            // Single-row operation identifying the row by its key columns, or by its id
+            let single = |kind: &str, object: &JsonObject, row: &T| {
+                let id = row_key_or_id(object, key_columns);
+                serde_json::json!({ "type": kind, "table": table, "id": id, "data": row })
+            };
+
+            for (key, subscription) in channels.iter().copied() {
+                let mut updated: Vec<usize> = Vec::new();
+                let mut removed: Vec<usize> = Vec::new();
+                for (index, object) in objects.iter().enumerate() {
+                    let outcome = match (
+                        is_update,
+                        subscription
+                            .query
+                            .check_with(object, &subscription.patterns),
+                    ) {
+                        (true, true) => MatchOutcome::Update,
+                        (true, false) if subscription.emit_leave_events => MatchOutcome::Leave,
+                        (false, true) => MatchOutcome::Leave,
+                        _ => MatchOutcome::NoMatch,
+                    };
+                    match subscription.restrict(outcome, object) {
+                        MatchOutcome::Update => updated.push(index),
+                        MatchOutcome::Leave => removed.push(index),
+                        _ => {}
+                    }
+                }
+
+                let mut messages: Vec<serde_json::Value> = Vec::new();
+                for (kind, indices) in [("update", updated), ("delete", removed)] {
+                    if indices.is_empty() {
+                        continue;
+                    }
+
+                    if subscription.split_batches {
+                        messages.extend(indices.into_iter().map(|index| {
+                            project(single(kind, &objects[index], &unserialized_data[index]))
+                        }));
+                    } else {
+                        let rows: Vec<T> = indices
+                            .into_iter()
+                            .map(|index| unserialized_data[index].clone())
+                            .collect();
+                        let serialized_operation = serde_json::to_value(match kind {
+                            "update" => OperationNotification::UpdateMany {
+                                table: table.clone(),
+                                data: rows,
+                            },
+                            _ => OperationNotification::DeleteMany {
+                                table: table.clone(),
+                                data: rows,
+                            },
+                        })
+                        .unwrap();
+                        messages.push(project(serialized_operation));
+                    }
+                }
+
+                if !messages.is_empty() {
+                    fan_out.send_all(key, subscription, messages);
+                }
+            }
+        }
+    };
+
+    fan_out
+}
+
+/// Effect of an operation on the results of a query, following the dispatcher rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOutcome {
+    /// Created rows enter the query results
+    Insert,
+    /// The updated row is still part of the query results
+    Update,
+    /// The row leaves the query results, because it was deleted or no longer
+    /// matches after an update. Subscribers are sent a `Delete` notification.
+    Leave,
+    /// The operation does not affect the query results
+    NoMatch,
+    /// The query cannot be evaluated locally, and subscribers must refetch it
+    Refetch,
+}
+
+/// Evaluate the effect of an operation on a query, without any subscription or channel.
+/// A `CreateMany` is an `Insert` as soon as one of its rows matches the query, and
+/// bulk updates and deletions are evaluated the same way.
+pub fn operation_matches<T>(
+    query: &QueryTree,
+    notification: &OperationNotification<T>,
+) -> MatchOutcome
+where
+    T: Serialize,
+{
+    if !query.is_locally_evaluable() {
+        return MatchOutcome::Refetch;
+    }
+
+    let data = serde_json::to_value(notification).unwrap()["data"].take();
+
+    match notification {
+        OperationNotification::CreateMany { .. } => {
+            let objects = object_array_from_value(data).unwrap();
+            if objects.iter().any(|object| query.check(object)) {
+                MatchOutcome::Insert
+            } else {
+                MatchOutcome::NoMatch
+            }
+        }
+        OperationNotification::UpdateMany { .. } => {
+            let objects = object_array_from_value(data).unwrap();
+            if objects.iter().any(|object| query.check(object)) {
+                MatchOutcome::Update
+            } else if objects.is_empty() {
+                MatchOutcome::NoMatch
+            } else {
+                MatchOutcome::Leave
+            }
+        }
+        OperationNotification::DeleteMany { .. } => {
+            let objects = object_array_from_value(data).unwrap();
+            if objects.iter().any(|object| query.check(object)) {
+                MatchOutcome::Leave
+            } else {
+                MatchOutcome::NoMatch
+            }
+        }
+        _ => row_outcome(
+            query,
+            &PatternCache::default(),
+            notification,
+            &object_from_value(data).unwrap(),
+        ),
+    }
+}
+
+/// Effect of a single-row operation on a query, given its serialized row
+/// and the compiled patterns of the query
+fn row_outcome<T>(
+    query: &QueryTree,
+    patterns: &PatternCache,
+    notification: &OperationNotification<T>,
+    object: &JsonObject,
+) -> MatchOutcome {
+    match (notification, query.check_with(object, patterns)) {
+        (OperationNotification::Create { .. }, true) => MatchOutcome::Insert,
+        (OperationNotification::Update { .. }, true) => MatchOutcome::Update,
+        // Trick: because the object has been updated, it is possible that the query
+        // once matched it, but does not anymore. The row is signaled as leaving, so that
+        // clients that ever had this object in store delete it.
+        (OperationNotification::Update { .. }, false) => MatchOutcome::Leave,
+        (OperationNotification::Delete { .. }, true) => MatchOutcome::Leave,
+        _ => MatchOutcome::NoMatch,
+    }
+}
+
+/// Message sent to the subscriptions that must refetch their query after an operation
+pub fn refetch_message() -> serde_json::Value {
+    serde_json::json!({ "type": "refetch" })
+}
+
+/// Message probing the channels of the subscriptions, for the pruning of stale channels
+pub fn ping_message() -> serde_json::Value {
+    serde_json::json!({ "type": "ping" })
+}
+
+/// Message carrying the new number of rows matching the query of a count subscription
+pub fn count_message(count: u64) -> serde_json::Value {
+    serde_json::json!({ "type": "count", "count": count })
+}
+
+/// Message acknowledging the registration of the subscription of a channel
+pub fn subscribed_message(channel_id: &str) -> serde_json::Value {
+    serde_json::json!({ "type": "subscribed", "channelId": channel_id })
+}
+
+/// Terminal message sent to every channel when the subscriptions are shut down
+pub fn closed_message() -> serde_json::Value {
+    serde_json::json!({ "type": "closed" })
+}
+
+/// Description of an active subscription, returned by `TableSubscriptions::inspect`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubscriptionInfo {
+    #[serde(rename = "channelId")]
+    pub channel_id: String,
+    pub table: String,
+    pub label: Option<String>,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    /// Channel ids of the coalesced duplicates of the subscription
+    pub aliases: Vec<String>,
+    /// Current number of matching rows, for subscriptions streaming it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+}
+
+/// Channel id of the query at `index` of a subscription group
+pub fn group_channel_id(group: &str, index: usize) -> String {
+    format!("{group}#{index}")
+}
+
+/// Subscriptions of a table, with the aliases of coalesced duplicate subscriptions
+struct Registry<C> {
+    subscriptions: HashMap<String, Subscription<C>>,
+    /// Channel ids of coalesced duplicates, mapped to the channel id owning the subscription
+    aliases: HashMap<String, String>,
+    /// Number of channel ids (owner included) referencing each subscription
+    references: HashMap<String, usize>,
+}
+
+impl<C> Registry<C> {
+    /// Remove a subscription, along with its aliases and reference count
+    fn remove(&mut self, channel_id: &str) -> Option<Subscription<C>> {
+        self.aliases.retain(|_, owner| owner != channel_id);
+        self.references.remove(channel_id);
+        self.subscriptions.remove(channel_id)
+    }
+
+    /// Drop the alias of a channel id, if any, releasing its reference to the subscription
+    /// it was coalesced into, which is removed with its last reference
+    fn release_alias(&mut self, channel_id: &str) {
+        let Some(owner) = self.aliases.remove(channel_id) else {
+            return;
+        };
+        if let Some(references) = self.references.get_mut(&owner) {
+            *references -= 1;
+            if *references == 0 {
+                self.remove(&owner);
+            }
+        }
+    }
+
+    /// Find the subscription of the same client to the same query, if any
+    fn find_duplicate(&self, subscription: &Subscription<C>) -> Option<String> {
+        let client_id = subscription.client_id.as_ref()?;
+        let window = |subscription: &Subscription<C>| {
+            subscription
+                .refetch_throttle
+                .as_ref()
+                .map(|throttle| throttle.window)
+        };
+
+        self.subscriptions
+            .iter()
+            .find(|(_, existing)| {
+                existing.client_id.as_ref() == Some(client_id)
+                    && existing.query == subscription.query
+                    && existing.columns == subscription.columns
+                    && existing.count.is_some() == subscription.count.is_some()
+                    && existing.tail.is_some() == subscription.tail.is_some()
+                    && existing.group == subscription.group
+                    && window(existing) == window(subscription)
+            })
+            .map(|(channel_id, _)| channel_id.clone())
+    }
+}
+
+/// The subscriptions of a single table, locked in a thread-safe and async-safe way.
+pub struct TableSubscriptions<C> {
+    registry: RwLock<Registry<C>>,
+    closed: AtomicBool,
+    projection: Option<Projection>,
+    log: Option<Arc<NotificationLog>>,
+    default_order: Option<OrderBy>,
+    key_columns: Option<Vec<String>>,
+    enum_columns: Option<EnumColumns>,
+    operator_policy: Option<OperatorPolicy>,
+    read_only: bool,
+    shared_payloads: bool,
+    /// Last snapshots fetched with a soft timeout, by channel id
+    snapshots: Arc<Mutex<HashMap<String, serde_json::Value>>>,
+}
+
+impl<C: NotificationChannel> Default for TableSubscriptions<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: NotificationChannel> TableSubscriptions<C> {
+    /// Create an empty subscriptions map
+    pub fn new() -> Self {
+        TableSubscriptions {
+            registry: RwLock::new(Registry {
+                subscriptions: HashMap::new(),
+                aliases: HashMap::new(),
+                references: HashMap::new(),
+            }),
+            closed: AtomicBool::new(false),
+            projection: None,
+            log: None,
+            default_order: None,
+            key_columns: None,
+            enum_columns: None,
+            operator_policy: None,
+            read_only: false,
+            shared_payloads: false,
+            snapshots: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Register the projection applied to the rows of the table
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = Some(projection);
+    }
+
+    /// Record the notifications of the table in a notification log, which can be shared
+    /// between tables. Notifications sent to channels then carry their `sequence` number.
+    pub fn set_log(&mut self, log: Arc<NotificationLog>) {
+        self.log = Some(log);
+    }
+
+    /// Register the ordering applied to the `Many` queries of the table
+    /// without pagination options nor ordering of their own
+    pub fn set_default_order(&mut self, order_by: OrderBy) {
+        self.default_order = Some(order_by);
+    }
+
+    /// Register the primary key columns of the table, for tables not keyed by `id`.
+    /// Rows leaving the results of a query are then identified by their key in the
+    /// synthetic `Delete` notifications: the key value for a single column,
+    /// or an object of the key columns values for composite keys.
+    pub fn set_key_columns(&mut self, columns: Vec<String>) {
+        self.key_columns = Some(columns);
+    }
+
+    /// Primary keys of the rows of a serialized snapshot of the table (see `snapshot_ids`)
+    pub fn snapshot_ids(&self, snapshot: &serde_json::Value) -> Vec<serde_json::Value> {
+        snapshot_ids(snapshot, self.key_columns.as_deref())
+    }
+
+    /// Register the allowed values of the text enum columns of the table.
+    /// Operations writing other values are rejected by `check_operation`, and the
+    /// subscriptions comparing these columns to other values are never evaluated.
+    pub fn set_enum_columns(&mut self, enum_columns: EnumColumns) {
+        self.enum_columns = Some(enum_columns);
+    }
+
+    /// Register the operators that clients may use on each column of the table.
+    /// Queries using other operators are rejected by `check_query`.
+    pub fn set_operator_policy(&mut self, policy: OperatorPolicy) {
+        self.operator_policy = Some(policy);
+    }
+
+    /// Register the table as read-only, such as a SQL view: its queries can be fetched
+    /// and subscribed to, but every operation on it is rejected by `check_operation`
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Serialize the notifications sent to several channels once per distinct payload,
+    /// and deliver them through `NotificationChannel::send_serialized` instead of cloning
+    /// a `serde_json::Value` per channel. Channels receive the same JSON either way.
+    pub fn set_shared_payloads(&mut self, shared_payloads: bool) {
+        self.shared_payloads = shared_payloads;
+    }
+
+    /// Check an operation before executing it: the table must not be read-only,
+    /// and the written values must fit the registered enum columns, if any
+    pub fn check_operation(&self, operation: &GranularOperation) -> Result<(), OperationError> {
+        if self.read_only {
+            return Err(OperationError::ReadOnly(operation.get_table().to_string()));
+        }
+        if let Some(enum_columns) = &self.enum_columns {
+            enum_columns.check_operation(operation)?;
+        }
+        Ok(())
+    }
+
+    /// Check a query against the registered operator policy, if any, before fetching it.
+    /// The conditions of its correlated subqueries are left to the policies of their tables.
+    pub fn check_query(&self, query: &QueryTree) -> Result<(), PolicyError> {
+        match &self.operator_policy {
+            Some(policy) => policy.check_query(query),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply the registered default ordering, if any, to a query before fetching it
+    pub fn order(&self, query: QueryTree) -> QueryTree {
+        match &self.default_order {
+            Some(order_by) => query.with_default_order(order_by),
+            None => query,
+        }
+    }
+
+    /// Apply the registered projection, if any, to a serialized snapshot or notification.
+    /// Snapshots must go through this function to be consistent with notifications.
+    pub fn project(&self, mut value: serde_json::Value) -> serde_json::Value {
+        if let Some(projection) = self.projection {
+            project_data(&mut value, projection);
+        }
+        value
+    }
+
+    /// Add a subscription under a channel id.
+    /// Fails if the subscriptions have been shut down.
+    /// Subscriptions asking for an acknowledgment are sent a `subscribed` message once registered.
+    ///
+    /// If the same client already subscribed to an identical query, the channel id is
+    /// aliased to the existing subscription instead, so that notifications are only
+    /// delivered once (through the channel of the existing subscription).
+    pub async fn subscribe(
+        &self,
+        channel_id: &str,
+        mut subscription: Subscription<C>,
+    ) -> Result<(), ChannelError> {
+        subscription.unsatisfiable = match (&self.enum_columns, &subscription.query.condition) {
+            (Some(enum_columns), Some(condition)) => enum_columns.is_unsatisfiable(condition),
+            _ => false,
+        };
+        subscription.key_columns = self.key_columns.clone();
+
+        let mut registry = self.registry.write().await;
+
+        // Checked under the lock, so that no subscription can slip in during a shutdown
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(ChannelError::ShutDown);
+        }
+
+        // Closed channels are pruned with the next notification
+        if subscription.ack {
+            let _ = subscription.send(subscribed_message(channel_id));
+        }
+
+        // A channel id subscribing again no longer references its previous subscription
+        registry.release_alias(channel_id);
+
+        if let Some(snapshot) = subscription.snapshot.take() {
+            self.snapshots
+                .lock()
+                .unwrap()
+                .insert(channel_id.to_string(), snapshot);
+        }
+
+        if let Some(owner) = registry.find_duplicate(&subscription) {
+            *registry.references.entry(owner.clone()).or_insert(1) += 1;
+            registry.aliases.insert(channel_id.to_string(), owner);
+            return Ok(());
+        }
+
+        registry.remove(channel_id);
+        registry.references.insert(channel_id.to_string(), 1);
+        registry
+            .subscriptions
+            .insert(channel_id.to_string(), subscription);
+        Ok(())
+    }
+
+    /// Remove a subscription by channel id.
+    /// A coalesced subscription is only removed once all of its channel ids unsubscribed.
+    pub async fn unsubscribe(&self, channel_id: &str) -> Option<Subscription<C>> {
+        let mut registry = self.registry.write().await;
+
+        let owner = registry
+            .aliases
+            .remove(channel_id)
+            .unwrap_or_else(|| channel_id.to_string());
+
+        self.snapshots.lock().unwrap().remove(channel_id);
+
+        let references = registry.references.get_mut(&owner)?;
+        *references -= 1;
+
+        if *references == 0 {
+            registry.remove(&owner)
+        } else {
+            None
+        }
+    }
+
+    /// Remove all the subscriptions of a subscription group at once, under a single lock
+    /// so that no notification reaches only part of the group.
+    /// Returns the number of removed subscriptions.
+    pub async fn unsubscribe_group(&self, group: &str) -> usize {
+        let mut registry = self.registry.write().await;
+
+        let members: Vec<String> = registry
+            .subscriptions
+            .iter()
+            .filter(|(_, subscription)| subscription.group().is_some_and(|(name, _)| name == group))
+            .map(|(channel_id, _)| channel_id.clone())
+            .collect();
+
+        let mut snapshots = self.snapshots.lock().unwrap();
+        for channel_id in &members {
+            snapshots.remove(channel_id);
+            registry.remove(channel_id);
+        }
+        members.len()
+    }
+
+    /// Process a database operation notification, notify the relevant
+    /// channels about the change that occured, and remove the channels that errored out.
+    ///
+    /// With a notification log, the notification is recorded before being sent.
+    /// If it cannot be recorded, it is sent all the same, without sequence number.
+    ///
+    /// Returns the number of channels that received a message (notification or refetch).
+    pub async fn process<T>(&self, operation: &OperationNotification<T>) -> usize
+    where
+        T: Clone + Serialize,
+    {
+        let sequence = self.log.as_ref().and_then(|log| {
+            let notification = self.project(serde_json::to_value(operation).unwrap());
+            log.record(operation.get_table(), &notification).ok()
+        });
+
+        // The read lock must be released before acquiring the write lock
+        let (failing_channels, notified): (Vec<String>, usize) = {
+            let registry = self.registry.read().await;
+            let fan_out = process_sequenced_channel_event(
+                &registry.subscriptions,
+                operation,
+                self.projection,
+                sequence,
+                self.key_columns.as_deref(),
+                self.shared_payloads,
+            );
+            (
+                fan_out.failing.into_iter().map(String::from).collect(),
+                fan_out.notified,
+            )
+        };
+
+        self.prune(failing_channels).await;
+        notified
+    }
+
+    /// Send a refetch message to the subscriptions whose queries depend on another table
+    /// through a correlated subquery, after an operation on that table.
+    /// Returns the number of channels that received the message.
+    pub async fn refetch_dependents(&self, table: &str) -> usize {
+        let (failing_channels, notified): (Vec<String>, usize) = {
+            let registry = self.registry.read().await;
+            let mut fan_out = FanOut::new(self.shared_payloads);
+            let refetch = fan_out.share(refetch_message());
+            for (key, subscription) in &registry.subscriptions {
+                if subscription.query.subquery_tables().contains(&table) {
+                    fan_out.send_refetch(key, subscription, refetch);
+                }
+            }
+            (
+                fan_out.failing.into_iter().map(String::from).collect(),
+                fan_out.notified,
+            )
+        };
+
+        self.prune(failing_channels).await;
+        notified
+    }
+
+    /// Send the deferred refetches of the throttled subscriptions whose window elapsed.
+    /// Returns the number of channels that received a refetch message.
+    pub async fn flush_refetches(&self) -> usize {
+        let now = Instant::now();
+        let (failing_channels, notified): (Vec<String>, usize) = {
+            let registry = self.registry.read().await;
+            let mut fan_out = FanOut::new(self.shared_payloads);
+            let refetch = fan_out.share(refetch_message());
+            for (key, subscription) in &registry.subscriptions {
+                if let Some(throttle) = &subscription.refetch_throttle {
+                    if throttle.take_due(now) {
+                        fan_out.send_shared(key, subscription, refetch);
+                    }
+                }
+            }
+            (
+                fan_out.failing.into_iter().map(String::from).collect(),
+                fan_out.notified,
+            )
+        };
+
+        self.prune(failing_channels).await;
+        notified
+    }
+
+    /// Remove the subscriptions of channels that errored out
+    async fn prune(&self, failing_channels: Vec<String>) {
+        if !failing_channels.is_empty() {
+            let mut registry = self.registry.write().await;
+            let mut snapshots = self.snapshots.lock().unwrap();
+            for key in failing_channels {
+                snapshots.remove(&key);
+                registry.remove(&key);
+            }
+        }
+    }
+
+    /// Record an activity of the client of a channel, such as a keep-alive message, for the
+    /// `Liveness::Idle` criterion. Returns whether the channel is subscribed.
+    pub async fn touch(&self, channel_id: &str) -> bool {
+        let mut registry = self.registry.write().await;
+        let owner = registry
+            .aliases
+            .get(channel_id)
+            .cloned()
+            .unwrap_or_else(|| channel_id.to_string());
+
+        match registry.subscriptions.get_mut(&owner) {
+            Some(subscription) => {
+                subscription.last_activity = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the subscriptions whose channel is dead according to a liveness criterion,
+    /// for the clients that vanished without unsubscribing while no notification was
+    /// sent to them. Returns the number of removed subscriptions.
+    pub async fn prune_stale(&self, liveness: Liveness) -> usize {
+        let stale: Vec<String> = self
+            .registry
+            .read()
+            .await
+            .subscriptions
+            .iter()
+            .filter(|(_, subscription)| match liveness {
+                Liveness::Probe => !subscription.channel.probe(),
+                Liveness::Idle(timeout) => subscription.last_activity.elapsed() >= timeout,
+            })
+            .map(|(channel_id, _)| channel_id.clone())
+            .collect();
+
+        let pruned = stale.len();
+        self.prune(stale).await;
+        pruned
+    }
+
+    /// Send a terminal message to every channel, remove all subscriptions,
+    /// and refuse new subscriptions from now on.
+    pub async fn shutdown(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        let mut registry = self.registry.write().await;
+        self.snapshots.lock().unwrap().clear();
+        registry.aliases.clear();
+        registry.references.clear();
+        for (_, subscription) in registry.subscriptions.drain() {
+            // Channels that already errored out are dropped all the same
+            let _ = subscription.send(closed_message());
+        }
+    }
+
+    /// Whether the subscriptions have been shut down
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Number of active subscriptions (coalesced duplicates count once)
+    pub async fn len(&self) -> usize {
+        self.registry.read().await.subscriptions.len()
+    }
+
+    /// Whether there are no active subscriptions
+    pub async fn is_empty(&self) -> bool {
+        self.registry.read().await.subscriptions.is_empty()
+    }
+
+    /// Describe the active subscriptions, sorted by channel id
+    pub async fn inspect(&self) -> Vec<SubscriptionInfo> {
+        let registry = self.registry.read().await;
+        let mut infos: Vec<SubscriptionInfo> = registry
+            .subscriptions
+            .iter()
+            .map(|(channel_id, subscription)| {
+                let mut aliases: Vec<String> = registry
+                    .aliases
+                    .iter()
+                    .filter(|(_, owner)| *owner == channel_id)
+                    .map(|(alias, _)| alias.clone())
+                    .collect();
+                aliases.sort();
+                SubscriptionInfo {
+                    channel_id: channel_id.clone(),
+                    table: subscription.query.table.clone(),
+                    label: subscription.label.clone(),
+                    request_id: subscription.request_id.clone(),
+                    aliases,
+                    count: subscription.count(),
+                }
+            })
+            .collect();
+        infos.sort_by(|a, b| a.channel_id.cmp(&b.channel_id));
+        infos
+    }
+}
+
+impl<C: NotificationChannel + Send + Sync + 'static> TableSubscriptions<C> {
+    /// Spawn a background task pruning the stale channels periodically (see `prune_stale`).
+    /// The task stops once the subscriptions are shut down or dropped.
+    pub fn spawn_stale_pruning(self: &Arc<Self>, config: StalePruning) -> JoinHandle<()> {
+        let subscriptions = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                config.tick().await;
+                let Some(subscriptions) = subscriptions.upgrade() else {
+                    return;
+                };
+                if subscriptions.is_closed() {
+                    return;
+                }
+                subscriptions.prune_stale(config.liveness).await;
+            }
+        })
+    }
+
+    /// Spawn a background task sending the deferred refetches of the throttled
+    /// subscriptions periodically (see `flush_refetches`).
+    /// The task stops once the subscriptions are shut down or dropped.
+    pub fn spawn_refetch_flushing(self: &Arc<Self>, config: RefetchFlushing) -> JoinHandle<()> {
+        let subscriptions = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                config.tick().await;
+                let Some(subscriptions) = subscriptions.upgrade() else {
+                    return;
+                };
+                if subscriptions.is_closed() {
+                    return;
+                }
+                subscriptions.flush_refetches().await;
+            }
+        })
+    }
+}
+
+impl<C: NotificationChannel + Clone + Send + 'static> TableSubscriptions<C> {
+    /// Refetch the snapshot of a subscription with a soft deadline, for slow queries that
+    /// should not block the client.
+    ///
+    /// If a snapshot of the subscription was cached by a previous call (or given when
+    /// subscribing, see `Subscription::with_snapshot`) and the refetch
+    /// takes longer than `soft_timeout`, the cached snapshot is returned with a
+    /// `stale: true` field, and the fresh snapshot is sent through the channel of the
+    /// subscription once the refetch completes. Otherwise, the refetch is awaited.
+    /// The fresh snapshot is projected and reduced to the columns of the subscription,
+    /// and cached while the channel is subscribed. A failed refetch is returned if it
+    /// was awaited, and neither cached nor sent otherwise.
+    pub async fn refetch_with_soft_timeout<F, E>(
+        &self,
+        channel_id: &str,
+        soft_timeout: Duration,
+        refetch: F,
+    ) -> Result<serde_json::Value, E>
+    where
+        F: Future<Output = Result<serde_json::Value, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        let subscription = {
+            let registry = self.registry.read().await;
+            let owner = registry
+                .aliases
+                .get(channel_id)
+                .map_or(channel_id, String::as_str);
+            registry.subscriptions.get(owner).map(|subscription| {
+                (
+                    subscription.channel.clone(),
+                    subscription.request_id.clone(),
+                    subscription.columns.clone(),
+                )
+            })
+        };
+
+        let projection = self.projection;
+        let columns = subscription
+            .as_ref()
+            .and_then(|(_, _, columns)| columns.clone());
+        let mut task = tokio::spawn(async move {
+            let mut value = refetch.await?;
+            if let Some(projection) = projection {
+                project_data(&mut value, projection);
+            }
+            if let Some(columns) = &columns {
+                select_columns(&mut value, columns);
+            }
+            Ok(value)
+        });
+        let cached = self.snapshots.lock().unwrap().get(channel_id).cloned();
+
+        // Snapshots are only cached for subscribed channels
+        let Some((channel, request_id, _)) = subscription else {
+            return task.await.expect("Refetch task panicked");
+        };
+
+        let value = match cached {
+            Some(mut cached) => match tokio::time::timeout(soft_timeout, &mut task).await {
+                Ok(value) => value.expect("Refetch task panicked"),
+                Err(_) => {
+                    // Serve the cached snapshot, and push the fresh one when it is ready
+                    let snapshots = self.snapshots.clone();
+                    let channel_id = channel_id.to_string();
+                    tokio::spawn(async move {
+                        let Ok(Ok(value)) = task.await else {
+                            return;
+                        };
+                        // Unless the channel unsubscribed in the meantime
+                        match snapshots.lock().unwrap().get_mut(&channel_id) {
+                            Some(snapshot) => *snapshot = value.clone(),
+                            None => return,
+                        }
+                        let _ = channel.send_value(tag_request_id(value, request_id.as_deref()));
+                    });
+
+                    if let serde_json::Value::Object(object) = &mut cached {
+                        object.insert("stale".to_string(), true.into());
+                    }
+                    return Ok(cached);
+                }
+            },
+            None => task.await.expect("Refetch task panicked"),
+        }?;
+
+        self.snapshots
+            .lock()
+            .unwrap()
+            .insert(channel_id.to_string(), value.clone());
+        Ok(value)
+    }
+}
+
+/// Access control hook of the admin subscriptions, given the identifier of the client
+/// connection (such as the label of a Tauri webview)
+pub type AdminAuthorizer = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Admin-level subscriptions, receiving every operation notification of every table
+/// regardless of any query, for monitoring tools.
+///
+/// Clients are only allowed to subscribe if the registered authorizer accepts them:
+/// without authorizer, every subscription is refused.
+pub struct AdminSubscriptions<C> {
+    channels: RwLock<HashMap<String, C>>,
+    authorizer: Option<AdminAuthorizer>,
+}
+
+impl<C: NotificationChannel> Default for AdminSubscriptions<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: NotificationChannel> AdminSubscriptions<C> {
+    /// Create an empty set of admin subscriptions, refusing every client
+    pub fn new() -> Self {
+        AdminSubscriptions {
+            channels: RwLock::new(HashMap::new()),
+            authorizer: None,
+        }
+    }
+
+    /// Register the access control hook deciding which clients can subscribe
+    pub fn set_authorizer(&mut self, authorizer: AdminAuthorizer) {
+        self.authorizer = Some(authorizer);
+    }
+
+    /// Add an admin subscription under a channel id.
+    /// Fails with `ChannelError::Forbidden` if the authorizer refuses the client.
+    pub async fn subscribe(
+        &self,
+        client_id: &str,
+        channel_id: &str,
+        channel: C,
+    ) -> Result<(), ChannelError> {
+        match &self.authorizer {
+            Some(authorizer) if authorizer(client_id) => {
+                self.channels
+                    .write()
+                    .await
+                    .insert(channel_id.to_string(), channel);
+                Ok(())
+            }
+            _ => Err(ChannelError::Forbidden),
+        }
+    }
+
+    /// Remove an admin subscription by channel id
+    pub async fn unsubscribe(&self, channel_id: &str) -> Option<C> {
+        self.channels.write().await.remove(channel_id)
+    }
+
+    /// Send a serialized operation notification to every admin channel, with the identifier
+    /// of the client that initiated the operation in an `origin` field, if known.
+    /// Channels that errored out are removed.
+    ///
+    /// Returns the number of channels that received the notification.
+    pub async fn process(&self, notification: &serde_json::Value, origin: Option<&str>) -> usize {
+        let mut notification = notification.clone();
+        if let (Some(origin), serde_json::Value::Object(object)) = (origin, &mut notification) {
+            object.insert("origin".to_string(), origin.into());
+        }
+
+        let (failing_channels, notified): (Vec<String>, usize) = {
+            let channels = self.channels.read().await;
+            let failing: Vec<String> = channels
+                .iter()
+                .filter(|(_, channel)| channel.send_value(notification.clone()).is_err())
+                .map(|(channel_id, _)| channel_id.clone())
+                .collect();
+            let notified = channels.len() - failing.len();
+            (failing, notified)
+        };
+
+        if !failing_channels.is_empty() {
+            let mut channels = self.channels.write().await;
+            for channel_id in &failing_channels {
+                channels.remove(channel_id);
+            }
+        }
+
+        notified
+    }
+
+    /// Number of admin subscriptions
+    pub async fn len(&self) -> usize {
+        self.channels.read().await.len()
+    }
+
+    /// Whether there are no admin subscriptions
+    pub async fn is_empty(&self) -> bool {
+        self.channels.read().await.is_empty()
+    }
+}
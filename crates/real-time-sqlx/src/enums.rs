@@ -0,0 +1,96 @@
+//! Validation of text enum columns, whose values belong to a fixed set.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::EnumError,
+    operations::serialize::{GranularOperation, JsonObject},
+    queries::serialize::{Condition, ConstraintValue, FinalType, Operator},
+};
+
+/// Allowed values of the text enum columns of a table, registered at table setup
+#[derive(Debug, Clone, Default)]
+pub struct EnumColumns {
+    columns: HashMap<String, Vec<String>>,
+}
+
+impl EnumColumns {
+    /// Create an empty set of enum columns
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the allowed values of a column
+    pub fn with_column(mut self, column: &str, values: &[&str]) -> Self {
+        self.columns.insert(
+            column.to_string(),
+            values.iter().map(|value| value.to_string()).collect(),
+        );
+        self
+    }
+
+    /// Whether a value is allowed in a column. Columns that are not registered accept
+    /// any value, and `null` is left to the nullability of the column.
+    pub fn allows(&self, column: &str, value: &serde_json::Value) -> bool {
+        match (self.columns.get(column), value) {
+            (None, _) | (_, serde_json::Value::Null) => true,
+            (Some(values), serde_json::Value::String(value)) => values.contains(value),
+            (Some(_), _) => false,
+        }
+    }
+
+    /// Check the enum columns of a written row
+    pub fn check_row(&self, row: &JsonObject) -> Result<(), EnumError> {
+        match row
+            .iter()
+            .find(|(column, value)| !self.allows(column, value))
+        {
+            Some((column, value)) => Err(EnumError::InvalidValue {
+                column: column.clone(),
+                value: value.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the rows written by an operation
+    pub fn check_operation(&self, operation: &GranularOperation) -> Result<(), EnumError> {
+        match operation {
+            GranularOperation::Create { data, .. } | GranularOperation::Update { data, .. } => {
+                self.check_row(data)
+            }
+            GranularOperation::CreateMany { data, .. } => {
+                data.iter().try_for_each(|row| self.check_row(row))
+            }
+            GranularOperation::Delete { .. } => Ok(()),
+        }
+    }
+
+    /// Whether a condition can never match, because it compares an enum column
+    /// for equality with values outside of its set only
+    pub fn is_unsatisfiable(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::And { conditions } => conditions
+                .iter()
+                .any(|condition| self.is_unsatisfiable(condition)),
+            Condition::Or { conditions } => {
+                !conditions.is_empty()
+                    && conditions
+                        .iter()
+                        .all(|condition| self.is_unsatisfiable(condition))
+            }
+            Condition::Single { constraint } if constraint.function.is_none() => {
+                let outside = |value: &FinalType| {
+                    *value != FinalType::Null
+                        && !self.allows(&constraint.column, &serde_json::to_value(value).unwrap())
+                };
+                match (&constraint.operator, &constraint.value) {
+                    (Operator::Equal, ConstraintValue::Final(value)) => outside(value),
+                    (Operator::In, ConstraintValue::List(values)) => values.iter().all(outside),
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
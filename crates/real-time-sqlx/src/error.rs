@@ -1,7 +1,14 @@
 //! Custom errors
 
+use std::time::Duration;
+
 use thiserror::Error;
 
+use crate::{
+    database::DbKind,
+    queries::serialize::{FinalType, Operator},
+};
+
 /// Deserialization errors
 #[derive(Error, Debug)]
 pub enum DeserializeError {
@@ -9,4 +16,170 @@ pub enum DeserializeError {
     IncompatibleValue(serde_json::Value),
     #[error("JSON Value could not be coerced to a Map")]
     IncompatibleMap(serde_json::Value),
+    #[error("Operator {operator} on column {column} requires {expected}")]
+    IncompatibleConstraint {
+        column: String,
+        operator: Operator,
+        expected: &'static str,
+    },
+    /// Deserialization failure of the node at `path` (a JSON pointer, empty for the root)
+    #[error("Malformed JSON value at \"{path}\": {source}")]
+    Malformed {
+        path: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Errors without known location are reported at the root
+impl From<serde_json::Error> for DeserializeError {
+    fn from(source: serde_json::Error) -> Self {
+        DeserializeError::Malformed {
+            path: String::new(),
+            source,
+        }
+    }
+}
+
+/// Value binding errors
+#[derive(Error, Debug)]
+pub enum BindError {
+    #[error("Number {0} cannot be bound to a query")]
+    UnsupportedNumber(serde_json::Number),
+    #[error("Array {0:?} cannot be bound to a query")]
+    UnsupportedArray(Vec<FinalType>),
+    #[error("Invalid base64 value for a binary column: {0}")]
+    InvalidBinary(#[from] base64::DecodeError),
+}
+
+/// Snapshot serialization errors
+#[derive(Error, Debug)]
+pub enum SnapshotError {
+    #[error("Row without a string or number key in column {0}")]
+    MissingKey(String),
+    #[error("Duplicate key {0}")]
+    DuplicateKey(String),
+    #[error("No data model registered for table {0}")]
+    UnknownTable(String),
+}
+
+/// Enum column validation errors
+#[derive(Error, Debug)]
+pub enum EnumError {
+    #[error("Value {value} is not allowed in enum column {column}")]
+    InvalidValue {
+        column: String,
+        value: serde_json::Value,
+    },
+}
+
+/// Operator policy errors
+#[derive(Error, Debug)]
+pub enum PolicyError {
+    #[error("Operator {operator} is not allowed on column {column} of table {table}")]
+    OperatorNotAllowed {
+        table: String,
+        column: String,
+        operator: Operator,
+    },
+}
+
+/// Operation errors: operations rejected before reaching the database, and failures
+/// of the granular operations executed on it
+#[derive(Error, Debug)]
+pub enum OperationError {
+    #[error("Table {0} is read-only")]
+    ReadOnly(String),
+    #[error("No table {0} registered in the dispatcher")]
+    UnknownTable(String),
+    #[error(transparent)]
+    Enum(#[from] EnumError),
+    #[error(transparent)]
+    Deserialize(#[from] DeserializeError),
+    #[error(transparent)]
+    Bind(#[from] BindError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    /// Row written by the operation that cannot be built into its data model
+    #[error("Row could not be converted to the data model: {0}")]
+    FromRow(#[source] sqlx::Error),
+    /// Failure of a statement of the operation, with its context, or of a query it runs
+    #[error(transparent)]
+    Query(#[from] QueryError),
+}
+
+impl OperationError {
+    /// Database error, with or without the context of its statement
+    pub fn database_error(&self) -> Option<&sqlx::Error> {
+        match self {
+            OperationError::Database(err) => Some(err),
+            OperationError::Query(err) => err.database_error(),
+            _ => None,
+        }
+    }
+}
+
+/// Rate limiting errors
+#[derive(Error, Debug)]
+pub enum RateLimitError {
+    #[error("Rate limit exceeded, retry after {retry_after:?}")]
+    Exceeded { retry_after: Duration },
+}
+
+/// Real-time channel errors
+#[derive(Error, Debug)]
+pub enum ChannelError {
+    #[error("Channel is closed")]
+    Closed,
+    #[error("Dispatcher has been shut down")]
+    ShutDown,
+    #[error("Subscription refused by the access control hook")]
+    Forbidden,
+    #[error("No table {0} registered in the dispatcher")]
+    UnknownTable(String),
+}
+
+/// Query execution errors
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("Query timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Query cancelled")]
+    Cancelled,
+    #[error("No database connection available after {0:?}")]
+    PoolExhausted(Duration),
+    #[error("Insert into {0} produced no row")]
+    NoRowInserted(String),
+    #[error("No table {0} registered in the dispatcher")]
+    UnknownTable(String),
+    #[error("{feature} is not supported by the {backend:?} backend")]
+    Unsupported {
+        feature: &'static str,
+        backend: DbKind,
+    },
+    #[error(transparent)]
+    Deserialize(#[from] DeserializeError),
+    #[error(transparent)]
+    Bind(#[from] BindError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    /// Database error of a generated query, with its SQL and its bindings (their types,
+    /// or their values if `database::set_debug_bindings` is enabled)
+    #[error("{source} in `{sql}` bound to {bindings}")]
+    Statement {
+        sql: String,
+        bindings: String,
+        #[source]
+        source: sqlx::Error,
+    },
+}
+
+impl QueryError {
+    /// Database error, with or without the context of its statement
+    pub fn database_error(&self) -> Option<&sqlx::Error> {
+        match self {
+            QueryError::Database(err) | QueryError::Statement { source: err, .. } => Some(err),
+            _ => None,
+        }
+    }
 }
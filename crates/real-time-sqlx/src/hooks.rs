@@ -0,0 +1,106 @@
+//! Hooks running side effects after operations commit, such as sending push
+//! notifications or enqueuing jobs.
+
+use std::{collections::HashMap, error::Error, future::Future, pin::Pin, sync::Arc};
+
+use serde::Serialize;
+
+use crate::operations::serialize::{OperationNotification, Tabled};
+
+/// Kind of operation that a hook is registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OperationKind {
+    Create,
+    CreateMany,
+    Update,
+    UpdateMany,
+    Delete,
+    DeleteMany,
+}
+
+impl OperationKind {
+    /// Kind of the operation that produced a notification
+    pub fn of<T>(notification: &OperationNotification<T>) -> Self {
+        match notification {
+            OperationNotification::Create { .. } => OperationKind::Create,
+            OperationNotification::CreateMany { .. } => OperationKind::CreateMany,
+            OperationNotification::Update { .. } => OperationKind::Update,
+            OperationNotification::UpdateMany { .. } => OperationKind::UpdateMany,
+            OperationNotification::Delete { .. } => OperationKind::Delete,
+            OperationNotification::DeleteMany { .. } => OperationKind::DeleteMany,
+        }
+    }
+}
+
+/// Error returned by a hook
+pub type HookError = Box<dyn Error + Send + Sync>;
+
+/// Future returned by a hook
+pub type HookFuture = Pin<Box<dyn Future<Output = Result<(), HookError>> + Send>>;
+
+/// Hook receiving the notification of a committed operation, with its rows as JSON
+pub type PostCommitHook =
+    Arc<dyn Fn(Arc<OperationNotification<serde_json::Value>>) -> HookFuture + Send + Sync>;
+
+/// Hooks run after operations commit, by table and operation kind.
+///
+/// Hooks run once the operation is committed: a failing hook cannot roll it back,
+/// so its error is logged and the other hooks still run.
+#[derive(Clone, Default)]
+pub struct PostCommitHooks {
+    hooks: HashMap<(String, OperationKind), Vec<PostCommitHook>>,
+}
+
+impl PostCommitHooks {
+    /// Create an empty hook registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a hook run after each committed operation of a kind on a table.
+    /// Hooks of the same table and kind run in registration order.
+    pub fn with_hook<F, Fut>(mut self, table: &str, kind: OperationKind, hook: F) -> Self
+    where
+        F: Fn(Arc<OperationNotification<serde_json::Value>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), HookError>> + Send + 'static,
+    {
+        let hook: PostCommitHook = Arc::new(move |notification| Box::pin(hook(notification)));
+        self.hooks
+            .entry((table.to_string(), kind))
+            .or_default()
+            .push(hook);
+        self
+    }
+
+    /// Whether no hook is registered
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Run the hooks registered for the table and kind of a committed operation,
+    /// logging their errors. Returns the number of hooks that succeeded.
+    pub async fn run<T: Serialize>(&self, notification: &OperationNotification<T>) -> usize {
+        let key = (
+            notification.get_table().to_string(),
+            OperationKind::of(notification),
+        );
+        let Some(hooks) = self.hooks.get(&key) else {
+            return 0;
+        };
+
+        let notification = Arc::new(notification.to_json());
+        let mut succeeded = 0;
+        for hook in hooks {
+            match hook(notification.clone()).await {
+                Ok(()) => succeeded += 1,
+                Err(err) => tracing::error!(
+                    table = %key.0,
+                    kind = ?key.1,
+                    error = %err,
+                    "post-commit hook failed"
+                ),
+            }
+        }
+        succeeded
+    }
+}
@@ -1,12 +1,24 @@
 //! Real-time SQLx library
 
 pub mod backends;
+pub mod cancellation;
 pub mod database;
+pub mod dispatcher;
+pub mod enums;
 pub mod error;
+pub mod hooks;
+pub mod liveness;
 pub mod macros;
+pub mod notification_log;
 pub mod operations;
+pub mod operators;
 pub mod queries;
+pub mod rate_limit;
+#[cfg(feature = "sqlite")]
+pub mod replay;
+pub mod snapshots;
 pub mod utils;
+pub mod writes;
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,49 @@
+//! Detection of the stale channels of clients that vanished without unsubscribing.
+
+use std::time::Duration;
+
+/// Criterion deciding that the channel of a subscription is dead.
+///
+/// Channels are pruned as soon as a notification fails to be sent to them, but the
+/// channels of clients that silently vanished linger as long as no operation concerns
+/// their query: `TableSubscriptions::prune_stale` checks them with this criterion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Liveness {
+    /// Probe each channel with `NotificationChannel::probe`, which sends a `ping`
+    /// message by default: the channels that fail are dead
+    #[default]
+    Probe,
+    /// The channels that were neither subscribed nor touched by their client
+    /// (see `TableSubscriptions::touch`) for this long are dead
+    Idle(Duration),
+}
+
+/// Configuration of the periodic pruning of stale channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StalePruning {
+    /// Delay between two prunings
+    pub interval: Duration,
+    /// Criterion deciding which channels are dead
+    pub liveness: Liveness,
+}
+
+impl StalePruning {
+    /// Prune the stale channels at the given interval, probing them
+    pub fn new(interval: Duration) -> Self {
+        StalePruning {
+            interval,
+            liveness: Liveness::default(),
+        }
+    }
+
+    /// Set the criterion deciding which channels are dead
+    pub fn with_liveness(mut self, liveness: Liveness) -> Self {
+        self.liveness = liveness;
+        self
+    }
+
+    /// Wait until the next pruning
+    pub async fn tick(&self) {
+        tokio::time::sleep(self.interval).await;
+    }
+}
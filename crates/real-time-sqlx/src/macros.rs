@@ -38,7 +38,7 @@ macro_rules! serialize_rows_static {
 /// granular_operations!(sqlite, ("todos", Todo), ("users", User));
 ///
 /// // Use it to execute a granular operation and serialize the result to JSON.
-/// let serialized: serde_json::Value = granular_operation_static(operation, &pool).await;
+/// let serialized: serde_json::Value = granular_operation_static(operation, &pool).await?;
 /// ```
 #[macro_export]
 macro_rules! granular_operations {
@@ -46,14 +46,14 @@ macro_rules! granular_operations {
         async fn granular_operation_static(
             operation: $crate::operations::serialize::GranularOperation,
             pool: &$crate::database_pool!($db_type),
-        ) -> serde_json::Value {
+        ) -> Result<serde_json::Value, $crate::error::QueryError> {
             match operation.get_table() {
                 $(
                     $table_name => {
                         // Dynamically invoke the correct database function based on $db_type
                         let result: Option<$crate::operations::serialize::OperationNotification<$struct>> =
-                            $crate::granular_operation_fn!($db_type)(operation, pool).await;
-                        serde_json::to_value(result).unwrap()
+                            $crate::granular_operation_fn!($db_type)(operation, pool).await?;
+                        Ok(serde_json::to_value(result).unwrap())
                     }
                 )+
                 _ => panic!("Table not found"),
@@ -80,6 +80,20 @@ macro_rules! database_pool {
   };
 }
 
+/// Returns the database backend kind based on the database type.
+#[macro_export]
+macro_rules! database_kind {
+    (sqlite) => {
+        $crate::database::DbKind::Sqlite
+    };
+    (mysql) => {
+        $crate::database::DbKind::MySql
+    };
+    (postgresql) => {
+        $crate::database::DbKind::Postgres
+    };
+}
+
 /// Returns the appropriate database row type based on the database type.
 #[macro_export]
 macro_rules! database_row {
@@ -104,7 +118,139 @@ macro_rules! granular_operation_fn {
         $crate::database::mysql::granular_operation_mysql
     };
     (postgresql) => {
-        $crate::database::postgresql::granular_operation_postgresql
+        $crate::database::postgres::granular_operation_postgres
+    };
+}
+
+/// Returns the appropriate granular operation function also returning the notices
+/// raised by the database, depending on the database type.
+#[macro_export]
+macro_rules! granular_operation_with_notices_fn {
+    (sqlite) => {
+        $crate::database::sqlite::granular_operation_sqlite_with_notices
+    };
+    (mysql) => {
+        $crate::database::mysql::granular_operation_mysql_with_notices
+    };
+    (postgresql) => {
+        $crate::database::postgres::granular_operation_postgres_with_notices
+    };
+}
+
+/// Returns the appropriate dry-run operation validation function depending on the database type.
+#[macro_export]
+macro_rules! validate_operation_fn {
+    (sqlite) => {
+        $crate::database::sqlite::validate_operation_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::validate_operation_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::validate_operation_postgres
+    };
+}
+
+/// Returns the appropriate non-atomic `CreateMany` function depending on the database type.
+#[macro_export]
+macro_rules! create_many_partial_fn {
+    (sqlite) => {
+        $crate::database::sqlite::create_many_partial_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::create_many_partial_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::create_many_partial_postgres
+    };
+}
+
+/// Returns the appropriate atomic set synchronization function depending on the database type.
+#[macro_export]
+macro_rules! sync_set_fn {
+    (sqlite) => {
+        $crate::database::sqlite::sync_set_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::sync_set_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::sync_set_postgres
+    };
+}
+
+/// Returns the appropriate function executing a granular operation, and returning the
+/// notices raised by the database, on a connection depending on the database type.
+#[macro_export]
+macro_rules! execute_operation_with_notices_fn {
+    (sqlite) => {
+        $crate::database::sqlite::execute_operation_sqlite_with_notices
+    };
+    (mysql) => {
+        $crate::database::mysql::execute_operation_mysql_with_notices
+    };
+    (postgresql) => {
+        $crate::database::postgres::execute_operation_postgres_with_notices
+    };
+}
+
+/// Returns the appropriate function executing a granular operation on a connection
+/// depending on the database type.
+#[macro_export]
+macro_rules! execute_operation_fn {
+    (sqlite) => {
+        $crate::database::sqlite::execute_operation_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::execute_operation_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::execute_operation_postgres
+    };
+}
+
+/// Returns the appropriate dry-run operation validation function on a connection
+/// depending on the database type.
+#[macro_export]
+macro_rules! execute_validate_operation_fn {
+    (sqlite) => {
+        $crate::database::sqlite::execute_validate_operation_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::execute_validate_operation_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::execute_validate_operation_postgres
+    };
+}
+
+/// Returns the appropriate non-atomic `CreateMany` function on a connection
+/// depending on the database type.
+#[macro_export]
+macro_rules! execute_create_many_partial_fn {
+    (sqlite) => {
+        $crate::database::sqlite::execute_create_many_partial_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::execute_create_many_partial_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::execute_create_many_partial_postgres
+    };
+}
+
+/// Returns the appropriate atomic set synchronization function on a connection
+/// depending on the database type.
+#[macro_export]
+macro_rules! execute_sync_set_fn {
+    (sqlite) => {
+        $crate::database::sqlite::execute_sync_set_sqlite
+    };
+    (mysql) => {
+        $crate::database::mysql::execute_sync_set_mysql
+    };
+    (postgresql) => {
+        $crate::database::postgres::execute_sync_set_postgres
     };
 }
 
@@ -118,6 +264,48 @@ macro_rules! fetch_query_fn {
         $crate::database::mysql::fetch_mysql_query
     };
     (postgresql) => {
-        $crate::database::postgresql::fetch_postgresql_query
+        $crate::database::postgres::fetch_postgres_query
+    };
+}
+
+/// Returns the appropriate connection checking function depending on the database type.
+#[macro_export]
+macro_rules! check_connection_fn {
+    (sqlite) => {
+        $crate::database::sqlite::check_sqlite_connection
+    };
+    (mysql) => {
+        $crate::database::mysql::check_mysql_connection
+    };
+    (postgresql) => {
+        $crate::database::postgres::check_postgres_connection
+    };
+}
+
+/// Returns the appropriate cancellable query fetching function depending on the database type.
+#[macro_export]
+macro_rules! fetch_query_cancellable_fn {
+    (sqlite) => {
+        $crate::database::sqlite::fetch_sqlite_query_cancellable_with_acquire_timeout
+    };
+    (mysql) => {
+        $crate::database::mysql::fetch_mysql_query_cancellable_with_acquire_timeout
+    };
+    (postgresql) => {
+        $crate::database::postgres::fetch_postgres_query_cancellable_with_acquire_timeout
+    };
+}
+
+/// Returns the appropriate concurrent batch fetching function depending on the database type.
+#[macro_export]
+macro_rules! fetch_many_fn {
+    (sqlite) => {
+        $crate::database::sqlite::fetch_many_sqlite_queries_with_acquire_timeout
+    };
+    (mysql) => {
+        $crate::database::mysql::fetch_many_mysql_queries_with_acquire_timeout
+    };
+    (postgresql) => {
+        $crate::database::postgres::fetch_many_postgres_queries_with_acquire_timeout
     };
 }
@@ -0,0 +1,253 @@
+//! Durable log of the notifications fanned out to the subscriptions,
+//! so that clients can resume from a sequence number across server restarts.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A notification recorded in the log, with its sequence number
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggedNotification {
+    pub sequence: u64,
+    pub table: String,
+    pub notification: serde_json::Value,
+}
+
+/// Storage of the notification log. Entries are appended with increasing sequence numbers.
+pub trait NotificationSink: Send + Sync {
+    /// Append an entry to the log
+    fn append(&self, entry: &LoggedNotification) -> io::Result<()>;
+
+    /// Read the entries whose sequence number is strictly greater than the given one,
+    /// in order. Entries dropped by rotation are not returned.
+    fn read_after(&self, sequence: u64) -> io::Result<Vec<LoggedNotification>>;
+
+    /// Sequence number of the last entry of the log, if any
+    fn last_sequence(&self) -> io::Result<Option<u64>>;
+}
+
+/// Notification log, assigning sequence numbers to the notifications it records.
+/// Sequence numbers start at 1 and resume after the last entry of the sink.
+pub struct NotificationLog {
+    sink: Box<dyn NotificationSink>,
+    last_sequence: Mutex<u64>,
+}
+
+impl NotificationLog {
+    /// Open a notification log on top of a sink, resuming its sequence numbers
+    pub fn new(sink: impl NotificationSink + 'static) -> io::Result<Self> {
+        let last_sequence = sink.last_sequence()?.unwrap_or(0);
+
+        Ok(NotificationLog {
+            sink: Box::new(sink),
+            last_sequence: Mutex::new(last_sequence),
+        })
+    }
+
+    /// Append a notification of a table to the log, and return its sequence number
+    pub fn record(&self, table: &str, notification: &serde_json::Value) -> io::Result<u64> {
+        // Held during the append, so that entries are written in sequence order
+        let mut last_sequence = self.last_sequence.lock().unwrap();
+
+        let entry = LoggedNotification {
+            sequence: *last_sequence + 1,
+            table: table.to_string(),
+            notification: notification.clone(),
+        };
+        self.sink.append(&entry)?;
+
+        *last_sequence = entry.sequence;
+        Ok(entry.sequence)
+    }
+
+    /// Notifications recorded after the given sequence number, for clients to catch up
+    pub fn replay(&self, after: u64) -> io::Result<Vec<LoggedNotification>> {
+        self.sink.read_after(after)
+    }
+
+    /// Sequence number of the last recorded notification (0 if there is none)
+    pub fn last_sequence(&self) -> u64 {
+        *self.last_sequence.lock().unwrap()
+    }
+}
+
+/// Default maximum size of a log segment before rotation
+const DEFAULT_SEGMENT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Default number of log segments kept on disk
+const DEFAULT_SEGMENTS: usize = 8;
+
+/// Segment of the log currently appended to
+struct Segment {
+    file: File,
+    size: u64,
+}
+
+/// Append-only file sink, writing one JSON entry per line.
+///
+/// The log is split in segment files named after the sequence number of their first entry.
+/// A new segment is started once the current one exceeds its maximum size, and the oldest
+/// segments are deleted beyond the maximum number of segments.
+pub struct FileSink {
+    directory: PathBuf,
+    max_segment_bytes: u64,
+    max_segments: usize,
+    current: Mutex<Option<Segment>>,
+}
+
+impl FileSink {
+    /// Open a file sink in a directory, created if needed
+    pub fn open(directory: impl AsRef<Path>) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+
+        Ok(FileSink {
+            directory: directory.as_ref().to_path_buf(),
+            max_segment_bytes: DEFAULT_SEGMENT_BYTES,
+            max_segments: DEFAULT_SEGMENTS,
+            current: Mutex::new(None),
+        })
+    }
+
+    /// Set the maximum size of a segment and the number of segments kept on disk
+    pub fn with_rotation(mut self, max_segment_bytes: u64, max_segments: usize) -> Self {
+        self.max_segment_bytes = max_segment_bytes;
+        self.max_segments = max_segments.max(1);
+        self
+    }
+
+    /// Segment files of the log, with the sequence number of their first entry, in order
+    pub fn segments(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        let mut segments = vec![];
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "log") {
+                if let Some(first) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<u64>().ok())
+                {
+                    segments.push((first, path));
+                }
+            }
+        }
+
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Path of the segment starting at a sequence number
+    fn segment_path(&self, first: u64) -> PathBuf {
+        self.directory.join(format!("{first:020}.log"))
+    }
+
+    /// Open a segment file for appending
+    fn open_segment(path: &Path) -> io::Result<Segment> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let mut size = file.metadata()?.len();
+
+        // Terminate a line truncated by an interrupted write, so that it is skipped
+        if size > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+                size += 1;
+            }
+        }
+
+        Ok(Segment { file, size })
+    }
+
+    /// Read the entries of a segment file. A truncated last line (interrupted write) is skipped.
+    fn read_segment(path: &Path) -> io::Result<Vec<LoggedNotification>> {
+        let mut entries = vec![];
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl NotificationSink for FileSink {
+    fn append(&self, entry: &LoggedNotification) -> io::Result<()> {
+        let mut current = self.current.lock().unwrap();
+
+        // Resume the last segment after a restart
+        if current.is_none() {
+            if let Some((_, path)) = self.segments()?.pop() {
+                *current = Some(Self::open_segment(&path)?);
+            }
+        }
+
+        // Rotate the full segment, and delete the oldest ones
+        if current
+            .as_ref()
+            .is_none_or(|segment| segment.size >= self.max_segment_bytes)
+        {
+            *current = Some(Self::open_segment(&self.segment_path(entry.sequence))?);
+
+            let segments = self.segments()?;
+            let excess = segments.len().saturating_sub(self.max_segments);
+            for (_, path) in &segments[..excess] {
+                fs::remove_file(path)?;
+            }
+        }
+
+        let segment = current.as_mut().unwrap();
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        segment.file.write_all(&line)?;
+        segment.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn read_after(&self, sequence: u64) -> io::Result<Vec<LoggedNotification>> {
+        let segments = self.segments()?;
+        let mut entries = vec![];
+
+        for (index, (_, path)) in segments.iter().enumerate() {
+            // Skip the segments that end before the requested sequence number
+            if let Some((next_first, _)) = segments.get(index + 1) {
+                if *next_first <= sequence + 1 {
+                    continue;
+                }
+            }
+
+            entries.extend(
+                Self::read_segment(path)?
+                    .into_iter()
+                    .filter(|entry| entry.sequence > sequence),
+            );
+        }
+
+        Ok(entries)
+    }
+
+    fn last_sequence(&self) -> io::Result<Option<u64>> {
+        // The last segment can be empty if the server stopped right after a rotation
+        for (_, path) in self.segments()?.iter().rev() {
+            if let Some(entry) = Self::read_segment(path)?.last() {
+                return Ok(Some(entry.sequence));
+            }
+        }
+
+        Ok(None)
+    }
+}
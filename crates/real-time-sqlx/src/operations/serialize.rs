@@ -1,8 +1,15 @@
 //! Serialize and deserialize database operations from JSON
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{error::DeserializeError, queries::serialize::FinalType};
+use crate::{
+    error::DeserializeError,
+    queries::serialize::{Condition, FinalType, QueryTree, ReturnType},
+    snapshots::Identifiable,
+    utils::deserialize_at,
+};
 
 /// Generic JSON object type
 pub type JsonObject = serde_json::Map<String, serde_json::Value>;
@@ -31,22 +38,105 @@ pub fn object_array_from_value(
     }
 }
 
+/// Merge a partial JSON object into a base object, for instance to reconstruct
+/// a full row from a cached object and a partial update.
+/// Nested objects are merged recursively, any other patch value (including `null`)
+/// replaces the base value.
+pub fn merge_json_object(base: &mut JsonObject, patch: &JsonObject) {
+    for (key, value) in patch {
+        match (base.get_mut(key), value) {
+            (
+                Some(serde_json::Value::Object(base_object)),
+                serde_json::Value::Object(patch_object),
+            ) => {
+                merge_json_object(base_object, patch_object);
+            }
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Field-level diff between two versions of a row: `{ field: { "old": .., "new": .. } }`
+/// for each field whose value changed. Fields missing from one of the rows are
+/// compared as `null`, and unchanged fields are skipped.
+pub fn field_diff(previous: &JsonObject, current: &JsonObject) -> JsonObject {
+    let null = serde_json::Value::Null;
+
+    previous
+        .keys()
+        .chain(current.keys().filter(|key| !previous.contains_key(*key)))
+        .filter_map(|key| {
+            let old = previous.get(key).unwrap_or(&null);
+            let new = current.get(key).unwrap_or(&null);
+            (old != new).then(|| (key.clone(), serde_json::json!({ "old": old, "new": new })))
+        })
+        .collect()
+}
+
 /// Entities related to a specific table
 pub trait Tabled {
     fn get_table(&self) -> &str;
 }
 
+/// Behavior of an insertion when a row conflicts with an existing one (duplicate key)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// The insertion fails
+    #[default]
+    #[serde(rename = "error")]
+    Error,
+    /// The conflicting row is skipped, without notification
+    #[serde(rename = "ignore")]
+    Ignore,
+    /// The conflicting row replaces the existing one
+    #[serde(rename = "replace")]
+    Replace,
+}
+
+impl ConflictPolicy {
+    /// Whether this is the default policy
+    pub fn is_error(&self) -> bool {
+        *self == ConflictPolicy::Error
+    }
+}
+
 /// An incoming granular operation to be performed in the database
 /// The data can be partial or complete, depending on the operation.
+/// Unknown fields are rejected rather than silently dropped.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+#[serde(tag = "type", deny_unknown_fields)]
 pub enum GranularOperation {
     #[serde(rename = "create")]
-    Create { table: String, data: JsonObject },
+    Create {
+        table: String,
+        data: JsonObject,
+        #[serde(
+            rename = "onConflict",
+            default,
+            skip_serializing_if = "ConflictPolicy::is_error"
+        )]
+        on_conflict: ConflictPolicy,
+        /// Client-supplied reference (such as the temporary ID of an optimistic record),
+        /// echoed back in the creation notification
+        #[serde(rename = "clientRef", default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<FinalType>,
+    },
     #[serde(rename = "create_many")]
     CreateMany {
         table: String,
         data: Vec<JsonObject>,
+        #[serde(
+            rename = "onConflict",
+            default,
+            skip_serializing_if = "ConflictPolicy::is_error"
+        )]
+        on_conflict: ConflictPolicy,
+        /// Client-supplied reference (such as the temporary ID of an optimistic record),
+        /// echoed back in the creation notification
+        #[serde(rename = "clientRef", default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<FinalType>,
     },
     #[serde(rename = "update")]
     Update {
@@ -58,6 +148,64 @@ pub enum GranularOperation {
     Delete { table: String, id: FinalType },
 }
 
+/// Deserialize an operation from a JSON value, naming the offending field
+/// (for instance an unknown one) in the error, along with the JSON pointer path
+/// of the malformed field (such as `/data/2` for a row of a `create_many` operation)
+impl TryFrom<serde_json::Value> for GranularOperation {
+    type Error = DeserializeError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match GranularOperation::deserialize(&value) {
+            Ok(operation) => Ok(operation),
+            Err(source) => {
+                Err(check_operation(&value)
+                    .err()
+                    .unwrap_or(DeserializeError::Malformed {
+                        path: String::new(),
+                        source,
+                    }))
+            }
+        }
+    }
+}
+
+/// Check that an operation deserializes, its fields first
+fn check_operation(value: &serde_json::Value) -> Result<(), DeserializeError> {
+    if let Some(id) = value.get("id") {
+        deserialize_at::<FinalType>(id, "/id")?;
+    }
+    if let Some(on_conflict) = value.get("onConflict") {
+        deserialize_at::<ConflictPolicy>(on_conflict, "/onConflict")?;
+    }
+    if let Some(client_ref) = value.get("clientRef") {
+        deserialize_at::<FinalType>(client_ref, "/clientRef")?;
+    }
+    match (
+        value.get("type").and_then(|kind| kind.as_str()),
+        value.get("data"),
+    ) {
+        (Some("create_many"), Some(serde_json::Value::Array(rows))) => {
+            for (index, row) in rows.iter().enumerate() {
+                deserialize_at::<JsonObject>(row, &format!("/data/{index}"))?;
+            }
+        }
+        (Some("create_many"), Some(data)) => {
+            deserialize_at::<Vec<JsonObject>>(data, "/data")?;
+        }
+        (_, Some(data)) => {
+            deserialize_at::<JsonObject>(data, "/data")?;
+        }
+        (_, None) => {}
+    }
+    deserialize_at::<GranularOperation>(value, "").map(|_| ())
+}
+
+impl Tabled for SyncSet {
+    fn get_table(&self) -> &str {
+        &self.table
+    }
+}
+
 impl Tabled for GranularOperation {
     /// Helper method to get the table name from the operation
     fn get_table(&self) -> &str {
@@ -76,14 +224,30 @@ impl Tabled for GranularOperation {
 #[serde(tag = "type")]
 pub enum OperationNotification<T> {
     #[serde(rename = "create")]
-    Create { table: String, data: T },
+    Create {
+        table: String,
+        data: T,
+        /// Client reference of the creation operation, if any
+        #[serde(rename = "clientRef", default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<FinalType>,
+    },
     #[serde(rename = "create_many")]
-    CreateMany { table: String, data: Vec<T> },
+    CreateMany {
+        table: String,
+        data: Vec<T>,
+        /// Client reference of the creation operation, if any
+        #[serde(rename = "clientRef", default, skip_serializing_if = "Option::is_none")]
+        client_ref: Option<FinalType>,
+    },
     #[serde(rename = "update")]
     Update {
         table: String,
         id: FinalType,
         data: T,
+        /// Row before the update, when known. Used to detect which columns changed,
+        /// and only sent to the subscriptions that ask for it.
+        #[serde(skip)]
+        previous: Option<T>,
     },
     #[serde(rename = "delete")]
     Delete {
@@ -91,6 +255,216 @@ pub enum OperationNotification<T> {
         id: FinalType,
         data: T,
     },
+    /// Rows updated by a single bulk operation, after the update
+    #[serde(rename = "update_many")]
+    UpdateMany { table: String, data: Vec<T> },
+    /// Rows deleted by a single bulk operation
+    #[serde(rename = "delete_many")]
+    DeleteMany { table: String, data: Vec<T> },
+}
+
+/// Result of a non-atomic `CreateMany` operation, where rows are inserted individually
+/// and can fail independently. Both maps are keyed by the index of the input row.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialCreateMany<T> {
+    pub table: String,
+    pub created: BTreeMap<usize, T>,
+    pub errors: BTreeMap<usize, String>,
+}
+
+impl<T: Clone> PartialCreateMany<T> {
+    /// Notification of the rows that were created, in input order.
+    /// Returns `None` if every row failed.
+    pub fn notification(&self) -> Option<OperationNotification<T>> {
+        if self.created.is_empty() {
+            return None;
+        }
+
+        Some(OperationNotification::CreateMany {
+            table: self.table.clone(),
+            data: self.created.values().cloned().collect(),
+            client_ref: None,
+        })
+    }
+}
+
+/// An incoming request to bring the rows of a table matching a condition to a desired
+/// state. Rows are matched by their `id` against the current rows: missing ones are
+/// created, differing ones updated, and the current rows absent from the set deleted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncSet {
+    pub table: String,
+    pub condition: Option<Condition>,
+    pub rows: Vec<JsonObject>,
+}
+
+impl SyncSet {
+    /// Query of the current rows of the synced subset
+    pub fn query(&self) -> QueryTree {
+        QueryTree {
+            return_type: ReturnType::Many,
+            table: self.table.clone(),
+            condition: self.condition.clone(),
+            paginate: None,
+            distinct_on: vec![],
+            order_by: None,
+        }
+    }
+
+    /// Granular operations bringing the current rows (serialized to JSON) to the desired
+    /// set: deletions first, so that recreated unique values do not conflict, then updates
+    /// of the rows with at least one differing column, then creations.
+    /// Desired rows without an `id`, or whose `id` is not among the current rows, are created.
+    pub fn operations(
+        self,
+        current: &[serde_json::Value],
+    ) -> Result<Vec<GranularOperation>, DeserializeError> {
+        let mut current_by_id: Vec<(&serde_json::Value, &serde_json::Value)> = current
+            .iter()
+            .filter_map(|row| row.get("id").map(|id| (id, row)))
+            .collect();
+        let mut updates = vec![];
+        let mut creations = vec![];
+
+        for mut row in self.rows {
+            let position = row
+                .get("id")
+                .and_then(|id| current_by_id.iter().position(|(current, _)| *current == id));
+
+            let Some(position) = position else {
+                creations.push(GranularOperation::Create {
+                    table: self.table.clone(),
+                    data: row,
+                    on_conflict: ConflictPolicy::Error,
+                    client_ref: None,
+                });
+                continue;
+            };
+
+            let (_, previous) = current_by_id.remove(position);
+            let id = FinalType::try_from(row.remove("id").unwrap())?;
+            if row
+                .iter()
+                .any(|(column, value)| previous.get(column) != Some(value))
+            {
+                updates.push(GranularOperation::Update {
+                    table: self.table.clone(),
+                    id,
+                    data: row,
+                });
+            }
+        }
+
+        let mut operations = current_by_id
+            .into_iter()
+            .map(|(id, _)| {
+                Ok(GranularOperation::Delete {
+                    table: self.table.clone(),
+                    id: FinalType::try_from(id.clone())?,
+                })
+            })
+            .collect::<Result<Vec<_>, DeserializeError>>()?;
+        operations.extend(updates);
+        operations.extend(creations);
+
+        Ok(operations)
+    }
+}
+
+impl<T: Serialize> OperationNotification<T> {
+    /// Convert the rows of the notification to JSON, keeping the row before an update
+    pub fn to_json(&self) -> OperationNotification<serde_json::Value> {
+        let row = |row: &T| serde_json::to_value(row).unwrap();
+        let rows = |rows: &[T]| rows.iter().map(row).collect();
+
+        match self {
+            OperationNotification::Create {
+                table,
+                data,
+                client_ref,
+            } => OperationNotification::Create {
+                table: table.clone(),
+                data: row(data),
+                client_ref: client_ref.clone(),
+            },
+            OperationNotification::CreateMany {
+                table,
+                data,
+                client_ref,
+            } => OperationNotification::CreateMany {
+                table: table.clone(),
+                data: rows(data),
+                client_ref: client_ref.clone(),
+            },
+            OperationNotification::Update {
+                table,
+                id,
+                data,
+                previous,
+            } => OperationNotification::Update {
+                table: table.clone(),
+                id: id.clone(),
+                data: row(data),
+                previous: previous.as_ref().map(row),
+            },
+            OperationNotification::Delete { table, id, data } => OperationNotification::Delete {
+                table: table.clone(),
+                id: id.clone(),
+                data: row(data),
+            },
+            OperationNotification::UpdateMany { table, data } => {
+                OperationNotification::UpdateMany {
+                    table: table.clone(),
+                    data: rows(data),
+                }
+            }
+            OperationNotification::DeleteMany { table, data } => {
+                OperationNotification::DeleteMany {
+                    table: table.clone(),
+                    data: rows(data),
+                }
+            }
+        }
+    }
+}
+
+impl<T: Identifiable> OperationNotification<T> {
+    /// Build an update notification, keyed by the primary key of the updated row.
+    /// Rows without their key column (such as rows restricted to some returned columns)
+    /// are keyed by `id`, the key targeted by the operation.
+    pub fn update(table: &str, id: FinalType, data: T, previous: Option<T>) -> Self {
+        OperationNotification::Update {
+            table: table.to_string(),
+            id: data.id().unwrap_or(id),
+            data,
+            previous,
+        }
+    }
+
+    /// Build a deletion notification, keyed by the primary key of the deleted row.
+    /// Rows without their key column are keyed by `id`, the key targeted by the operation.
+    pub fn delete(table: &str, id: FinalType, data: T) -> Self {
+        OperationNotification::Delete {
+            table: table.to_string(),
+            id: data.id().unwrap_or(id),
+            data,
+        }
+    }
+
+    /// Primary keys of the rows of the notification, extracted from their data,
+    /// including the rows of bulk operations which have no single id
+    pub fn keys(&self) -> Vec<Option<FinalType>> {
+        let key = |row: &T| row.id().ok();
+        match self {
+            OperationNotification::Create { data, .. }
+            | OperationNotification::Update { data, .. }
+            | OperationNotification::Delete { data, .. } => vec![key(data)],
+            OperationNotification::CreateMany { data, .. }
+            | OperationNotification::UpdateMany { data, .. }
+            | OperationNotification::DeleteMany { data, .. } => data.iter().map(key).collect(),
+        }
+    }
 }
 
 impl<T> Tabled for OperationNotification<T> {
@@ -101,6 +475,8 @@ impl<T> Tabled for OperationNotification<T> {
             OperationNotification::CreateMany { table, .. } => table,
             OperationNotification::Update { table, .. } => table,
             OperationNotification::Delete { table, .. } => table,
+            OperationNotification::UpdateMany { table, .. } => table,
+            OperationNotification::DeleteMany { table, .. } => table,
         }
     }
 }
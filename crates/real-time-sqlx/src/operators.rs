@@ -0,0 +1,76 @@
+//! Policies restricting the operators that clients may use on each column,
+//! to prevent expensive or unindexed filters.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::PolicyError,
+    queries::serialize::{Condition, Operator, QueryTree},
+};
+
+/// Operators allowed on the columns of a table, registered at table setup.
+///
+/// Columns without operators of their own accept the default operators if any,
+/// and every operator otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct OperatorPolicy {
+    columns: HashMap<String, Vec<Operator>>,
+    default: Option<Vec<Operator>>,
+}
+
+impl OperatorPolicy {
+    /// Create a policy allowing every operator on every column
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the operators allowed on a column
+    pub fn with_column(mut self, column: &str, operators: &[Operator]) -> Self {
+        self.columns.insert(column.to_string(), operators.to_vec());
+        self
+    }
+
+    /// Register the operators allowed on the columns without operators of their own
+    pub fn with_default(mut self, operators: &[Operator]) -> Self {
+        self.default = Some(operators.to_vec());
+        self
+    }
+
+    /// Whether an operator is allowed on a column
+    pub fn allows(&self, column: &str, operator: &Operator) -> bool {
+        match self.columns.get(column).or(self.default.as_ref()) {
+            Some(operators) => operators.contains(operator),
+            None => true,
+        }
+    }
+
+    /// Check the constraints of a condition on the table of the policy.
+    /// The conditions of correlated subqueries apply to other tables, and are skipped.
+    pub fn check_condition(&self, table: &str, condition: &Condition) -> Result<(), PolicyError> {
+        match condition {
+            Condition::Single { constraint } => {
+                if self.allows(&constraint.column, &constraint.operator) {
+                    Ok(())
+                } else {
+                    Err(PolicyError::OperatorNotAllowed {
+                        table: table.to_string(),
+                        column: constraint.column.clone(),
+                        operator: constraint.operator.clone(),
+                    })
+                }
+            }
+            Condition::And { conditions } | Condition::Or { conditions } => conditions
+                .iter()
+                .try_for_each(|condition| self.check_condition(table, condition)),
+            Condition::Not { condition } => self.check_condition(table, condition),
+            Condition::Exists { .. } => Ok(()),
+        }
+    }
+
+    /// Check the constraints of a query on the table of the policy
+    pub fn check_query(&self, query: &QueryTree) -> Result<(), PolicyError> {
+        query.condition.as_ref().map_or(Ok(()), |condition| {
+            self.check_condition(&query.table, condition)
+        })
+    }
+}
@@ -1,10 +1,22 @@
 //! Query system for real-time SQLX
 
-use serialize::{Condition, Constraint, ConstraintValue, FinalType, Operator, QueryTree};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use serde_json::Number;
+use serialize::{
+    Condition, Constraint, ConstraintValue, FinalType, Operator, OrderBy, PaginateOptions,
+    QueryTree, ReturnType, ScalarFunction,
+};
 
 use crate::{
+    database::{encode_binary, DbKind},
+    error::DeserializeError,
     operations::serialize::JsonObject,
-    utils::{sql_ilike, sql_like},
+    utils::{prefix_upper_bound, sort_json_keys, sql_ilike, sql_like, ILikePattern, LikePattern},
 };
 
 pub mod display;
@@ -14,6 +26,70 @@ pub mod serialize;
 //                        QUERY SYSTEM IMPLEMENTATION                        //
 // ************************************************************************* //
 
+/// Compare two JSON numbers, whatever their internal representation.
+/// Integers are compared exactly, and other numbers as floats. Numbers that cannot be
+/// represented as floats (with serde_json's `arbitrary_precision` feature) are parsed from
+/// their string form. Returns `None` if the numbers cannot be compared (NaN), in which case
+/// they should be considered as non-matching.
+fn compare_numbers(n: &Number, m: &Number) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (n.as_i64(), m.as_i64()) {
+        return Some(a.cmp(&b));
+    }
+    if let (Some(a), Some(b)) = (n.as_u64(), m.as_u64()) {
+        return Some(a.cmp(&b));
+    }
+    // One of the numbers is a u64 above i64::MAX and the other one a negative i64
+    if let (Some(_), Some(b)) = (n.as_u64(), m.as_i64()) {
+        if b < 0 {
+            return Some(Ordering::Greater);
+        }
+    }
+    if let (Some(a), Some(_)) = (n.as_i64(), m.as_u64()) {
+        if a < 0 {
+            return Some(Ordering::Less);
+        }
+    }
+
+    let as_float = |number: &Number| {
+        number
+            .as_f64()
+            .or_else(|| number.to_string().parse::<f64>().ok())
+    };
+
+    as_float(n)?.partial_cmp(&as_float(m)?)
+}
+
+/// Check if a JSON value contains another one, with the semantics of the PostgreSQL
+/// `@>` JSONB operator: objects contain the key-value pairs of other objects, arrays
+/// contain the elements of other arrays (in any order), and scalars are compared for
+/// equality. As a special case, a top-level array contains its scalar elements.
+pub fn json_contains(container: &serde_json::Value, candidate: &serde_json::Value) -> bool {
+    use serde_json::Value;
+
+    fn contains(container: &Value, candidate: &Value, top_level: bool) -> bool {
+        match (container, candidate) {
+            (Value::Object(object), Value::Object(other)) => other.iter().all(|(key, value)| {
+                object
+                    .get(key)
+                    .is_some_and(|element| contains(element, value, false))
+            }),
+            (Value::Array(array), Value::Array(other)) => other
+                .iter()
+                .all(|value| array.iter().any(|element| contains(element, value, false))),
+            (Value::Array(array), scalar) if top_level && !scalar.is_object() => {
+                array.iter().any(|element| contains(element, scalar, false))
+            }
+            (Value::Number(n), Value::Number(m)) => compare_numbers(n, m) == Some(Ordering::Equal),
+            (Value::Object(_) | Value::Array(_), _) | (_, Value::Object(_) | Value::Array(_)) => {
+                false
+            }
+            (scalar, other) => scalar == other,
+        }
+    }
+
+    contains(container, candidate, true)
+}
+
 /// Comparing 2 final types
 impl FinalType {
     /// Compare self (left side) with another final type (right side) using an operator
@@ -33,7 +109,23 @@ impl FinalType {
                 (FinalType::String(s), FinalType::String(t)) => sql_ilike(t, s),
                 _ => false,
             },
-            _ => panic!("Invalid operator {} for comparison", operator),
+            Operator::IEqual => match (self, other) {
+                (FinalType::String(s), FinalType::String(t)) => {
+                    s.to_lowercase() == t.to_lowercase()
+                }
+                _ => false,
+            },
+            // A scalar only contains itself
+            Operator::Contains => self.equals(other),
+            // Same half-open range as in SQL, in code point order
+            Operator::PrefixMatch => match (self, other) {
+                (FinalType::String(s), FinalType::String(prefix)) => {
+                    s >= prefix && prefix_upper_bound(prefix).is_none_or(|upper| *s < upper)
+                }
+                _ => false,
+            },
+            // List operators with a single value, rejected by validation, do not match
+            _ => false,
         }
     }
 
@@ -43,17 +135,15 @@ impl FinalType {
     pub fn equals(&self, other: &FinalType) -> bool {
         match (self, other) {
             (FinalType::Number(n), FinalType::Number(m)) => {
-                if n.is_f64() && m.is_f64() {
-                    n.as_f64().unwrap() == m.as_f64().unwrap()
-                } else if n.is_i64() && m.is_i64() {
-                    n.as_i64().unwrap() == m.as_i64().unwrap()
-                } else {
-                    false
-                }
+                compare_numbers(n, m) == Some(Ordering::Equal)
             }
             (FinalType::String(s), FinalType::String(t)) => s == t,
             (FinalType::Bool(b), FinalType::Bool(c)) => b == c,
             (FinalType::Null, FinalType::Null) => true,
+            // Binary columns are read as base64 strings
+            (FinalType::Binary(b), FinalType::Binary(c)) => b == c,
+            (FinalType::String(s), FinalType::Binary(b))
+            | (FinalType::Binary(b), FinalType::String(s)) => *s == encode_binary(b),
             _ => false,
         }
     }
@@ -62,13 +152,7 @@ impl FinalType {
     pub fn less_than(&self, other: &FinalType) -> bool {
         match (self, other) {
             (FinalType::Number(n), FinalType::Number(m)) => {
-                if n.is_f64() && m.is_f64() {
-                    n.as_f64().unwrap() < m.as_f64().unwrap()
-                } else if n.is_i64() && m.is_i64() {
-                    n.as_i64().unwrap() < m.as_i64().unwrap()
-                } else {
-                    false
-                }
+                compare_numbers(n, m) == Some(Ordering::Less)
             }
             (FinalType::String(s), FinalType::String(t)) => s < t,
             (FinalType::Bool(b), FinalType::Bool(c)) => b < c,
@@ -80,13 +164,7 @@ impl FinalType {
     pub fn greater_than(&self, other: &FinalType) -> bool {
         match (self, other) {
             (FinalType::Number(n), FinalType::Number(m)) => {
-                if n.is_f64() && m.is_f64() {
-                    n.as_f64().unwrap() > m.as_f64().unwrap()
-                } else if n.is_i64() && m.is_i64() {
-                    n.as_i64().unwrap() > m.as_i64().unwrap()
-                } else {
-                    false
-                }
+                compare_numbers(n, m) == Some(Ordering::Greater)
             }
             (FinalType::String(s), FinalType::String(t)) => s > t,
             (FinalType::Bool(b), FinalType::Bool(c)) => b > c,
@@ -105,13 +183,35 @@ impl FinalType {
     }
 }
 
+impl Operator {
+    /// Comparison and quantifier of a quantified operator: `(comparison, true)` for `ALL`,
+    /// `(comparison, false)` for `ANY`. `None` for the other operators.
+    pub fn quantified(&self) -> Option<(Operator, bool)> {
+        match self {
+            Operator::LessThanAny => Some((Operator::LessThan, false)),
+            Operator::GreaterThanAny => Some((Operator::GreaterThan, false)),
+            Operator::LessThanOrEqualAny => Some((Operator::LessThanOrEqual, false)),
+            Operator::GreaterThanOrEqualAny => Some((Operator::GreaterThanOrEqual, false)),
+            Operator::LessThanAll => Some((Operator::LessThan, true)),
+            Operator::GreaterThanAll => Some((Operator::GreaterThan, true)),
+            Operator::LessThanOrEqualAll => Some((Operator::LessThanOrEqual, true)),
+            Operator::GreaterThanOrEqualAll => Some((Operator::GreaterThanOrEqual, true)),
+            _ => None,
+        }
+    }
+}
+
 impl ConstraintValue {
     /// Compare a constraint value with a final type (a constraint value can be a list of final types)
     /// NOTE : assume that the ConstraintValue is always on the right side of the comparison
     /// (for instance with the operator IN)
+    ///
+    /// Lists mixing value types, and values incompatible with the operator, never match,
+    /// like they are rejected by validation.
     pub fn compare(&self, other: &FinalType, operator: &Operator) -> bool {
         match self {
-            ConstraintValue::Final(final_type) => final_type.compare(other, operator),
+            ConstraintValue::Final(final_type) => other.compare(final_type, operator),
+            ConstraintValue::List(_) if !self.is_homogeneous() => false,
             ConstraintValue::List(list) => match operator {
                 Operator::In => {
                     for value in list {
@@ -121,8 +221,60 @@ impl ConstraintValue {
                     }
                     false
                 }
-                _ => panic!("Invalid operator {} for list comparison", operator),
+                // An empty list matches every value
+                Operator::NotIn => !self.compare(other, &Operator::In),
+                // An empty list matches every value with `ALL`, and none with `ANY`
+                _ => match operator.quantified() {
+                    Some((comparison, true)) => {
+                        list.iter().all(|value| other.compare(value, &comparison))
+                    }
+                    Some((comparison, false)) => {
+                        list.iter().any(|value| other.compare(value, &comparison))
+                    }
+                    None => false,
+                },
+            },
+            // Columns are resolved against an object, and JSON values are only
+            // compared by containment: they do not match as plain values
+            ConstraintValue::Column { .. } | ConstraintValue::Json(_) => false,
+        }
+    }
+
+    /// Whether all the elements of a list have the same type (all numbers, all strings...).
+    /// Always true for other values.
+    pub fn is_homogeneous(&self) -> bool {
+        match self {
+            ConstraintValue::List(list) => list
+                .windows(2)
+                .all(|pair| std::mem::discriminant(&pair[0]) == std::mem::discriminant(&pair[1])),
+            _ => true,
+        }
+    }
+
+    /// Convert the constraint value to the JSON value it was deserialized from
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("Failed to serialize constraint value")
+    }
+}
+
+impl ScalarFunction {
+    /// Evaluate the function on a JSON value, like the database does. Values of other
+    /// types than the function expects evaluate to `null`, which matches no comparison.
+    pub fn apply(&self, value: &serde_json::Value) -> serde_json::Value {
+        use serde_json::Value;
+
+        match (self, value) {
+            (ScalarFunction::Length, Value::String(string)) => string.chars().count().into(),
+            (ScalarFunction::Lower, Value::String(string)) => string.to_lowercase().into(),
+            (ScalarFunction::Upper, Value::String(string)) => string.to_uppercase().into(),
+            (ScalarFunction::Abs, Value::Number(number)) => match number.as_i64() {
+                Some(integer) => integer.unsigned_abs().into(),
+                None if number.is_u64() => Value::Number(number.clone()),
+                None => number
+                    .as_f64()
+                    .map_or(Value::Null, |float| float.abs().into()),
             },
+            _ => Value::Null,
         }
     }
 }
@@ -131,32 +283,145 @@ impl ConstraintValue {
 //                       CHECKS AGAINST JSON OBJECT                          //
 // ************************************************************************* //
 
+/// `like` and `ilike` patterns of a query, compiled once.
+/// Patterns missing from the cache (such as patterns read from another column of
+/// the row) are matched without compilation.
+#[derive(Debug, Clone, Default)]
+pub struct PatternCache {
+    like: HashMap<String, LikePattern>,
+    ilike: HashMap<String, ILikePattern>,
+}
+
+impl PatternCache {
+    /// Compile the constant patterns of a query
+    pub fn new(query: &QueryTree) -> Self {
+        let mut cache = PatternCache::default();
+        if let Some(condition) = &query.condition {
+            cache.collect(condition);
+        }
+        cache
+    }
+
+    /// Compile the constant patterns of a condition, recursively.
+    /// Subqueries are skipped, as they are never evaluated locally.
+    fn collect(&mut self, condition: &Condition) {
+        match condition {
+            Condition::Single { constraint } => {
+                if let ConstraintValue::Final(FinalType::String(pattern)) = &constraint.value {
+                    match constraint.operator {
+                        Operator::Like => {
+                            self.like
+                                .entry(pattern.clone())
+                                .or_insert_with(|| LikePattern::new(pattern));
+                        }
+                        Operator::ILike => {
+                            self.ilike
+                                .entry(pattern.clone())
+                                .or_insert_with(|| ILikePattern::new(pattern));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Condition::And { conditions } | Condition::Or { conditions } => conditions
+                .iter()
+                .for_each(|condition| self.collect(condition)),
+            Condition::Not { condition } => self.collect(condition),
+            Condition::Exists { .. } => {}
+        }
+    }
+
+    /// Match a value against a pattern, compiled if it is cached
+    fn matches(&self, operator: &Operator, pattern: &str, value: &str) -> bool {
+        match operator {
+            Operator::Like => match self.like.get(pattern) {
+                Some(compiled) => compiled.matches(value),
+                None => sql_like(pattern, value),
+            },
+            Operator::ILike => match self.ilike.get(pattern) {
+                Some(compiled) => compiled.matches(value),
+                None => sql_ilike(pattern, value),
+            },
+            _ => panic!("Invalid operator {} for pattern matching", operator),
+        }
+    }
+}
+
 pub trait Checkable {
-    fn check(&self, object: &JsonObject) -> bool;
+    /// Check against a JSON object, using the compiled patterns of the cache
+    fn check_with(&self, object: &JsonObject, patterns: &PatternCache) -> bool;
+
+    /// Check against a JSON object, compiling no pattern
+    fn check(&self, object: &JsonObject) -> bool {
+        self.check_with(object, &PatternCache::default())
+    }
 }
 
 impl Checkable for Constraint {
-    /// Check if a constraint is satisfied by a JSON object
-    fn check(&self, object: &JsonObject) -> bool {
-        let value = object
-            .get(&self.column)
-            .expect("Column not found in JSON object");
+    /// Check if a constraint is satisfied by a JSON object.
+    /// Columns missing from the object (such as projected out or partial rows), and
+    /// values that cannot be compared (arrays or objects), do not match.
+    fn check_with(&self, object: &JsonObject, patterns: &PatternCache) -> bool {
+        let Some(value) = object.get(&self.column) else {
+            return false;
+        };
+        let applied;
+        let value = match &self.function {
+            Some(function) => {
+                applied = function.apply(value);
+                &applied
+            }
+            None => value,
+        };
+
+        // Containment is checked on the JSON values, which can be arrays or objects
+        if let Operator::Contains = self.operator {
+            let other = match &self.value {
+                ConstraintValue::Column { column } => match object.get(column) {
+                    Some(other) => other.clone(),
+                    None => return false,
+                },
+                value => value.to_json(),
+            };
+
+            return json_contains(value, &other);
+        }
+
+        let Ok(final_type) = FinalType::try_from(value.clone()) else {
+            return false;
+        };
 
-        let final_type = FinalType::try_from(value.clone())
-            .expect(format!("Incompatible value for column: {value}").as_str());
+        match &self.value {
+            // The right side of the comparison is read from the same object
+            ConstraintValue::Column { column } => {
+                let Some(Ok(other_type)) = object.get(column).cloned().map(FinalType::try_from)
+                else {
+                    return false;
+                };
 
-        self.value.compare(&final_type, &self.operator)
+                final_type.compare(&other_type, &self.operator)
+            }
+            ConstraintValue::Final(FinalType::String(pattern))
+                if matches!(self.operator, Operator::Like | Operator::ILike) =>
+            {
+                match &final_type {
+                    FinalType::String(value) => patterns.matches(&self.operator, pattern, value),
+                    _ => false,
+                }
+            }
+            value => value.compare(&final_type, &self.operator),
+        }
     }
 }
 
 impl Checkable for Condition {
     /// Check if a condition is satisfied by a JSON object
-    fn check(&self, object: &JsonObject) -> bool {
+    fn check_with(&self, object: &JsonObject, patterns: &PatternCache) -> bool {
         match self {
-            Condition::Single { constraint } => constraint.check(object),
+            Condition::Single { constraint } => constraint.check_with(object, patterns),
             Condition::And { conditions } => {
                 for condition in conditions {
-                    if !condition.check(object) {
+                    if !condition.check_with(object, patterns) {
                         return false;
                     }
                 }
@@ -164,23 +429,349 @@ impl Checkable for Condition {
             }
             Condition::Or { conditions } => {
                 for condition in conditions {
-                    if condition.check(object) {
+                    if condition.check_with(object, patterns) {
                         return true;
                     }
                 }
                 false
             }
+            // Unlike SQL, where `NOT` of an unknown comparison (with NULL) is still
+            // unknown, rows that do not match the negated condition always match
+            Condition::Not { condition } => !condition.check_with(object, patterns),
+            // The rows of the subquery table are unknown here: queries using correlated
+            // subqueries are not evaluated locally, but refetched instead
+            Condition::Exists { .. } => false,
         }
     }
 }
 
 impl Checkable for QueryTree {
     /// Check if a query is satisfied by a JSON object
-    fn check(&self, object: &JsonObject) -> bool {
+    fn check_with(&self, object: &JsonObject, patterns: &PatternCache) -> bool {
         if let Some(condition) = &self.condition {
-            condition.check(object)
+            condition.check_with(object, patterns)
         } else {
             true
         }
     }
 }
+
+impl DbKind {
+    /// Whether NULL values are sorted before all other values in ascending order.
+    /// Descending order reverses the placement on all backends.
+    fn nulls_first(&self) -> bool {
+        match self {
+            DbKind::Sqlite | DbKind::MySql => true,
+            DbKind::Postgres => false,
+        }
+    }
+
+    /// Compare two strings with the default collation of the backend:
+    /// - SQLite: `BINARY` (byte order)
+    /// - PostgreSQL: byte order, which matches the `C` collation only
+    /// - MySQL: case-insensitive, approximating `utf8mb4_0900_ai_ci` (accents are not folded)
+    fn collate(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            DbKind::Sqlite | DbKind::Postgres => a.cmp(b),
+            DbKind::MySql => a.to_lowercase().cmp(&b.to_lowercase()),
+        }
+    }
+
+    /// Compare two JSON values in an `ORDER BY` clause, in ascending order
+    fn compare_values(&self, a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+        use serde_json::Value;
+
+        // Booleans are sorted as integers, and numbers before strings
+        fn rank(value: &Value) -> u8 {
+            match value {
+                Value::Null => 0,
+                Value::Bool(_) | Value::Number(_) => 1,
+                _ => 2,
+            }
+        }
+
+        fn as_number(value: &Value) -> Option<Number> {
+            match value {
+                Value::Bool(b) => Some((*b as i64).into()),
+                Value::Number(n) => Some(n.clone()),
+                _ => None,
+            }
+        }
+
+        match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) if self.nulls_first() => Ordering::Less,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) if self.nulls_first() => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (Value::String(a), Value::String(b)) => self.collate(a, b),
+            _ => match (as_number(a), as_number(b)) {
+                (Some(a), Some(b)) => compare_numbers(&a, &b).unwrap_or(Ordering::Equal),
+                _ => rank(a).cmp(&rank(b)),
+            },
+        }
+    }
+}
+
+// ************************************************************************* //
+//                   OPERATOR / VALUE TYPE COMPATIBILITY                     //
+// ************************************************************************* //
+
+impl Constraint {
+    /// Check that the constraint value is compatible with its operator, so that
+    /// mismatches are reported instead of silently never matching.
+    pub fn validate(&self) -> Result<(), DeserializeError> {
+        let compatible = match (&self.operator, &self.value) {
+            (Operator::Contains, _) if self.function.is_some() => {
+                Err("a column without scalar function")
+            }
+            // Lists are compared element by element, which requires a single type
+            (operator, ConstraintValue::List(_))
+                if (matches!(operator, Operator::In | Operator::NotIn)
+                    || operator.quantified().is_some())
+                    && !self.value.is_homogeneous() =>
+            {
+                Err("a list of values of the same type")
+            }
+            (operator, ConstraintValue::List(_)) if operator.quantified().is_some() => Ok(()),
+            (operator, _) if operator.quantified().is_some() => Err("a list value"),
+            // The range bounds are computed from a constant prefix
+            (Operator::PrefixMatch, ConstraintValue::Final(FinalType::String(_))) => Ok(()),
+            (Operator::PrefixMatch, _) => Err("a string value"),
+            // Any value can be compared with another column
+            (_, ConstraintValue::Column { .. }) => Ok(()),
+            (Operator::Contains, _) => Ok(()),
+            (Operator::In | Operator::NotIn, ConstraintValue::List(_)) => Ok(()),
+            (Operator::In | Operator::NotIn, _) => Err("a list value"),
+            (
+                Operator::Like | Operator::ILike | Operator::IEqual,
+                ConstraintValue::Final(FinalType::String(_)),
+            ) => Ok(()),
+            (Operator::Like | Operator::ILike | Operator::IEqual, _) => Err("a string value"),
+            (_, ConstraintValue::Final(_)) => Ok(()),
+            (_, _) => Err("a single value"),
+        };
+
+        compatible.map_err(|expected| DeserializeError::IncompatibleConstraint {
+            column: self.column.clone(),
+            operator: self.operator.clone(),
+            expected,
+        })
+    }
+}
+
+impl Condition {
+    /// Check that all the constraints of the condition are valid
+    pub fn validate(&self) -> Result<(), DeserializeError> {
+        match self {
+            Condition::Single { constraint } => constraint.validate(),
+            Condition::And { conditions } | Condition::Or { conditions } => {
+                conditions.iter().try_for_each(Condition::validate)
+            }
+            Condition::Not { condition } => condition.validate(),
+            Condition::Exists { subquery, .. } => subquery.validate(),
+        }
+    }
+
+    /// Collect the correlated subqueries of the condition, without their own subqueries
+    fn collect_subqueries<'a>(&'a self, subqueries: &mut Vec<&'a QueryTree>) {
+        match self {
+            Condition::Single { .. } => {}
+            Condition::And { conditions } | Condition::Or { conditions } => conditions
+                .iter()
+                .for_each(|condition| condition.collect_subqueries(subqueries)),
+            Condition::Not { condition } => condition.collect_subqueries(subqueries),
+            Condition::Exists { subquery, .. } => subqueries.push(subquery),
+        }
+    }
+
+    /// Collect the tables of the correlated subqueries of the condition, recursively
+    fn collect_subquery_tables<'a>(&'a self, tables: &mut Vec<&'a str>) {
+        match self {
+            Condition::Single { .. } => {}
+            Condition::And { conditions } | Condition::Or { conditions } => conditions
+                .iter()
+                .for_each(|condition| condition.collect_subquery_tables(tables)),
+            Condition::Not { condition } => condition.collect_subquery_tables(tables),
+            Condition::Exists { subquery, .. } => {
+                tables.push(&subquery.table);
+                if let Some(condition) = &subquery.condition {
+                    condition.collect_subquery_tables(tables);
+                }
+            }
+        }
+    }
+}
+
+impl OrderBy {
+    /// Ordering column, and whether the order is descending
+    pub fn ordering(&self) -> (&str, bool) {
+        match self {
+            OrderBy::Asc(column) => (column, false),
+            OrderBy::Desc(column) => (column, true),
+        }
+    }
+}
+
+impl PaginateOptions {
+    /// Ordering column of the pagination, and whether the order is descending.
+    /// By default, paginated queries are ordered by ID descending.
+    pub fn ordering(&self) -> (&str, bool) {
+        self.order_by
+            .as_ref()
+            .map_or(("id", true), OrderBy::ordering)
+    }
+}
+
+impl QueryTree {
+    /// Query of a single row by primary key
+    pub fn by_id(table: &str, id: FinalType) -> Self {
+        QueryTree {
+            return_type: ReturnType::Single,
+            table: table.to_string(),
+            condition: Some(Condition::Single {
+                constraint: Constraint {
+                    column: "id".to_string(),
+                    function: None,
+                    operator: Operator::Equal,
+                    value: ConstraintValue::Final(id),
+                },
+            }),
+            paginate: None,
+            distinct_on: vec![],
+            order_by: None,
+        }
+    }
+
+    /// Check that the constraints of the query condition are valid.
+    /// To be called on deserialized queries before using them.
+    pub fn validate(&self) -> Result<(), DeserializeError> {
+        self.condition.as_ref().map_or(Ok(()), Condition::validate)
+    }
+
+    /// Ordering column of the rows of the query, and whether the order is descending.
+    /// Pagination options take precedence over the ordering of the query.
+    pub fn ordering(&self) -> Option<(&str, bool)> {
+        match (&self.paginate, &self.order_by) {
+            (Some(paginate), _) => Some(paginate.ordering()),
+            (None, Some(order_by)) => Some(order_by.ordering()),
+            (None, None) => None,
+        }
+    }
+
+    /// Apply a default ordering to a `Many` query without pagination options nor
+    /// ordering of its own, so that its rows are returned in a deterministic order
+    pub fn with_default_order(mut self, order_by: &OrderBy) -> Self {
+        if matches!(self.return_type, ReturnType::Many) && self.ordering().is_none() {
+            self.order_by = Some(order_by.clone());
+        }
+        self
+    }
+
+    /// Simulate the query on a list of rows: filter them with the query condition,
+    /// then sort and paginate them like the given database backend would.
+    /// Rows are kept in their input order when the query has no ordering,
+    /// and a single-row query returns at most one row.
+    pub fn apply(&self, kind: DbKind, rows: Vec<JsonObject>) -> Vec<JsonObject> {
+        let mut rows: Vec<JsonObject> = rows.into_iter().filter(|row| self.check(row)).collect();
+
+        if let Some((column, descending)) = self.ordering() {
+            // Keyset pagination: keep the rows strictly after the cursor
+            if let Some(cursor) = self
+                .paginate
+                .as_ref()
+                .and_then(|paginate| paginate.cursor.as_ref())
+            {
+                let cursor = serde_json::to_value(cursor).unwrap();
+                let expected = if descending {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                };
+
+                rows.retain(|row| {
+                    let value = row.get(column).unwrap_or(&serde_json::Value::Null);
+                    !value.is_null() && kind.compare_values(value, &cursor) == expected
+                });
+            }
+
+            rows.sort_by(|a, b| {
+                let a = a.get(column).unwrap_or(&serde_json::Value::Null);
+                let b = b.get(column).unwrap_or(&serde_json::Value::Null);
+                let ordering = kind.compare_values(a, b);
+
+                if descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            });
+        }
+
+        if let Some(paginate) = &self.paginate {
+            let offset = paginate.offset.unwrap_or(0) as usize;
+            rows = rows
+                .into_iter()
+                .skip(offset)
+                .take(paginate.per_page as usize)
+                .collect();
+        }
+
+        if let ReturnType::Single = self.return_type {
+            rows.truncate(1);
+        }
+
+        rows
+    }
+
+    /// Whether operation notifications can be checked against the query locally.
+    /// Queries using `DISTINCT ON` depend on the other rows of each group, and queries
+    /// using correlated subqueries depend on the rows of other tables,
+    /// so their subscribers must refetch them instead.
+    pub fn is_locally_evaluable(&self) -> bool {
+        self.distinct_on.is_empty() && self.subquery_tables().is_empty()
+    }
+
+    /// Correlated subqueries of the condition of the query (`exists` conditions),
+    /// without the subqueries nested in them
+    pub fn subqueries(&self) -> Vec<&QueryTree> {
+        let mut subqueries = vec![];
+        if let Some(condition) = &self.condition {
+            condition.collect_subqueries(&mut subqueries);
+        }
+        subqueries
+    }
+
+    /// Tables of the correlated subqueries of the query (`exists` conditions).
+    /// Operations on these tables can change the results of the query.
+    pub fn subquery_tables(&self) -> Vec<&str> {
+        let mut tables = vec![];
+        if let Some(condition) = &self.condition {
+            condition.collect_subquery_tables(&mut tables);
+        }
+        tables
+    }
+
+    /// Deterministic serialization of the query, for use as a cache or subscription key.
+    /// Object keys are sorted and absent optional fields are serialized as explicit nulls,
+    /// so that structurally identical queries always produce the same key.
+    pub fn canonical_key(&self) -> String {
+        let value = serde_json::to_value(self).expect("Failed to serialize query");
+        sort_json_keys(value).to_string()
+    }
+}
+
+/// Queries are equal when their canonical keys are equal
+impl PartialEq for QueryTree {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical_key() == other.canonical_key()
+    }
+}
+
+impl Eq for QueryTree {}
+
+impl Hash for QueryTree {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_key().hash(state);
+    }
+}
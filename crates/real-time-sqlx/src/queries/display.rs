@@ -6,22 +6,22 @@ use crate::utils::format_list;
 
 use super::serialize::{
     Condition, Constraint, ConstraintValue, FinalType, Operator, OrderBy, PaginateOptions,
-    QueryTree,
+    QueryTree, ScalarFunction,
 };
 
 impl fmt::Display for FinalType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FinalType::Number(number) => {
-                if number.is_f64() {
-                    write!(f, "{}", number.as_f64().unwrap())
-                } else {
-                    write!(f, "{}", number.as_i64().unwrap())
-                }
-            }
+            FinalType::Number(number) => write!(f, "{}", number),
             FinalType::String(string) => write!(f, "'{string}'"),
             FinalType::Bool(bool) => write!(f, "{}", if *bool { 1 } else { 0 }),
             FinalType::Null => write!(f, "NULL"),
+            FinalType::Array(list) => write!(f, "ARRAY[{}]", format_list(list, ", ")),
+            FinalType::Binary(bytes) => {
+                write!(f, "X'")?;
+                bytes.iter().try_for_each(|byte| write!(f, "{byte:02X}"))?;
+                write!(f, "'")
+            }
         }
     }
 }
@@ -31,8 +31,10 @@ impl fmt::Display for ConstraintValue {
         match self {
             ConstraintValue::Final(value) => write!(f, "{}", value),
             ConstraintValue::List(list) => {
-                write!(f, "{}", format_list(&list, ", "))
+                write!(f, "{}", format_list(list, ", "))
             }
+            ConstraintValue::Column { column } => write!(f, "\"{}\"", column),
+            ConstraintValue::Json(value) => write!(f, "'{value}'"),
         }
     }
 }
@@ -47,15 +49,45 @@ impl fmt::Display for Operator {
             Operator::GreaterThanOrEqual => write!(f, ">="),
             Operator::NotEqual => write!(f, "!="),
             Operator::In => write!(f, "in"),
+            Operator::NotIn => write!(f, "not in"),
             Operator::Like => write!(f, "like"),
             Operator::ILike => write!(f, "ilike"),
+            Operator::IEqual => write!(f, "iequal"),
+            Operator::Contains => write!(f, "@>"),
+            Operator::PrefixMatch => write!(f, "prefix"),
+            Operator::LessThanAny => write!(f, "< any"),
+            Operator::GreaterThanAny => write!(f, "> any"),
+            Operator::LessThanOrEqualAny => write!(f, "<= any"),
+            Operator::GreaterThanOrEqualAny => write!(f, ">= any"),
+            Operator::LessThanAll => write!(f, "< all"),
+            Operator::GreaterThanAll => write!(f, "> all"),
+            Operator::LessThanOrEqualAll => write!(f, "<= all"),
+            Operator::GreaterThanOrEqualAll => write!(f, ">= all"),
+        }
+    }
+}
+
+impl fmt::Display for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScalarFunction::Length => write!(f, "LENGTH"),
+            ScalarFunction::Lower => write!(f, "LOWER"),
+            ScalarFunction::Upper => write!(f, "UPPER"),
+            ScalarFunction::Abs => write!(f, "ABS"),
         }
     }
 }
 
 impl fmt::Display for Constraint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "\"{}\" {} {}", self.column, self.operator, self.value)
+        match &self.function {
+            Some(function) => write!(
+                f,
+                "{}(\"{}\") {} {}",
+                function, self.column, self.operator, self.value
+            ),
+            None => write!(f, "\"{}\" {} {}", self.column, self.operator, self.value),
+        }
     }
 }
 
@@ -64,10 +96,25 @@ impl fmt::Display for Condition {
         match self {
             Condition::Single { constraint } => write!(f, "{}", constraint),
             Condition::Or { conditions } => {
-                write!(f, "({})", format_list(&conditions, " OR "))
+                write!(f, "({})", format_list(conditions, " OR "))
             }
             Condition::And { conditions } => {
-                write!(f, "({})", format_list(&conditions, " AND "))
+                write!(f, "({})", format_list(conditions, " AND "))
+            }
+            Condition::Not { condition } => write!(f, "NOT ({})", condition),
+            Condition::Exists {
+                subquery,
+                correlate_on: (inner, outer),
+            } => {
+                let table = &subquery.table;
+                write!(
+                    f,
+                    "EXISTS (SELECT 1 FROM {table} WHERE {table}.{inner} = {outer}"
+                )?;
+                if let Some(condition) = &subquery.condition {
+                    write!(f, " AND {}", condition)?;
+                }
+                write!(f, ")")
             }
         }
     }
@@ -3,7 +3,7 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Number;
 
-use crate::error::DeserializeError;
+use crate::{database::encode_binary, error::DeserializeError, utils::deserialize_at};
 
 /// Query final constraint value (ie "native" types)
 /// Prevents recursive lists of values
@@ -14,6 +14,19 @@ pub enum FinalType {
     String(String),
     Bool(bool),
     Null,
+    /// List of values bound as a single array parameter, on PostgreSQL only.
+    /// Produced by the query traversal, never deserialized.
+    #[serde(skip_deserializing)]
+    Array(Vec<FinalType>),
+    /// Bytes compared to a binary column, serialized as a base64 string.
+    /// Produced from base64 strings by `decode_binary_constraints`, never deserialized.
+    #[serde(skip_deserializing, serialize_with = "serialize_binary")]
+    Binary(Vec<u8>),
+}
+
+/// Serialize bytes as a base64 string, like binary columns
+fn serialize_binary<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode_binary(bytes))
 }
 
 /// For binding values to queries, JSON values must be converted to native types
@@ -38,10 +51,16 @@ impl TryFrom<serde_json::Value> for FinalType {
 pub enum ConstraintValue {
     Final(FinalType),
     List(Vec<FinalType>),
+    /// Reference to another column of the same row, serialized as `{ "column": "name" }`
+    Column {
+        column: String,
+    },
+    /// Arbitrary JSON value (object or nested array), for containment constraints
+    Json(serde_json::Value),
 }
 
 /// Constraint operator
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Operator {
     #[serde(rename = "=")]
     Equal,
@@ -57,23 +76,80 @@ pub enum Operator {
     NotEqual,
     #[serde(rename = "in")]
     In,
+    #[serde(rename = "not_in")]
+    NotIn,
     #[serde(rename = "like")]
     Like,
     #[serde(rename = "ilike")]
     ILike,
+    /// Case-insensitive equality, for lookups on columns storing values in their
+    /// original case (`LOWER(column) = LOWER(?)`). Unlike `ilike`, the value has
+    /// no wildcards.
+    #[serde(rename = "iequal")]
+    IEqual,
+    /// JSON / array containment (PostgreSQL `@>`): the column holds all the elements
+    /// or key-value pairs of the value. Substrings are matched with `like` instead.
+    #[serde(rename = "contains")]
+    Contains,
+    /// Strings starting with a prefix, compiled to a half-open range
+    /// (`column >= 'ab' AND column < 'ac'`) that can use the indexes of the column
+    #[serde(rename = "prefix")]
+    PrefixMatch,
+    /// Quantified comparisons with the values of a list: the column compares to
+    /// any of them (`> ANY`), or to all of them (`> ALL`)
+    #[serde(rename = "<any")]
+    LessThanAny,
+    #[serde(rename = ">any")]
+    GreaterThanAny,
+    #[serde(rename = "<=any")]
+    LessThanOrEqualAny,
+    #[serde(rename = ">=any")]
+    GreaterThanOrEqualAny,
+    #[serde(rename = "<all")]
+    LessThanAll,
+    #[serde(rename = ">all")]
+    GreaterThanAll,
+    #[serde(rename = "<=all")]
+    LessThanOrEqualAll,
+    #[serde(rename = ">=all")]
+    GreaterThanOrEqualAll,
+}
+
+/// Whitelisted scalar function, applied to the column of a constraint
+/// before the comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScalarFunction {
+    /// Number of characters of a string
+    #[serde(rename = "length")]
+    Length,
+    #[serde(rename = "lower")]
+    Lower,
+    #[serde(rename = "upper")]
+    Upper,
+    /// Absolute value of a number
+    #[serde(rename = "abs")]
+    Abs,
 }
 
 /// Query constraint
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Constraint {
     pub column: String,
+    /// Optional scalar function applied to the column, serialized as `fn`
+    #[serde(rename = "fn", default, skip_serializing_if = "Option::is_none")]
+    pub function: Option<ScalarFunction>,
     pub operator: Operator,
     pub value: ConstraintValue,
 }
 
 /// Query condition (contains constraints)
+///
+/// Besides the canonical tagged form, conditions can be deserialized from the
+/// `{ "anyOf": [...] }` and `{ "allOf": [...] }` shorthands holding a flat list of
+/// constraints, which are expanded into an `Or` / `And` of `Single` conditions.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+#[serde(tag = "type", from = "ConditionShorthand")]
 pub enum Condition {
     #[serde(rename = "and")]
     And { conditions: Vec<Condition> },
@@ -81,6 +157,103 @@ pub enum Condition {
     Or { conditions: Vec<Condition> },
     #[serde(rename = "single")]
     Single { constraint: Constraint },
+    /// Negation of a condition: rows that do not satisfy it
+    #[serde(rename = "not")]
+    Not { condition: Box<Condition> },
+    /// Correlated subquery: rows for which the subquery on another table has at least
+    /// one row whose first `correlateOn` column equals the second column of the row.
+    /// Only the table and condition of the subquery are used.
+    #[serde(rename = "exists")]
+    Exists {
+        subquery: Box<QueryTree>,
+        #[serde(rename = "correlateOn")]
+        correlate_on: (String, String),
+    },
+}
+
+impl Condition {
+    /// Build an `Or` condition from a flat list of constraints
+    pub fn any_of(constraints: Vec<Constraint>) -> Self {
+        Condition::Or {
+            conditions: constraints
+                .into_iter()
+                .map(|constraint| Condition::Single { constraint })
+                .collect(),
+        }
+    }
+
+    /// Build an `And` condition from a flat list of constraints
+    pub fn all_of(constraints: Vec<Constraint>) -> Self {
+        Condition::And {
+            conditions: constraints
+                .into_iter()
+                .map(|constraint| Condition::Single { constraint })
+                .collect(),
+        }
+    }
+}
+
+/// Canonical tagged form of a deserialized condition
+#[derive(Deserialize)]
+#[serde(tag = "type", deny_unknown_fields)]
+enum CanonicalCondition {
+    #[serde(rename = "and")]
+    And { conditions: Vec<Condition> },
+    #[serde(rename = "or")]
+    Or { conditions: Vec<Condition> },
+    #[serde(rename = "single")]
+    Single { constraint: Constraint },
+    #[serde(rename = "not")]
+    Not { condition: Box<Condition> },
+    #[serde(rename = "exists")]
+    Exists {
+        subquery: Box<QueryTree>,
+        #[serde(rename = "correlateOn")]
+        correlate_on: (String, String),
+    },
+}
+
+/// Accepted condition forms for deserialization: shorthands or canonical form
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum ConditionShorthand {
+    AnyOf {
+        #[serde(rename = "anyOf")]
+        any_of: Vec<Constraint>,
+    },
+    AllOf {
+        #[serde(rename = "allOf")]
+        all_of: Vec<Constraint>,
+    },
+    Canonical(CanonicalCondition),
+}
+
+impl From<ConditionShorthand> for Condition {
+    fn from(value: ConditionShorthand) -> Self {
+        match value {
+            ConditionShorthand::AnyOf { any_of } => Condition::any_of(any_of),
+            ConditionShorthand::AllOf { all_of } => Condition::all_of(all_of),
+            ConditionShorthand::Canonical(CanonicalCondition::And { conditions }) => {
+                Condition::And { conditions }
+            }
+            ConditionShorthand::Canonical(CanonicalCondition::Or { conditions }) => {
+                Condition::Or { conditions }
+            }
+            ConditionShorthand::Canonical(CanonicalCondition::Single { constraint }) => {
+                Condition::Single { constraint }
+            }
+            ConditionShorthand::Canonical(CanonicalCondition::Not { condition }) => {
+                Condition::Not { condition }
+            }
+            ConditionShorthand::Canonical(CanonicalCondition::Exists {
+                subquery,
+                correlate_on,
+            }) => Condition::Exists {
+                subquery,
+                correlate_on,
+            },
+        }
+    }
 }
 
 /// Query return type (single row vs multiple rows)
@@ -104,22 +277,133 @@ pub enum OrderBy {
 
 /// Pagination options
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PaginateOptions {
     #[serde(rename = "perPage")]
     pub per_page: u64,
     pub offset: Option<u64>,
     #[serde(rename = "orderBy")]
     pub order_by: Option<OrderBy>,
+    /// Keyset pagination: only return the rows strictly after this value of the
+    /// ordering column. The ordering column should be unique for pages not to skip rows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<FinalType>,
 }
 
-/// Final serialized query tree
+/// Final serialized query tree.
+/// Unknown fields are rejected, so that a misspelled key (a `condition` typo matching
+/// every row) fails the deserialization instead of being silently dropped.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct QueryTree {
     #[serde(rename = "return")]
     pub return_type: ReturnType,
     pub table: String,
     pub condition: Option<Condition>,
     pub paginate: Option<PaginateOptions>,
+    /// PostgreSQL only: keep the first row of each group of rows sharing these columns
+    #[serde(rename = "distinctOn", default, skip_serializing_if = "Vec::is_empty")]
+    pub distinct_on: Vec<String>,
+    /// Ordering of the rows of a query without pagination options.
+    /// Without it, the rows are returned in the arbitrary order of the database.
+    #[serde(rename = "orderBy", default, skip_serializing_if = "Option::is_none")]
+    pub order_by: Option<OrderBy>,
+}
+
+/// Deserialize a query from a JSON value, naming the offending field
+/// (for instance an unknown one) in the error, along with the JSON pointer path
+/// of the deepest malformed node (such as `/condition/conditions/2/constraint/operator`)
+impl TryFrom<serde_json::Value> for QueryTree {
+    type Error = DeserializeError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match QueryTree::deserialize(&value) {
+            Ok(query) => Ok(query),
+            // Walk the query to locate the error, the root one being the fallback
+            Err(source) => {
+                Err(check_query(&value, "")
+                    .err()
+                    .unwrap_or(DeserializeError::Malformed {
+                        path: String::new(),
+                        source,
+                    }))
+            }
+        }
+    }
+}
+
+/// Present, non-null field of a JSON object node
+fn field<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    value.get(key).filter(|field| !field.is_null())
+}
+
+/// Check that a query node deserializes, its nested nodes first
+fn check_query(value: &serde_json::Value, path: &str) -> Result<(), DeserializeError> {
+    if let Some(condition) = field(value, "condition") {
+        check_condition(condition, &format!("{path}/condition"))?;
+    }
+    if let Some(paginate) = field(value, "paginate") {
+        if let Some(cursor) = field(paginate, "cursor") {
+            deserialize_at::<FinalType>(cursor, &format!("{path}/paginate/cursor"))?;
+        }
+        deserialize_at::<PaginateOptions>(paginate, &format!("{path}/paginate"))?;
+    }
+    deserialize_at::<QueryTree>(value, path).map(|_| ())
+}
+
+/// Check that a condition node deserializes, its nested nodes first
+fn check_condition(value: &serde_json::Value, path: &str) -> Result<(), DeserializeError> {
+    for shorthand in ["anyOf", "allOf"] {
+        if let Some(serde_json::Value::Array(constraints)) = field(value, shorthand) {
+            for (index, constraint) in constraints.iter().enumerate() {
+                check_constraint(constraint, &format!("{path}/{shorthand}/{index}"))?;
+            }
+        }
+    }
+
+    if let Some(condition_type) = field(value, "type") {
+        match condition_type.as_str() {
+            Some("and" | "or") => {
+                if let Some(serde_json::Value::Array(conditions)) = field(value, "conditions") {
+                    for (index, condition) in conditions.iter().enumerate() {
+                        check_condition(condition, &format!("{path}/conditions/{index}"))?;
+                    }
+                }
+            }
+            Some("single") => {
+                if let Some(constraint) = field(value, "constraint") {
+                    check_constraint(constraint, &format!("{path}/constraint"))?;
+                }
+            }
+            Some("exists") => {
+                if let Some(subquery) = field(value, "subquery") {
+                    check_query(subquery, &format!("{path}/subquery"))?;
+                }
+            }
+            _ => {}
+        }
+        // The canonical form reports errors more precisely than the untagged shorthands
+        deserialize_at::<CanonicalCondition>(value, path)?;
+    }
+
+    deserialize_at::<Condition>(value, path).map(|_| ())
+}
+
+/// Check that a constraint node deserializes, its fields first
+fn check_constraint(value: &serde_json::Value, path: &str) -> Result<(), DeserializeError> {
+    if let Some(column) = field(value, "column") {
+        deserialize_at::<String>(column, &format!("{path}/column"))?;
+    }
+    if let Some(function) = field(value, "fn") {
+        deserialize_at::<ScalarFunction>(function, &format!("{path}/fn"))?;
+    }
+    if let Some(operator) = field(value, "operator") {
+        deserialize_at::<Operator>(operator, &format!("{path}/operator"))?;
+    }
+    if let Some(constraint_value) = field(value, "value") {
+        deserialize_at::<ConstraintValue>(constraint_value, &format!("{path}/value"))?;
+    }
+    deserialize_at::<Constraint>(value, path).map(|_| ())
 }
 
 /// Returned query data
@@ -132,6 +416,26 @@ pub enum QueryData<D> {
     Many(Vec<D>),
 }
 
+/// A page of rows fetched with keyset pagination, with the cursor of the next page
+/// (`None` when there are no more rows)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<D> {
+    pub data: Vec<D>,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<FinalType>,
+}
+
+/// Rows of a `Many` query, returned in full when there are at most a threshold of them,
+/// or as their first keyset page otherwise
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdaptivePage<D> {
+    pub data: Vec<D>,
+    /// Whether the rows exceed the threshold, and only their first page was returned
+    pub paginated: bool,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<FinalType>,
+}
+
 /// Helper implementations for unwrapping query data
 impl<D> QueryData<D> {
     pub fn unwrap_single(self) -> D {
@@ -155,4 +459,25 @@ impl<D> QueryData<D> {
             QueryData::Many(data) => data,
         }
     }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        match self {
+            QueryData::Single(data) => data.is_some() as usize,
+            QueryData::Many(data) => data.len(),
+        }
+    }
+
+    /// Whether there is no row
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Map the inner data to another type, keeping the return type
+    pub fn map<E>(self, f: impl FnMut(D) -> E) -> QueryData<E> {
+        match self {
+            QueryData::Single(data) => QueryData::Single(data.map(f)),
+            QueryData::Many(data) => QueryData::Many(data.into_iter().map(f).collect()),
+        }
+    }
 }
@@ -0,0 +1,79 @@
+//! Per-connection rate limiting of incoming operations.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::error::RateLimitError;
+
+/// Token bucket of a single connection
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token bucket rate limiter, keyed by connection.
+///
+/// Each connection can perform bursts of up to `capacity` operations,
+/// and regains `refill_per_second` operations per second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter. Panics if the refill rate is not strictly positive.
+    pub fn new(capacity: u32, refill_per_second: f64) -> Self {
+        assert!(
+            refill_per_second > 0.0,
+            "The refill rate of a rate limiter must be positive"
+        );
+
+        RateLimiter {
+            capacity: capacity as f64,
+            refill_per_second,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Consume a token for an operation of the given connection.
+    /// Fails with the delay after which a token will be available if the bucket is empty.
+    pub fn check(&self, key: &str) -> Result<(), RateLimitError> {
+        self.check_at(key, Instant::now())
+    }
+
+    /// Consume a token for an operation of the given connection at a given instant
+    pub(crate) fn check_at(&self, key: &str, now: Instant) -> Result<(), RateLimitError> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+
+        // Refill the bucket with the tokens regained since the last operation
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * self.refill_per_second).min(self.capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(RateLimitError::Exceeded {
+                retry_after: Duration::from_secs_f64(missing / self.refill_per_second),
+            })
+        }
+    }
+
+    /// Forget the bucket of a connection, for instance when it closes
+    pub fn remove(&self, key: &str) {
+        self.buckets.lock().unwrap().remove(key);
+    }
+}
@@ -0,0 +1,133 @@
+//! Replay of recorded sessions, to reproduce the notifications received by subscriptions.
+//!
+//! A session is an ordered list of `ReplayEvent`s (subscriptions and operations), which
+//! serializes to JSON so that it can be attached to bug reports. Replaying it against a
+//! fresh database returns the messages each subscription would have received.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteRow, FromRow, SqlitePool};
+
+use crate::{
+    database::{serialize_rows, sqlite::fetch_sqlite_query, sqlite::granular_operation_sqlite},
+    dispatcher::{NotificationChannel, Subscription, TableSubscriptions},
+    error::ChannelError,
+    operations::serialize::{GranularOperation, OperationNotification, Tabled},
+    queries::serialize::QueryTree,
+    snapshots::Identifiable,
+};
+
+/// An event of a recorded session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ReplayEvent {
+    /// Subscription of a channel to a query
+    #[serde(rename = "subscribe")]
+    Subscribe {
+        #[serde(rename = "channelId")]
+        channel_id: String,
+        query: QueryTree,
+    },
+    /// Removal of the subscription of a channel
+    #[serde(rename = "unsubscribe")]
+    Unsubscribe {
+        #[serde(rename = "channelId")]
+        channel_id: String,
+    },
+    /// Database operation, whose notification is dispatched to the subscriptions
+    #[serde(rename = "operation")]
+    Operation { operation: GranularOperation },
+}
+
+/// Messages received by each channel of a replayed session, in order, starting with
+/// the initial snapshot of its query
+pub type ReplayedMessages = BTreeMap<String, Vec<serde_json::Value>>;
+
+/// Channel recording the messages it receives
+#[derive(Clone, Default)]
+struct RecordingChannel {
+    messages: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl RecordingChannel {
+    /// Record a message
+    fn record(&self, value: serde_json::Value) {
+        self.messages.lock().unwrap().push(value);
+    }
+}
+
+impl NotificationChannel for RecordingChannel {
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        self.record(value);
+        Ok(())
+    }
+}
+
+/// Replay a recorded session against a SQLite database, and return the messages
+/// each channel received. The database should be a fresh one (for instance in memory)
+/// with the schema, and the initial rows, of the session.
+///
+/// Rows are serialized through `T`, and operations that fail or produce no notification
+/// (such as updates of missing rows) are skipped, as with the Tauri commands.
+/// Unsubscribed channels keep the messages they received until then.
+pub async fn replay<T>(events: &[ReplayEvent], pool: &SqlitePool) -> ReplayedMessages
+where
+    T: for<'r> FromRow<'r, SqliteRow> + Identifiable + Clone,
+{
+    let mut tables: HashMap<String, TableSubscriptions<RecordingChannel>> = HashMap::new();
+    let mut channels: BTreeMap<String, RecordingChannel> = BTreeMap::new();
+
+    for event in events {
+        match event {
+            ReplayEvent::Subscribe { channel_id, query } => {
+                let channel = RecordingChannel::default();
+                let rows = fetch_sqlite_query(query, pool)
+                    .await
+                    .expect("Failed to fetch the query of a replayed subscription");
+                channel.record(serialize_rows::<T, _>(&rows));
+
+                tables
+                    .entry(query.table.clone())
+                    .or_default()
+                    .subscribe(
+                        channel_id,
+                        Subscription::new(query.clone(), channel.clone()),
+                    )
+                    .await
+                    .expect("Replayed subscriptions are never shut down");
+                channels.insert(channel_id.clone(), channel);
+            }
+            ReplayEvent::Unsubscribe { channel_id } => {
+                for subscriptions in tables.values() {
+                    subscriptions.unsubscribe(channel_id).await;
+                }
+            }
+            ReplayEvent::Operation { operation } => {
+                // Failed operations notify nothing, as with the Tauri commands
+                let notification: Option<OperationNotification<T>> =
+                    granular_operation_sqlite(operation.clone(), pool)
+                        .await
+                        .ok()
+                        .flatten();
+
+                if let (Some(notification), Some(subscriptions)) =
+                    (notification, tables.get(operation.get_table()))
+                {
+                    subscriptions.process(&notification).await;
+                }
+            }
+        }
+    }
+
+    channels
+        .into_iter()
+        .map(|(channel_id, channel)| {
+            let messages = channel.messages.lock().unwrap().clone();
+            (channel_id, messages)
+        })
+        .collect()
+}
@@ -0,0 +1,241 @@
+//! Differences between query snapshots, for clients that refetch full queries.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{
+    error::SnapshotError,
+    operations::serialize::JsonObject,
+    queries::serialize::{FinalType, QueryData},
+    utils::sort_json_keys,
+};
+
+/// Rows identified by a primary key, read from their serialized data, so that snapshot
+/// differences and operation notifications derive the keys of their rows from the rows
+/// themselves. The key is read from the `id` field by default.
+pub trait Identifiable: Serialize {
+    /// Name of the primary key column
+    fn key_column() -> &'static str {
+        "id"
+    }
+
+    /// Primary key of the row. Fails with `SnapshotError::MissingKey` if its key column
+    /// is missing (such as from a projected row) or does not hold a string or a number.
+    fn id(&self) -> Result<FinalType, SnapshotError> {
+        let row = serde_json::to_value(self).unwrap_or_default();
+        key_of(row.get(Self::key_column()), Self::key_column())
+    }
+}
+
+/// Read a primary key from the value of a key column
+fn key_of(value: Option<&serde_json::Value>, key_column: &str) -> Result<FinalType, SnapshotError> {
+    value
+        .filter(|value| value.is_string() || value.is_number())
+        .and_then(|value| FinalType::try_from(value.clone()).ok())
+        .ok_or_else(|| SnapshotError::MissingKey(key_column.to_string()))
+}
+
+/// Serialized rows are identified by their `id` field
+impl Identifiable for JsonObject {
+    fn id(&self) -> Result<FinalType, SnapshotError> {
+        key_of(self.get(Self::key_column()), Self::key_column())
+    }
+}
+
+/// Rows without model struct are identified by their `id` field
+impl Identifiable for serde_json::Value {}
+
+/// A change to apply to a cached snapshot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum CacheMutation<T> {
+    #[serde(rename = "insert")]
+    Insert { data: T },
+    #[serde(rename = "update")]
+    Update { id: FinalType, data: T },
+    #[serde(rename = "remove")]
+    Remove { id: FinalType },
+}
+
+/// Compute the changes turning an old snapshot into a new one, by primary key.
+/// Rows missing from the new snapshot are removed first, then the new and changed
+/// rows are inserted or updated in the order of the new snapshot.
+/// Unchanged rows produce no mutation.
+/// Fails with `SnapshotError::MissingKey` if a row of either snapshot has no primary key.
+pub fn diff_snapshots<T>(old: &[T], new: &[T]) -> Result<Vec<CacheMutation<T>>, SnapshotError>
+where
+    T: Identifiable + Clone + PartialEq,
+{
+    let old_ids = old
+        .iter()
+        .map(Identifiable::id)
+        .collect::<Result<Vec<_>, _>>()?;
+    let new_ids = new
+        .iter()
+        .map(Identifiable::id)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Keys are compared in their JSON form, as final types cannot be hashed
+    let json = |id: &FinalType| serde_json::to_string(id).unwrap_or_default();
+    let old_rows: HashMap<String, &T> = old_ids.iter().map(json).zip(old).collect();
+    let new_keys: HashSet<String> = new_ids.iter().map(json).collect();
+
+    let removed = old_ids
+        .iter()
+        .filter(|id| !new_keys.contains(&json(id)))
+        .map(|id| CacheMutation::Remove { id: id.clone() });
+
+    let changed = new_ids
+        .iter()
+        .zip(new)
+        .filter_map(|(id, row)| match old_rows.get(&json(id)) {
+            None => Some(CacheMutation::Insert { data: row.clone() }),
+            Some(old_row) if *old_row != row => Some(CacheMutation::Update {
+                id: id.clone(),
+                data: row.clone(),
+            }),
+            Some(_) => None,
+        });
+
+    Ok(removed.chain(changed).collect())
+}
+
+/// Convert a serialized `Many` snapshot into a `Keyed` one, whose rows are an object
+/// keyed by the value of a key column (`{ "type": "keyed", "data": { "1": {...} } }`),
+/// for clients merging them into a normalized store. String keys are used as is, and
+/// numbers and booleans in their JSON form. The order of the rows is not kept.
+/// Other snapshots are returned as is.
+///
+/// Fails if a row has no string, number or boolean key, or if two rows share a key.
+pub fn keyed_snapshot(
+    mut snapshot: serde_json::Value,
+    key_column: &str,
+) -> Result<serde_json::Value, SnapshotError> {
+    if snapshot["type"] != "many" {
+        return Ok(snapshot);
+    }
+    let rows = match snapshot["data"].take() {
+        serde_json::Value::Array(rows) => rows,
+        _ => vec![],
+    };
+
+    let mut keyed = JsonObject::new();
+    for row in rows {
+        let key = match row.get(key_column) {
+            Some(serde_json::Value::String(key)) => key.clone(),
+            Some(key @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_))) => {
+                key.to_string()
+            }
+            _ => return Err(SnapshotError::MissingKey(key_column.to_string())),
+        };
+        if keyed.contains_key(&key) {
+            return Err(SnapshotError::DuplicateKey(key));
+        }
+        keyed.insert(key, row);
+    }
+
+    snapshot["type"] = "keyed".into();
+    snapshot["data"] = serde_json::Value::Object(keyed);
+    Ok(snapshot)
+}
+
+/// Stable content hash of the rows of a snapshot, as 16 hexadecimal digits.
+/// The rows are hashed in their canonical serialization (object keys sorted), so that
+/// the hash only changes with their values and order. Uses 64-bit FNV-1a, which does
+/// not depend on the Rust version nor on the process, unlike the standard hasher.
+pub fn snapshot_hash(rows: &serde_json::Value) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let canonical = sort_json_keys(rows.clone()).to_string();
+    let hash = canonical.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    });
+
+    format!("{hash:016x}")
+}
+
+/// Add the hash of its rows to a serialized `Many` snapshot, in a `hash` field.
+/// If the client already has a snapshot with this hash, the rows are left out and the
+/// snapshot is replaced by `{ "type": "unchanged", "hash": ... }`.
+/// `Single` snapshots are returned as is.
+pub fn with_snapshot_hash(
+    mut snapshot: serde_json::Value,
+    known_hash: Option<&str>,
+) -> serde_json::Value {
+    if snapshot["type"] != "many" {
+        return snapshot;
+    }
+
+    let hash = snapshot_hash(&snapshot["data"]);
+    if known_hash == Some(hash.as_str()) {
+        return serde_json::json!({ "type": "unchanged", "hash": hash });
+    }
+
+    snapshot["hash"] = serde_json::Value::from(hash);
+    snapshot
+}
+
+/// Wire shape of the snapshots sent to clients, replacing the `{ "type", "data" }`
+/// envelope of `QueryData` for frontends expecting another one
+pub trait SnapshotEnvelope: Send + Sync {
+    /// Wrap the rows of a snapshot, along with its other fields as metadata
+    /// (such as its `hash` or the `nextCursor` of a page)
+    fn wrap(&self, data: QueryData<serde_json::Value>, meta: JsonObject) -> serde_json::Value;
+}
+
+/// Default envelope, `{ "type": "single" | "many", "data", ...meta }`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaggedEnvelope;
+
+impl SnapshotEnvelope for TaggedEnvelope {
+    fn wrap(&self, data: QueryData<serde_json::Value>, meta: JsonObject) -> serde_json::Value {
+        let mut value = serde_json::to_value(data).unwrap();
+        if let serde_json::Value::Object(object) = &mut value {
+            object.extend(meta);
+        }
+        value
+    }
+}
+
+/// Envelope `{ "rows": [...], "meta": {...} }`, where `Single` snapshots have at most one row
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RowsEnvelope;
+
+impl SnapshotEnvelope for RowsEnvelope {
+    fn wrap(&self, data: QueryData<serde_json::Value>, meta: JsonObject) -> serde_json::Value {
+        let rows = match data {
+            QueryData::Single(row) => row.into_iter().collect(),
+            QueryData::Many(rows) => rows,
+        };
+        serde_json::json!({ "rows": rows, "meta": meta })
+    }
+}
+
+/// Rewrap a serialized `Single` or `Many` snapshot in another envelope, its fields other
+/// than `type` and `data` becoming metadata. Other snapshots (keyed or unchanged ones)
+/// are returned as is.
+pub fn wrap_snapshot(
+    snapshot: serde_json::Value,
+    envelope: &dyn SnapshotEnvelope,
+) -> serde_json::Value {
+    let serde_json::Value::Object(mut meta) = snapshot else {
+        return snapshot;
+    };
+
+    let data = match (
+        meta.get("type").and_then(|kind| kind.as_str()),
+        meta.get("data"),
+    ) {
+        (Some("single"), Some(row)) => {
+            QueryData::Single(Some(row.clone()).filter(|row| !row.is_null()))
+        }
+        (Some("many"), Some(serde_json::Value::Array(rows))) => QueryData::Many(rows.clone()),
+        _ => return serde_json::Value::Object(meta),
+    };
+    meta.remove("type");
+    meta.remove("data");
+
+    envelope.wrap(data, meta)
+}
@@ -1,7 +1,24 @@
 //! Tests
 
+pub mod backends;
+pub mod cancellation;
+pub mod decoders;
+pub mod dispatcher;
 pub mod dummy;
 pub mod engine;
+pub mod enums;
+pub mod health;
+pub mod hooks;
+pub mod notification_log;
 pub mod operations;
+pub mod operators;
+pub mod plans;
 pub mod queries;
+pub mod rate_limit;
+pub mod replay;
+pub mod replicas;
+pub mod seed;
+pub mod snapshots;
+pub mod timeout;
 pub mod utils;
+pub mod writes;
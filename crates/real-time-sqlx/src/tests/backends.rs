@@ -0,0 +1,365 @@
+//! Backend-parameterized test harness.
+//!
+//! Test suites are written once as functions generic over a `TestBackend`,
+//! and the `backend_tests!` macro generates one test per database backend.
+//!
+//! SQLite always runs against an in-memory database. PostgreSQL and MySQL run against
+//! the databases given by the `POSTGRES_DATABASE_URL` and `MYSQL_DATABASE_URL`
+//! environment variables, and are skipped when these are not set.
+
+use sqlx::{
+    mysql::MySqlRow, postgres::PgRow, sqlite::SqliteRow, FromRow, MySql, Pool, Postgres, Sqlite,
+};
+
+use crate::{
+    database::{
+        mysql::{
+            create_many_partial_mysql, fetch_mysql_adaptive_page, fetch_mysql_page,
+            fetch_mysql_query, granular_operation_mysql, granular_operation_mysql_with_options,
+            sync_set_mysql, validate_operation_mysql,
+        },
+        postgres::{
+            create_many_partial_postgres, fetch_postgres_adaptive_page, fetch_postgres_page,
+            fetch_postgres_query, granular_operation_postgres,
+            granular_operation_postgres_with_options, sync_set_postgres,
+            validate_operation_postgres,
+        },
+        sqlite::{
+            create_many_partial_sqlite, fetch_sqlite_adaptive_page, fetch_sqlite_page,
+            fetch_sqlite_query, granular_operation_sqlite, granular_operation_sqlite_with_options,
+            sync_set_sqlite, validate_operation_sqlite,
+        },
+        OperationOptions,
+    },
+    error::{OperationError, QueryError},
+    operations::serialize::{
+        GranularOperation, JsonObject, OperationNotification, PartialCreateMany, SyncSet,
+    },
+    queries::serialize::{AdaptivePage, Page, QueryData, QueryTree},
+    snapshots::Identifiable,
+};
+
+use super::dummy::{
+    dummy_mysql_database, dummy_postgres_database, dummy_sqlite_database,
+    prepare_dummy_mysql_database, prepare_dummy_postgres_database, prepare_dummy_sqlite_database,
+    Todo,
+};
+
+/// A database backend that the test suites can run against
+pub(crate) trait TestBackend {
+    type Database: sqlx::Database;
+
+    /// Create and seed an isolated dummy database.
+    /// Returns `None` if the backend is not available in the current environment.
+    async fn setup() -> Option<Pool<Self::Database>>;
+
+    /// Fetch a query from the dummy database, mapping rows to `Todo`s
+    async fn fetch(query: &QueryTree, pool: &Pool<Self::Database>) -> QueryData<Todo>;
+
+    /// Fetch a keyset page of a query from the dummy database
+    async fn fetch_page(query: &QueryTree, pool: &Pool<Self::Database>) -> Page<Todo>;
+
+    /// Fetch the rows of a query in full below a threshold, or their first page above it
+    async fn fetch_adaptive_page(
+        query: &QueryTree,
+        pool: &Pool<Self::Database>,
+        threshold: u64,
+    ) -> AdaptivePage<Todo>;
+
+    /// Execute a granular operation on the dummy database
+    async fn execute(
+        operation: GranularOperation,
+        pool: &Pool<Self::Database>,
+    ) -> Option<OperationNotification<Todo>>;
+
+    /// Execute a granular operation on the dummy database with options,
+    /// mapping the returned rows to any row struct
+    async fn execute_with_options<T>(
+        operation: GranularOperation,
+        options: &OperationOptions,
+        pool: &Pool<Self::Database>,
+    ) -> Option<OperationNotification<T>>
+    where
+        T: for<'r> FromRow<'r, <Self::Database as sqlx::Database>::Row> + Identifiable;
+
+    /// Insert rows individually in the dummy database, reporting failures by index
+    async fn create_many_partial(
+        table: &str,
+        data: Vec<JsonObject>,
+        pool: &Pool<Self::Database>,
+    ) -> Result<PartialCreateMany<Todo>, QueryError>;
+
+    /// Dry run a granular operation on the dummy database, without writing it
+    async fn validate(
+        operation: GranularOperation,
+        pool: &Pool<Self::Database>,
+    ) -> Result<(), OperationError>;
+
+    /// Bring a subset of the dummy database to a desired set of rows
+    async fn sync_set(
+        sync: SyncSet,
+        pool: &Pool<Self::Database>,
+    ) -> Result<Vec<OperationNotification<Todo>>, OperationError>;
+}
+
+/// In-memory SQLite backend
+pub(crate) struct SqliteBackend;
+
+impl TestBackend for SqliteBackend {
+    type Database = Sqlite;
+
+    async fn setup() -> Option<Pool<Sqlite>> {
+        let pool = dummy_sqlite_database().await;
+        prepare_dummy_sqlite_database(&pool).await;
+        Some(pool)
+    }
+
+    async fn fetch(query: &QueryTree, pool: &Pool<Sqlite>) -> QueryData<Todo> {
+        fetch_sqlite_query(query, pool)
+            .await
+            .unwrap()
+            .map(|row| Todo::from_row(&row).expect("Failed to convert row"))
+    }
+
+    async fn fetch_page(query: &QueryTree, pool: &Pool<Sqlite>) -> Page<Todo> {
+        fetch_sqlite_page(query, pool).await.unwrap()
+    }
+
+    async fn fetch_adaptive_page(
+        query: &QueryTree,
+        pool: &Pool<Sqlite>,
+        threshold: u64,
+    ) -> AdaptivePage<Todo> {
+        fetch_sqlite_adaptive_page(query, pool, threshold)
+            .await
+            .unwrap()
+    }
+
+    async fn execute(
+        operation: GranularOperation,
+        pool: &Pool<Sqlite>,
+    ) -> Option<OperationNotification<Todo>> {
+        granular_operation_sqlite(operation, pool).await.unwrap()
+    }
+
+    async fn execute_with_options<T>(
+        operation: GranularOperation,
+        options: &OperationOptions,
+        pool: &Pool<Sqlite>,
+    ) -> Option<OperationNotification<T>>
+    where
+        T: for<'r> FromRow<'r, SqliteRow> + Identifiable,
+    {
+        granular_operation_sqlite_with_options(operation, pool, options)
+            .await
+            .unwrap()
+    }
+
+    async fn create_many_partial(
+        table: &str,
+        data: Vec<JsonObject>,
+        pool: &Pool<Sqlite>,
+    ) -> Result<PartialCreateMany<Todo>, QueryError> {
+        create_many_partial_sqlite(table, data, pool).await
+    }
+
+    async fn validate(
+        operation: GranularOperation,
+        pool: &Pool<Sqlite>,
+    ) -> Result<(), OperationError> {
+        validate_operation_sqlite(operation, pool).await
+    }
+
+    async fn sync_set(
+        sync: SyncSet,
+        pool: &Pool<Sqlite>,
+    ) -> Result<Vec<OperationNotification<Todo>>, OperationError> {
+        sync_set_sqlite(sync, pool, &OperationOptions::default()).await
+    }
+}
+
+/// PostgreSQL backend, configured with `POSTGRES_DATABASE_URL`
+pub(crate) struct PostgresBackend;
+
+impl TestBackend for PostgresBackend {
+    type Database = Postgres;
+
+    async fn setup() -> Option<Pool<Postgres>> {
+        let pool = dummy_postgres_database().await?;
+        prepare_dummy_postgres_database(&pool).await;
+        Some(pool)
+    }
+
+    async fn fetch(query: &QueryTree, pool: &Pool<Postgres>) -> QueryData<Todo> {
+        fetch_postgres_query(query, pool)
+            .await
+            .unwrap()
+            .map(|row| Todo::from_row(&row).expect("Failed to convert row"))
+    }
+
+    async fn fetch_page(query: &QueryTree, pool: &Pool<Postgres>) -> Page<Todo> {
+        fetch_postgres_page(query, pool).await.unwrap()
+    }
+
+    async fn fetch_adaptive_page(
+        query: &QueryTree,
+        pool: &Pool<Postgres>,
+        threshold: u64,
+    ) -> AdaptivePage<Todo> {
+        fetch_postgres_adaptive_page(query, pool, threshold)
+            .await
+            .unwrap()
+    }
+
+    async fn execute(
+        operation: GranularOperation,
+        pool: &Pool<Postgres>,
+    ) -> Option<OperationNotification<Todo>> {
+        granular_operation_postgres(operation, pool).await.unwrap()
+    }
+
+    async fn execute_with_options<T>(
+        operation: GranularOperation,
+        options: &OperationOptions,
+        pool: &Pool<Postgres>,
+    ) -> Option<OperationNotification<T>>
+    where
+        T: for<'r> FromRow<'r, PgRow> + Identifiable,
+    {
+        granular_operation_postgres_with_options(operation, pool, options)
+            .await
+            .unwrap()
+    }
+
+    async fn create_many_partial(
+        table: &str,
+        data: Vec<JsonObject>,
+        pool: &Pool<Postgres>,
+    ) -> Result<PartialCreateMany<Todo>, QueryError> {
+        create_many_partial_postgres(table, data, pool).await
+    }
+
+    async fn validate(
+        operation: GranularOperation,
+        pool: &Pool<Postgres>,
+    ) -> Result<(), OperationError> {
+        validate_operation_postgres(operation, pool).await
+    }
+
+    async fn sync_set(
+        sync: SyncSet,
+        pool: &Pool<Postgres>,
+    ) -> Result<Vec<OperationNotification<Todo>>, OperationError> {
+        sync_set_postgres(sync, pool, &OperationOptions::default()).await
+    }
+}
+
+/// MySQL backend, configured with `MYSQL_DATABASE_URL`
+pub(crate) struct MySqlBackend;
+
+impl TestBackend for MySqlBackend {
+    type Database = MySql;
+
+    async fn setup() -> Option<Pool<MySql>> {
+        let pool = dummy_mysql_database().await?;
+        prepare_dummy_mysql_database(&pool).await;
+        Some(pool)
+    }
+
+    async fn fetch(query: &QueryTree, pool: &Pool<MySql>) -> QueryData<Todo> {
+        fetch_mysql_query(query, pool)
+            .await
+            .unwrap()
+            .map(|row| Todo::from_row(&row).expect("Failed to convert row"))
+    }
+
+    async fn fetch_page(query: &QueryTree, pool: &Pool<MySql>) -> Page<Todo> {
+        fetch_mysql_page(query, pool).await.unwrap()
+    }
+
+    async fn fetch_adaptive_page(
+        query: &QueryTree,
+        pool: &Pool<MySql>,
+        threshold: u64,
+    ) -> AdaptivePage<Todo> {
+        fetch_mysql_adaptive_page(query, pool, threshold)
+            .await
+            .unwrap()
+    }
+
+    async fn execute(
+        operation: GranularOperation,
+        pool: &Pool<MySql>,
+    ) -> Option<OperationNotification<Todo>> {
+        granular_operation_mysql(operation, pool).await.unwrap()
+    }
+
+    async fn execute_with_options<T>(
+        operation: GranularOperation,
+        options: &OperationOptions,
+        pool: &Pool<MySql>,
+    ) -> Option<OperationNotification<T>>
+    where
+        T: for<'r> FromRow<'r, MySqlRow> + Identifiable,
+    {
+        granular_operation_mysql_with_options(operation, pool, options)
+            .await
+            .unwrap()
+    }
+
+    async fn create_many_partial(
+        table: &str,
+        data: Vec<JsonObject>,
+        pool: &Pool<MySql>,
+    ) -> Result<PartialCreateMany<Todo>, QueryError> {
+        create_many_partial_mysql(table, data, pool).await
+    }
+
+    async fn validate(
+        operation: GranularOperation,
+        pool: &Pool<MySql>,
+    ) -> Result<(), OperationError> {
+        validate_operation_mysql(operation, pool).await
+    }
+
+    async fn sync_set(
+        sync: SyncSet,
+        pool: &Pool<MySql>,
+    ) -> Result<Vec<OperationNotification<Todo>>, OperationError> {
+        sync_set_mysql(sync, pool, &OperationOptions::default()).await
+    }
+}
+
+/// Generate one test per backend for each given generic test function.
+///
+/// Example:
+/// ```ignore
+/// async fn test_create<B: TestBackend>() { ... }
+///
+/// // Generates `test_create_sqlite`, `test_create_postgres` and `test_create_mysql`
+/// backend_tests!(test_create);
+/// ```
+macro_rules! backend_tests {
+    ($($test:ident),+ $(,)?) => {
+        $(
+            $crate::macros::paste::paste! {
+                #[tokio::test]
+                async fn [<$test _sqlite>]() {
+                    $test::<$crate::tests::backends::SqliteBackend>().await;
+                }
+
+                #[tokio::test]
+                async fn [<$test _postgres>]() {
+                    $test::<$crate::tests::backends::PostgresBackend>().await;
+                }
+
+                #[tokio::test]
+                async fn [<$test _mysql>]() {
+                    $test::<$crate::tests::backends::MySqlBackend>().await;
+                }
+            }
+        )+
+    };
+}
+
+pub(crate) use backend_tests;
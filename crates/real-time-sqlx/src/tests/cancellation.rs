@@ -0,0 +1,136 @@
+//! Cancellation tests of in-flight fetches.
+//! Slow queries are emulated with views over expensive statements.
+
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::{
+    cancellation::{CancellationToken, ClientTokens},
+    database::{
+        postgres::{fetch_postgres_query, fetch_postgres_query_cancellable},
+        sqlite::{fetch_sqlite_query, fetch_sqlite_query_cancellable},
+    },
+    error::QueryError,
+    queries::serialize::QueryTree,
+};
+
+use super::{
+    dummy::{
+        dummy_postgres_database, dummy_sqlite_database, prepare_dummy_postgres_database,
+        prepare_dummy_sqlite_database,
+    },
+    utils::read_serialized_query,
+};
+
+/// Delay before the simulated disconnection
+const DISCONNECT_AFTER: Duration = Duration::from_millis(100);
+
+/// Query fetching all rows from the slow view
+fn slow_query() -> QueryTree {
+    serde_json::from_value(json!({ "return": "many", "table": "slow_todos" })).unwrap()
+}
+
+/// Disconnect a client after a delay
+fn disconnect_later(tokens: &std::sync::Arc<ClientTokens>, client_id: &'static str) {
+    let tokens = tokens.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(DISCONNECT_AFTER).await;
+        tokens.disconnect(client_id);
+    });
+}
+
+/// Test that disconnecting a client cancels its token only, and that it gets
+/// a fresh token when reconnecting
+#[tokio::test]
+async fn test_client_tokens() {
+    let tokens = ClientTokens::new();
+    let first = tokens.token("main");
+    let other = tokens.token("other");
+
+    tokens.disconnect("main");
+    assert!(first.is_cancelled());
+    assert!(!other.is_cancelled());
+    first.cancelled().await;
+
+    assert!(!tokens.token("main").is_cancelled());
+}
+
+/// Test that a fast query completes normally with a live token
+#[tokio::test]
+async fn test_cancellable_fast_query() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query = read_serialized_query("02_many.json");
+    let rows = fetch_sqlite_query_cancellable(&query, &pool, &CancellationToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(rows.unwrap_many().len(), 3);
+}
+
+/// Test that a slow SQLite query is interrupted when its client disconnects mid-fetch,
+/// and that the connection can be reused
+#[tokio::test]
+async fn test_cancel_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    // Scan a billion generated rows without returning any of them
+    sqlx::query(
+        "CREATE VIEW slow_todos AS \
+        WITH RECURSIVE counter(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM counter WHERE x < 1000000000) \
+        SELECT x AS id FROM counter WHERE x < 0",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let tokens = std::sync::Arc::new(ClientTokens::new());
+    let token = tokens.token("main");
+    disconnect_later(&tokens, "main");
+
+    let start = Instant::now();
+    let result = fetch_sqlite_query_cancellable(&slow_query(), &pool, &token).await;
+    assert!(matches!(result, Err(QueryError::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // The pool holds a single connection, which must still be usable
+    let rows = fetch_sqlite_query(&read_serialized_query("02_many.json"), &pool)
+        .await
+        .unwrap();
+    assert_eq!(rows.unwrap_many().len(), 3);
+}
+
+/// Test that a slow PostgreSQL query is cancelled on the server when its client
+/// disconnects mid-fetch, and that the connection can be reused right away
+#[tokio::test]
+async fn test_cancel_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+
+    sqlx::query(
+        "CREATE TEMPORARY VIEW slow_todos AS SELECT todos.* FROM todos CROSS JOIN pg_sleep(5)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let tokens = std::sync::Arc::new(ClientTokens::new());
+    let token = tokens.token("main");
+    disconnect_later(&tokens, "main");
+
+    let start = Instant::now();
+    let result = fetch_postgres_query_cancellable(&slow_query(), &pool, &token).await;
+    assert!(matches!(result, Err(QueryError::Cancelled)));
+
+    // The pending statement was aborted rather than drained to completion
+    let rows = fetch_postgres_query(&read_serialized_query("02_many.json"), &pool)
+        .await
+        .unwrap();
+    assert_eq!(rows.unwrap_many().len(), 3);
+    assert!(start.elapsed() < Duration::from_secs(4));
+}
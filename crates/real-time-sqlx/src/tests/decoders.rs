@@ -0,0 +1,614 @@
+//! Declared column decoders tests
+
+use serde::Serialize;
+use serde_json::json;
+use sqlx::FromRow;
+
+use crate::{
+    database::{
+        decode_binary_constraints,
+        mysql::mysql_row_to_json,
+        postgres::{
+            fetch_postgres_query_with_decoders, granular_operation_postgres_with_options,
+            postgres_row_to_json, postgres_row_to_json_with_decoders, postgres_rows_to_json,
+        },
+        set_safe_integers,
+        sqlite::{
+            fetch_sqlite_query, fetch_sqlite_query_with_decoders,
+            granular_operation_sqlite_with_options, sqlite_row_to_json,
+            sqlite_row_to_json_with_decoders, sqlite_rows_to_json_with_decoders,
+        },
+        stringify_large_integers, ColumnDecoder, ColumnDecoders, OperationOptions,
+    },
+    operations::serialize::{object_from_value, GranularOperation, OperationNotification},
+    queries::{serialize::QueryTree, Checkable},
+    snapshots::Identifiable,
+};
+
+use super::dummy::{dummy_mysql_database, dummy_postgres_database, dummy_sqlite_database};
+
+/// Decoders for the settings table
+fn settings_decoders() -> ColumnDecoders {
+    ColumnDecoders::from([
+        ("enabled".to_string(), ColumnDecoder::Bool),
+        ("meta".to_string(), ColumnDecoder::Json),
+        ("created_at".to_string(), ColumnDecoder::Timestamp),
+    ])
+}
+
+/// Test that declared decoders override misleading SQLite type names
+#[tokio::test]
+async fn test_sqlite_decoders() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query(
+        "CREATE TABLE settings (id INTEGER PRIMARY KEY, enabled INTEGER, meta TEXT, \
+        created_at INTEGER, name TEXT)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO settings VALUES \
+        (1, 1, '{\"theme\": \"dark\"}', 1700000000, 'first'), \
+        (2, 0, 'not json', '2024-01-01 00:00:00', 'second')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let rows = sqlx::query("SELECT * FROM settings ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    // The type name heuristic reads booleans as integers and JSON as text
+    assert_eq!(sqlite_row_to_json(&rows[0])["enabled"], json!(1));
+    assert_eq!(
+        sqlite_row_to_json(&rows[0])["meta"],
+        json!("{\"theme\": \"dark\"}")
+    );
+
+    let decoded = sqlite_rows_to_json_with_decoders(&rows, &settings_decoders());
+    assert_eq!(
+        decoded,
+        json!([
+            {
+                "id": 1,
+                "enabled": true,
+                "meta": { "theme": "dark" },
+                "created_at": 1700000000,
+                "name": "first"
+            },
+            {
+                "id": 2,
+                "enabled": false,
+                "meta": "not json",
+                "created_at": "2024-01-01 00:00:00",
+                "name": "second"
+            }
+        ])
+    );
+}
+
+/// Test that declared decoders override PostgreSQL type names
+/// unknown to the heuristic
+#[tokio::test]
+async fn test_postgres_decoders() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+
+    let row = sqlx::query("SELECT 1::INT8 AS id, '[1, 2]'::TEXT AS tags")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let decoders = ColumnDecoders::from([
+        ("id".to_string(), ColumnDecoder::Integer),
+        ("tags".to_string(), ColumnDecoder::Json),
+    ]);
+
+    assert_eq!(
+        postgres_row_to_json_with_decoders(&row, &decoders),
+        json!({ "id": 1, "tags": [1, 2] })
+    );
+}
+
+/// A row with a binary column
+#[derive(Debug, Serialize, FromRow)]
+struct Attachment {
+    id: i64,
+    data: Vec<u8>,
+}
+
+impl Identifiable for Attachment {}
+
+/// Decoders for the attachments table
+fn attachment_decoders() -> ColumnDecoders {
+    ColumnDecoders::from([("data".to_string(), ColumnDecoder::Binary)])
+}
+
+/// Operation creating an attachment from a base64 payload
+fn create_attachment() -> GranularOperation {
+    serde_json::from_value(json!({
+        "type": "create",
+        "table": "attachments",
+        "data": { "id": 1, "data": "AAEC/w==" }
+    }))
+    .unwrap()
+}
+
+/// Test that base64 values of binary columns are bound as bytes, and read back as base64
+#[tokio::test]
+async fn test_sqlite_binary_roundtrip() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE attachments (id INTEGER PRIMARY KEY, data BLOB)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let Some(OperationNotification::Create { data, .. }) =
+        granular_operation_sqlite_with_options::<_, Attachment>(
+            create_attachment(),
+            &pool,
+            &OperationOptions::default().with_decoders(attachment_decoders()),
+        )
+        .await
+        .unwrap()
+    else {
+        panic!("Expected a creation notification");
+    };
+    assert_eq!(data.id, 1);
+    assert_eq!(data.data, vec![0, 1, 2, 255]);
+
+    let row = sqlx::query("SELECT * FROM attachments")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let expected = json!({ "id": 1, "data": "AAEC/w==" });
+    assert_eq!(
+        sqlite_row_to_json_with_decoders(&row, &attachment_decoders()),
+        expected
+    );
+    assert_eq!(sqlite_row_to_json(&row), expected);
+}
+
+/// Test that invalid base64 values of binary columns are rejected
+#[tokio::test]
+#[should_panic]
+async fn test_sqlite_binary_invalid() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE attachments (id INTEGER PRIMARY KEY, data BLOB)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let operation = serde_json::from_value(json!({
+        "type": "create",
+        "table": "attachments",
+        "data": { "id": 1, "data": "not base64!" }
+    }))
+    .unwrap();
+
+    granular_operation_sqlite_with_options::<_, Attachment>(
+        operation,
+        &pool,
+        &OperationOptions::default().with_decoders(attachment_decoders()),
+    )
+    .await
+    .unwrap();
+}
+
+/// Test the binary column round trip on a PostgreSQL `BYTEA` column
+#[tokio::test]
+async fn test_postgres_binary_roundtrip() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::query("CREATE TEMPORARY TABLE attachments (id INT8 PRIMARY KEY, data BYTEA)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let Some(OperationNotification::Create { data, .. }) =
+        granular_operation_postgres_with_options::<_, Attachment>(
+            create_attachment(),
+            &pool,
+            &OperationOptions::default().with_decoders(attachment_decoders()),
+        )
+        .await
+        .unwrap()
+    else {
+        panic!("Expected a creation notification");
+    };
+    assert_eq!(data.data, vec![0, 1, 2, 255]);
+
+    let row = sqlx::query("SELECT * FROM attachments")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let mut decoders = attachment_decoders();
+    decoders.insert("id".to_string(), ColumnDecoder::Integer);
+    assert_eq!(
+        postgres_row_to_json_with_decoders(&row, &decoders),
+        json!({ "id": 1, "data": "AAEC/w==" })
+    );
+}
+
+/// Query of the attachments whose binary column compares to base64 values
+fn attachments_where(operator: &str, value: serde_json::Value) -> QueryTree {
+    serde_json::from_value(json!({
+        "return": "many",
+        "table": "attachments",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "data", "operator": operator, "value": value }
+        }
+    }))
+    .unwrap()
+}
+
+/// Test that filtering on a binary column binds the base64 values as bytes, and that
+/// the engine matches them against the base64 strings of the serialized rows
+#[tokio::test]
+async fn test_sqlite_binary_filter() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE attachments (id INTEGER PRIMARY KEY, data BLOB)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO attachments VALUES (1, ?), (2, ?)")
+        .bind(vec![0u8, 1, 2, 255])
+        .bind(vec![3u8])
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // Bound as text, the value never equals a blob
+    let query = attachments_where("=", json!("AAEC/w=="));
+    assert!(fetch_sqlite_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many()
+        .is_empty());
+
+    let rows = fetch_sqlite_query_with_decoders(&query, &pool, &attachment_decoders())
+        .await
+        .unwrap()
+        .unwrap_many();
+    let rows = sqlite_rows_to_json_with_decoders(&rows, &attachment_decoders());
+    assert_eq!(rows, json!([{ "id": 1, "data": "AAEC/w==" }]));
+
+    let decoded = decode_binary_constraints(&query, &attachment_decoders()).unwrap();
+    for query in [&query, &decoded] {
+        assert!(query.check(&object_from_value(rows[0].clone()).unwrap()));
+        assert!(!query.check(&object_from_value(json!({ "id": 2, "data": "Aw==" })).unwrap()));
+    }
+
+    let query = attachments_where("not_in", json!(["Aw==", "BA=="]));
+    let rows = fetch_sqlite_query_with_decoders(&query, &pool, &attachment_decoders())
+        .await
+        .unwrap()
+        .unwrap_many();
+    assert_eq!(rows.len(), 1);
+}
+
+/// Test filtering on a PostgreSQL `BYTEA` column, `in` lists being bound as a `BYTEA[]`
+#[tokio::test]
+async fn test_postgres_binary_filter() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::query("CREATE TEMPORARY TABLE attachments (id INT8 PRIMARY KEY, data BYTEA)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO attachments VALUES (1, $1), (2, $2)")
+        .bind(vec![0u8, 1, 2, 255])
+        .bind(vec![3u8])
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    for query in [
+        attachments_where("=", json!("AAEC/w==")),
+        attachments_where("in", json!(["AAEC/w==", "BA=="])),
+    ] {
+        let mut conn = pool.acquire().await.unwrap();
+        let rows = fetch_postgres_query_with_decoders(&query, &mut *conn, &attachment_decoders())
+            .await
+            .unwrap()
+            .unwrap_many();
+        assert_eq!(rows.len(), 1);
+
+        let row = postgres_row_to_json_with_decoders(&rows[0], &attachment_decoders());
+        let decoded = decode_binary_constraints(&query, &attachment_decoders()).unwrap();
+        assert!(decoded.check(&object_from_value(row).unwrap()));
+    }
+}
+
+/// Integer above the JavaScript safe range (2^53 + 1)
+const LARGE_INTEGER: i64 = 9_007_199_254_740_993;
+
+/// Test that integers beyond the JavaScript safe range can be serialized as exact strings,
+/// per column or globally
+#[tokio::test]
+async fn test_sqlite_safe_integers() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE counters (id INTEGER PRIMARY KEY, total INTEGER)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO counters VALUES (1, ?)")
+        .bind(LARGE_INTEGER)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let row = sqlx::query("SELECT * FROM counters")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    // Integers are serialized as numbers by default
+    assert_eq!(
+        sqlite_row_to_json(&row),
+        json!({ "id": 1, "total": LARGE_INTEGER })
+    );
+
+    // Per column, integers within the safe range staying numbers
+    let decoders = ColumnDecoders::from([
+        ("id".to_string(), ColumnDecoder::SafeInteger),
+        ("total".to_string(), ColumnDecoder::SafeInteger),
+    ]);
+    assert_eq!(
+        sqlite_row_to_json_with_decoders(&row, &decoders),
+        json!({ "id": 1, "total": "9007199254740993" })
+    );
+
+    // Globally
+    set_safe_integers(true);
+    let json = sqlite_row_to_json(&row);
+    set_safe_integers(false);
+    assert_eq!(json, json!({ "id": 1, "total": "9007199254740993" }));
+}
+
+/// Test that large integers of serialized structs are converted to strings
+#[test]
+fn test_stringify_large_integers() {
+    let mut value = json!({
+        "id": 1,
+        "total": LARGE_INTEGER,
+        "negative": -LARGE_INTEGER,
+        "unsigned": u64::MAX,
+        "ratio": 0.5,
+        "history": [9_007_199_254_740_991_i64, LARGE_INTEGER],
+    });
+    stringify_large_integers(&mut value);
+
+    assert_eq!(
+        value,
+        json!({
+            "id": 1,
+            "total": "9007199254740993",
+            "negative": "-9007199254740993",
+            "unsigned": "18446744073709551615",
+            "ratio": 0.5,
+            "history": [9_007_199_254_740_991_i64, "9007199254740993"],
+        })
+    );
+}
+
+/// Test that the rows of a wide table are converted column by column, with the values
+/// and types of every column
+#[tokio::test]
+async fn test_sqlite_wide_rows() {
+    let pool = dummy_sqlite_database().await;
+    let columns: Vec<String> = (0..300)
+        .map(|index| match index % 3 {
+            0 => format!("int_{index} INTEGER"),
+            1 => format!("real_{index} REAL"),
+            _ => format!("text_{index} TEXT"),
+        })
+        .collect();
+    sqlx::query(&format!("CREATE TABLE wide ({})", columns.join(", ")))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let values: Vec<String> = (0..300)
+        .map(|index| match index % 3 {
+            0 => index.to_string(),
+            1 => format!("{index}.5"),
+            _ => format!("'value {index}'"),
+        })
+        .collect();
+    sqlx::query(&format!("INSERT INTO wide VALUES ({})", values.join(", ")))
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let row = sqlx::query("SELECT * FROM wide")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let value = sqlite_row_to_json(&row);
+
+    let object = value.as_object().unwrap();
+    assert_eq!(object.len(), 300);
+    for index in 0..300 {
+        let (column, expected) = match index % 3 {
+            0 => (format!("int_{index}"), json!(index)),
+            1 => (format!("real_{index}"), json!(index as f64 + 0.5)),
+            _ => (format!("text_{index}"), json!(format!("value {index}"))),
+        };
+        assert_eq!(object[&column], expected, "column {column}");
+    }
+}
+
+/// Test that numbers are serialized consistently with the declared type of their column:
+/// integral values of `REAL` columns stay floats, and the values of untyped expressions
+/// and `NUMERIC` columns keep the type they are stored with instead of being dropped
+#[tokio::test]
+async fn test_sqlite_number_types() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE prices (id INTEGER PRIMARY KEY, amount REAL, quantity NUMERIC)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO prices VALUES (1, 3, 3), (2, 3.5, 3.5)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let rows = sqlx::query("SELECT *, amount * 2 AS doubled, id + 1 AS next FROM prices")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    let first = sqlite_row_to_json(&rows[0]);
+    let second = sqlite_row_to_json(&rows[1]);
+
+    assert!(first["amount"].is_f64());
+    assert_eq!(first["amount"].to_string(), "3.0");
+    assert_eq!(
+        first,
+        json!({ "id": 1, "amount": 3.0, "quantity": 3, "doubled": 6.0, "next": 2 })
+    );
+    assert_eq!(
+        second,
+        json!({ "id": 2, "amount": 3.5, "quantity": 3.5, "doubled": 7.0, "next": 3 })
+    );
+}
+
+/// Serialization expected from the row of equivalent columns inserted in each backend
+fn typed_row() -> serde_json::Value {
+    json!({
+        "id": 1,
+        "count": 7,
+        "ratio": 0.5,
+        "label": "Label",
+        "done": true,
+        "payload": "AQI=",
+        "missing": null
+    })
+}
+
+/// Test that the rows of SQLite, PostgreSQL and MySQL serialize equivalent columns alike
+#[tokio::test]
+async fn test_row_to_json_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::raw_sql(
+        "CREATE TABLE typed_rows (id INTEGER PRIMARY KEY, count INTEGER, ratio REAL, \
+        label TEXT, done BOOLEAN, payload BLOB, missing TEXT); \
+        INSERT INTO typed_rows VALUES (1, 7, 0.5, 'Label', TRUE, X'0102', NULL);",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let row = sqlx::query("SELECT * FROM typed_rows")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(sqlite_row_to_json(&row), typed_row());
+}
+
+#[tokio::test]
+async fn test_row_to_json_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::raw_sql(
+        "CREATE TEMPORARY TABLE typed_rows (id INT8 PRIMARY KEY, count INT4, ratio FLOAT8, \
+        label VARCHAR(255), done BOOL, payload BYTEA, missing TEXT); \
+        INSERT INTO typed_rows VALUES (1, 7, 0.5, 'Label', TRUE, '\\x0102', NULL);",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let row = sqlx::query("SELECT * FROM typed_rows")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(postgres_row_to_json(&row), typed_row());
+}
+
+/// Test that PostgreSQL dates, times and timestamps are serialized as ISO 8601 strings,
+/// in the binary format of prepared statements and in the text format of raw SQL
+#[tokio::test]
+async fn test_row_to_json_postgres_temporal() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::raw_sql(
+        "CREATE TEMPORARY TABLE events (id INT8 PRIMARY KEY, day DATE, at TIME, \
+        created_at TIMESTAMP, updated_at TIMESTAMPTZ, rows_count INT4, amount FLOAT8); \
+        INSERT INTO events VALUES \
+        (1, '2024-02-29', '13:45:30.25', '2024-02-29 13:45:30.25', \
+        '2024-02-29 13:45:30+02', 12, 3.5), \
+        (2, '1999-12-31', '00:00:00', '1969-07-20 20:17:40', 'infinity', NULL, NULL);",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let rows = sqlx::query("SELECT * FROM events ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        postgres_rows_to_json(&rows),
+        json!([
+            {
+                "id": 1,
+                "day": "2024-02-29",
+                "at": "13:45:30.25",
+                "created_at": "2024-02-29T13:45:30.25",
+                "updated_at": "2024-02-29T11:45:30Z",
+                "rows_count": 12,
+                "amount": 3.5
+            },
+            {
+                "id": 2,
+                "day": "1999-12-31",
+                "at": "00:00:00",
+                "created_at": "1969-07-20T20:17:40",
+                "updated_at": "infinity",
+                "rows_count": null,
+                "amount": null
+            }
+        ])
+    );
+
+    // Raw SQL returns the text format of the database
+    let rows = sqlx::raw_sql("SELECT id, day FROM events WHERE id = 1")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        postgres_rows_to_json(&rows),
+        json!([{ "id": 1, "day": "2024-02-29" }])
+    );
+}
+
+#[tokio::test]
+async fn test_row_to_json_mysql() {
+    let Some(pool) = dummy_mysql_database().await else {
+        return;
+    };
+    sqlx::raw_sql(
+        "CREATE TEMPORARY TABLE typed_rows (id BIGINT PRIMARY KEY, count INT, ratio DOUBLE, \
+        label VARCHAR(255), done BOOLEAN, payload BLOB, missing TEXT); \
+        INSERT INTO typed_rows VALUES (1, 7, 0.5, 'Label', TRUE, X'0102', NULL);",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let row = sqlx::query("SELECT * FROM typed_rows")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(mysql_row_to_json(&row), typed_row());
+}
@@ -0,0 +1,1853 @@
+//! Subscriptions and notifications fan-out tests
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{
+    database::{serialize_rows, sqlite::fetch_sqlite_query, sqlite::granular_operation_sqlite},
+    dispatcher::{
+        closed_message, group_channel_id, operation_matches, process_channel_event,
+        refetch_message, snapshot_ids, AdminSubscriptions, EventChannel, EventEmitter,
+        MatchOutcome, NotificationChannel, Subscription, TableSubscriptions,
+    },
+    error::{ChannelError, OperationError, QueryError},
+    liveness::{Liveness, StalePruning},
+    operations::serialize::{object_from_value, JsonObject},
+    operations::serialize::{GranularOperation, OperationNotification},
+    queries::serialize::{FinalType, OrderBy, QueryTree},
+    utils::tag_request_id,
+};
+
+use super::{
+    dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database, DummyChannel, Todo},
+    utils::{read_serialized_operation, read_serialized_query},
+};
+
+/// Test that a client-supplied request id is echoed in a fetch response
+#[tokio::test]
+async fn test_request_id_fetch() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query = read_serialized_query("02_many.json");
+    let rows = fetch_sqlite_query(&query, &pool).await.unwrap();
+    let value = tag_request_id(serialize_rows::<Todo, _>(&rows), Some("fetch-1"));
+
+    assert_eq!(value["requestId"], "fetch-1");
+    assert_eq!(value["type"], "many");
+    assert_eq!(value["data"].as_array().unwrap().len(), 3);
+}
+
+/// Test that the request id of a subscription is echoed in its notifications
+#[tokio::test]
+async fn test_request_id_notification() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let channel = DummyChannel::default();
+    let mut channels = HashMap::new();
+    channels.insert(
+        "channel".to_string(),
+        Subscription::new(read_serialized_query("02_many.json"), channel.clone())
+            .with_request_id(Some("subscribe-1".to_string())),
+    );
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let failing_channels = process_channel_event(&channels, &notification);
+    assert!(failing_channels.is_empty());
+
+    let messages = channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["requestId"], "subscribe-1");
+    assert_eq!(messages[0]["type"], "create");
+    assert_eq!(messages[0]["data"]["title"], "Fourth todo");
+}
+
+/// Test that shutting down subscriptions notifies and removes every channel,
+/// and refuses new subscriptions
+#[tokio::test]
+async fn test_shutdown() {
+    let subscriptions = TableSubscriptions::new();
+    let first_channel = DummyChannel::default();
+    let second_channel = DummyChannel::default();
+
+    for (channel_id, channel) in [("first", &first_channel), ("second", &second_channel)] {
+        subscriptions
+            .subscribe(
+                channel_id,
+                Subscription::new(read_serialized_query("02_many.json"), channel.clone()),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(subscriptions.len().await, 2);
+
+    subscriptions.shutdown().await;
+
+    assert!(subscriptions.is_closed());
+    assert!(subscriptions.is_empty().await);
+    assert_eq!(first_channel.messages(), vec![closed_message()]);
+    assert_eq!(second_channel.messages(), vec![closed_message()]);
+
+    let refused = subscriptions
+        .subscribe(
+            "third",
+            Subscription::new(
+                read_serialized_query("02_many.json"),
+                DummyChannel::default(),
+            ),
+        )
+        .await;
+    assert!(refused.is_err());
+    assert!(subscriptions.is_empty().await);
+}
+
+/// Test that update notifications are only forwarded to watching subscriptions
+/// when one of their watched columns changed
+#[tokio::test]
+async fn test_watch_columns() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let watching_channel = DummyChannel::default();
+    let unfiltered_channel = DummyChannel::default();
+    let mut channels = HashMap::new();
+    channels.insert(
+        "watching".to_string(),
+        Subscription::new(
+            read_serialized_query("02_many.json"),
+            watching_channel.clone(),
+        )
+        .with_watch_columns(Some(vec!["title".to_string()])),
+    );
+    channels.insert(
+        "unfiltered".to_string(),
+        Subscription::new(
+            read_serialized_query("02_many.json"),
+            unfiltered_channel.clone(),
+        ),
+    );
+
+    // Only the unwatched column changes
+    let operation: GranularOperation = serde_json::from_value(serde_json::json!({
+        "type": "update",
+        "table": "todos",
+        "id": 1,
+        "data": { "content": "Churn" }
+    }))
+    .unwrap();
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    assert!(watching_channel.messages().is_empty());
+    assert_eq!(unfiltered_channel.messages().len(), 1);
+
+    // The watched column changes
+    let operation = read_serialized_operation("03_update.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    let messages = watching_channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "update");
+    assert_eq!(messages[0]["data"]["title"], "Updated todo");
+    assert!(messages[0].get("previous").is_none());
+    assert_eq!(unfiltered_channel.messages().len(), 2);
+}
+
+/// Test projection adding a computed field to every todo
+fn add_display_name(row: &mut serde_json::Value) {
+    let display_name = format!("#{} {}", row["id"], row["title"].as_str().unwrap());
+    row["displayName"] = serde_json::Value::String(display_name);
+}
+
+/// Test that a registered projection is applied to both snapshots and notifications
+#[tokio::test]
+async fn test_projection() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let mut subscriptions = TableSubscriptions::new();
+    subscriptions.set_projection(add_display_name);
+
+    // Initial snapshot
+    let query = read_serialized_query("02_many.json");
+    let rows = fetch_sqlite_query(&query, &pool).await.unwrap();
+    let snapshot = subscriptions.project(serialize_rows::<Todo, _>(&rows));
+
+    let display_names: Vec<&str> = snapshot["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["displayName"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        display_names,
+        vec!["#1 First todo", "#2 Second todo", "#3 Third todo"]
+    );
+
+    // Subsequent notification
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe("channel", Subscription::new(query, channel.clone()))
+        .await
+        .unwrap();
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    subscriptions.process(&notification).await;
+
+    let messages = channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "create");
+    assert_eq!(messages[0]["data"]["displayName"], "#4 Fourth todo");
+
+    // Rows without projection are left untouched
+    let unprojected: JsonObject =
+        serde_json::from_value(serde_json::to_value(&notification).unwrap()["data"].clone())
+            .unwrap();
+    assert!(!unprojected.contains_key("displayName"));
+}
+
+/// Test that subscriptions to queries that cannot be evaluated locally are told to refetch
+#[tokio::test]
+async fn test_refetch_not_locally_evaluable() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let mut query = read_serialized_query("02_many.json");
+    query.distinct_on = vec!["title".to_string()];
+
+    let channel = DummyChannel::default();
+    let mut channels = HashMap::new();
+    channels.insert(
+        "channel".to_string(),
+        Subscription::new(query, channel.clone()),
+    );
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    assert_eq!(channel.messages(), vec![refetch_message()]);
+}
+
+/// Test that update notifications carry the previous row for the subscriptions asking for it
+#[tokio::test]
+async fn test_include_previous() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let previous_channel = DummyChannel::default();
+    let default_channel = DummyChannel::default();
+    let mut channels = HashMap::new();
+    channels.insert(
+        "previous".to_string(),
+        Subscription::new(
+            read_serialized_query("02_many.json"),
+            previous_channel.clone(),
+        )
+        .with_previous(true),
+    );
+    channels.insert(
+        "default".to_string(),
+        Subscription::new(
+            read_serialized_query("02_many.json"),
+            default_channel.clone(),
+        ),
+    );
+
+    let operation = read_serialized_operation("03_update.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    let messages = previous_channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "update");
+    assert_eq!(messages[0]["data"]["title"], "Updated todo");
+    assert_eq!(messages[0]["previous"]["id"], 3);
+    assert_eq!(messages[0]["previous"]["title"], "Third todo");
+    assert_eq!(messages[0]["previous"]["content"], "This is the third todo");
+
+    let messages = default_channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].get("previous").is_none());
+}
+
+/// Test that update notifications carry a diff of the changed fields only,
+/// for the subscriptions asking for it
+#[tokio::test]
+async fn test_include_diff() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let diff_channel = DummyChannel::default();
+    let selected_channel = DummyChannel::default();
+    let default_channel = DummyChannel::default();
+    let query = read_serialized_query("02_many.json");
+    let mut channels = HashMap::new();
+    channels.insert(
+        "diff".to_string(),
+        Subscription::new(query.clone(), diff_channel.clone()).with_diff(true),
+    );
+    channels.insert(
+        "selected".to_string(),
+        Subscription::new(query.clone(), selected_channel.clone())
+            .with_diff(true)
+            .with_columns(Some(vec!["id".to_string(), "content".to_string()])),
+    );
+    channels.insert(
+        "default".to_string(),
+        Subscription::new(query, default_channel.clone()),
+    );
+
+    // Only the title changes
+    let operation = GranularOperation::try_from(json!({
+        "type": "update",
+        "table": "todos",
+        "id": 3,
+        "data": { "title": "Updated todo", "content": "This is the third todo" }
+    }))
+    .unwrap();
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    let messages = diff_channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        messages[0]["diff"],
+        json!({ "title": { "old": "Third todo", "new": "Updated todo" } })
+    );
+    assert!(messages[0].get("previous").is_none());
+
+    // The diff is reduced to the selected columns
+    assert_eq!(selected_channel.messages()[0]["diff"], json!({}));
+
+    assert!(default_channel.messages()[0].get("diff").is_none());
+}
+
+/// Test that count subscriptions follow the rows created, deleted, and updated in
+/// and out of their query, and refetch when an update cannot be evaluated
+#[tokio::test]
+async fn test_count_subscription() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "content", "operator": "like", "value": "%urgent%" }
+        }
+    }))
+    .unwrap();
+    subscriptions
+        .subscribe(
+            "badge",
+            Subscription::new(query, channel.clone()).with_count(0),
+        )
+        .await
+        .unwrap();
+
+    let operations = [
+        // Created in, then out of the query
+        json!({ "type": "create", "table": "todos", "data": { "title": "A", "content": "urgent" } }),
+        json!({ "type": "create", "table": "todos", "data": { "title": "B", "content": "later" } }),
+        // Updated out, in, and within the query
+        json!({ "type": "update", "table": "todos", "id": 4, "data": { "content": "done" } }),
+        json!({ "type": "update", "table": "todos", "id": 5, "data": { "content": "urgent!" } }),
+        json!({ "type": "update", "table": "todos", "id": 5, "data": { "title": "B!" } }),
+        json!({ "type": "create", "table": "todos", "data": { "title": "C", "content": "urgent" } }),
+        // Deleted in, then out of the query
+        json!({ "type": "delete", "table": "todos", "id": 6 }),
+        json!({ "type": "delete", "table": "todos", "id": 4 }),
+    ];
+    let mut counts = vec![];
+    for operation in operations {
+        let operation = GranularOperation::try_from(operation).unwrap();
+        let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        subscriptions.process(&notification).await;
+        counts.push(subscriptions.inspect().await[0].count);
+    }
+    assert_eq!(counts, [1, 1, 0, 1, 1, 2, 1, 1].map(Some).to_vec());
+
+    // Only the changes of the count are streamed
+    let messages = channel.messages();
+    assert_eq!(
+        messages
+            .iter()
+            .map(|message| message["count"].as_u64().unwrap())
+            .collect::<Vec<_>>(),
+        vec![1, 0, 1, 2, 1]
+    );
+    assert!(messages.iter().all(|message| message["type"] == "count"));
+
+    // Without the row before the update, the count must be refetched
+    let notification = OperationNotification::Update {
+        table: "todos".to_string(),
+        id: FinalType::Number(5.into()),
+        data: object_from_value(json!({ "id": 5, "title": "B", "content": "" })).unwrap(),
+        previous: None,
+    };
+    subscriptions.process(&notification).await;
+    assert_eq!(channel.messages().last().unwrap(), &refetch_message());
+}
+
+/// Channel recording the JSON text of its notifications, like a websocket server would
+#[derive(Debug, Clone, Default)]
+struct TextChannel {
+    messages: Arc<Mutex<Vec<String>>>,
+    shared: Arc<Mutex<usize>>,
+}
+
+impl NotificationChannel for TextChannel {
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        self.messages.lock().unwrap().push(value.to_string());
+        Ok(())
+    }
+
+    fn send_serialized(&self, payload: Arc<str>) -> Result<(), ChannelError> {
+        *self.shared.lock().unwrap() += 1;
+        self.messages.lock().unwrap().push(payload.to_string());
+        Ok(())
+    }
+}
+
+/// Test that shared payloads deliver the same JSON text as per-channel serialization
+#[tokio::test]
+async fn test_shared_payloads() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "content", "operator": "like", "value": "%urgent%" }
+        }
+    }))
+    .unwrap();
+    let subscriptions = |channels: &HashMap<&str, TextChannel>| {
+        let channel = |id: &str| channels[id].clone();
+        vec![
+            ("first", Subscription::new(query.clone(), channel("first"))),
+            (
+                "second",
+                Subscription::new(query.clone(), channel("second")),
+            ),
+            (
+                "request",
+                Subscription::new(query.clone(), channel("request"))
+                    .with_request_id(Some("request-1".to_string())),
+            ),
+            (
+                "columns",
+                Subscription::new(query.clone(), channel("columns"))
+                    .with_columns(Some(vec!["id".to_string(), "title".to_string()])),
+            ),
+            (
+                "previous",
+                Subscription::new(query.clone(), channel("previous"))
+                    .with_previous(true)
+                    .with_diff(true),
+            ),
+            (
+                "group-a",
+                Subscription::new(query.clone(), channel("group-a")).with_group("a", 0),
+            ),
+            (
+                "group-b",
+                Subscription::new(query.clone(), channel("group-b")).with_group("b", 1),
+            ),
+        ]
+    };
+
+    let ids = [
+        "first", "second", "request", "columns", "previous", "group-a", "group-b",
+    ];
+    let channels: [HashMap<&str, TextChannel>; 2] = [
+        ids.map(|id| (id, TextChannel::default())).into(),
+        ids.map(|id| (id, TextChannel::default())).into(),
+    ];
+    let mut dispatchers = [TableSubscriptions::new(), TableSubscriptions::new()];
+    dispatchers[1].set_shared_payloads(true);
+    for (dispatcher, channels) in dispatchers.iter().zip(&channels) {
+        for (id, subscription) in subscriptions(channels) {
+            dispatcher.subscribe(id, subscription).await.unwrap();
+        }
+    }
+
+    let operations = [
+        json!({ "type": "create", "table": "todos", "data": { "title": "A", "content": "urgent" } }),
+        json!({ "type": "update", "table": "todos", "id": 4, "data": { "title": "A!" } }),
+        json!({ "type": "update", "table": "todos", "id": 4, "data": { "content": "done" } }),
+        json!({ "type": "update", "table": "todos", "id": 4, "data": { "content": "urgent" } }),
+        json!({ "type": "delete", "table": "todos", "id": 4 }),
+    ];
+    for operation in operations {
+        let operation = GranularOperation::try_from(operation).unwrap();
+        let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        for dispatcher in &dispatchers {
+            assert_eq!(dispatcher.process(&notification).await, ids.len());
+        }
+    }
+
+    for id in ids {
+        let [per_channel, shared] = [&channels[0][id], &channels[1][id]];
+        assert_eq!(per_channel.messages.lock().unwrap().len(), 5);
+        assert_eq!(
+            *per_channel.messages.lock().unwrap(),
+            *shared.messages.lock().unwrap()
+        );
+        assert_eq!(*per_channel.shared.lock().unwrap(), 0);
+        assert_eq!(*shared.shared.lock().unwrap(), 5);
+    }
+
+    // Groups subscribed to the same query are not served each other's payloads
+    for (id, group, index) in [("group-a", "a", 0), ("group-b", "b", 1)] {
+        for message in channels[1][id].messages.lock().unwrap().iter() {
+            let message: serde_json::Value = serde_json::from_str(message).unwrap();
+            assert_eq!(message["group"], group);
+            assert_eq!(message["queryIndex"], index);
+        }
+    }
+}
+
+/// Test that duplicate subscriptions of the same client are coalesced
+/// and reference counted
+#[tokio::test]
+async fn test_coalesce_duplicates() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let subscriptions = TableSubscriptions::new();
+    let first_channel = DummyChannel::default();
+    let second_channel = DummyChannel::default();
+    let other_client_channel = DummyChannel::default();
+
+    for (channel_id, channel, client_id) in [
+        ("first", &first_channel, "client"),
+        ("second", &second_channel, "client"),
+        ("other", &other_client_channel, "other-client"),
+    ] {
+        subscriptions
+            .subscribe(
+                channel_id,
+                Subscription::new(read_serialized_query("02_many.json"), channel.clone())
+                    .with_client_id(Some(client_id.to_string())),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(subscriptions.len().await, 2);
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    subscriptions.process(&notification).await;
+
+    // Each notification is delivered once per client
+    assert_eq!(
+        first_channel.messages().len() + second_channel.messages().len(),
+        1
+    );
+    assert_eq!(other_client_channel.messages().len(), 1);
+
+    // The coalesced subscription is kept until its last channel id unsubscribes
+    assert!(subscriptions.unsubscribe("first").await.is_none());
+    assert_eq!(subscriptions.len().await, 2);
+
+    subscriptions.process(&notification).await;
+    assert_eq!(
+        first_channel.messages().len() + second_channel.messages().len(),
+        2
+    );
+
+    assert!(subscriptions.unsubscribe("second").await.is_some());
+    assert!(subscriptions.unsubscribe("other").await.is_some());
+    assert!(subscriptions.is_empty().await);
+}
+
+/// Test that a coalesced channel id subscribing to another query releases the
+/// subscription it was coalesced into
+#[tokio::test]
+async fn test_resubscribe_alias() {
+    let subscriptions = TableSubscriptions::new();
+    let subscribe = |channel_id: &'static str, query: &'static str| {
+        let subscriptions = &subscriptions;
+        async move {
+            subscriptions
+                .subscribe(
+                    channel_id,
+                    Subscription::new(read_serialized_query(query), DummyChannel::default())
+                        .with_client_id(Some("client".to_string())),
+                )
+                .await
+                .unwrap();
+        }
+    };
+
+    subscribe("first", "02_many.json").await;
+    subscribe("second", "02_many.json").await;
+    assert_eq!(subscriptions.len().await, 1);
+
+    // The alias is dropped, and the owner is released once it unsubscribes
+    subscribe("second", "01_single.json").await;
+    assert_eq!(subscriptions.len().await, 2);
+    assert!(subscriptions.unsubscribe("first").await.is_some());
+    assert_eq!(subscriptions.len().await, 1);
+
+    assert!(subscriptions.unsubscribe("second").await.is_some());
+    assert!(subscriptions.is_empty().await);
+}
+
+/// Todo row with the given id
+fn todo(id: i32) -> Todo {
+    Todo {
+        id,
+        title: format!("Todo {id}"),
+        content: "Content".to_string(),
+    }
+}
+
+/// Test the effect of each kind of operation on a query, without any channel
+#[test]
+fn test_operation_matches() {
+    // Matches the todo with id 2 only
+    let query = read_serialized_query("04_many_with_condition.json");
+    let table = "todos".to_string();
+
+    let create = |id| OperationNotification::Create {
+        table: table.clone(),
+        data: todo(id),
+        client_ref: None,
+    };
+    assert_eq!(operation_matches(&query, &create(2)), MatchOutcome::Insert);
+    assert_eq!(operation_matches(&query, &create(3)), MatchOutcome::NoMatch);
+
+    let create_many = |ids: &[i32]| OperationNotification::CreateMany {
+        table: table.clone(),
+        data: ids.iter().copied().map(todo).collect(),
+        client_ref: None,
+    };
+    assert_eq!(
+        operation_matches(&query, &create_many(&[1, 2])),
+        MatchOutcome::Insert
+    );
+    assert_eq!(
+        operation_matches(&query, &create_many(&[1, 3])),
+        MatchOutcome::NoMatch
+    );
+
+    let update = |id: i32| OperationNotification::Update {
+        table: table.clone(),
+        id: FinalType::Number(id.into()),
+        data: todo(id),
+        previous: None,
+    };
+    assert_eq!(operation_matches(&query, &update(2)), MatchOutcome::Update);
+    // The updated row may have matched the query before the update
+    assert_eq!(operation_matches(&query, &update(3)), MatchOutcome::Leave);
+
+    let delete = |id: i32| OperationNotification::Delete {
+        table: table.clone(),
+        id: FinalType::Number(id.into()),
+        data: todo(id),
+    };
+    assert_eq!(operation_matches(&query, &delete(2)), MatchOutcome::Leave);
+    assert_eq!(operation_matches(&query, &delete(3)), MatchOutcome::NoMatch);
+
+    // Every operation matches a query without condition
+    let query = read_serialized_query("02_many.json");
+    assert_eq!(operation_matches(&query, &create(3)), MatchOutcome::Insert);
+    assert_eq!(operation_matches(&query, &update(3)), MatchOutcome::Update);
+
+    // Queries that cannot be evaluated locally are refetched
+    let query = read_serialized_query("13_distinct_on.json");
+    assert_eq!(operation_matches(&query, &create(2)), MatchOutcome::Refetch);
+}
+
+/// Test that the default ordering of a table applies to non-paginated `Many` fetches only
+#[tokio::test]
+async fn test_default_order() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let mut subscriptions = TableSubscriptions::<DummyChannel>::new();
+    subscriptions.set_default_order(OrderBy::Desc("id".to_string()));
+
+    let ids = |rows: serde_json::Value| -> Vec<i64> {
+        rows["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["id"].as_i64().unwrap())
+            .collect()
+    };
+
+    // The rows of a non-paginated query are ordered by the default ordering
+    let query = subscriptions.order(read_serialized_query("02_many.json"));
+    let rows = fetch_sqlite_query(&query, &pool).await.unwrap();
+    assert_eq!(ids(serialize_rows::<Todo, _>(&rows)), vec![3, 2, 1]);
+
+    // An explicit ordering takes precedence over the default ordering
+    let mut query = read_serialized_query("02_many.json");
+    query.order_by = Some(OrderBy::Asc("id".to_string()));
+    let rows = fetch_sqlite_query(&subscriptions.order(query), &pool)
+        .await
+        .unwrap();
+    assert_eq!(ids(serialize_rows::<Todo, _>(&rows)), vec![1, 2, 3]);
+
+    // Paginated and single-row queries are left untouched
+    let query = read_serialized_query("09_paginated_many.json");
+    assert!(subscriptions.order(query).order_by.is_none());
+    let query = read_serialized_query("01_single.json");
+    assert!(subscriptions.order(query).order_by.is_none());
+}
+
+/// Test that processing a notification reports the number of channels it reached
+#[tokio::test]
+async fn test_fan_out_count() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let subscriptions = TableSubscriptions::new();
+    let channels: Vec<DummyChannel> = (0..4).map(|_| DummyChannel::default()).collect();
+
+    // Three subscriptions match every row, the last one only matches the todo with id 2
+    for (index, channel) in channels.iter().enumerate() {
+        let query = if index < 3 {
+            "02_many.json"
+        } else {
+            "04_many_with_condition.json"
+        };
+        subscriptions
+            .subscribe(
+                &format!("channel-{index}"),
+                Subscription::new(read_serialized_query(query), channel.clone()),
+            )
+            .await
+            .unwrap();
+    }
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(subscriptions.process(&notification).await, 3);
+    assert!(channels[3].messages().is_empty());
+}
+
+/// Test that queries with correlated subqueries are refetched on changes of both tables
+#[tokio::test]
+async fn test_exists_refetch() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            Subscription::new(read_serialized_query("15_exists.json"), channel.clone()),
+        )
+        .await
+        .unwrap();
+
+    // Operation on the table of the query
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(subscriptions.process(&notification).await, 1);
+
+    // Operation on the table of the subquery
+    assert_eq!(subscriptions.refetch_dependents("comments").await, 1);
+    assert_eq!(subscriptions.refetch_dependents("other").await, 0);
+
+    assert_eq!(
+        channel.messages(),
+        vec![refetch_message(), refetch_message()]
+    );
+}
+
+/// Test the lifecycle of a subscription to a single row: other rows are never notified,
+/// updates replace the row and its deletion removes it
+#[tokio::test]
+async fn test_subscribe_by_id() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    let subscription = Subscription::by_id("todos", FinalType::Number(3.into()), channel.clone());
+
+    // The initial snapshot is the watched row
+    let rows = fetch_sqlite_query(&subscription.query, &pool)
+        .await
+        .unwrap();
+    let snapshot = serialize_rows::<Todo, _>(&rows);
+    assert_eq!(snapshot["type"], "single");
+    assert_eq!(snapshot["data"]["id"], 3);
+
+    subscriptions
+        .subscribe("channel", subscription)
+        .await
+        .unwrap();
+
+    let operations = [
+        // Other rows
+        json!({ "type": "create", "table": "todos", "data": { "title": "New", "content": "New" } }),
+        json!({ "type": "update", "table": "todos", "id": 2, "data": { "title": "Other" } }),
+        json!({ "type": "delete", "table": "todos", "id": 1 }),
+        // Watched row
+        json!({ "type": "update", "table": "todos", "id": 3, "data": { "title": "Watched" } }),
+        json!({ "type": "delete", "table": "todos", "id": 3 }),
+    ];
+    let mut notified = vec![];
+    for operation in operations {
+        let operation: GranularOperation = serde_json::from_value(operation).unwrap();
+        let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        notified.push(subscriptions.process(&notification).await);
+    }
+
+    assert_eq!(notified, vec![0, 0, 0, 1, 1]);
+    let messages = channel.messages();
+    assert_eq!(messages[0]["type"], "update");
+    assert_eq!(messages[0]["data"]["title"], "Watched");
+    assert_eq!(messages[1]["type"], "delete");
+    assert_eq!(messages[1]["id"], 3);
+}
+
+/// Test that updated rows that do not match a query are signaled as deleted,
+/// unless the subscription opted out of leave events
+#[tokio::test]
+async fn test_leave_events() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    // Both queries only match the todo with id 2
+    let emitting_channel = DummyChannel::default();
+    let silent_channel = DummyChannel::default();
+    let mut channels = HashMap::new();
+    channels.insert(
+        "emitting".to_string(),
+        Subscription::new(
+            read_serialized_query("04_many_with_condition.json"),
+            emitting_channel.clone(),
+        ),
+    );
+    channels.insert(
+        "silent".to_string(),
+        Subscription::new(
+            read_serialized_query("04_many_with_condition.json"),
+            silent_channel.clone(),
+        )
+        .with_leave_events(false),
+    );
+
+    // The todo with id 3 is filtered out
+    let operation = read_serialized_operation("03_update.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    let messages = emitting_channel.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "delete");
+    assert_eq!(messages[0]["id"], 3);
+    assert!(silent_channel.messages().is_empty());
+
+    // Deletions of matching rows are sent regardless
+    let operation =
+        serde_json::from_value(json!({ "type": "delete", "table": "todos", "id": 2 })).unwrap();
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(process_channel_event(&channels, &notification).is_empty());
+
+    assert_eq!(emitting_channel.messages().len(), 2);
+    assert_eq!(silent_channel.messages().len(), 1);
+    assert_eq!(silent_channel.messages()[0]["type"], "delete");
+}
+
+/// Subscription to the products in stock
+fn in_stock_subscription(channel: DummyChannel) -> Subscription<DummyChannel> {
+    let query = serde_json::from_value(json!({
+        "return": "many",
+        "table": "products",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "stock", "operator": ">", "value": 0 }
+        }
+    }))
+    .unwrap();
+    Subscription::new(query, channel)
+}
+
+/// Update notification of a product running out of stock
+fn out_of_stock_notification() -> OperationNotification<JsonObject> {
+    OperationNotification::Update {
+        table: "products".to_string(),
+        id: FinalType::Number(1.into()),
+        data: object_from_value(json!({ "warehouse": 1, "code": "A1", "stock": 0 })).unwrap(),
+        previous: None,
+    }
+}
+
+/// Test that rows leaving the results of a query are identified by the registered key
+/// columns of their table in the synthetic `Delete` notifications
+#[tokio::test]
+async fn test_leave_events_key_columns() {
+    for (key_columns, expected_id) in [
+        (None, json!(1)),
+        (Some(vec!["code"]), json!("A1")),
+        (
+            Some(vec!["warehouse", "code"]),
+            json!({ "warehouse": 1, "code": "A1" }),
+        ),
+    ] {
+        let mut subscriptions = TableSubscriptions::new();
+        if let Some(key_columns) = key_columns {
+            subscriptions.set_key_columns(key_columns.into_iter().map(String::from).collect());
+        }
+        let channel = DummyChannel::default();
+        subscriptions
+            .subscribe("channel", in_stock_subscription(channel.clone()))
+            .await
+            .unwrap();
+
+        assert_eq!(subscriptions.process(&out_of_stock_notification()).await, 1);
+
+        let messages = channel.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["type"], "delete");
+        assert_eq!(messages[0]["id"], expected_id);
+        assert_eq!(messages[0]["data"]["code"], "A1");
+    }
+}
+
+/// Test that tail subscriptions and subscriptions to a single row identify rows by the
+/// registered key columns of their table
+#[tokio::test]
+async fn test_held_rows_key_columns() {
+    let mut subscriptions = TableSubscriptions::new();
+    subscriptions.set_key_columns(vec!["code".to_string()]);
+
+    let snapshot = json!({
+        "type": "many",
+        "data": [{ "warehouse": 1, "code": "A1", "stock": 3 }]
+    });
+    let ids = subscriptions.snapshot_ids(&snapshot);
+    assert_eq!(ids, vec![json!("A1")]);
+
+    let tail_channel = DummyChannel::default();
+    let row_channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "tail",
+            in_stock_subscription(tail_channel.clone()).with_tail(ids),
+        )
+        .await
+        .unwrap();
+    subscriptions
+        .subscribe(
+            "row",
+            Subscription::by_id(
+                "products",
+                FinalType::String("A1".to_string()),
+                row_channel.clone(),
+            ),
+        )
+        .await
+        .unwrap();
+
+    // The `id` of the operation does not identify the rows of the table
+    let update = |code: &str| OperationNotification::<JsonObject>::Update {
+        table: "products".to_string(),
+        id: FinalType::Number(1.into()),
+        data: object_from_value(json!({ "warehouse": 1, "code": code, "stock": 2 })).unwrap(),
+        previous: None,
+    };
+    assert_eq!(subscriptions.process(&update("B2")).await, 0);
+    assert_eq!(subscriptions.process(&update("A1")).await, 2);
+
+    assert_eq!(tail_channel.messages().len(), 1);
+    assert_eq!(tail_channel.messages()[0]["data"]["code"], "A1");
+    assert_eq!(row_channel.messages().len(), 1);
+    assert_eq!(row_channel.messages()[0]["data"]["code"], "A1");
+}
+
+/// Test that subscriptions projecting their columns match notifications against the full
+/// rows, even when their condition references a column that is not projected
+#[tokio::test]
+async fn test_subscription_columns() {
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            in_stock_subscription(channel.clone()).with_columns(Some(vec!["code".to_string()])),
+        )
+        .await
+        .unwrap();
+
+    let restocked = OperationNotification::Update {
+        table: "products".to_string(),
+        id: FinalType::Number(1.into()),
+        data: object_from_value(json!({ "warehouse": 1, "code": "A1", "stock": 5 })).unwrap(),
+        previous: None,
+    };
+    assert_eq!(subscriptions.process(&restocked).await, 1);
+    assert_eq!(subscriptions.process(&out_of_stock_notification()).await, 1);
+
+    let messages = channel.messages();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["type"], "update");
+    assert_eq!(messages[0]["data"], json!({ "code": "A1" }));
+    assert_eq!(messages[1]["type"], "delete");
+    assert_eq!(messages[1]["id"], 1);
+    assert_eq!(messages[1]["data"], json!({ "code": "A1" }));
+}
+
+/// Test that the labels of the subscriptions are exposed by the inspection helper
+#[tokio::test]
+async fn test_inspect_labels() {
+    let subscriptions = TableSubscriptions::new();
+    let query = read_serialized_query("02_many.json");
+    subscriptions
+        .subscribe(
+            "b",
+            Subscription::new(query.clone(), DummyChannel::default())
+                .with_label(Some("TodoList".to_string())),
+        )
+        .await
+        .unwrap();
+    subscriptions
+        .subscribe("a", Subscription::new(query, DummyChannel::default()))
+        .await
+        .unwrap();
+
+    let infos = subscriptions.inspect().await;
+    assert_eq!(infos.len(), 2);
+    assert_eq!(infos[0].channel_id, "a");
+    assert_eq!(infos[0].label, None);
+    assert_eq!(infos[1].channel_id, "b");
+    assert_eq!(infos[1].table, "todos");
+    assert_eq!(infos[1].label.as_deref(), Some("TodoList"));
+    assert_eq!(
+        serde_json::to_value(&infos[1]).unwrap()["channelId"],
+        json!("b")
+    );
+
+    subscriptions.unsubscribe("b").await;
+    assert_eq!(subscriptions.inspect().await.len(), 1);
+}
+
+/// Test that subscriptions asking for an acknowledgment receive a `subscribed` message
+/// once registered, even when their query matches no rows
+#[tokio::test]
+async fn test_subscription_ack() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "=", "value": 999 }
+        }
+    }))
+    .unwrap();
+    let rows = fetch_sqlite_query(&query, &pool).await.unwrap();
+    let snapshot = serialize_rows::<Todo, _>(&rows);
+    assert_eq!(snapshot["data"], json!([]));
+
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            Subscription::new(query.clone(), channel.clone())
+                .with_request_id(Some("sub-1".to_string()))
+                .with_ack(true),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        channel.messages(),
+        vec![json!({ "type": "subscribed", "channelId": "channel", "requestId": "sub-1" })]
+    );
+
+    // Without acknowledgment, nothing is sent until a notification
+    let silent = DummyChannel::default();
+    subscriptions
+        .subscribe("silent", Subscription::new(query, silent.clone()))
+        .await
+        .unwrap();
+    assert!(silent.messages().is_empty());
+}
+
+/// Test that operations on read-only tables (views) are rejected up front,
+/// while their queries can still be fetched
+#[tokio::test]
+async fn test_read_only_view() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    sqlx::query("CREATE VIEW todos_view AS SELECT * FROM todos")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let mut subscriptions = TableSubscriptions::<DummyChannel>::new();
+    subscriptions.set_read_only(true);
+
+    let query: QueryTree =
+        serde_json::from_value(json!({ "return": "many", "table": "todos_view" })).unwrap();
+    let rows = fetch_sqlite_query(&query, &pool).await.unwrap();
+    assert_eq!(
+        serialize_rows::<Todo, _>(&rows)["data"]
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+
+    let create: GranularOperation = serde_json::from_value(json!({
+        "type": "create",
+        "table": "todos_view",
+        "data": { "title": "New todo", "content": "Content" }
+    }))
+    .unwrap();
+    let err = subscriptions.check_operation(&create).unwrap_err();
+    assert!(matches!(&err, OperationError::ReadOnly(table) if table == "todos_view"));
+    assert_eq!(err.to_string(), "Table todos_view is read-only");
+}
+
+/// Test that subscriptions splitting batches receive a `CreateMany` as one `Create`
+/// notification per matching row, while counting as a single notified channel
+#[tokio::test]
+async fn test_split_batches() {
+    let subscriptions = TableSubscriptions::new();
+    let batched = DummyChannel::default();
+    let split = DummyChannel::default();
+    let query = read_serialized_query("02_many.json");
+    subscriptions
+        .subscribe("batched", Subscription::new(query.clone(), batched.clone()))
+        .await
+        .unwrap();
+    subscriptions
+        .subscribe(
+            "split",
+            Subscription::new(query, split.clone()).with_split_batches(true),
+        )
+        .await
+        .unwrap();
+
+    let rows: Vec<JsonObject> = (1..=3)
+        .map(|id| {
+            object_from_value(json!({ "id": id, "title": format!("Todo {id}"), "content": "" }))
+                .unwrap()
+        })
+        .collect();
+    let notification = OperationNotification::CreateMany {
+        table: "todos".to_string(),
+        data: rows,
+        client_ref: None,
+    };
+    assert_eq!(subscriptions.process(&notification).await, 2);
+
+    let messages = batched.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "create_many");
+
+    let messages = split.messages();
+    assert_eq!(messages.len(), 3);
+    for (index, message) in messages.iter().enumerate() {
+        assert_eq!(message["type"], "create");
+        assert_eq!(message["data"]["id"], index + 1);
+    }
+}
+
+/// Test that bulk updates and deletions are split per subscription, each channel
+/// only receiving the affected rows that match its own query
+#[tokio::test]
+async fn test_bulk_split() {
+    let subscriptions = TableSubscriptions::new();
+    let low = DummyChannel::default();
+    let high = DummyChannel::default();
+    let query = |operator: &str| -> QueryTree {
+        serde_json::from_value(json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": operator, "value": 3 }
+            }
+        }))
+        .unwrap()
+    };
+    subscriptions
+        .subscribe("low", Subscription::new(query("<"), low.clone()))
+        .await
+        .unwrap();
+    subscriptions
+        .subscribe("high", Subscription::new(query(">="), high.clone()))
+        .await
+        .unwrap();
+
+    let rows = |ids: &[i64]| -> Vec<JsonObject> {
+        ids.iter()
+            .map(|id| {
+                object_from_value(json!({ "id": id, "title": "Todo", "content": "" })).unwrap()
+            })
+            .collect()
+    };
+    let ids = |message: &serde_json::Value| -> Vec<i64> {
+        message["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|row| row["id"].as_i64().unwrap())
+            .collect()
+    };
+
+    // Each subscription learns about its own subset of the deleted rows
+    let notification = OperationNotification::DeleteMany {
+        table: "todos".to_string(),
+        data: rows(&[1, 2, 4]),
+    };
+    assert_eq!(subscriptions.process(&notification).await, 2);
+
+    let messages = low.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "delete_many");
+    assert_eq!(ids(&messages[0]), vec![1, 2]);
+
+    let messages = high.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["type"], "delete_many");
+    assert_eq!(ids(&messages[0]), vec![4]);
+
+    // Rows outside every query notify no one
+    let notification = OperationNotification::DeleteMany {
+        table: "todos".to_string(),
+        data: rows(&[1, 2]),
+    };
+    assert_eq!(
+        operation_matches(&query(">="), &notification),
+        MatchOutcome::NoMatch
+    );
+    assert_eq!(subscriptions.process(&notification).await, 1);
+    assert_eq!(high.messages().len(), 1);
+
+    // Updated rows that do not match anymore leave the query results
+    let notification = OperationNotification::UpdateMany {
+        table: "todos".to_string(),
+        data: rows(&[2, 5]),
+    };
+    assert_eq!(subscriptions.process(&notification).await, 2);
+
+    let messages = low.messages();
+    assert_eq!(messages.len(), 4);
+    assert_eq!(messages[2]["type"], "update_many");
+    assert_eq!(ids(&messages[2]), vec![2]);
+    assert_eq!(messages[3]["type"], "delete_many");
+    assert_eq!(ids(&messages[3]), vec![5]);
+
+    let messages = high.messages();
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[1]["type"], "update_many");
+    assert_eq!(ids(&messages[1]), vec![5]);
+    assert_eq!(messages[2]["type"], "delete_many");
+    assert_eq!(ids(&messages[2]), vec![2]);
+}
+
+/// Test that admin subscriptions receive the notifications of every table,
+/// and that the access control hook refuses unauthorized clients
+#[tokio::test]
+async fn test_admin_subscriptions() {
+    let mut admin = AdminSubscriptions::new();
+
+    // Without authorizer, admin subscriptions are refused
+    assert!(matches!(
+        admin
+            .subscribe("monitor", "admin", DummyChannel::default())
+            .await,
+        Err(ChannelError::Forbidden)
+    ));
+
+    admin.set_authorizer(Box::new(|client_id| client_id == "monitor"));
+    assert!(matches!(
+        admin
+            .subscribe("main", "admin", DummyChannel::default())
+            .await,
+        Err(ChannelError::Forbidden)
+    ));
+    let channel = DummyChannel::default();
+    admin
+        .subscribe("monitor", "admin", channel.clone())
+        .await
+        .unwrap();
+
+    for (table, origin) in [("todos", Some("main")), ("users", None)] {
+        let notification = OperationNotification::Create {
+            table: table.to_string(),
+            data: object_from_value(json!({ "id": 1 })).unwrap(),
+            client_ref: None,
+        };
+        let value = serde_json::to_value(&notification).unwrap();
+        assert_eq!(admin.process(&value, origin).await, 1);
+    }
+
+    let messages = channel.messages();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["type"], "create");
+    assert_eq!(messages[0]["table"], "todos");
+    assert_eq!(messages[0]["origin"], "main");
+    assert_eq!(messages[1]["table"], "users");
+    assert!(messages[1].get("origin").is_none());
+
+    admin.unsubscribe("admin").await;
+    assert!(admin.is_empty().await);
+}
+
+/// A mock global event emitter recording the events emitted through it
+#[derive(Clone, Default)]
+struct MockEmitter {
+    events: Arc<Mutex<Vec<(String, serde_json::Value)>>>,
+}
+
+impl EventEmitter for MockEmitter {
+    fn emit_value(&self, event: &str, payload: serde_json::Value) -> Result<(), ChannelError> {
+        self.events
+            .lock()
+            .unwrap()
+            .push((event.to_string(), payload));
+        Ok(())
+    }
+}
+
+/// Test that event channels emit notifications on the event of their table,
+/// tagged with the channel id of their subscription
+#[tokio::test]
+async fn test_event_channel() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let emitter = MockEmitter::default();
+    let subscriptions = TableSubscriptions::new();
+    subscriptions
+        .subscribe(
+            "events",
+            Subscription::new(
+                read_serialized_query("02_many.json"),
+                EventChannel::new(emitter.clone(), "todos", "events"),
+            ),
+        )
+        .await
+        .unwrap();
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(subscriptions.process(&notification).await, 1);
+
+    let events = emitter.events.lock().unwrap().clone();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].0, "real-time-sqlx:todos");
+    assert_eq!(
+        events[0].1,
+        json!({
+            "type": "create",
+            "table": "todos",
+            "data": { "id": 4, "title": "Fourth todo", "content": "This is the fourth todo" },
+            "channelId": "events"
+        })
+    );
+}
+
+/// Refetch of a `Many` snapshot holding a single value, after a delay
+async fn slow_refetch(value: i64, delay: Duration) -> Result<serde_json::Value, QueryError> {
+    tokio::time::sleep(delay).await;
+    Ok(json!({ "type": "many", "data": [value] }))
+}
+
+/// Test that a refetch exceeding its soft timeout serves the cached snapshot of the
+/// subscription, then pushes the fresh snapshot through its channel
+#[tokio::test]
+async fn test_refetch_soft_timeout() {
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            Subscription::new(read_serialized_query("02_many.json"), channel.clone()),
+        )
+        .await
+        .unwrap();
+    let soft_timeout = Duration::from_millis(20);
+
+    // Without cached snapshot, the refetch is awaited
+    let value = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, slow_refetch(1, soft_timeout * 2))
+        .await
+        .unwrap();
+    assert_eq!(value, json!({ "type": "many", "data": [1] }));
+
+    // A fast refetch is returned directly
+    let value = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, slow_refetch(2, Duration::ZERO))
+        .await
+        .unwrap();
+    assert_eq!(value, json!({ "type": "many", "data": [2] }));
+    assert!(channel.messages().is_empty());
+
+    // A slow refetch serves the stale snapshot, then pushes the fresh one
+    let value = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, slow_refetch(3, soft_timeout * 5))
+        .await
+        .unwrap();
+    assert_eq!(value, json!({ "type": "many", "data": [2], "stale": true }));
+    assert!(channel.messages().is_empty());
+
+    tokio::time::sleep(soft_timeout * 10).await;
+    assert_eq!(
+        channel.messages(),
+        vec![json!({ "type": "many", "data": [3] })]
+    );
+
+    // A failed refetch is returned, and does not replace the cached snapshot
+    let result = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, async {
+            Err::<serde_json::Value, _>(QueryError::Cancelled)
+        })
+        .await;
+    assert!(matches!(result, Err(QueryError::Cancelled)));
+
+    // The fresh snapshot was cached
+    let value = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, slow_refetch(4, soft_timeout * 5))
+        .await
+        .unwrap();
+    assert_eq!(value, json!({ "type": "many", "data": [3], "stale": true }));
+
+    // Snapshots of unsubscribed channels are dropped
+    subscriptions.unsubscribe("channel").await;
+    let value = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, slow_refetch(5, soft_timeout * 2))
+        .await
+        .unwrap();
+    assert_eq!(value, json!({ "type": "many", "data": [5] }));
+}
+
+/// Test that the initial snapshot of a subscription is served by its first refetch
+/// exceeding the soft timeout
+#[tokio::test]
+async fn test_refetch_soft_timeout_initial_snapshot() {
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            Subscription::new(read_serialized_query("02_many.json"), channel.clone())
+                .with_snapshot(json!({ "type": "many", "data": [0] })),
+        )
+        .await
+        .unwrap();
+    let soft_timeout = Duration::from_millis(20);
+
+    let value = subscriptions
+        .refetch_with_soft_timeout("channel", soft_timeout, slow_refetch(1, soft_timeout * 5))
+        .await
+        .unwrap();
+    assert_eq!(value, json!({ "type": "many", "data": [0], "stale": true }));
+
+    tokio::time::sleep(soft_timeout * 10).await;
+    assert_eq!(
+        channel.messages(),
+        vec![json!({ "type": "many", "data": [1] })]
+    );
+}
+
+/// Test that inserts into ordered subscriptions carry the sort key of the created rows,
+/// even when the ordering column is not selected
+#[tokio::test]
+async fn test_ordered_insert_sort_key() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let mut ordered = read_serialized_query("02_many.json");
+    ordered.order_by = Some(OrderBy::Desc("title".to_string()));
+    let ordered_channel = DummyChannel::default();
+    let unordered_channel = DummyChannel::default();
+
+    let subscriptions = TableSubscriptions::new();
+    subscriptions
+        .subscribe(
+            "ordered",
+            Subscription::new(ordered, ordered_channel.clone())
+                .with_columns(Some(vec!["id".to_string()])),
+        )
+        .await
+        .unwrap();
+    subscriptions
+        .subscribe(
+            "unordered",
+            Subscription::new(
+                read_serialized_query("02_many.json"),
+                unordered_channel.clone(),
+            ),
+        )
+        .await
+        .unwrap();
+
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    subscriptions.process(&notification).await;
+
+    let OperationNotification::Create { data, .. } = &notification else {
+        panic!("Expected a create operation");
+    };
+    let messages = ordered_channel.messages();
+    assert_eq!(messages[0]["data"], json!({ "id": data.id }));
+    assert_eq!(
+        messages[0]["sortKey"],
+        json!({ "column": "title", "order": "desc", "value": data.title })
+    );
+    assert!(unordered_channel.messages()[0].get("sortKey").is_none());
+}
+
+/// Test that a tail subscription to the latest rows forwards new rows and the changes
+/// of the rows it holds, but not the changes of older rows
+#[tokio::test]
+async fn test_tail_subscription() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let rows: Vec<serde_json::Value> = (0..30)
+        .map(|index| json!({ "title": format!("Todo {index}"), "content": "" }))
+        .collect();
+    let operation = GranularOperation::try_from(
+        json!({ "type": "create_many", "table": "todos", "data": rows }),
+    )
+    .unwrap();
+    granular_operation_sqlite::<_, Todo>(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The latest 20 rows, with ids from 14 to 33
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "paginate": { "perPage": 20, "orderBy": { "column": "id", "order": "desc" } }
+    }))
+    .unwrap();
+    let snapshot = serialize_rows::<Todo, _>(&fetch_sqlite_query(&query, &pool).await.unwrap());
+    let ids = snapshot_ids(&snapshot, None);
+    assert_eq!(ids.len(), 20);
+    assert_eq!(ids[0], json!(33));
+
+    let channel = DummyChannel::default();
+    let subscriptions = TableSubscriptions::new();
+    subscriptions
+        .subscribe(
+            "tail",
+            Subscription::new(query, channel.clone()).with_tail(ids),
+        )
+        .await
+        .unwrap();
+
+    let execute = |operation: serde_json::Value| {
+        let pool = pool.clone();
+        let subscriptions = &subscriptions;
+        async move {
+            let operation = GranularOperation::try_from(operation).unwrap();
+            let notification: OperationNotification<Todo> =
+                granular_operation_sqlite(operation, &pool)
+                    .await
+                    .unwrap()
+                    .unwrap();
+            subscriptions.process(&notification).await
+        }
+    };
+    let update = |id: i64| json!({ "type": "update", "table": "todos", "id": id, "data": { "title": "Updated" } });
+    let delete = |id: i64| json!({ "type": "delete", "table": "todos", "id": id });
+
+    // New rows are forwarded, and held from then on
+    let create =
+        json!({ "type": "create", "table": "todos", "data": { "title": "New", "content": "" } });
+    assert_eq!(execute(create).await, 1);
+    assert_eq!(execute(update(34)).await, 1);
+
+    // Changes of held rows are forwarded, but not those of older rows
+    assert_eq!(execute(update(1)).await, 0);
+    assert_eq!(execute(delete(2)).await, 0);
+    assert_eq!(execute(update(20)).await, 1);
+    assert_eq!(execute(delete(20)).await, 1);
+
+    let kinds: Vec<_> = channel
+        .messages()
+        .iter()
+        .map(|message| (message["type"].clone(), message["data"]["id"].clone()))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (json!("create"), json!(34)),
+            (json!("update"), json!(34)),
+            (json!("update"), json!(20)),
+            (json!("delete"), json!(20)),
+        ]
+    );
+}
+
+/// Test that pruning stale channels removes the dead ones only, probed or idle
+#[tokio::test]
+async fn test_prune_stale() {
+    let subscriptions = TableSubscriptions::new();
+    let alive = DummyChannel::default();
+    let dead = DummyChannel::default();
+    for (channel_id, channel) in [("alive", &alive), ("dead", &dead)] {
+        subscriptions
+            .subscribe(
+                channel_id,
+                Subscription::new(read_serialized_query("02_many.json"), channel.clone()),
+            )
+            .await
+            .unwrap();
+    }
+
+    // The client of the dead channel vanished without unsubscribing
+    dead.close();
+    assert_eq!(subscriptions.prune_stale(Liveness::Probe).await, 1);
+    assert_eq!(subscriptions.len().await, 1);
+    assert_eq!(alive.messages(), vec![json!({ "type": "ping" })]);
+
+    // Idle channels are pruned unless their client touched them
+    let timeout = Duration::from_millis(50);
+    tokio::time::sleep(timeout).await;
+    assert!(subscriptions.touch("alive").await);
+    assert!(!subscriptions.touch("dead").await);
+    assert_eq!(subscriptions.prune_stale(Liveness::Idle(timeout)).await, 0);
+    tokio::time::sleep(timeout).await;
+    assert_eq!(subscriptions.prune_stale(Liveness::Idle(timeout)).await, 1);
+    assert!(subscriptions.is_empty().await);
+}
+
+/// Test that the background pruning task removes dead channels periodically
+#[tokio::test]
+async fn test_stale_pruning_task() {
+    let subscriptions = Arc::new(TableSubscriptions::new());
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            Subscription::new(read_serialized_query("02_many.json"), channel.clone()),
+        )
+        .await
+        .unwrap();
+
+    let interval = Duration::from_millis(10);
+    let task = subscriptions.spawn_stale_pruning(StalePruning::new(interval));
+    channel.close();
+    tokio::time::sleep(interval * 5).await;
+    assert!(subscriptions.is_empty().await);
+
+    // The task stops with the subscriptions
+    subscriptions.shutdown().await;
+    tokio::time::timeout(interval * 5, task)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+/// Test that the queries of a subscription group are notified with the group name and
+/// their index, and torn down together in a single call
+#[tokio::test]
+async fn test_subscription_group() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query = |operator: &str, id: i64| -> QueryTree {
+        serde_json::from_value(json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": operator, "value": id }
+            }
+        }))
+        .unwrap()
+    };
+
+    let channel = DummyChannel::default();
+    let other = DummyChannel::default();
+    let subscriptions = TableSubscriptions::new();
+    for (index, query) in [query("<=", 3), query(">", 1)].into_iter().enumerate() {
+        subscriptions
+            .subscribe(
+                &group_channel_id("view", index),
+                Subscription::new(query, channel.clone()).with_group("view", index),
+            )
+            .await
+            .unwrap();
+    }
+    subscriptions
+        .subscribe("other", Subscription::new(query(">", 0), other.clone()))
+        .await
+        .unwrap();
+
+    // Row 3 matches both queries of the group
+    let operation = read_serialized_operation("03_update.json");
+    let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(subscriptions.process(&notification).await, 3);
+
+    let mut tags: Vec<_> = channel
+        .messages()
+        .iter()
+        .map(|message| (message["group"].clone(), message["queryIndex"].clone()))
+        .collect();
+    tags.sort_by_key(|(_, index)| index.as_u64());
+    assert_eq!(
+        tags,
+        vec![(json!("view"), json!(0)), (json!("view"), json!(1))]
+    );
+    assert!(other.messages()[0].get("group").is_none());
+
+    // The whole group is torn down at once, leaving the other subscription
+    assert_eq!(subscriptions.unsubscribe_group("view").await, 2);
+    assert_eq!(subscriptions.unsubscribe_group("view").await, 0);
+    assert_eq!(subscriptions.len().await, 1);
+    assert_eq!(subscriptions.process(&notification).await, 1);
+    assert_eq!(channel.messages().len(), 2);
+}
+
+/// Test that a burst of operations within the refetch window of a throttled subscription
+/// triggers a single refetch, sent once the window elapsed, which sees the final state
+#[tokio::test]
+async fn test_refetch_throttling() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    sqlx::query("CREATE TABLE comments (id INTEGER PRIMARY KEY, todo_id INTEGER, content TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let window = Duration::from_millis(100);
+    let query = read_serialized_query("15_exists.json");
+    let subscriptions = TableSubscriptions::new();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe(
+            "channel",
+            Subscription::new(query.clone(), channel.clone()).with_refetch_window(Some(window)),
+        )
+        .await
+        .unwrap();
+
+    // A burst of todos, each with a comment matching the subquery
+    for _ in 0..5 {
+        let operation = read_serialized_operation("01_create.json");
+        let notification: OperationNotification<Todo> = granular_operation_sqlite(operation, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        let OperationNotification::Create { data, .. } = &notification else {
+            panic!("Expected a creation notification");
+        };
+        sqlx::query("INSERT INTO comments (todo_id, content) VALUES (?, 'great')")
+            .bind(data.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(subscriptions.process(&notification).await, 0);
+        assert_eq!(subscriptions.refetch_dependents("comments").await, 0);
+    }
+
+    // The refetch is deferred until the window elapsed
+    assert_eq!(subscriptions.flush_refetches().await, 0);
+    assert!(channel.messages().is_empty());
+
+    tokio::time::sleep(window).await;
+    assert_eq!(subscriptions.flush_refetches().await, 1);
+    assert_eq!(subscriptions.flush_refetches().await, 0);
+    assert_eq!(channel.messages(), vec![refetch_message()]);
+
+    // The refetch happens after the whole burst
+    let rows = fetch_sqlite_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many();
+    assert_eq!(rows.len(), 5);
+
+    // Unthrottled subscriptions are refetched on every operation
+    let other = DummyChannel::default();
+    subscriptions
+        .subscribe("other", Subscription::new(query, other.clone()))
+        .await
+        .unwrap();
+    assert_eq!(subscriptions.refetch_dependents("comments").await, 1);
+    assert_eq!(subscriptions.refetch_dependents("comments").await, 1);
+    assert_eq!(other.messages().len(), 2);
+}
@@ -1,9 +1,18 @@
 //! Dummy data for testing
 
-use std::fs;
+use std::{
+    env, fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use serde::{Deserialize, Serialize};
-use sqlx::{prelude::FromRow, Pool, Sqlite};
+use sqlx::{prelude::FromRow, Database, Executor, IntoArguments, MySql, Pool, Postgres, Sqlite};
+
+use crate::{dispatcher::NotificationChannel, error::ChannelError, snapshots::Identifiable};
 
 /// A dummy struct for testing purposes
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Eq, PartialEq)]
@@ -13,21 +22,47 @@ pub struct Todo {
     pub content: String,
 }
 
-#[cfg(feature = "sqlite")]
-/// Create an in-memory Sqlite database and return a pool connection
-pub async fn dummy_sqlite_database() -> Pool<Sqlite> {
-    sqlx::sqlite::SqlitePoolOptions::new()
-        .connect("sqlite::memory:")
-        .await
-        .expect("Failed to create an in-memory sqlite database")
+impl Identifiable for Todo {}
+
+/// A dummy channel that records the notifications sent through it
+#[derive(Debug, Clone, Default)]
+pub struct DummyChannel {
+    messages: Arc<Mutex<Vec<serde_json::Value>>>,
+    closed: Arc<AtomicBool>,
 }
 
-#[cfg(feature = "sqlite")]
-/// Create and seed a Sqlite database from a pool connection
-pub async fn prepare_dummy_sqlite_database(pool: &Pool<Sqlite>) {
+impl DummyChannel {
+    /// Get a copy of the notifications received so far
+    pub fn messages(&self) -> Vec<serde_json::Value> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Simulate a client that vanished: sending through the channel fails from now on
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+impl NotificationChannel for DummyChannel {
+    fn send_value(&self, value: serde_json::Value) -> Result<(), ChannelError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(ChannelError::Closed);
+        }
+        self.messages.lock().unwrap().push(value);
+        Ok(())
+    }
+}
+
+/// Execute the dummy database creation script, followed by the shared insertion script
+async fn seed_dummy_database<DB>(pool: &Pool<DB>, create_script: &str)
+where
+    DB: Database,
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: IntoArguments<'q, DB>,
+{
     let mut tx = pool.begin().await.unwrap();
 
-    let create_stmt = fs::read_to_string("src/tests/sql/01_create.sql").unwrap();
+    let create_stmt = fs::read_to_string(Path::new("src/tests/sql").join(create_script)).unwrap();
     let query = sqlx::query(&create_stmt);
 
     query
@@ -47,3 +82,62 @@ pub async fn prepare_dummy_sqlite_database(pool: &Pool<Sqlite>) {
         .await
         .expect("Failed to prepare a dummy database");
 }
+
+#[cfg(feature = "sqlite")]
+/// Create an in-memory Sqlite database and return a pool connection
+pub async fn dummy_sqlite_database() -> Pool<Sqlite> {
+    sqlx::sqlite::SqlitePoolOptions::new()
+        .connect("sqlite::memory:")
+        .await
+        .expect("Failed to create an in-memory sqlite database")
+}
+
+#[cfg(feature = "sqlite")]
+/// Create and seed a Sqlite database from a pool connection
+pub async fn prepare_dummy_sqlite_database(pool: &Pool<Sqlite>) {
+    seed_dummy_database(pool, "01_create.sql").await;
+}
+
+#[cfg(feature = "postgres")]
+/// Connect to the PostgreSQL database given by the `POSTGRES_DATABASE_URL`
+/// environment variable, if set. The pool holds a single connection, so that
+/// the temporary dummy table is isolated to the current test.
+pub async fn dummy_postgres_database() -> Option<Pool<Postgres>> {
+    let url = env::var("POSTGRES_DATABASE_URL").ok()?;
+
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&url)
+        .await
+        .expect("Failed to connect to the PostgreSQL database");
+
+    Some(pool)
+}
+
+#[cfg(feature = "postgres")]
+/// Create and seed a temporary PostgreSQL dummy table from a pool connection
+pub async fn prepare_dummy_postgres_database(pool: &Pool<Postgres>) {
+    seed_dummy_database(pool, "01_create_postgres.sql").await;
+}
+
+#[cfg(feature = "mysql")]
+/// Connect to the MySQL database given by the `MYSQL_DATABASE_URL`
+/// environment variable, if set. The pool holds a single connection, so that
+/// the temporary dummy table is isolated to the current test.
+pub async fn dummy_mysql_database() -> Option<Pool<MySql>> {
+    let url = env::var("MYSQL_DATABASE_URL").ok()?;
+
+    let pool = sqlx::mysql::MySqlPoolOptions::new()
+        .max_connections(1)
+        .connect(&url)
+        .await
+        .expect("Failed to connect to the MySQL database");
+
+    Some(pool)
+}
+
+#[cfg(feature = "mysql")]
+/// Create and seed a temporary MySQL dummy table from a pool connection
+pub async fn prepare_dummy_mysql_database(pool: &Pool<MySql>) {
+    seed_dummy_database(pool, "01_create_mysql.sql").await;
+}
@@ -5,16 +5,29 @@
 //                     TESTING AGAINST SQLITE BACKEND                        //
 // ************************************************************************* //
 
-use sqlx::FromRow;
+use serde::Serialize;
+use serde_json::{json, Number};
+use sqlx::{FromRow, Row};
 
 use crate::{
-    database::sqlite::fetch_sqlite_query,
-    operations::serialize::object_from_value,
-    queries::{serialize::QueryTree, Checkable},
+    database::{
+        postgres::fetch_postgres_query,
+        sqlite::{fetch_sqlite_query, sqlite_row_to_json},
+        DbKind,
+    },
+    operations::serialize::{object_from_value, JsonObject},
+    queries::{
+        serialize::{Constraint, ConstraintValue, FinalType, Operator, QueryTree, ScalarFunction},
+        Checkable, PatternCache,
+    },
+    utils::prefix_upper_bound,
 };
 
 use super::{
-    dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database, Todo},
+    dummy::{
+        dummy_postgres_database, dummy_sqlite_database, prepare_dummy_postgres_database,
+        prepare_dummy_sqlite_database, Todo,
+    },
     utils::read_serialized_query,
 };
 
@@ -68,7 +81,7 @@ async fn test_engine_many() {
     prepare_dummy_sqlite_database(&pool).await;
 
     let query = read_serialized_query("02_many.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
     let all_rows = result.unwrap_many();
 
     let engine_todos = filter_todos(&query);
@@ -83,7 +96,7 @@ async fn test_engine_single_with_condition() {
     prepare_dummy_sqlite_database(&pool).await;
 
     let query = read_serialized_query("03_single_with_condition.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
     let single_row = Todo::from_row(&result.unwrap_single()).unwrap();
 
     let engine_todos = filter_todos(&query);
@@ -99,7 +112,7 @@ async fn test_engine_many_with_condition() {
     prepare_dummy_sqlite_database(&pool).await;
 
     let query = read_serialized_query("04_many_with_condition.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
     let single_row = Todo::from_row(&result.unwrap_many()[0]).unwrap();
 
     let engine_todos = filter_todos(&query);
@@ -115,7 +128,7 @@ async fn test_engine_nested_or() {
     prepare_dummy_sqlite_database(&pool).await;
 
     let query = read_serialized_query("05_nested_or.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
     let all_rows = result.unwrap_many();
 
     let engine_todos = filter_todos(&query);
@@ -130,7 +143,7 @@ async fn test_engine_empty() {
     prepare_dummy_sqlite_database(&pool).await;
 
     let query = read_serialized_query("06_empty.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
     let single_row = result.unwrap_optional_single();
 
     let engine_todos = filter_todos(&query);
@@ -139,6 +152,48 @@ async fn test_engine_empty() {
     assert_eq!(engine_todos.len(), 0);
 }
 
+/// Test that a `NOT` wrapping an `OR` gives the same rows in SQLite and in the engine
+#[tokio::test]
+async fn test_engine_not_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query = read_serialized_query("16_not_or.json");
+    let all_rows = fetch_sqlite_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many()
+        .into_iter()
+        .map(|r| Todo::from_row(&r).unwrap())
+        .collect::<Vec<Todo>>();
+
+    let engine_todos = filter_todos(&query);
+
+    assert_eq!(engine_todos, all_rows);
+    assert_eq!(engine_todos.len(), 1);
+    assert_eq!(engine_todos[0].id, 2);
+}
+
+/// Test that a `NOT` wrapping an `OR` gives the same rows in PostgreSQL and in the engine
+#[tokio::test]
+async fn test_engine_not_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+
+    let query = read_serialized_query("16_not_or.json");
+    let all_rows = fetch_postgres_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many()
+        .into_iter()
+        .map(|r| Todo::from_row(&r).unwrap())
+        .collect::<Vec<Todo>>();
+
+    assert_eq!(filter_todos(&query), all_rows);
+}
+
 /// Test `IN` operations with arrays
 #[tokio::test]
 async fn test_engine_in() {
@@ -146,7 +201,7 @@ async fn test_engine_in() {
     prepare_dummy_sqlite_database(&pool).await;
 
     let query = read_serialized_query("07_in.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
     let all_rows = result
         .unwrap_many()
         .into_iter()
@@ -157,3 +212,767 @@ async fn test_engine_in() {
 
     assert_eq!(engine_todos, all_rows);
 }
+
+/// Test that `IN` lists mixing value types never match in the engine,
+/// consistently with their rejection by validation
+#[test]
+fn test_engine_in_mixed_types() {
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "in", "value": [1, "two", true] }
+        }
+    }))
+    .unwrap();
+
+    assert!(query.validate().is_err());
+    assert!(filter_todos(&query).is_empty());
+}
+
+/// Test that constraints on columns absent from the object (such as projected out
+/// columns), or on values that cannot be compared, do not match instead of panicking
+#[test]
+fn test_engine_missing_column() {
+    let object =
+        object_from_value(json!({ "id": 1, "title": "First todo", "tags": ["urgent"] })).unwrap();
+    let constraint = |constraint: serde_json::Value| -> Constraint {
+        serde_json::from_value(constraint).unwrap()
+    };
+
+    let missing = [
+        json!({ "column": "content", "operator": "=", "value": "Content" }),
+        json!({ "column": "content", "operator": "!=", "value": "Content" }),
+        json!({ "column": "content", "operator": "like", "value": "%" }),
+        json!({ "column": "content", "operator": "contains", "value": ["urgent"] }),
+        json!({ "column": "id", "operator": "=", "value": { "column": "content" } }),
+        json!({ "column": "tags", "operator": "contains", "value": { "column": "content" } }),
+        // Arrays cannot be compared as final values
+        json!({ "column": "tags", "operator": "=", "value": "urgent" }),
+        json!({ "column": "id", "operator": "=", "value": { "column": "tags" } }),
+    ];
+    for value in missing {
+        assert!(!constraint(value.clone()).check(&object), "{value}");
+    }
+
+    // Other constraints of the condition are still evaluated
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "or",
+            "conditions": [
+                { "type": "single", "constraint": { "column": "content", "operator": "=", "value": "x" } },
+                { "type": "single", "constraint": { "column": "id", "operator": "=", "value": 1 } }
+            ]
+        }
+    }))
+    .unwrap();
+    assert!(query.check(&object));
+}
+
+/// Test that values incompatible with their operator, which are rejected by validation,
+/// do not match in the engine instead of panicking
+#[test]
+fn test_engine_incompatible_values() {
+    let object = object_from_value(json!({ "id": 1, "title": "First todo" })).unwrap();
+    let constraint = |constraint: serde_json::Value| -> Constraint {
+        serde_json::from_value(constraint).unwrap()
+    };
+
+    let incompatible = [
+        // Scalar values for list operators
+        json!({ "column": "id", "operator": "in", "value": 1 }),
+        json!({ "column": "id", "operator": "not_in", "value": 2 }),
+        json!({ "column": "id", "operator": ">all", "value": 0 }),
+        json!({ "column": "id", "operator": "<any", "value": 2 }),
+        // Columns compared with quantified operators
+        json!({ "column": "id", "operator": "<=any", "value": { "column": "id" } }),
+        // Lists for scalar operators
+        json!({ "column": "id", "operator": "=", "value": [1] }),
+        // JSON values outside of containment
+        json!({ "column": "id", "operator": "=", "value": { "id": 1 } }),
+        json!({ "column": "id", "operator": "in", "value": [[1]] }),
+    ];
+    for value in incompatible {
+        let constraint = constraint(value.clone());
+        assert!(constraint.validate().is_err(), "{value}");
+        assert!(!constraint.check(&object), "{value}");
+    }
+}
+
+/// Test ordering and `LIKE` comparisons, which are not symmetric
+#[tokio::test]
+async fn test_engine_all_of() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query = read_serialized_query("11_all_of.json");
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
+    let mut all_rows = result
+        .unwrap_many()
+        .into_iter()
+        .map(|r| Todo::from_row(&r).unwrap())
+        .collect::<Vec<Todo>>();
+    all_rows.sort_by_key(|todo| todo.id);
+
+    let engine_todos = filter_todos(&query);
+
+    assert_eq!(engine_todos, all_rows);
+}
+
+/// Test `LIKE` operations where each row supplies its own pattern column
+#[tokio::test]
+async fn test_engine_like_column() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE patterns (id INTEGER PRIMARY KEY, name TEXT, pattern TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO patterns (id, name, pattern) VALUES \
+        (1, 'hello', 'he%'), (2, 'world', 'w_rld'), (3, 'other', 'x%'), (4, 'he', 'hello')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let query = read_serialized_query("12_like_column.json");
+    let result = fetch_sqlite_query(&query, &pool).await.unwrap();
+    let rows = result
+        .unwrap_many()
+        .iter()
+        .map(sqlite_row_to_json)
+        .collect::<Vec<_>>();
+
+    let ids = rows.iter().map(|r| r["id"].clone()).collect::<Vec<_>>();
+    assert_eq!(ids, vec![serde_json::json!(1), serde_json::json!(2)]);
+
+    // All rows are checked by the engine, including the non-matching ones
+    let all_rows = sqlx::query("SELECT * FROM patterns ORDER BY id")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    let engine_rows = all_rows
+        .iter()
+        .map(sqlite_row_to_json)
+        .filter(|row| query.check(&object_from_value(row.clone()).unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(engine_rows, rows);
+}
+
+/// Test that the compiled patterns of subscriptions give the same matches as patterns
+/// parsed at each check
+#[test]
+fn test_engine_compiled_patterns() {
+    let rows = todos()
+        .iter()
+        .map(|todo| object_from_value(serde_json::to_value(todo).unwrap()).unwrap())
+        .collect::<Vec<_>>();
+
+    for (column, operator, pattern) in [
+        ("title", "like", "%todo"),
+        ("title", "like", "S_cond%"),
+        ("content", "like", "%is%the%"),
+        ("title", "ilike", "%TODO"),
+        ("content", "ilike", "this is the _____ todo"),
+        ("title", "like", "%%"),
+        ("title", "like", "Third"),
+    ] {
+        let query: QueryTree = serde_json::from_value(json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": column, "operator": operator, "value": pattern }
+            }
+        }))
+        .unwrap();
+        let patterns = PatternCache::new(&query);
+
+        for row in &rows {
+            assert_eq!(
+                query.check_with(row, &patterns),
+                query.check(row),
+                "{query} on {row:?}"
+            );
+        }
+    }
+}
+
+/// Test that the engine applies keyset pagination cursors like the SQL query engine
+#[tokio::test]
+async fn test_engine_cursor() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    for order in ["asc", "desc"] {
+        let query: QueryTree = serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "paginate": { "perPage": 5, "cursor": 2, "orderBy": { "column": "id", "order": order } }
+        }))
+        .unwrap();
+
+        let result = fetch_sqlite_query(&query, &pool).await.unwrap();
+        let all_rows = result
+            .unwrap_many()
+            .into_iter()
+            .map(|r| Todo::from_row(&r).unwrap())
+            .collect::<Vec<Todo>>();
+
+        let rows = todos()
+            .into_iter()
+            .map(|t| object_from_value(serde_json::to_value(t).unwrap()).unwrap())
+            .collect();
+        let engine_todos = query
+            .apply(DbKind::Sqlite, rows)
+            .into_iter()
+            .map(|row| serde_json::from_value(serde_json::Value::Object(row)).unwrap())
+            .collect::<Vec<Todo>>();
+
+        assert_eq!(engine_todos, all_rows);
+        assert_eq!(engine_todos.len(), 1);
+    }
+}
+
+/// Test that comparing numbers outside of the i64 range does not panic,
+/// and that integers and floats are compared by value
+#[test]
+fn test_engine_large_numbers() {
+    let huge = FinalType::Number(u64::MAX.into());
+    let negative = FinalType::Number((-1).into());
+    let large_float = FinalType::Number(Number::from_f64(1e300).unwrap());
+    let integer = FinalType::Number(3.into());
+    let integral_float = FinalType::Number(Number::from_f64(3.0).unwrap());
+
+    assert!(huge.equals(&huge));
+    assert!(huge.greater_than(&negative));
+    assert!(negative.less_than(&huge));
+    assert!(large_float.greater_than(&huge));
+    assert!(huge.less_than_or_equal(&large_float));
+    assert!(integer.equals(&integral_float));
+    assert!(!integer.equals(&huge));
+
+    // A constraint checked against a number above i64::MAX is simply non-matching
+    let constraint = Constraint {
+        column: "id".to_string(),
+        function: None,
+        operator: Operator::Equal,
+        value: ConstraintValue::Final(FinalType::Number(1.into())),
+    };
+    let object = object_from_value(serde_json::json!({ "id": u64::MAX })).unwrap();
+
+    assert!(!constraint.check(&object));
+}
+
+// ************************************************************************* //
+//                   ORDERING SEMANTICS OF EACH BACKEND                      //
+// ************************************************************************* //
+
+/// Row with a nullable, mixed-case column to exercise NULL placement and collation
+#[derive(Debug, Clone, PartialEq, FromRow, Serialize)]
+struct Note {
+    id: i32,
+    label: Option<String>,
+}
+
+/// Insert notes whose order depends on NULL placement and case sensitivity
+const INSERT_NOTES: &str =
+    "INSERT INTO notes (id, label) VALUES (1, 'banana'), (2, NULL), (3, 'Cherry'), (4, 'apple')";
+
+/// Ordered queries over the notes, in both directions and with an offset
+fn ordered_notes_queries() -> Vec<QueryTree> {
+    ["asc", "desc"]
+        .into_iter()
+        .flat_map(|order| {
+            [0, 1].into_iter().map(move |offset| {
+                serde_json::from_value(serde_json::json!({
+                    "return": "many",
+                    "table": "notes",
+                    "paginate": {
+                        "perPage": 3,
+                        "offset": offset,
+                        "orderBy": { "column": "label", "order": order }
+                    }
+                }))
+                .unwrap()
+            })
+        })
+        .collect()
+}
+
+/// Simulate a query on the notes with the engine, returning the ids of the matches
+fn apply_notes(kind: DbKind, query: &QueryTree, notes: &[Note]) -> Vec<i32> {
+    let rows = notes
+        .iter()
+        .map(|note| object_from_value(serde_json::to_value(note).unwrap()).unwrap())
+        .collect();
+
+    query
+        .apply(kind, rows)
+        .into_iter()
+        .map(|row| row["id"].as_i64().unwrap() as i32)
+        .collect()
+}
+
+/// Test that the engine sorts and paginates like SQLite
+#[tokio::test]
+async fn test_engine_apply_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE notes (id INTEGER PRIMARY KEY, label TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(INSERT_NOTES).execute(&pool).await.unwrap();
+
+    let notes: Vec<Note> = sqlx::query_as("SELECT * FROM notes")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    for query in ordered_notes_queries() {
+        let result = fetch_sqlite_query(&query, &pool).await.unwrap();
+        let ids: Vec<i32> = result
+            .unwrap_many()
+            .iter()
+            .map(|row| Note::from_row(row).unwrap().id)
+            .collect();
+
+        assert_eq!(apply_notes(DbKind::Sqlite, &query, &notes), ids);
+    }
+
+    // NULLs first, then byte order (uppercase before lowercase)
+    let ascending = &ordered_notes_queries()[0];
+    assert_eq!(
+        apply_notes(DbKind::Sqlite, ascending, &notes),
+        vec![2, 3, 4]
+    );
+    assert_eq!(apply_notes(DbKind::MySql, ascending, &notes), vec![2, 4, 1]);
+}
+
+/// Test that the engine sorts and paginates like PostgreSQL
+#[tokio::test]
+async fn test_engine_apply_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::query("CREATE TEMPORARY TABLE notes (id INTEGER PRIMARY KEY, label TEXT COLLATE \"C\")")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(INSERT_NOTES).execute(&pool).await.unwrap();
+
+    let notes: Vec<Note> = sqlx::query_as("SELECT * FROM notes")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+    for query in ordered_notes_queries() {
+        let result = fetch_postgres_query(&query, &pool).await.unwrap();
+        let ids: Vec<i32> = result
+            .unwrap_many()
+            .iter()
+            .map(|row| Note::from_row(row).unwrap().id)
+            .collect();
+
+        assert_eq!(apply_notes(DbKind::Postgres, &query, &notes), ids);
+    }
+
+    // NULLs last in ascending order
+    let ascending = &ordered_notes_queries()[0];
+    assert_eq!(
+        apply_notes(DbKind::Postgres, ascending, &notes),
+        vec![3, 4, 1]
+    );
+}
+
+// ************************************************************************* //
+//                          JSON CONTAINMENT                                 //
+// ************************************************************************* //
+
+/// Insert items with array and object JSON columns
+const INSERT_ITEMS: &str = r#"INSERT INTO items (id, tags, meta) VALUES
+    (1, '["urgent", "work"]', '{"status": "open", "priority": 1}'),
+    (2, '["work"]', '{"status": "closed", "priority": 1}'),
+    (3, '["urgent"]', '{"status": "open", "priority": 2}'),
+    (4, '[]', '{}')"#;
+
+/// Items as decoded JSON objects, for the engine
+fn items() -> Vec<JsonObject> {
+    [
+        json!({ "id": 1, "tags": ["urgent", "work"], "meta": { "status": "open", "priority": 1 } }),
+        json!({ "id": 2, "tags": ["work"], "meta": { "status": "closed", "priority": 1 } }),
+        json!({ "id": 3, "tags": ["urgent"], "meta": { "status": "open", "priority": 2 } }),
+        json!({ "id": 4, "tags": [], "meta": {} }),
+    ]
+    .into_iter()
+    .map(|item| object_from_value(item).unwrap())
+    .collect()
+}
+
+/// Containment queries over the items, with the ids of their expected matches
+fn contains_queries() -> Vec<(QueryTree, Vec<i32>)> {
+    let query = |column: &str, value: serde_json::Value| {
+        serde_json::from_value(json!({
+            "return": "many",
+            "table": "items",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": column, "operator": "contains", "value": value }
+            },
+            "paginate": { "perPage": 10, "orderBy": { "column": "id", "order": "asc" } }
+        }))
+        .unwrap()
+    };
+
+    vec![
+        (read_serialized_query("14_contains.json"), vec![1, 3]),
+        // Array element containment, in any order
+        (query("tags", json!(["work", "urgent"])), vec![1]),
+        (query("tags", json!("work")), vec![1, 2]),
+        (query("tags", json!([])), vec![1, 2, 3, 4]),
+        // JSON object containment
+        (query("meta", json!({ "status": "open" })), vec![1, 3]),
+        (
+            query("meta", json!({ "status": "open", "priority": 1 })),
+            vec![1],
+        ),
+        (query("meta", json!({ "status": "done" })), vec![]),
+    ]
+}
+
+/// Simulate a query on the items with the engine, returning the ids of the matches
+fn apply_items(query: &QueryTree) -> Vec<i32> {
+    let mut ids: Vec<i32> = items()
+        .iter()
+        .filter(|item| query.condition.as_ref().unwrap().check(item))
+        .map(|item| item["id"].as_i64().unwrap() as i32)
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Test JSON containment with the engine, including nested values
+#[test]
+fn test_engine_contains() {
+    for (query, expected) in contains_queries() {
+        assert_eq!(apply_items(&query), expected);
+    }
+
+    let nested = object_from_value(json!({
+        "tags": [["a", "b"], "c"],
+        "meta": { "owner": { "name": "Alice", "team": "core" }, "scores": [1, 2.0] }
+    }))
+    .unwrap();
+    let contains = |column: &str, value: serde_json::Value| {
+        Constraint {
+            column: column.to_string(),
+            function: None,
+            operator: Operator::Contains,
+            value: serde_json::from_value(value).unwrap(),
+        }
+        .check(&nested)
+    };
+
+    assert!(contains("tags", json!([["b"]])));
+    assert!(contains("tags", json!(["c"])));
+    // Only top-level arrays contain their scalar elements
+    assert!(!contains("tags", json!(["a"])));
+    assert!(contains("meta", json!({ "owner": { "name": "Alice" } })));
+    assert!(contains("meta", json!({ "scores": [2] })));
+    assert!(!contains("meta", json!({ "owner": { "name": "Bob" } })));
+    assert!(!contains("meta", json!(["owner"])));
+}
+
+/// Test that SQLite JSON containment matches the engine
+#[tokio::test]
+async fn test_contains_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE items (id INTEGER PRIMARY KEY, tags TEXT, meta TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(INSERT_ITEMS).execute(&pool).await.unwrap();
+
+    for (query, expected) in contains_queries() {
+        let ids: Vec<i32> = fetch_sqlite_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        assert_eq!(ids, expected);
+        assert_eq!(apply_items(&query), ids);
+    }
+}
+
+/// Test that PostgreSQL JSON containment matches the engine
+#[tokio::test]
+async fn test_contains_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::query("CREATE TEMPORARY TABLE items (id INTEGER PRIMARY KEY, tags JSONB, meta JSONB)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(INSERT_ITEMS).execute(&pool).await.unwrap();
+
+    for (query, expected) in contains_queries() {
+        let ids: Vec<i32> = fetch_postgres_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        assert_eq!(ids, expected);
+        assert_eq!(apply_items(&query), ids);
+    }
+}
+
+/// Names for prefix queries, around the code points that need special upper bounds
+const NAMES: [&str; 13] = [
+    "",
+    "a",
+    "aa",
+    "ab",
+    "abc",
+    "abz",
+    "ac",
+    "b",
+    "a\u{10FFFF}",
+    "a\u{10FFFF}x",
+    "\u{10FFFF}",
+    "\u{D7FF}z",
+    "\u{E000}",
+];
+
+/// Test that prefix queries give the same matches on the engine and on SQLite as
+/// `starts_with`, including prefixes ending with the maximum code point, before
+/// the surrogate range, or empty
+#[tokio::test]
+async fn test_engine_prefix() {
+    assert_eq!(prefix_upper_bound("ab").as_deref(), Some("ac"));
+    assert_eq!(prefix_upper_bound("a\u{10FFFF}").as_deref(), Some("b"));
+    assert_eq!(prefix_upper_bound("\u{D7FF}").as_deref(), Some("\u{E000}"));
+    assert_eq!(prefix_upper_bound("\u{10FFFF}\u{10FFFF}"), None);
+    assert_eq!(prefix_upper_bound(""), None);
+
+    let pool = dummy_sqlite_database().await;
+    sqlx::query("CREATE TABLE names (id INTEGER PRIMARY KEY, name TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    for (id, name) in NAMES.iter().enumerate() {
+        sqlx::query("INSERT INTO names VALUES (?, ?)")
+            .bind(id as i32)
+            .bind(name)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    for prefix in ["ab", "a", "a\u{10FFFF}", "\u{10FFFF}", "\u{D7FF}", ""] {
+        let query: QueryTree = serde_json::from_value(json!({
+            "return": "many",
+            "table": "names",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "name", "operator": "prefix", "value": prefix }
+            }
+        }))
+        .unwrap();
+        query.validate().unwrap();
+
+        let expected: Vec<i32> = (0..NAMES.len() as i32)
+            .filter(|&id| NAMES[id as usize].starts_with(prefix))
+            .collect();
+        let mut sql_ids: Vec<i32> = fetch_sqlite_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+        sql_ids.sort();
+        let engine_ids: Vec<i32> = (0..NAMES.len() as i32)
+            .filter(|&id| {
+                query.check(
+                    &object_from_value(json!({ "id": id, "name": NAMES[id as usize] })).unwrap(),
+                )
+            })
+            .collect();
+
+        assert_eq!(sql_ids, expected, "{prefix:?}");
+        assert_eq!(engine_ids, expected, "{prefix:?}");
+    }
+}
+
+/// Test that quantified comparisons give the same matches on the engine and on SQLite,
+/// including empty lists
+#[tokio::test]
+async fn test_engine_quantified() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    for (operator, value, expected) in [
+        (">all", json!([1, 2]), vec![3]),
+        (">all", json!([0, 3]), vec![]),
+        (">all", json!([]), vec![1, 2, 3]),
+        ("<any", json!([2, 3]), vec![1, 2]),
+        ("<any", json!([1]), vec![]),
+        ("<any", json!([]), vec![]),
+        ("<=all", json!([2, 3]), vec![1, 2]),
+        (">=any", json!([3, 0]), vec![1, 2, 3]),
+    ] {
+        let query: QueryTree = serde_json::from_value(json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": operator, "value": value }
+            }
+        }))
+        .unwrap();
+        query.validate().unwrap();
+
+        let rows = fetch_sqlite_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many();
+        // Rows matched through several comparisons come in no particular order
+        let mut sql_ids = rows
+            .iter()
+            .map(|row| row.get::<i32, _>("id"))
+            .collect::<Vec<_>>();
+        sql_ids.sort();
+        let engine_ids = filter_todos(&query)
+            .iter()
+            .map(|todo| todo.id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(sql_ids, expected, "{query}");
+        assert_eq!(engine_ids, expected, "{query}");
+    }
+}
+
+/// Test that an empty `in` list matches nothing on the engine and on SQLite,
+/// alone and within compound conditions
+#[tokio::test]
+async fn test_engine_empty_in() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let empty_in = json!({
+        "type": "single",
+        "constraint": { "column": "id", "operator": "in", "value": [] }
+    });
+    let first = json!({
+        "type": "single",
+        "constraint": { "column": "id", "operator": "=", "value": 1 }
+    });
+    for (condition, expected) in [
+        (empty_in.clone(), vec![]),
+        (
+            json!({ "type": "or", "conditions": [empty_in.clone(), first.clone()] }),
+            vec![1],
+        ),
+        (
+            json!({ "type": "and", "conditions": [empty_in, first] }),
+            vec![],
+        ),
+    ] {
+        let query: QueryTree = serde_json::from_value(json!({
+            "return": "many",
+            "table": "todos",
+            "condition": condition
+        }))
+        .unwrap();
+        query.validate().unwrap();
+
+        let rows = fetch_sqlite_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many();
+        let sql_ids = rows
+            .iter()
+            .map(|row| row.get::<i32, _>("id"))
+            .collect::<Vec<_>>();
+        let engine_ids = filter_todos(&query)
+            .iter()
+            .map(|todo| todo.id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(sql_ids, expected, "{query}");
+        assert_eq!(engine_ids, expected, "{query}");
+    }
+}
+
+/// Test that scalar functions applied to the column of a constraint give the same
+/// results in SQL and in the engine
+#[tokio::test]
+async fn test_engine_scalar_functions() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    // The titles are "First todo", "Second todo" and "Third todo"
+    for (column, function, operator, value, expected) in [
+        ("title", "length", ">", json!(10), vec![2]),
+        ("title", "length", "=", json!(10), vec![1, 3]),
+        ("title", "length", "in", json!([11, 12]), vec![2]),
+        ("content", "length", "<=any", json!([22]), vec![1, 3]),
+        ("title", "lower", "=", json!("first todo"), vec![1]),
+        ("title", "upper", "like", json!("THIRD%"), vec![3]),
+        ("id", "abs", ">=", json!(2), vec![2, 3]),
+    ] {
+        let query: QueryTree = serde_json::from_value(json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": {
+                    "column": column,
+                    "fn": function,
+                    "operator": operator,
+                    "value": value
+                }
+            }
+        }))
+        .unwrap();
+        query.validate().unwrap();
+
+        let rows = fetch_sqlite_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many();
+        let mut sql_ids = rows
+            .iter()
+            .map(|row| row.get::<i32, _>("id"))
+            .collect::<Vec<_>>();
+        sql_ids.sort();
+        let engine_ids = filter_todos(&query)
+            .iter()
+            .map(|todo| todo.id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(sql_ids, expected, "{query}");
+        assert_eq!(engine_ids, expected, "{query}");
+    }
+
+    // Lengths count characters, and values of other types evaluate to null
+    assert_eq!(ScalarFunction::Length.apply(&json!("été")), json!(3));
+    assert_eq!(ScalarFunction::Length.apply(&json!(12)), json!(null));
+    assert_eq!(
+        ScalarFunction::Abs.apply(&json!(i64::MIN)),
+        json!(9_223_372_036_854_775_808_u64)
+    );
+    assert_eq!(ScalarFunction::Abs.apply(&json!(-1.5)), json!(1.5));
+    assert_eq!(ScalarFunction::Upper.apply(&json!(null)), json!(null));
+}
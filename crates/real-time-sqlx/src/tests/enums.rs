@@ -0,0 +1,133 @@
+//! Enum columns tests
+
+use serde_json::json;
+
+use crate::{
+    dispatcher::{Subscription, TableSubscriptions},
+    enums::EnumColumns,
+    error::{EnumError, OperationError},
+    operations::serialize::{object_from_value, GranularOperation, OperationNotification},
+    queries::serialize::{FinalType, QueryTree},
+};
+
+use super::dummy::DummyChannel;
+
+/// Enum columns of a table of tickets
+fn ticket_enums() -> EnumColumns {
+    EnumColumns::new().with_column("status", &["open", "done"])
+}
+
+/// Deserialize a ticket creation operation
+fn create_ticket(status: serde_json::Value) -> GranularOperation {
+    serde_json::from_value(json!({
+        "type": "create",
+        "table": "tickets",
+        "data": { "title": "Fix the build", "status": status }
+    }))
+    .unwrap()
+}
+
+/// Test that operations writing values outside of the set of an enum column are rejected
+#[test]
+fn test_enum_operations() {
+    let mut subscriptions = TableSubscriptions::<DummyChannel>::new();
+    subscriptions.set_enum_columns(ticket_enums());
+
+    assert!(subscriptions
+        .check_operation(&create_ticket(json!("open")))
+        .is_ok());
+    assert!(subscriptions
+        .check_operation(&create_ticket(json!(null)))
+        .is_ok());
+
+    let OperationError::Enum(EnumError::InvalidValue { column, value }) = subscriptions
+        .check_operation(&create_ticket(json!("closed")))
+        .unwrap_err()
+    else {
+        panic!("Expected an invalid enum value");
+    };
+    assert_eq!(column, "status");
+    assert_eq!(value, json!("closed"));
+
+    // Non-string values are outside of the set as well
+    assert!(subscriptions
+        .check_operation(&create_ticket(json!(1)))
+        .is_err());
+
+    let update: GranularOperation = serde_json::from_value(json!({
+        "type": "update",
+        "table": "tickets",
+        "id": 1,
+        "data": { "status": "pending" }
+    }))
+    .unwrap();
+    assert!(subscriptions.check_operation(&update).is_err());
+}
+
+/// Test that conditions comparing enum columns to impossible values are detected
+#[test]
+fn test_enum_unsatisfiable() {
+    let enums = ticket_enums();
+    let condition = |condition: serde_json::Value| {
+        serde_json::from_value::<QueryTree>(json!({
+            "return": "many",
+            "table": "tickets",
+            "condition": condition
+        }))
+        .unwrap()
+        .condition
+        .unwrap()
+    };
+    let status = |operator: &str, value: serde_json::Value| {
+        json!({
+            "type": "single",
+            "constraint": { "column": "status", "operator": operator, "value": value }
+        })
+    };
+
+    assert!(!enums.is_unsatisfiable(&condition(status("=", json!("open")))));
+    assert!(enums.is_unsatisfiable(&condition(status("=", json!("closed")))));
+    assert!(!enums.is_unsatisfiable(&condition(status("in", json!(["closed", "done"])))));
+    assert!(enums.is_unsatisfiable(&condition(status("in", json!(["closed", "pending"])))));
+    assert!(!enums.is_unsatisfiable(&condition(status("!=", json!("closed")))));
+    assert!(enums.is_unsatisfiable(&condition(json!({
+        "type": "and",
+        "conditions": [status("=", json!("closed")), status("!=", json!("open"))]
+    }))));
+    assert!(!enums.is_unsatisfiable(&condition(json!({
+        "type": "or",
+        "conditions": [status("=", json!("closed")), status("=", json!("open"))]
+    }))));
+}
+
+/// Test that subscriptions with unsatisfiable conditions are never notified,
+/// not even with leave events
+#[tokio::test]
+async fn test_enum_subscriptions() {
+    let mut subscriptions = TableSubscriptions::new();
+    subscriptions.set_enum_columns(ticket_enums());
+
+    let query = serde_json::from_value(json!({
+        "return": "many",
+        "table": "tickets",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "status", "operator": "=", "value": "closed" }
+        }
+    }))
+    .unwrap();
+    let channel = DummyChannel::default();
+    subscriptions
+        .subscribe("channel", Subscription::new(query, channel.clone()))
+        .await
+        .unwrap();
+
+    let update = OperationNotification::Update {
+        table: "tickets".to_string(),
+        id: FinalType::Number(1.into()),
+        data: object_from_value(json!({ "id": 1, "status": "open" })).unwrap(),
+        previous: None,
+    };
+    assert_eq!(subscriptions.process(&update).await, 0);
+    assert!(channel.messages().is_empty());
+}
@@ -0,0 +1,35 @@
+//! Connection health check tests
+
+use crate::database::{postgres::check_postgres_connection, sqlite::check_sqlite_connection};
+
+use super::dummy::{dummy_postgres_database, dummy_sqlite_database};
+
+/// Test that the connection check succeeds on a usable SQLite pool, and fails once
+/// the pool is closed
+#[tokio::test]
+async fn test_check_sqlite_connection() {
+    let pool = dummy_sqlite_database().await;
+    check_sqlite_connection(&pool).await.unwrap();
+
+    pool.close().await;
+    assert!(matches!(
+        check_sqlite_connection(&pool).await,
+        Err(sqlx::Error::PoolClosed)
+    ));
+}
+
+/// Test that the connection check succeeds on a usable PostgreSQL pool, and fails
+/// once the pool is closed
+#[tokio::test]
+async fn test_check_postgres_connection() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    check_postgres_connection(&pool).await.unwrap();
+
+    pool.close().await;
+    assert!(matches!(
+        check_postgres_connection(&pool).await,
+        Err(sqlx::Error::PoolClosed)
+    ));
+}
@@ -0,0 +1,82 @@
+//! Post-commit hooks tests
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+use serde_json::json;
+use sqlx::Row;
+
+use crate::{
+    database::{sqlite::granular_operation_sqlite_with_options, OperationOptions},
+    hooks::{OperationKind, PostCommitHooks},
+    operations::serialize::{GranularOperation, OperationNotification},
+};
+
+use super::dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database, Todo};
+
+/// Test that a post-commit hook fires exactly once per successful create, and that
+/// failing hooks neither stop the other hooks nor roll back the operation
+#[tokio::test]
+async fn test_post_commit_hooks() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let created = Arc::new(Mutex::new(Vec::new()));
+    let updates = Arc::new(AtomicUsize::new(0));
+    let hooks = PostCommitHooks::new()
+        .with_hook("todos", OperationKind::Create, |_| async {
+            Err("push service unavailable".into())
+        })
+        .with_hook("todos", OperationKind::Create, {
+            let created = created.clone();
+            move |notification| {
+                let created = created.clone();
+                async move {
+                    if let OperationNotification::Create { data, .. } = notification.as_ref() {
+                        created.lock().unwrap().push(data["title"].clone());
+                    }
+                    Ok(())
+                }
+            }
+        })
+        .with_hook("todos", OperationKind::Update, {
+            let updates = updates.clone();
+            move |_| {
+                updates.fetch_add(1, Ordering::SeqCst);
+                async { Ok(()) }
+            }
+        });
+
+    let options = OperationOptions::default();
+    let operations = [
+        json!({ "type": "create", "table": "todos", "data": { "title": "A", "content": "" } }),
+        // Fails: the title is not nullable
+        json!({ "type": "create", "table": "todos", "data": { "title": null, "content": "" } }),
+        json!({ "type": "update", "table": "todos", "id": 1, "data": { "title": "A!" } }),
+        json!({ "type": "create", "table": "todos", "data": { "title": "B", "content": "" } }),
+        json!({ "type": "delete", "table": "todos", "id": 2 }),
+    ];
+    let mut succeeded = vec![];
+    for operation in operations {
+        let operation = GranularOperation::try_from(operation).unwrap();
+        let result =
+            granular_operation_sqlite_with_options::<_, Todo>(operation, &pool, &options).await;
+        if let Ok(Some(notification)) = result {
+            succeeded.push(hooks.run(&notification).await);
+        }
+    }
+
+    // The failing create hook is not counted, and deletions have no hook
+    assert_eq!(succeeded, vec![1, 1, 1, 0]);
+    assert_eq!(*created.lock().unwrap(), vec![json!("A"), json!("B")]);
+    assert_eq!(updates.load(Ordering::SeqCst), 1);
+
+    // Failing hooks do not roll back the committed creations
+    let row = sqlx::query("SELECT COUNT(*) AS count FROM todos WHERE title IN ('A', 'B')")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<i64, _>("count"), 2);
+}
@@ -0,0 +1,130 @@
+//! Notification log tests
+
+use std::{fs, path::PathBuf, sync::Arc};
+
+use crate::{
+    database::sqlite::granular_operation_sqlite,
+    dispatcher::{Subscription, TableSubscriptions},
+    notification_log::{FileSink, NotificationLog, NotificationSink},
+    operations::serialize::OperationNotification,
+};
+
+use super::{
+    dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database, DummyChannel, Todo},
+    utils::{read_serialized_operation, read_serialized_query},
+};
+
+/// Empty temporary directory for a log
+fn log_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("real-time-sqlx-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+/// Test that logged notifications survive a dispatcher restart and can be replayed
+#[tokio::test]
+async fn test_notification_log_restart() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    let directory = log_directory("restart");
+
+    // Before the restart
+    {
+        let log = Arc::new(NotificationLog::new(FileSink::open(&directory).unwrap()).unwrap());
+        let mut subscriptions = TableSubscriptions::new();
+        subscriptions.set_log(log.clone());
+
+        let channel = DummyChannel::default();
+        subscriptions
+            .subscribe(
+                "channel",
+                Subscription::new(read_serialized_query("02_many.json"), channel.clone()),
+            )
+            .await
+            .unwrap();
+
+        for operation in ["01_create.json", "03_update.json"] {
+            let notification: OperationNotification<Todo> =
+                granular_operation_sqlite(read_serialized_operation(operation), &pool)
+                    .await
+                    .unwrap()
+                    .unwrap();
+            subscriptions.process(&notification).await;
+        }
+
+        // Notifications carry their sequence number
+        let sequences: Vec<u64> = channel
+            .messages()
+            .iter()
+            .map(|message| message["sequence"].as_u64().unwrap())
+            .collect();
+        assert_eq!(sequences, vec![1, 2]);
+    }
+
+    // After the restart, sequence numbers resume from the log
+    let log = Arc::new(NotificationLog::new(FileSink::open(&directory).unwrap()).unwrap());
+    assert_eq!(log.last_sequence(), 2);
+
+    let mut subscriptions = TableSubscriptions::<DummyChannel>::new();
+    subscriptions.set_log(log.clone());
+    let notification: OperationNotification<Todo> =
+        granular_operation_sqlite(read_serialized_operation("04_delete.json"), &pool)
+            .await
+            .unwrap()
+            .unwrap();
+    subscriptions.process(&notification).await;
+
+    // A client that saw the first notification catches up from the log
+    let replayed = log.replay(1).unwrap();
+    let sequences: Vec<u64> = replayed.iter().map(|entry| entry.sequence).collect();
+    assert_eq!(sequences, vec![2, 3]);
+    assert_eq!(replayed[0].table, "todos");
+    assert_eq!(replayed[0].notification["type"], "update");
+    assert_eq!(replayed[1].notification["type"], "delete");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
+
+/// Test that the file sink rotates its segments and drops the oldest ones
+#[test]
+fn test_notification_log_rotation() {
+    let directory = log_directory("rotation");
+    let sink = FileSink::open(&directory).unwrap().with_rotation(1, 2);
+    let log = NotificationLog::new(sink).unwrap();
+
+    for index in 0..5 {
+        log.record("todos", &serde_json::json!({ "index": index }))
+            .unwrap();
+    }
+
+    // One entry per segment, and only the last two segments are kept
+    let sink = FileSink::open(&directory).unwrap();
+    let firsts: Vec<u64> = sink
+        .segments()
+        .unwrap()
+        .iter()
+        .map(|(first, _)| *first)
+        .collect();
+    assert_eq!(firsts, vec![4, 5]);
+    assert_eq!(sink.last_sequence().unwrap(), Some(5));
+
+    let sequences: Vec<u64> = log
+        .replay(0)
+        .unwrap()
+        .iter()
+        .map(|entry| entry.sequence)
+        .collect();
+    assert_eq!(sequences, vec![4, 5]);
+    assert_eq!(log.replay(4).unwrap().len(), 1);
+
+    // A line truncated by an interrupted write is skipped, and appending resumes cleanly
+    let (_, last) = sink.segments().unwrap().pop().unwrap();
+    fs::write(&last, "{\"sequence\": 5, \"tab").unwrap();
+    let log = NotificationLog::new(FileSink::open(&directory).unwrap()).unwrap();
+    assert_eq!(log.last_sequence(), 4);
+    log.record("todos", &serde_json::json!({})).unwrap();
+    assert_eq!(log.replay(4).unwrap()[0].sequence, 5);
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -2,12 +2,22 @@
 
 use std::{fs, path::Path};
 
-use crate::database::sqlite::granular_operation_sqlite;
-use crate::operations::serialize::{GranularOperation, OperationNotification};
-use crate::tests::dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Column, FromRow, Row};
 
+use crate::database::{sqlite::granular_operation_sqlite_with_options, OperationOptions};
+use crate::error::{DeserializeError, OperationError, QueryError};
+use crate::operations::serialize::{
+    merge_json_object, object_from_value, GranularOperation, OperationNotification, SyncSet,
+};
+use crate::queries::serialize::FinalType;
+use crate::queries::serialize::QueryData;
+use crate::snapshots::Identifiable;
+
+use super::backends::{backend_tests, SqliteBackend, TestBackend};
 use super::dummy::Todo;
-use super::utils::read_serialized_operation;
+use super::utils::{read_serialized_operation, read_serialized_query};
 
 #[tokio::test]
 async fn test_deserialize_operations() {
@@ -21,31 +31,33 @@ async fn test_deserialize_operations() {
 
         // Deserialize the query from json
         let query: serde_json::Value = serde_json::from_str(&serialized_operation).unwrap();
-        serde_json::from_value::<GranularOperation>(query).expect(&format!(
-            "Failed to deserialize operation: {}",
-            entry.file_name().into_string().unwrap()
-        ));
+        serde_json::from_value::<GranularOperation>(query).unwrap_or_else(|_| {
+            panic!(
+                "Failed to deserialize operation: {}",
+                entry.file_name().into_string().unwrap()
+            )
+        });
     }
 }
 
 // ************************************************************************* //
-//                     TESTING AGAINST SQLITE BACKEND                        //
+//                        TESTING AGAINST ALL BACKENDS                       //
 // ************************************************************************* //
 
 /// Test single row creation
-#[tokio::test]
-async fn test_sqlite_create() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_create<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let operation = read_serialized_operation("01_create.json");
-    let result = granular_operation_sqlite(operation, &pool).await;
+    let result = B::execute(operation, &pool).await;
 
     assert!(result.is_some());
     let result: OperationNotification<Todo> = result.unwrap();
 
     match result {
-        OperationNotification::Create { table: _, data } => {
+        OperationNotification::Create { data, .. } => {
             assert_eq!(data.id, 4);
             assert_eq!(data.title, "Fourth todo");
             assert_eq!(data.content, "This is the fourth todo");
@@ -55,19 +67,19 @@ async fn test_sqlite_create() {
 }
 
 /// Test multiple row creation
-#[tokio::test]
-async fn test_sqlite_create_many() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_create_many<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let operation = read_serialized_operation("02_create_many.json");
-    let result = granular_operation_sqlite(operation, &pool).await;
+    let result = B::execute(operation, &pool).await;
 
     assert!(result.is_some());
     let result: OperationNotification<Todo> = result.unwrap();
 
     match result {
-        OperationNotification::CreateMany { table: _, data } => {
+        OperationNotification::CreateMany { data, .. } => {
             assert_eq!(data.len(), 2);
 
             let first_data = &data[0];
@@ -84,14 +96,63 @@ async fn test_sqlite_create_many() {
     }
 }
 
-/// Test single row update
+/// Test that multiple row creation returns rows in the order of the input rows
+async fn test_create_many_ordered<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let operation = read_serialized_operation("05_create_many_ordered.json");
+    let result = B::execute(operation, &pool).await;
+
+    assert!(result.is_some());
+    let result: OperationNotification<Todo> = result.unwrap();
+
+    match result {
+        OperationNotification::CreateMany { data, .. } => {
+            let ids: Vec<i32> = data.iter().map(|todo| todo.id).collect();
+            assert_eq!(ids, vec![10, 6, 8]);
+
+            assert_eq!(data[0].title, "Tenth todo");
+            assert_eq!(data[1].title, "Sixth todo");
+            assert_eq!(data[2].title, "Eighth todo");
+        }
+        _ => panic!("Expected a create many operation"),
+    }
+}
+
+/// Test that a multiple row creation is not bound by the parameter limit of SQLite
+/// (32,766 by default), its rows being inserted by separate statements
 #[tokio::test]
-async fn test_sqlite_update() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_create_many_parameter_limit() {
+    let pool = SqliteBackend::setup().await.unwrap();
+
+    let rows: Vec<serde_json::Value> = (0..20_000)
+        .map(|index| json!({ "title": format!("Todo {index}"), "content": "" }))
+        .collect();
+    let operation = GranularOperation::try_from(
+        json!({ "type": "create_many", "table": "todos", "data": rows }),
+    )
+    .unwrap();
+
+    match SqliteBackend::execute(operation, &pool).await {
+        Some(OperationNotification::CreateMany { data, .. }) => {
+            assert_eq!(data.len(), 20_000);
+            assert_eq!(data[0].title, "Todo 0");
+            assert_eq!(data[19_999].title, "Todo 19999");
+        }
+        _ => panic!("Expected a create many operation"),
+    }
+}
+
+/// Test single row update
+async fn test_update<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let operation = read_serialized_operation("03_update.json");
-    let result = granular_operation_sqlite(operation, &pool).await;
+    let result = B::execute(operation, &pool).await;
 
     assert!(result.is_some());
     let result: OperationNotification<Todo> = result.unwrap();
@@ -101,23 +162,28 @@ async fn test_sqlite_update() {
             table: _,
             id: _,
             data,
+            previous,
         } => {
             assert_eq!(data.id, 3);
             assert_eq!(data.title, "Updated todo");
             assert_eq!(data.content, "This todo was updated");
+
+            let previous = previous.expect("Expected the row before the update");
+            assert_eq!(previous.id, 3);
+            assert_eq!(previous.title, "Third todo");
         }
         _ => panic!("Expected an update operation"),
     }
 }
 
 /// Test single row deletion
-#[tokio::test]
-async fn test_sqlite_delete() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_delete<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let operation = read_serialized_operation("04_delete.json");
-    let result = granular_operation_sqlite(operation, &pool).await;
+    let result = B::execute(operation, &pool).await;
 
     assert!(result.is_some());
     let result: OperationNotification<Todo> = result.unwrap();
@@ -129,3 +195,770 @@ async fn test_sqlite_delete() {
         _ => panic!("Expected a delete operation"),
     }
 }
+
+/// Test inserting a duplicate-key row under each conflict policy
+async fn test_create_conflict<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let operation = |on_conflict: &str, data: serde_json::Value| -> GranularOperation {
+        let kind = if data.is_array() {
+            "create_many"
+        } else {
+            "create"
+        };
+        serde_json::from_value(json!({
+            "type": kind,
+            "table": "todos",
+            "onConflict": on_conflict,
+            "data": data
+        }))
+        .unwrap()
+    };
+    let first_todo = |pool| async move {
+        let query = serde_json::from_value(json!({
+            "return": "single",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": "=", "value": 1 }
+            }
+        }))
+        .unwrap();
+        B::fetch(&query, pool).await.unwrap_single()
+    };
+    let duplicate = json!({ "id": 1, "title": "Duplicate todo", "content": "Duplicate" });
+
+    // Error (default): the duplicate row is reported as a failure
+    let data = vec![object_from_value(duplicate.clone()).unwrap()];
+    let result = B::create_many_partial("todos", data, &pool).await.unwrap();
+    assert!(result.created.is_empty());
+    assert_eq!(result.errors.len(), 1);
+
+    // Ignore: the duplicate row is skipped without notification
+    let result = B::execute(operation("ignore", duplicate.clone()), &pool).await;
+    assert!(result.is_none());
+    assert_eq!(first_todo(&pool).await.title, "First todo");
+
+    let rows = json!([duplicate, { "id": 10, "title": "Tenth todo", "content": "Tenth" }]);
+    match B::execute(operation("ignore", rows), &pool).await {
+        Some(OperationNotification::CreateMany { data, .. }) => {
+            assert_eq!(data.len(), 1);
+            assert_eq!(data[0].id, 10);
+        }
+        _ => panic!("Expected a create many operation"),
+    }
+
+    // Replace: the duplicate row replaces the existing one
+    match B::execute(operation("replace", duplicate.clone()), &pool).await {
+        Some(OperationNotification::Create { data, .. }) => {
+            assert_eq!(data.id, 1);
+            assert_eq!(data.title, "Duplicate todo");
+        }
+        _ => panic!("Expected a create operation"),
+    }
+    assert_eq!(first_todo(&pool).await.title, "Duplicate todo");
+}
+
+/// Test that a non-atomic multiple row creation inserts the valid rows
+/// and reports the invalid ones by index
+async fn test_create_many_partial<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let data = vec![
+        object_from_value(json!({ "title": "Fourth todo", "content": "Fourth" })).unwrap(),
+        // Missing a non-null column
+        object_from_value(json!({ "title": "Invalid todo" })).unwrap(),
+        object_from_value(json!({ "title": "Fifth todo", "content": "Fifth" })).unwrap(),
+    ];
+    let result = B::create_many_partial("todos", data, &pool).await.unwrap();
+
+    assert_eq!(result.created.keys().collect::<Vec<_>>(), vec![&0, &2]);
+    assert_eq!(result.created[&0].title, "Fourth todo");
+    assert_eq!(result.created[&2].title, "Fifth todo");
+    assert_eq!(result.errors.keys().collect::<Vec<_>>(), vec![&1]);
+
+    // The notification only holds the created rows
+    match result.notification() {
+        Some(OperationNotification::CreateMany { table, data, .. }) => {
+            assert_eq!(table, "todos");
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].title, "Fourth todo");
+            assert_eq!(data[1].title, "Fifth todo");
+        }
+        _ => panic!("Expected a create many operation"),
+    }
+
+    // The valid rows were committed
+    let query = serde_json::from_value(json!({ "return": "many", "table": "todos" })).unwrap();
+    assert_eq!(B::fetch(&query, &pool).await.unwrap_many().len(), 5);
+}
+
+/// Test that a non-atomic multiple row creation fails as a whole when no connection
+/// can be acquired, instead of panicking
+#[tokio::test]
+async fn test_create_many_partial_closed_pool() {
+    let pool = SqliteBackend::setup().await.unwrap();
+    pool.close().await;
+
+    let data = vec![object_from_value(json!({ "title": "Fourth todo", "content": "" })).unwrap()];
+    let result = SqliteBackend::create_many_partial("todos", data, &pool).await;
+    assert!(matches!(
+        result,
+        Err(QueryError::Database(sqlx::Error::PoolClosed))
+    ));
+}
+
+/// Subset of the columns of a todo
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+struct TodoTitle {
+    id: i32,
+    title: String,
+}
+
+impl Identifiable for TodoTitle {}
+
+/// Names of the columns of a returned row
+#[derive(Debug, Serialize)]
+struct ReturnedColumns(Vec<String>);
+
+impl Identifiable for ReturnedColumns {}
+
+impl<'r, R: Row> FromRow<'r, R> for ReturnedColumns {
+    fn from_row(row: &'r R) -> Result<Self, sqlx::Error> {
+        Ok(Self(
+            row.columns()
+                .iter()
+                .map(|column| column.name().to_string())
+                .collect(),
+        ))
+    }
+}
+
+/// Test that operations only return the requested columns
+async fn test_returning<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let options = OperationOptions::default().with_returning_fields::<TodoTitle>();
+    let expected = vec!["id".to_string(), "title".to_string()];
+    assert_eq!(options.returning.as_ref(), Some(&expected));
+
+    let operation = read_serialized_operation("01_create.json");
+    match B::execute_with_options::<ReturnedColumns>(operation, &options, &pool).await {
+        Some(OperationNotification::Create { data, .. }) => assert_eq!(data.0, expected),
+        _ => panic!("Expected a create operation"),
+    }
+
+    let operation = read_serialized_operation("03_update.json");
+    match B::execute_with_options::<ReturnedColumns>(operation, &options, &pool).await {
+        Some(OperationNotification::Update { data, previous, .. }) => {
+            assert_eq!(data.0, expected);
+            assert_eq!(previous.unwrap().0, expected);
+        }
+        _ => panic!("Expected an update operation"),
+    }
+
+    let operation = read_serialized_operation("04_delete.json");
+    match B::execute_with_options::<ReturnedColumns>(operation, &options, &pool).await {
+        Some(OperationNotification::Delete { data, .. }) => assert_eq!(data.0, expected),
+        _ => panic!("Expected a delete operation"),
+    }
+}
+
+/// Test that the reduced rows can be converted to the struct of the returned columns
+#[tokio::test]
+async fn test_returning_from_row() {
+    let Some(pool) = SqliteBackend::setup().await else {
+        return;
+    };
+
+    let options = OperationOptions::default().with_returning(["id", "title"]);
+    let operation = read_serialized_operation("04_delete.json");
+    match granular_operation_sqlite_with_options::<_, TodoTitle>(operation, &pool, &options)
+        .await
+        .unwrap()
+    {
+        Some(OperationNotification::Delete { data, .. }) => assert_eq!(
+            serde_json::to_value(data).unwrap(),
+            json!({ "id": 1, "title": "First todo" })
+        ),
+        _ => panic!("Expected a delete operation"),
+    }
+}
+
+/// Todo keyed by its title instead of its id
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+struct TitledTodo {
+    id: i32,
+    title: String,
+}
+
+impl Identifiable for TitledTodo {
+    fn key_column() -> &'static str {
+        "title"
+    }
+}
+
+/// Test that the keys extracted from the rows of notifications match their ids
+async fn test_notification_keys<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+    let key = |id: i32| Some(FinalType::Number(id.into()));
+
+    let operation = read_serialized_operation("01_create.json");
+    let result: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+    match &result {
+        OperationNotification::Create { data, .. } => assert_eq!(result.keys(), [key(data.id)]),
+        _ => panic!("Expected a create operation"),
+    }
+
+    let operation = read_serialized_operation("02_create_many.json");
+    let result: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+    match &result {
+        OperationNotification::CreateMany { data, .. } => {
+            let ids: Vec<_> = data.iter().map(|todo| key(todo.id)).collect();
+            assert_eq!(result.keys(), ids);
+        }
+        _ => panic!("Expected a create many operation"),
+    }
+
+    let operation = read_serialized_operation("03_update.json");
+    let result: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+    match &result {
+        OperationNotification::Update { id, data, .. } => {
+            assert_eq!(result.keys(), [key(3)]);
+            assert_eq!(&data.id().unwrap(), id);
+        }
+        _ => panic!("Expected an update operation"),
+    }
+
+    let operation = read_serialized_operation("04_delete.json");
+    let result: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+    match &result {
+        OperationNotification::Delete { id, data, .. } => {
+            assert_eq!(result.keys(), [key(1)]);
+            assert_eq!(&data.id().unwrap(), id);
+        }
+        _ => panic!("Expected a delete operation"),
+    }
+
+    // Notifications built from a row are keyed by its primary key
+    let todo = Todo {
+        id: 2,
+        title: "Second todo".to_string(),
+        content: String::new(),
+    };
+    match OperationNotification::delete("todos", FinalType::Number(5.into()), todo) {
+        OperationNotification::Delete { id, .. } => assert_eq!(Some(id), key(2)),
+        _ => panic!("Expected a delete operation"),
+    }
+
+    // Rows without their key column are keyed by the operation
+    let row = json!({ "title": "Untitled" });
+    match OperationNotification::delete("todos", FinalType::Number(5.into()), row) {
+        OperationNotification::Delete { id, .. } => assert_eq!(Some(id), key(5)),
+        _ => panic!("Expected a delete operation"),
+    }
+}
+
+/// Test that the key column can be overridden
+#[test]
+fn test_identifiable_key_column() {
+    let todo = TitledTodo {
+        id: 1,
+        title: "First todo".to_string(),
+    };
+    assert_eq!(
+        todo.id().unwrap(),
+        FinalType::String("First todo".to_string())
+    );
+
+    let notification =
+        OperationNotification::update("todos", FinalType::Number(1.into()), todo, None);
+    match &notification {
+        OperationNotification::Update { id, .. } => {
+            assert_eq!(id, &FinalType::String("First todo".to_string()))
+        }
+        _ => panic!("Expected an update operation"),
+    }
+
+    // Rows without the key column have no key
+    assert!(json!({ "title": "Untitled" }).id().is_err());
+    assert_eq!(
+        json!({ "id": 4 }).id().unwrap(),
+        FinalType::Number(4.into())
+    );
+}
+
+/// Test that the client reference of a creation is echoed back unchanged in its
+/// notification, along with the server-assigned row
+async fn test_create_client_ref<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let operation = GranularOperation::try_from(json!({
+        "type": "create",
+        "table": "todos",
+        "data": { "title": "Optimistic todo", "content": "Created offline" },
+        "clientRef": "tmp-42"
+    }))
+    .unwrap();
+    let notification: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+
+    let serialized = serde_json::to_value(&notification).unwrap();
+    assert_eq!(serialized["clientRef"], json!("tmp-42"));
+    assert_eq!(serialized["data"]["id"], json!(4));
+
+    let operation = GranularOperation::try_from(json!({
+        "type": "create_many",
+        "table": "todos",
+        "data": [
+            { "title": "Fifth todo", "content": "" },
+            { "title": "Sixth todo", "content": "" }
+        ],
+        "clientRef": -7
+    }))
+    .unwrap();
+    let notification: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+
+    let serialized = serde_json::to_value(&notification).unwrap();
+    assert_eq!(serialized["clientRef"], json!(-7));
+    assert_eq!(serialized["data"][1]["id"], json!(6));
+
+    // Creations without a client reference are serialized as before
+    let operation = read_serialized_operation("01_create.json");
+    let notification: OperationNotification<Todo> = B::execute(operation, &pool).await.unwrap();
+    assert!(serde_json::to_value(&notification)
+        .unwrap()
+        .get("clientRef")
+        .is_none());
+}
+
+/// Test that a dry run reports the errors of an operation without writing anything,
+/// even when the operation is valid
+async fn test_validate_operation<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    // The row with id 1 already exists
+    let duplicate = GranularOperation::try_from(json!({
+        "type": "create_many",
+        "table": "todos",
+        "data": [
+            { "title": "Fourth todo", "content": "" },
+            { "id": 1, "title": "Duplicate todo", "content": "" }
+        ]
+    }))
+    .unwrap();
+    assert!(matches!(
+        B::validate(duplicate, &pool).await,
+        Err(OperationError::Database(_))
+    ));
+
+    let unknown_column = GranularOperation::try_from(json!({
+        "type": "update",
+        "table": "todos",
+        "id": 2,
+        "data": { "priority": 1 }
+    }))
+    .unwrap();
+    assert!(B::validate(unknown_column, &pool).await.is_err());
+
+    for operation in ["01_create.json", "03_update.json", "04_delete.json"] {
+        B::validate(read_serialized_operation(operation), &pool)
+            .await
+            .unwrap();
+    }
+
+    // Nothing was persisted
+    let QueryData::Many(rows) = B::fetch(&read_serialized_query("02_many.json"), &pool).await
+    else {
+        panic!("Expected many rows");
+    };
+    let titles: Vec<&str> = rows.iter().map(|row| row.title.as_str()).collect();
+    assert_eq!(titles, vec!["First todo", "Second todo", "Third todo"]);
+}
+
+/// Test that syncing a subset of rows to a desired set creates, updates and deletes
+/// the differing rows only, leaving the rows outside of the subset untouched
+async fn test_sync_set<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let sync: SyncSet = serde_json::from_value(json!({
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "in", "value": [1, 2] }
+        },
+        "rows": [
+            { "id": 1, "title": "First todo", "content": "Updated content" },
+            { "title": "Fourth todo", "content": "Fourth" }
+        ]
+    }))
+    .unwrap();
+    let notifications = B::sync_set(sync.clone(), &pool).await.unwrap();
+
+    assert_eq!(notifications.len(), 3);
+    match &notifications[0] {
+        OperationNotification::Delete { data, .. } => assert_eq!(data.id, 2),
+        _ => panic!("Expected a delete operation"),
+    }
+    match &notifications[1] {
+        OperationNotification::Update { data, .. } => {
+            assert_eq!(data.id, 1);
+            assert_eq!(data.content, "Updated content");
+        }
+        _ => panic!("Expected an update operation"),
+    }
+    match &notifications[2] {
+        OperationNotification::Create { data, .. } => assert_eq!(data.title, "Fourth todo"),
+        _ => panic!("Expected a create operation"),
+    }
+
+    let QueryData::Many(rows) = B::fetch(&read_serialized_query("02_many.json"), &pool).await
+    else {
+        panic!("Expected many rows");
+    };
+    let mut ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 3, 4]);
+
+    // A failing row rolls back the whole sync
+    let mut failing = sync;
+    failing.rows = vec![object_from_value(json!({ "title": "Invalid todo" })).unwrap()];
+    assert!(B::sync_set(failing, &pool).await.is_err());
+    let QueryData::Many(rows) = B::fetch(&read_serialized_query("02_many.json"), &pool).await
+    else {
+        panic!("Expected many rows");
+    };
+    assert_eq!(rows.len(), 3);
+}
+
+/// Test that a sync condition incompatible with its operator is rejected
+/// without touching the table
+async fn test_sync_set_invalid_condition<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let sync: SyncSet = serde_json::from_value(json!({
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "title", "operator": "prefix", "value": 3 }
+        },
+        "rows": []
+    }))
+    .unwrap();
+    assert!(matches!(
+        B::sync_set(sync, &pool).await,
+        Err(OperationError::Deserialize(
+            DeserializeError::IncompatibleConstraint { .. }
+        ))
+    ));
+
+    let QueryData::Many(rows) = B::fetch(&read_serialized_query("02_many.json"), &pool).await
+    else {
+        panic!("Expected many rows");
+    };
+    assert_eq!(rows.len(), 3);
+}
+
+backend_tests!(
+    test_create,
+    test_create_client_ref,
+    test_create_many,
+    test_create_many_ordered,
+    test_update,
+    test_delete,
+    test_notification_keys,
+    test_create_many_partial,
+    test_create_conflict,
+    test_returning,
+    test_validate_operation,
+    test_sync_set,
+    test_sync_set_invalid_condition,
+);
+
+/// Test merging a partial update into a full object
+#[test]
+fn test_merge_json_object_shallow() {
+    let mut base =
+        object_from_value(json!({ "id": 1, "title": "First todo", "content": "Content" })).unwrap();
+    let patch = object_from_value(json!({ "title": "Updated todo", "content": null })).unwrap();
+
+    merge_json_object(&mut base, &patch);
+
+    assert_eq!(
+        serde_json::Value::Object(base),
+        json!({ "id": 1, "title": "Updated todo", "content": null })
+    );
+}
+
+/// Test that nested objects are merged instead of replaced
+#[test]
+fn test_merge_json_object_nested() {
+    let mut base = object_from_value(json!({
+        "id": 1,
+        "metadata": { "tags": ["a"], "author": { "name": "Alice", "age": 30 } }
+    }))
+    .unwrap();
+    let patch = object_from_value(json!({
+        "metadata": { "tags": ["b"], "author": { "age": 31 }, "pinned": true }
+    }))
+    .unwrap();
+
+    merge_json_object(&mut base, &patch);
+
+    assert_eq!(
+        serde_json::Value::Object(base),
+        json!({
+            "id": 1,
+            "metadata": { "tags": ["b"], "author": { "name": "Alice", "age": 31 }, "pinned": true }
+        })
+    );
+}
+
+/// Test that misspelled operation keys are rejected instead of being silently dropped
+#[test]
+fn test_reject_unknown_operation_fields() {
+    let operation = json!({
+        "type": "create",
+        "table": "todos",
+        "data": { "title": "Title", "content": "Content" },
+        "onConflit": "ignore"
+    });
+
+    match GranularOperation::try_from(operation) {
+        Err(DeserializeError::Malformed { source: err, .. }) => {
+            assert!(
+                err.to_string().contains("unknown field `onConflit`"),
+                "{err}"
+            );
+        }
+        result => panic!("Expected an unknown field error, got {result:?}"),
+    }
+
+    // Malformed fields are located with their JSON pointer path
+    match GranularOperation::try_from(json!({
+        "type": "create_many",
+        "table": "todos",
+        "data": [{ "title": "Title" }, ["Title"]]
+    })) {
+        Err(DeserializeError::Malformed { path, .. }) => assert_eq!(path, "/data/1"),
+        result => panic!("Expected a malformed operation, got {result:?}"),
+    }
+    match GranularOperation::try_from(json!({ "type": "delete", "table": "todos", "id": [1] })) {
+        Err(DeserializeError::Malformed { path, .. }) => assert_eq!(path, "/id"),
+        result => panic!("Expected a malformed operation, got {result:?}"),
+    }
+
+    // The fields of the row data itself are not restricted
+    assert!(GranularOperation::try_from(json!({
+        "type": "update",
+        "table": "todos",
+        "id": 1,
+        "data": { "any_column": 1 }
+    }))
+    .is_ok());
+}
+
+/// Test that the notices raised by PostgreSQL during an operation are returned with it
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_postgres_notices() {
+    use crate::database::{postgres::granular_operation_postgres_with_notices, DatabaseNotice};
+
+    use super::backends::PostgresBackend;
+
+    let Some(pool) = PostgresBackend::setup().await else {
+        return;
+    };
+
+    // Raise a notice for each created row, from a trigger of the temporary table
+    sqlx::raw_sql(
+        "CREATE FUNCTION pg_temp.notice_todo() RETURNS trigger AS $$
+        BEGIN
+            RAISE NOTICE 'Created todo %', NEW.title;
+            RETURN NEW;
+        END
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER notice_todo AFTER INSERT ON todos
+            FOR EACH ROW EXECUTE FUNCTION pg_temp.notice_todo();",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let options = OperationOptions::default();
+    let operation = GranularOperation::try_from(json!({
+        "type": "create",
+        "table": "todos",
+        "data": { "title": "Noticed", "content": "" }
+    }))
+    .unwrap();
+    let outcome = granular_operation_postgres_with_notices::<_, Todo>(operation, &pool, &options)
+        .await
+        .unwrap();
+    assert!(matches!(
+        outcome.notification,
+        Some(OperationNotification::Create { .. })
+    ));
+    assert_eq!(
+        outcome.notices,
+        vec![DatabaseNotice {
+            level: "notice".to_string(),
+            code: None,
+            message: "Created todo Noticed".to_string(),
+        }]
+    );
+
+    // Operations raising no notice return none
+    let operation = GranularOperation::try_from(json!({
+        "type": "update",
+        "table": "todos",
+        "id": 1,
+        "data": { "title": "Quiet" }
+    }))
+    .unwrap();
+    let outcome = granular_operation_postgres_with_notices::<_, Todo>(operation, &pool, &options)
+        .await
+        .unwrap();
+    assert!(outcome.notification.is_some());
+    assert!(outcome.notices.is_empty());
+}
+
+/// Todo with a column that the table does not have
+#[derive(Debug, Serialize, FromRow)]
+struct TodoPriority {
+    id: i32,
+    priority: i32,
+}
+
+impl Identifiable for TodoPriority {}
+
+/// Test that failing operations and rows not buildable into the notification struct
+/// return an error instead of panicking
+#[tokio::test]
+async fn test_operation_errors() {
+    let pool = SqliteBackend::setup().await.unwrap();
+    let options = OperationOptions::default();
+
+    let operation = GranularOperation::try_from(json!({
+        "type": "create",
+        "table": "todos",
+        "data": { "title": "Malformed", "priority": 1 }
+    }))
+    .unwrap();
+    let result =
+        granular_operation_sqlite_with_options::<_, Todo>(operation, &pool, &options).await;
+    assert!(matches!(result, Err(err) if err.database_error().is_some()));
+
+    let operation = read_serialized_operation("01_create.json");
+    let result =
+        granular_operation_sqlite_with_options::<_, TodoPriority>(operation, &pool, &options).await;
+    assert!(matches!(
+        result,
+        Err(OperationError::FromRow(sqlx::Error::ColumnNotFound(column))) if column == "priority"
+    ));
+}
+
+/// Test that a creation suppressed by a SQLite trigger fails with a distinct error,
+/// unless conflicts are ignored
+#[tokio::test]
+async fn test_create_suppressed_sqlite() {
+    let pool = SqliteBackend::setup().await.unwrap();
+    sqlx::query("CREATE TRIGGER skip_todo BEFORE INSERT ON todos BEGIN SELECT RAISE(IGNORE); END")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let options = OperationOptions::default();
+    let operation = |kind: &str, on_conflict: &str| {
+        let data = json!({ "title": "Suppressed", "content": "" });
+        let data = if kind == "create" {
+            data
+        } else {
+            json!([data])
+        };
+        GranularOperation::try_from(
+            json!({ "type": kind, "table": "todos", "onConflict": on_conflict, "data": data }),
+        )
+        .unwrap()
+    };
+
+    for kind in ["create", "create_many"] {
+        let result = granular_operation_sqlite_with_options::<_, Todo>(
+            operation(kind, "error"),
+            &pool,
+            &options,
+        )
+        .await;
+        assert!(
+            matches!(&result, Err(OperationError::Query(QueryError::NoRowInserted(table))) if table == "todos"),
+            "{result:?}"
+        );
+
+        let result = granular_operation_sqlite_with_options::<_, Todo>(
+            operation(kind, "ignore"),
+            &pool,
+            &options,
+        )
+        .await;
+        assert!(result.unwrap().is_none());
+    }
+}
+
+/// Test that a creation suppressed by a PostgreSQL trigger fails with a distinct error
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_create_suppressed_postgres() {
+    use crate::database::postgres::granular_operation_postgres_with_options;
+
+    use super::backends::PostgresBackend;
+
+    let Some(pool) = PostgresBackend::setup().await else {
+        return;
+    };
+
+    // A BEFORE trigger returning NULL skips the insertion of the row
+    sqlx::raw_sql(
+        "CREATE FUNCTION pg_temp.skip_todo() RETURNS trigger AS $$
+        BEGIN
+            RETURN NULL;
+        END
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER skip_todo BEFORE INSERT ON todos
+            FOR EACH ROW EXECUTE FUNCTION pg_temp.skip_todo();",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let operation = GranularOperation::try_from(json!({
+        "type": "create",
+        "table": "todos",
+        "data": { "title": "Suppressed", "content": "" }
+    }))
+    .unwrap();
+    let result = granular_operation_postgres_with_options::<_, Todo>(
+        operation,
+        &pool,
+        &OperationOptions::default(),
+    )
+    .await;
+    assert!(
+        matches!(&result, Err(OperationError::Query(QueryError::NoRowInserted(table))) if table == "todos"),
+        "{result:?}"
+    );
+}
@@ -0,0 +1,97 @@
+//! Operator policy tests
+
+use serde_json::json;
+
+use crate::{
+    dispatcher::TableSubscriptions,
+    error::PolicyError,
+    operators::OperatorPolicy,
+    queries::serialize::{Operator, QueryTree},
+};
+
+use super::dummy::DummyChannel;
+
+/// Query on the todos table with the given condition
+fn query(condition: serde_json::Value) -> QueryTree {
+    serde_json::from_value(json!({ "return": "many", "table": "todos", "condition": condition }))
+        .unwrap()
+}
+
+/// Single constraint condition
+fn single(column: &str, operator: &str, value: serde_json::Value) -> serde_json::Value {
+    json!({
+        "type": "single",
+        "constraint": { "column": column, "operator": operator, "value": value }
+    })
+}
+
+/// Test that `like` is rejected on the columns it is not allowed on, while the allowed
+/// columns and operators pass
+#[test]
+fn test_operator_policy() {
+    let policy = OperatorPolicy::new()
+        .with_column("title", &[Operator::Equal, Operator::Like])
+        .with_default(&[Operator::Equal, Operator::In]);
+
+    assert!(policy
+        .check_query(&query(single("title", "like", json!("%todo%"))))
+        .is_ok());
+    assert!(policy
+        .check_query(&query(single("content", "=", json!("x"))))
+        .is_ok());
+    assert!(policy
+        .check_query(&query(single("id", "in", json!([1, 2]))))
+        .is_ok());
+
+    match policy.check_query(&query(single("content", "like", json!("%todo%")))) {
+        Err(PolicyError::OperatorNotAllowed {
+            table,
+            column,
+            operator,
+        }) => {
+            assert_eq!(table, "todos");
+            assert_eq!(column, "content");
+            assert_eq!(operator, Operator::Like);
+        }
+        result => panic!("Unexpected result {result:?}"),
+    }
+
+    // Nested constraints are checked as well
+    let nested = query(json!({
+        "type": "and",
+        "conditions": [
+            single("title", "like", json!("%todo%")),
+            { "type": "or", "conditions": [single("id", ">", json!(2))] }
+        ]
+    }));
+    assert!(policy.check_query(&nested).is_err());
+
+    // Correlated subqueries are left to the policies of their tables
+    let exists = query(json!({
+        "type": "exists",
+        "subquery": {
+            "return": "many",
+            "table": "tags",
+            "condition": single("name", "like", json!("%urgent%"))
+        },
+        "correlateOn": ["todo_id", "id"]
+    }));
+    assert!(policy.check_query(&exists).is_ok());
+    assert_eq!(exists.subqueries().len(), 1);
+}
+
+/// Test that tables only reject queries once an operator policy is registered
+#[test]
+fn test_table_operator_policy() {
+    let like = query(single("content", "like", json!("%todo%")));
+
+    let mut subscriptions = TableSubscriptions::<DummyChannel>::new();
+    assert!(subscriptions.check_query(&like).is_ok());
+
+    subscriptions
+        .set_operator_policy(OperatorPolicy::new().with_column("content", &[Operator::Equal]));
+    assert!(subscriptions.check_query(&like).is_err());
+    assert!(subscriptions
+        .check_query(&query(single("title", "like", json!("%todo%"))))
+        .is_ok());
+}
@@ -0,0 +1,199 @@
+//! Query plan tests: the SQL generated for filters on indexed columns must use
+//! the indexes of the dummy schema, instead of scanning the whole table.
+
+use serde_json::json;
+use sqlx::{MySql, Pool, Postgres, Row, Sqlite};
+
+use crate::{
+    database::{
+        mysql::bind_mysql_value, postgres::bind_postgres_value, prepare_sqlx_query,
+        sqlite::bind_sqlite_value, DbKind,
+    },
+    queries::serialize::QueryTree,
+    utils::{to_backtick_identifiers, to_numbered_placeholders},
+};
+
+use super::dummy::{
+    dummy_mysql_database, dummy_postgres_database, dummy_sqlite_database,
+    prepare_dummy_mysql_database, prepare_dummy_postgres_database, prepare_dummy_sqlite_database,
+};
+
+/// Secondary index of the dummy schema, on top of the `id` primary key
+const CREATE_TITLE_INDEX: &str = "CREATE INDEX todos_title ON todos (title)";
+
+/// Query on the todos table with a single constraint
+fn filter(column: &str, operator: &str, value: serde_json::Value) -> QueryTree {
+    serde_json::from_value(json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": column, "operator": operator, "value": value }
+        }
+    }))
+    .unwrap()
+}
+
+/// Equality and range filters on indexed columns
+fn indexed_filters() -> Vec<QueryTree> {
+    vec![
+        filter("id", "=", json!(2)),
+        filter("id", "in", json!([1, 3])),
+        filter("id", ">", json!(1)),
+        filter("id", "<=", json!(2)),
+        filter("title", "=", json!("Second todo")),
+        filter("title", ">=", json!("Second todo")),
+    ]
+}
+
+/// Filter that cannot use an index, to check that full scans are detected
+fn unindexed_filter() -> QueryTree {
+    filter("content", "like", json!("%second%"))
+}
+
+/// Query plan of the SQL generated for a query on SQLite (`EXPLAIN QUERY PLAN`)
+async fn sqlite_plan(query: &QueryTree, pool: &Pool<Sqlite>) -> String {
+    let (sql, values) = prepare_sqlx_query(query, DbKind::Sqlite).unwrap();
+    let sql = format!("EXPLAIN QUERY PLAN {}", to_numbered_placeholders(&sql));
+
+    let mut sqlx_query = sqlx::query(&sql);
+    for value in values {
+        sqlx_query = bind_sqlite_value(sqlx_query, value).unwrap();
+    }
+
+    sqlx_query
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get::<String, _>("detail"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a SQLite query plan only searches the table through an index
+fn sqlite_uses_index(plan: &str) -> bool {
+    plan.lines()
+        .all(|step| step.starts_with("SEARCH") && step.contains("USING"))
+}
+
+/// Query plan of the SQL generated for a query on PostgreSQL (`EXPLAIN`).
+/// Sequential scans are disabled while explaining, as the planner would always
+/// prefer them on the few rows of the dummy table: they are only chosen when
+/// no index can be used.
+async fn postgres_plan(query: &QueryTree, pool: &Pool<Postgres>) -> String {
+    let (sql, values) = prepare_sqlx_query(query, DbKind::Postgres).unwrap();
+    let sql = format!("EXPLAIN {sql}");
+
+    let mut sqlx_query = sqlx::query(&sql);
+    for value in values {
+        sqlx_query = bind_postgres_value(sqlx_query, value).unwrap();
+    }
+
+    let mut conn = pool.acquire().await.unwrap();
+    sqlx::query("SET enable_seqscan = off")
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+    let rows = sqlx_query.fetch_all(&mut *conn).await.unwrap();
+    sqlx::query("RESET enable_seqscan")
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+    rows.iter()
+        .map(|row| row.get::<String, _>("QUERY PLAN"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether a PostgreSQL query plan reads the table through an index
+fn postgres_uses_index(plan: &str) -> bool {
+    plan.contains("Index") && !plan.contains("Seq Scan")
+}
+
+/// Indexes used by the SQL generated for a query on MySQL (`key` column of `EXPLAIN`),
+/// or `None` for the tables read without index
+async fn mysql_plan(query: &QueryTree, pool: &Pool<MySql>) -> Vec<Option<String>> {
+    let (sql, values) = prepare_sqlx_query(query, DbKind::MySql).unwrap();
+    let sql = format!("EXPLAIN {}", to_backtick_identifiers(&sql));
+
+    let mut sqlx_query = sqlx::query(&sql);
+    for value in values {
+        sqlx_query = bind_mysql_value(sqlx_query, value).unwrap();
+    }
+
+    sqlx_query
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get::<Option<String>, _>("key"))
+        .collect()
+}
+
+/// Test that equality and range filters on indexed columns use an index on SQLite
+#[tokio::test]
+async fn test_index_usage_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    sqlx::query(CREATE_TITLE_INDEX)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    for query in indexed_filters() {
+        let plan = sqlite_plan(&query, &pool).await;
+        assert!(sqlite_uses_index(&plan), "{query}: {plan}");
+    }
+
+    let plan = sqlite_plan(&unindexed_filter(), &pool).await;
+    assert!(!sqlite_uses_index(&plan), "{plan}");
+}
+
+/// Test that equality and range filters on indexed columns use an index on PostgreSQL
+#[tokio::test]
+async fn test_index_usage_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+    sqlx::query(CREATE_TITLE_INDEX)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    for query in indexed_filters() {
+        let plan = postgres_plan(&query, &pool).await;
+        assert!(postgres_uses_index(&plan), "{query}: {plan}");
+    }
+
+    let plan = postgres_plan(&unindexed_filter(), &pool).await;
+    assert!(!postgres_uses_index(&plan), "{plan}");
+}
+
+/// Test that equality filters on indexed columns use an index on MySQL.
+/// Range filters are left out: on the few rows of the dummy table, the optimizer
+/// may rightfully prefer a full scan, and MySQL cannot disable full scans.
+#[tokio::test]
+async fn test_index_usage_mysql() {
+    let Some(pool) = dummy_mysql_database().await else {
+        return;
+    };
+    prepare_dummy_mysql_database(&pool).await;
+    sqlx::query(CREATE_TITLE_INDEX)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    for query in [
+        filter("id", "=", json!(2)),
+        filter("title", "=", json!("Second todo")),
+    ] {
+        let plan = mysql_plan(&query, &pool).await;
+        assert!(plan.iter().all(Option::is_some), "{query}: {plan:?}");
+    }
+
+    let plan = mysql_plan(&unindexed_filter(), &pool).await;
+    assert!(plan.iter().any(Option::is_none), "{plan:?}");
+}
@@ -1,13 +1,35 @@
 //! Serialized queries tests
 
-use sqlx::FromRow;
-use std::{fs, path::Path};
+use std::{collections::HashSet, fs, path::Path};
 
-use crate::database::sqlite::fetch_sqlite_query;
-use crate::queries::serialize::{QueryData, QueryTree};
-use crate::tests::dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database};
+use std::time::Duration;
 
-use super::dummy::Todo;
+use sqlx::{Connection, Row};
+
+use crate::database::{
+    mysql::bind_mysql_value,
+    postgres::{bind_postgres_value, fetch_postgres_query},
+    prepare_sqlx_query, serialize_rows, serialize_rows_dynamic, set_debug_bindings,
+    set_statement_caching,
+    sqlite::{
+        bind_sqlite_value, fetch_many_sqlite_queries, fetch_sqlite_query,
+        fetch_sqlite_query_with_timeout, sqlite_rows_to_json,
+    },
+    DbKind, TypeRegistry,
+};
+use crate::error::{BindError, DeserializeError, QueryError, SnapshotError};
+use crate::operations::serialize::{object_from_value, GranularOperation};
+use crate::queries::serialize::{
+    Condition, Constraint, ConstraintValue, FinalType, Operator, QueryData, QueryTree, ReturnType,
+    ScalarFunction,
+};
+use crate::queries::Checkable;
+
+use super::backends::{backend_tests, TestBackend};
+use super::dummy::{
+    dummy_postgres_database, dummy_sqlite_database, prepare_dummy_postgres_database,
+    prepare_dummy_sqlite_database, Todo,
+};
 use super::utils::read_serialized_query;
 
 #[tokio::test]
@@ -22,31 +44,118 @@ async fn test_deserialize_queries() {
 
         // Deserialize the query from json
         let query: serde_json::Value = serde_json::from_str(&serialized_query).unwrap();
-        serde_json::from_value::<QueryTree>(query).expect(&format!(
-            "Failed to deserialize query: {}",
-            entry.file_name().into_string().unwrap()
-        ));
+        serde_json::from_value::<QueryTree>(query).unwrap_or_else(|_| {
+            panic!(
+                "Failed to deserialize query: {}",
+                entry.file_name().into_string().unwrap()
+            )
+        });
+    }
+}
+
+/// Build an `id = value` constraint
+fn id_equals(value: i64) -> Constraint {
+    Constraint {
+        column: "id".to_string(),
+        function: None,
+        operator: Operator::Equal,
+        value: ConstraintValue::Final(FinalType::Number(value.into())),
     }
 }
 
+/// Test that the `anyOf` shorthand expands to the canonical `Or` of `Single` conditions
+#[test]
+fn test_deserialize_any_of() {
+    let shorthand = read_serialized_query("10_any_of.json");
+    let canonical = QueryTree {
+        return_type: ReturnType::Many,
+        table: "todos".to_string(),
+        condition: Some(Condition::Or {
+            conditions: vec![
+                Condition::Single {
+                    constraint: id_equals(1),
+                },
+                Condition::Single {
+                    constraint: id_equals(3),
+                },
+            ],
+        }),
+        paginate: None,
+        distinct_on: vec![],
+        order_by: None,
+    };
+
+    assert_eq!(
+        serde_json::to_value(&shorthand).unwrap(),
+        serde_json::to_value(&canonical).unwrap()
+    );
+    assert_eq!(
+        prepare_sqlx_query(&shorthand, DbKind::Sqlite).unwrap(),
+        prepare_sqlx_query(&canonical, DbKind::Sqlite).unwrap()
+    );
+}
+
+/// Test that the `allOf` shorthand can be nested in a canonical condition
+#[test]
+fn test_deserialize_all_of() {
+    let shorthand = read_serialized_query("11_all_of.json");
+    let canonical = QueryTree {
+        return_type: ReturnType::Many,
+        table: "todos".to_string(),
+        condition: Some(Condition::Or {
+            conditions: vec![
+                Condition::all_of(vec![
+                    Constraint {
+                        column: "id".to_string(),
+                        function: None,
+                        operator: Operator::GreaterThan,
+                        value: ConstraintValue::Final(FinalType::Number(1.into())),
+                    },
+                    Constraint {
+                        column: "title".to_string(),
+                        function: None,
+                        operator: Operator::Like,
+                        value: ConstraintValue::Final(FinalType::String("Second%".to_string())),
+                    },
+                ]),
+                Condition::Single {
+                    constraint: id_equals(1),
+                },
+            ],
+        }),
+        paginate: None,
+        distinct_on: vec![],
+        order_by: None,
+    };
+
+    assert_eq!(
+        serde_json::to_value(&shorthand).unwrap(),
+        serde_json::to_value(&canonical).unwrap()
+    );
+    assert_eq!(
+        prepare_sqlx_query(&shorthand, DbKind::Sqlite).unwrap(),
+        prepare_sqlx_query(&canonical, DbKind::Sqlite).unwrap()
+    );
+}
+
 // ************************************************************************* //
-//                     TESTING AGAINST SQLITE BACKEND                        //
+//                        TESTING AGAINST ALL BACKENDS                       //
 // ************************************************************************* //
 
 /// Test single row fetching
-#[tokio::test]
-async fn test_sqlite_single() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_single<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("01_single.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(row) => {
             let single_row = row.expect("Expected a single row");
 
-            let data = Todo::from_row(&single_row).expect("Failed to convert single row");
+            let data = single_row;
             assert_eq!(data.id, 1);
             assert_eq!(data.title, "First todo");
             assert_eq!(data.content, "This is the first todo");
@@ -56,13 +165,13 @@ async fn test_sqlite_single() {
 }
 
 /// Test many row fetching
-#[tokio::test]
-async fn test_sqlite_many() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_many<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("02_many.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(_) => {
@@ -71,17 +180,17 @@ async fn test_sqlite_many() {
         QueryData::Many(rows) => {
             assert_eq!(rows.len(), 3);
 
-            let first_row = Todo::from_row(&rows[0]).expect("Failed to convert first row");
+            let first_row = &rows[0];
             assert_eq!(first_row.id, 1);
             assert_eq!(first_row.title, "First todo");
             assert_eq!(first_row.content, "This is the first todo");
 
-            let second_row = Todo::from_row(&rows[1]).expect("Failed to convert second row");
+            let second_row = &rows[1];
             assert_eq!(second_row.id, 2);
             assert_eq!(second_row.title, "Second todo");
             assert_eq!(second_row.content, "This is the second todo");
 
-            let third_row = Todo::from_row(&rows[2]).expect("Failed to convert third row");
+            let third_row = &rows[2];
             assert_eq!(third_row.id, 3);
             assert_eq!(third_row.title, "Third todo");
             assert_eq!(third_row.content, "This is the third todo");
@@ -90,19 +199,19 @@ async fn test_sqlite_many() {
 }
 
 /// Test single row fetching with a condition
-#[tokio::test]
-async fn test_sqlite_single_with_condition() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_single_with_condition<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("03_single_with_condition.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(row) => {
             let single_row = row.expect("Expected a single row");
 
-            let data = Todo::from_row(&single_row).expect("Failed to convert single row");
+            let data = single_row;
             assert_eq!(data.id, 2);
             assert_eq!(data.title, "Second todo");
             assert_eq!(data.content, "This is the second todo");
@@ -112,13 +221,13 @@ async fn test_sqlite_single_with_condition() {
 }
 
 /// Test many row fetching with a condition returning a single row
-#[tokio::test]
-async fn test_sqlite_many_with_condition() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_many_with_condition<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("04_many_with_condition.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(_) => {
@@ -127,7 +236,7 @@ async fn test_sqlite_many_with_condition() {
         QueryData::Many(rows) => {
             assert_eq!(rows.len(), 1);
 
-            let data = Todo::from_row(&rows[0]).expect("Failed to convert first row");
+            let data = &rows[0];
             assert_eq!(data.id, 2);
             assert_eq!(data.title, "Second todo");
             assert_eq!(data.content, "This is the second todo");
@@ -136,13 +245,13 @@ async fn test_sqlite_many_with_condition() {
 }
 
 /// Test fetching many rows with a nested OR condition
-#[tokio::test]
-async fn test_sqlite_nested_or() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_nested_or<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("05_nested_or.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(_) => {
@@ -155,13 +264,13 @@ async fn test_sqlite_nested_or() {
 }
 
 /// Test single row fetching with no existing matching entry
-#[tokio::test]
-async fn test_sqlite_empty() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_empty<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("06_empty.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(row) => {
@@ -172,13 +281,13 @@ async fn test_sqlite_empty() {
 }
 
 /// Test `IN` operations with arrays
-#[tokio::test]
-async fn test_sqlite_in() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_in<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("07_in.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(_) => {
@@ -187,12 +296,12 @@ async fn test_sqlite_in() {
         QueryData::Many(rows) => {
             assert_eq!(rows.len(), 2);
 
-            let first_row = Todo::from_row(&rows[0]).expect("Failed to convert first row");
+            let first_row = &rows[0];
             assert_eq!(first_row.id, 1);
             assert_eq!(first_row.title, "First todo");
             assert_eq!(first_row.content, "This is the first todo");
 
-            let second_row = Todo::from_row(&rows[1]).expect("Failed to convert second row");
+            let second_row = &rows[1];
             assert_eq!(second_row.id, 3);
             assert_eq!(second_row.title, "Third todo");
             assert_eq!(second_row.content, "This is the third todo");
@@ -200,20 +309,44 @@ async fn test_sqlite_in() {
     }
 }
 
+/// Test that `not_in` excludes the listed values, in the database and in the engine
+async fn test_not_in<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "not_in", "value": [1, 3] }
+        }
+    }))
+    .unwrap();
+    let rows = B::fetch(&query, &pool).await.unwrap_many();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].id, 2);
+
+    let object = |id: i64| object_from_value(serde_json::json!({ "id": id })).unwrap();
+    assert!(query.check(&object(2)));
+    assert!(!query.check(&object(3)));
+}
+
 /// Test paginated single row queries
-#[tokio::test]
-async fn test_sqlite_paginated_single() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_paginated_single<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("08_paginated_single.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(row) => {
             assert!(row.is_some());
 
-            let row = Todo::from_row(&row.unwrap()).expect("Failed to convert row");
+            let row = row.unwrap();
 
             assert_eq!(row.id, 2);
             assert_eq!(row.title, "Second todo");
@@ -226,13 +359,13 @@ async fn test_sqlite_paginated_single() {
 }
 
 /// Test paginated multi row queries
-#[tokio::test]
-async fn test_sqlite_paginated_many() {
-    let pool = dummy_sqlite_database().await;
-    prepare_dummy_sqlite_database(&pool).await;
+async fn test_paginated_many<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
 
     let query = read_serialized_query("09_paginated_many.json");
-    let result = fetch_sqlite_query(&query, &pool).await;
+    let result = B::fetch(&query, &pool).await;
 
     match result {
         QueryData::Single(_) => {
@@ -241,7 +374,7 @@ async fn test_sqlite_paginated_many() {
         QueryData::Many(rows) => {
             assert_eq!(rows.len(), 1);
 
-            let row = Todo::from_row(&rows[0]).expect("Failed to convert row");
+            let row = &rows[0];
 
             assert_eq!(row.id, 2);
             assert_eq!(row.title, "Second todo");
@@ -249,3 +382,1232 @@ async fn test_sqlite_paginated_many() {
         }
     }
 }
+
+/// Test that structurally identical queries built differently share the same canonical key
+#[test]
+fn test_canonical_key() {
+    // Shuffled keys, omitted optional field and condition shorthand
+    let from_json: QueryTree = serde_json::from_value(serde_json::json!({
+        "condition": {
+            "anyOf": [
+                { "value": 1, "operator": "=", "column": "id" },
+                { "column": "title", "operator": "like", "value": "Third%" }
+            ]
+        },
+        "table": "todos",
+        "return": "many"
+    }))
+    .unwrap();
+
+    let built = QueryTree {
+        return_type: ReturnType::Many,
+        table: "todos".to_string(),
+        condition: Some(Condition::any_of(vec![
+            Constraint {
+                column: "id".to_string(),
+                function: None,
+                operator: Operator::Equal,
+                value: ConstraintValue::Final(FinalType::Number(1.into())),
+            },
+            Constraint {
+                column: "title".to_string(),
+                function: None,
+                operator: Operator::Like,
+                value: ConstraintValue::Final(FinalType::String("Third%".to_string())),
+            },
+        ])),
+        paginate: None,
+        distinct_on: vec![],
+        order_by: None,
+    };
+
+    assert_eq!(from_json.canonical_key(), built.canonical_key());
+    assert_eq!(from_json, built);
+    assert!(built.canonical_key().contains("\"paginate\":null"));
+
+    let keys: HashSet<QueryTree> = [from_json, built.clone()].into_iter().collect();
+    assert_eq!(keys.len(), 1);
+
+    // A different query produces a different key
+    let other = QueryTree {
+        table: "other".to_string(),
+        ..built.clone()
+    };
+    assert_ne!(other.canonical_key(), built.canonical_key());
+}
+
+/// Test that `DISTINCT ON` columns lead the generated ordering
+#[test]
+fn test_distinct_on_statement() {
+    let query = read_serialized_query("13_distinct_on.json");
+    let (sql, values) = prepare_sqlx_query(&query, DbKind::Postgres).unwrap();
+
+    assert_eq!(
+        sql,
+        "SELECT DISTINCT ON (user_id) * FROM events ORDER BY user_id ASC, created_at DESC LIMIT $1 "
+    );
+    assert_eq!(values.len(), 1);
+    assert!(!query.is_locally_evaluable());
+}
+
+/// Test that `DISTINCT ON` is refused by the backends that do not support it
+#[tokio::test]
+async fn test_distinct_on_unsupported() {
+    let pool = dummy_sqlite_database().await;
+    let query = read_serialized_query("13_distinct_on.json");
+
+    let result = fetch_sqlite_query_with_timeout(&query, &pool, Duration::from_secs(1)).await;
+    assert!(matches!(
+        result,
+        Err(QueryError::Unsupported {
+            backend: DbKind::Sqlite,
+            ..
+        })
+    ));
+}
+
+/// Test that database errors are returned by the fetch functions instead of panicking
+#[tokio::test]
+async fn test_fetch_error() {
+    let pool = dummy_sqlite_database().await;
+    let query = read_serialized_query("02_many.json");
+
+    // The dummy table was not created
+    let result = fetch_sqlite_query(&query, &pool).await;
+    assert!(matches!(result, Err(err) if err.database_error().is_some()));
+
+    // The pool is still usable afterwards
+    prepare_dummy_sqlite_database(&pool).await;
+    let result = fetch_sqlite_query(&query, &pool).await;
+    assert_eq!(result.unwrap().unwrap_many().len(), 3);
+}
+
+/// Test fetching the latest row per group with `DISTINCT ON` on PostgreSQL
+#[tokio::test]
+async fn test_distinct_on_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    sqlx::query(
+        "CREATE TEMPORARY TABLE events (id INTEGER PRIMARY KEY, user_id INTEGER, created_at INTEGER)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO events (id, user_id, created_at) VALUES \
+        (1, 1, 10), (2, 1, 20), (3, 2, 15), (4, 2, 5), (5, 3, 1)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let query = read_serialized_query("13_distinct_on.json");
+    let ids: Vec<i32> = fetch_postgres_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many()
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+
+    assert_eq!(ids, vec![2, 3, 5]);
+}
+
+/// Test fetching rows with the `anyOf` condition shorthand
+async fn test_any_of<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let query = read_serialized_query("10_any_of.json");
+    let result = B::fetch(&query, &pool).await;
+
+    let ids: Vec<i32> = result.unwrap_many().iter().map(|todo| todo.id).collect();
+    assert_eq!(ids, vec![1, 3]);
+}
+
+/// Test binding floats
+async fn test_bind_numbers<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "<", "value": 2.5 }
+        }
+    }))
+    .unwrap();
+    let mut ids: Vec<i32> = B::fetch(&query, &pool)
+        .await
+        .unwrap_many()
+        .iter()
+        .map(|todo| todo.id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2]);
+}
+
+/// Test that integers beyond the i64 range are rejected instead of being bound as
+/// lossy floats on the backends without unsigned 64-bit integers
+#[tokio::test]
+async fn test_bind_u64_max() {
+    let max = FinalType::Number(u64::MAX.into());
+
+    assert!(matches!(
+        bind_sqlite_value(sqlx::query("SELECT ?"), max.clone()),
+        Err(BindError::UnsupportedNumber(_))
+    ));
+    assert!(matches!(
+        bind_postgres_value(sqlx::query("SELECT $1"), max.clone()),
+        Err(BindError::UnsupportedNumber(_))
+    ));
+    assert!(bind_mysql_value(sqlx::query("SELECT ?"), max).is_ok());
+
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "=", "value": u64::MAX }
+        }
+    }))
+    .unwrap();
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    assert!(matches!(
+        fetch_sqlite_query(&query, &pool).await,
+        Err(QueryError::Bind(BindError::UnsupportedNumber(_)))
+    ));
+}
+
+/// Test keyset pagination to exhaustion, following the next cursor
+async fn test_cursor_pagination<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    for (order, expected_pages) in [
+        ("asc", vec![vec![1, 2], vec![3]]),
+        ("desc", vec![vec![3, 2], vec![1]]),
+    ] {
+        let mut query: QueryTree = serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "paginate": { "perPage": 2, "orderBy": { "column": "id", "order": order } }
+        }))
+        .unwrap();
+
+        let mut pages = vec![];
+        loop {
+            let page = B::fetch_page(&query, &pool).await;
+            pages.push(page.data.iter().map(|todo| todo.id).collect::<Vec<i32>>());
+
+            match page.next_cursor {
+                Some(cursor) => query.paginate.as_mut().unwrap().cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, expected_pages);
+    }
+}
+
+/// Test that small result sets are returned in full, and large ones as their first page
+async fn test_adaptive_pagination<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "paginate": { "perPage": 2, "orderBy": { "column": "id", "order": "asc" } }
+    }))
+    .unwrap();
+
+    // 3 rows, at most 5: all of them, without pagination
+    let page = B::fetch_adaptive_page(&query, &pool, 5).await;
+    assert!(!page.paginated);
+    assert_eq!(
+        page.data.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert!(page.next_cursor.is_none());
+
+    // 3 rows, more than 2: the first page of the query
+    let page = B::fetch_adaptive_page(&query, &pool, 2).await;
+    assert!(page.paginated);
+    assert_eq!(
+        page.data.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+        vec![1, 2]
+    );
+    assert_eq!(page.next_cursor, Some(FinalType::Number(2.into())));
+
+    // Without pagination options, pages hold as many rows as the threshold
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "orderBy": { "column": "id", "order": "desc" }
+    }))
+    .unwrap();
+    let page = B::fetch_adaptive_page(&query, &pool, 1).await;
+    assert!(page.paginated);
+    assert_eq!(
+        page.data.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+        vec![3]
+    );
+}
+
+/// Test a case-insensitive login lookup returning a single row: `iequal` matches the
+/// stored email whatever its case without treating `_` as a wildcard, while `ilike`
+/// lowers both sides on every backend
+async fn test_case_insensitive_single<B: TestBackend>() {
+    let Some(pool) = B::setup().await else {
+        return;
+    };
+
+    for title in ["UserXName@x.com", "User_Name@X.com"] {
+        let operation = GranularOperation::try_from(serde_json::json!({
+            "type": "create",
+            "table": "todos",
+            "data": { "title": title, "content": "" }
+        }))
+        .unwrap();
+        B::execute(operation, &pool).await.unwrap();
+    }
+
+    let login = |return_type: &str, operator: &str| -> QueryTree {
+        let query: QueryTree = serde_json::from_value(serde_json::json!({
+            "return": return_type,
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "title", "operator": operator, "value": "user_name@x.com" }
+            }
+        }))
+        .unwrap();
+        query.validate().unwrap();
+        query
+    };
+
+    let query = login("single", "iequal");
+    match B::fetch(&query, &pool).await {
+        QueryData::Single(row) => assert_eq!(row.expect("Expected a single row").id, 5),
+        QueryData::Many(_) => panic!("Expected a single row"),
+    }
+
+    // The engine gives the same single row
+    let rows = match B::fetch(&read_serialized_query("02_many.json"), &pool).await {
+        QueryData::Many(rows) => rows,
+        QueryData::Single(_) => panic!("Expected many rows"),
+    };
+    let objects: Vec<_> = rows
+        .iter()
+        .map(|row| object_from_value(serde_json::to_value(row).unwrap()).unwrap())
+        .collect();
+    let matches = query.apply(DbKind::Sqlite, objects.clone());
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0]["id"], 5);
+
+    // `_` matches any character with `ilike`
+    match B::fetch(&login("many", "ilike"), &pool).await {
+        QueryData::Many(rows) => {
+            let ids: HashSet<i32> = rows.iter().map(|row| row.id).collect();
+            assert_eq!(ids, HashSet::from([4, 5]));
+        }
+        QueryData::Single(_) => panic!("Expected many rows"),
+    }
+    assert_eq!(
+        login("many", "ilike").apply(DbKind::Sqlite, objects).len(),
+        2
+    );
+}
+
+backend_tests!(
+    test_single,
+    test_many,
+    test_single_with_condition,
+    test_many_with_condition,
+    test_nested_or,
+    test_empty,
+    test_in,
+    test_not_in,
+    test_paginated_single,
+    test_paginated_many,
+    test_any_of,
+    test_bind_numbers,
+    test_cursor_pagination,
+    test_adaptive_pagination,
+    test_case_insensitive_single,
+);
+
+/// Test that cached statements give the same results as uncached ones,
+/// with a single prepared statement per query shape
+#[tokio::test]
+async fn test_statement_caching() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    let mut conn = pool.acquire().await.unwrap();
+
+    let queries: Vec<QueryTree> = fs::read_dir("src/tests/queries")
+        .unwrap()
+        .map(|entry| read_serialized_query(entry.unwrap().file_name().to_str().unwrap()))
+        .filter(|query| query.table == "todos" && query.subquery_tables().is_empty())
+        .collect();
+    let shapes: HashSet<String> = queries
+        .iter()
+        .map(|query| prepare_sqlx_query(query, DbKind::Sqlite).unwrap().0)
+        .collect();
+
+    let mut results = vec![];
+    for caching in [false, true] {
+        set_statement_caching(caching);
+        conn.clear_cached_statements().await.unwrap();
+
+        // Run every query twice to hit the cache
+        let mut values = vec![];
+        for query in queries.iter().chain(queries.iter()) {
+            let rows = match fetch_sqlite_query(query, &mut *conn).await.unwrap() {
+                QueryData::Single(row) => row.into_iter().collect(),
+                QueryData::Many(rows) => rows,
+            };
+            values.push(sqlite_rows_to_json(&rows));
+        }
+
+        let expected_size = if caching { shapes.len() } else { 0 };
+        assert_eq!(conn.cached_statements_size(), expected_size);
+        results.push(values);
+    }
+
+    assert_eq!(results[0], results[1]);
+}
+
+/// Test that constraints with values incompatible with their operator are rejected
+#[test]
+fn test_validate_constraints() {
+    let constraint = |operator: &str, value: serde_json::Value| -> Constraint {
+        serde_json::from_value(serde_json::json!({
+            "column": "title",
+            "operator": operator,
+            "value": value
+        }))
+        .unwrap()
+    };
+    let incompatible = |constraint: Constraint, expected: &str| match constraint.validate() {
+        Err(DeserializeError::IncompatibleConstraint {
+            column,
+            expected: reason,
+            ..
+        }) => {
+            assert_eq!(column, "title");
+            assert_eq!(reason, expected);
+        }
+        result => panic!("Expected an incompatible constraint, got {result:?}"),
+    };
+
+    // `like`, `ilike` and `iequal` require strings
+    for operator in ["like", "ilike", "iequal"] {
+        incompatible(
+            constraint(operator, serde_json::json!(42)),
+            "a string value",
+        );
+        incompatible(
+            constraint(operator, serde_json::json!(null)),
+            "a string value",
+        );
+        incompatible(
+            constraint(operator, serde_json::json!(["a"])),
+            "a string value",
+        );
+        assert!(constraint(operator, serde_json::json!("a%"))
+            .validate()
+            .is_ok());
+    }
+
+    // `prefix` requires a constant string
+    for value in [
+        serde_json::json!(42),
+        serde_json::json!(["a"]),
+        serde_json::json!({ "column": "content" }),
+    ] {
+        incompatible(constraint("prefix", value), "a string value");
+    }
+    assert!(constraint("prefix", serde_json::json!("ab"))
+        .validate()
+        .is_ok());
+
+    // `in` and `not_in` require a list
+    for operator in ["in", "not_in"] {
+        incompatible(constraint(operator, serde_json::json!(1)), "a list value");
+        incompatible(constraint(operator, serde_json::json!("a")), "a list value");
+        assert!(constraint(operator, serde_json::json!([1, 2]))
+            .validate()
+            .is_ok());
+    }
+
+    // Lists mix no value types
+    for operator in ["in", "not_in", ">any", "<=all"] {
+        for list in [
+            serde_json::json!([1, "two", true]),
+            serde_json::json!(["a", null]),
+            serde_json::json!([true, 0]),
+        ] {
+            incompatible(
+                constraint(operator, list),
+                "a list of values of the same type",
+            );
+        }
+        assert!(constraint(operator, serde_json::json!(["a", "b"]))
+            .validate()
+            .is_ok());
+        assert!(constraint(operator, serde_json::json!([]))
+            .validate()
+            .is_ok());
+    }
+
+    // Comparisons require a single value
+    for operator in ["=", "!=", "<", ">", "<=", ">="] {
+        incompatible(
+            constraint(operator, serde_json::json!([1, 2])),
+            "a single value",
+        );
+        incompatible(
+            constraint(operator, serde_json::json!({ "a": 1 })),
+            "a single value",
+        );
+        assert!(constraint(operator, serde_json::json!(1))
+            .validate()
+            .is_ok());
+    }
+
+    // Quantified comparisons require a list, even against another column
+    for operator in [">all", "<any"] {
+        incompatible(constraint(operator, serde_json::json!(1)), "a list value");
+        incompatible(
+            constraint(operator, serde_json::json!({ "column": "pattern" })),
+            "a list value",
+        );
+        assert!(constraint(operator, serde_json::json!([1, 2]))
+            .validate()
+            .is_ok());
+    }
+
+    // Any value can be compared with another column or checked for containment
+    assert!(
+        constraint("like", serde_json::json!({ "column": "pattern" }))
+            .validate()
+            .is_ok()
+    );
+    assert!(constraint("contains", serde_json::json!({ "a": 1 }))
+        .validate()
+        .is_ok());
+
+    // Containment is checked on the column itself
+    let mut with_function = constraint("contains", serde_json::json!(["a"]));
+    with_function.function = Some(ScalarFunction::Lower);
+    incompatible(with_function, "a column without scalar function");
+
+    // Invalid constraints are found in nested conditions
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "and",
+            "conditions": [
+                { "type": "single", "constraint": { "column": "id", "operator": "=", "value": 1 } },
+                { "anyOf": [{ "column": "title", "operator": "like", "value": 42 }] }
+            ]
+        }
+    }))
+    .unwrap();
+    assert!(query.validate().is_err());
+
+    // All the query fixtures are valid
+    for entry in fs::read_dir("src/tests/queries").unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        assert!(read_serialized_query(&name).validate().is_ok(), "{name}");
+    }
+}
+
+/// Test that misspelled keys are rejected instead of being silently dropped
+#[test]
+fn test_reject_unknown_fields() {
+    let rejected = |value: serde_json::Value, field: &str| match QueryTree::try_from(value) {
+        Err(DeserializeError::Malformed { source: err, .. }) => {
+            assert!(
+                err.to_string()
+                    .contains(&format!("unknown field `{field}`")),
+                "{err}"
+            );
+        }
+        result => panic!("Expected an unknown field error, got {result:?}"),
+    };
+
+    // A misspelled `condition` would otherwise match every row
+    rejected(
+        serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "conditon": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": "=", "value": 1 }
+            }
+        }),
+        "conditon",
+    );
+    rejected(
+        serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "paginate": { "perPage": 10, "orderby": { "order": "asc", "column": "id" } }
+        }),
+        "orderby",
+    );
+
+    // Misspelled keys nested in conditions are rejected as well
+    let nested = serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "=", "value": 1, "negate": true }
+        }
+    });
+    assert!(QueryTree::try_from(nested).is_err());
+
+    // Well-formed queries are still accepted
+    let query = serde_json::to_value(read_serialized_query("04_many_with_condition.json")).unwrap();
+    assert!(QueryTree::try_from(query).is_ok());
+}
+
+/// Test that deserialization errors point at the malformed nested node
+#[test]
+fn test_malformed_paths() {
+    let malformed_at = |condition: serde_json::Value| {
+        let query =
+            serde_json::json!({ "return": "many", "table": "todos", "condition": condition });
+        match QueryTree::try_from(query) {
+            Err(DeserializeError::Malformed { path, source }) => (path, source.to_string()),
+            result => panic!("Expected a malformed query, got {result:?}"),
+        }
+    };
+    let constraint = |operator: &str| {
+        serde_json::json!({
+            "type": "single",
+            "constraint": { "column": "id", "operator": operator, "value": 1 }
+        })
+    };
+
+    // An unknown operator in the third condition of a nested `and`
+    let (path, message) = malformed_at(serde_json::json!({
+        "type": "or",
+        "conditions": [
+            constraint("="),
+            {
+                "type": "and",
+                "conditions": [constraint("="), constraint(">"), constraint("=>")]
+            }
+        ]
+    }));
+    assert_eq!(
+        path,
+        "/condition/conditions/1/conditions/2/constraint/operator"
+    );
+    assert!(message.contains("unknown variant `=>`"), "{message}");
+
+    // An unknown field of a constraint
+    let (path, message) = malformed_at(serde_json::json!({
+        "type": "single",
+        "constraint": { "column": "id", "operator": "=", "value": 1, "negate": true }
+    }));
+    assert_eq!(path, "/condition/constraint");
+    assert!(message.contains("unknown field `negate`"), "{message}");
+
+    // Shorthand lists and subqueries
+    let (path, _) = malformed_at(serde_json::json!({
+        "anyOf": [
+            { "column": "id", "operator": "=", "value": 1 },
+            { "column": "title", "fn": "reverse", "operator": "=", "value": "a" }
+        ]
+    }));
+    assert_eq!(path, "/condition/anyOf/1/fn");
+    let (path, _) = malformed_at(serde_json::json!({
+        "type": "exists",
+        "subquery": { "return": "many", "table": "comments", "condition": constraint("~") },
+        "correlateOn": ["todo_id", "id"]
+    }));
+    assert_eq!(path, "/condition/subquery/condition/constraint/operator");
+
+    // A malformed condition type is reported on the condition itself
+    let (path, message) = malformed_at(serde_json::json!({ "type": "xor", "conditions": [] }));
+    assert_eq!(path, "/condition");
+    assert!(message.contains("unknown variant `xor`"), "{message}");
+
+    // Pagination cursors cannot be objects
+    let query = serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "paginate": { "perPage": 10, "cursor": { "id": 1 } }
+    });
+    match QueryTree::try_from(query) {
+        Err(DeserializeError::Malformed { path, .. }) => assert_eq!(path, "/paginate/cursor"),
+        result => panic!("Expected a malformed query, got {result:?}"),
+    }
+}
+
+/// Comments of the dummy todos, for correlated subqueries
+const INSERT_COMMENTS: &str = "INSERT INTO comments (id, todo_id, content) VALUES \
+    (1, 1, 'A great todo'), (2, 1, 'Meh'), (3, 3, 'Meh')";
+
+/// Test the SQL generated for a correlated `EXISTS` condition
+#[test]
+fn test_exists_sql() {
+    let query = read_serialized_query("15_exists.json");
+
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::Sqlite).unwrap(),
+        (
+            "SELECT * FROM todos WHERE EXISTS (SELECT 1 FROM comments \
+            WHERE comments.\"todo_id\" = todos.\"id\" AND (\"content\" like ?))"
+                .to_string(),
+            vec![FinalType::String("%great%".to_string())]
+        )
+    );
+
+    // Queries depending on another table cannot be evaluated locally
+    assert_eq!(query.subquery_tables(), vec!["comments"]);
+    assert!(!query.is_locally_evaluable());
+    assert!(query.validate().is_ok());
+}
+
+/// Test the SQL generated for quantified comparisons, expanded for every backend
+#[test]
+fn test_quantified_sql() {
+    let query = |operator: &str, value: serde_json::Value| -> QueryTree {
+        serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": operator, "value": value }
+            }
+        }))
+        .unwrap()
+    };
+
+    assert_eq!(
+        prepare_sqlx_query(&query(">all", serde_json::json!([1, 2])), DbKind::Sqlite).unwrap(),
+        (
+            "SELECT * FROM todos WHERE (\"id\" > ? AND \"id\" > ?)".to_string(),
+            vec![FinalType::Number(1.into()), FinalType::Number(2.into())]
+        )
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query("<any", serde_json::json!([1, 2])), DbKind::Postgres)
+            .unwrap()
+            .0,
+        "SELECT * FROM todos WHERE (\"id\" < $1 OR \"id\" < $2)"
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query(">all", serde_json::json!([])), DbKind::Sqlite)
+            .unwrap()
+            .0,
+        "SELECT * FROM todos WHERE 1 = 1"
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query("<any", serde_json::json!([])), DbKind::Sqlite)
+            .unwrap()
+            .0,
+        "SELECT * FROM todos WHERE 1 = 0"
+    );
+}
+
+/// Test that a scalar value for a quantified comparison is rejected before
+/// reaching the SQL traversal
+#[tokio::test]
+async fn test_quantified_scalar_value() {
+    let query = |operator: &str| -> QueryTree {
+        serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": operator, "value": 1 }
+            }
+        }))
+        .unwrap()
+    };
+
+    for operator in [">all", "<any"] {
+        for backend in [DbKind::Sqlite, DbKind::MySql, DbKind::Postgres] {
+            assert!(matches!(
+                prepare_sqlx_query(&query(operator), backend),
+                Err(QueryError::Deserialize(
+                    DeserializeError::IncompatibleConstraint { .. }
+                ))
+            ));
+        }
+    }
+
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    assert!(matches!(
+        fetch_sqlite_query(&query(">all"), &pool).await,
+        Err(QueryError::Deserialize(
+            DeserializeError::IncompatibleConstraint { .. }
+        ))
+    ));
+}
+
+/// Test that a prefix constraint with a value other than a string is rejected
+/// before reaching the SQL traversal
+#[tokio::test]
+async fn test_prefix_malformed_value() {
+    let query = |value: serde_json::Value| -> QueryTree {
+        serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "title", "operator": "prefix", "value": value }
+            }
+        }))
+        .unwrap()
+    };
+
+    for value in [serde_json::json!(3), serde_json::json!(["First"])] {
+        for backend in [DbKind::Sqlite, DbKind::MySql, DbKind::Postgres] {
+            assert!(matches!(
+                prepare_sqlx_query(&query(value.clone()), backend),
+                Err(QueryError::Deserialize(
+                    DeserializeError::IncompatibleConstraint { .. }
+                ))
+            ));
+        }
+    }
+
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    assert!(matches!(
+        fetch_sqlite_query(&query(serde_json::json!(3)), &pool).await,
+        Err(QueryError::Deserialize(
+            DeserializeError::IncompatibleConstraint { .. }
+        ))
+    ));
+}
+
+/// Test that an empty `in` list compiles to a contradiction instead of invalid SQL
+#[test]
+fn test_empty_in_sql() {
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "id", "operator": "in", "value": [] }
+        }
+    }))
+    .unwrap();
+
+    for backend in [DbKind::Sqlite, DbKind::MySql, DbKind::Postgres] {
+        assert_eq!(
+            prepare_sqlx_query(&query, backend).unwrap(),
+            ("SELECT * FROM todos WHERE 1 = 0".to_string(), vec![])
+        );
+    }
+}
+
+/// Test that a `NOT` condition wraps the SQL of its child, preserving the order
+/// of the bound values
+#[test]
+fn test_not_sql() {
+    let query = read_serialized_query("16_not_or.json");
+
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::Sqlite).unwrap(),
+        (
+            "SELECT * FROM todos WHERE (NOT ((\"title\" = ? OR \"id\" > ?)) AND \"id\" >= ?)"
+                .to_string(),
+            vec![
+                FinalType::String("First todo".to_string()),
+                FinalType::Number(2.into()),
+                FinalType::Number(1.into()),
+            ]
+        )
+    );
+}
+
+/// Test that an empty `not_in` list compiles to a tautology, and that `not_in` lists
+/// are bound as a single array on PostgreSQL
+#[test]
+fn test_not_in_sql() {
+    let query = |value: serde_json::Value| -> QueryTree {
+        serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": "not_in", "value": value }
+            }
+        }))
+        .unwrap()
+    };
+    let list = vec![FinalType::Number(1.into()), FinalType::Number(3.into())];
+
+    for backend in [DbKind::Sqlite, DbKind::MySql, DbKind::Postgres] {
+        assert_eq!(
+            prepare_sqlx_query(&query(serde_json::json!([])), backend).unwrap(),
+            ("SELECT * FROM todos WHERE 1 = 1".to_string(), vec![])
+        );
+    }
+    assert_eq!(
+        prepare_sqlx_query(&query(serde_json::json!([1, 3])), DbKind::Postgres).unwrap(),
+        (
+            "SELECT * FROM todos WHERE \"id\" <> ALL($1)".to_string(),
+            vec![FinalType::Array(list.clone())]
+        )
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query(serde_json::json!([1, 3])), DbKind::Sqlite).unwrap(),
+        (
+            "SELECT * FROM todos WHERE \"id\" not in (?, ?)".to_string(),
+            list
+        )
+    );
+}
+
+/// Test that `in` lists are bound as a single array on PostgreSQL, and expanded
+/// into one placeholder per value on the other backends
+#[test]
+fn test_in_array_sql() {
+    let query = |value: serde_json::Value| -> QueryTree {
+        serde_json::from_value(serde_json::json!({
+            "return": "many",
+            "table": "todos",
+            "condition": {
+                "type": "single",
+                "constraint": { "column": "id", "operator": "in", "value": value }
+            }
+        }))
+        .unwrap()
+    };
+    let list = vec![FinalType::Number(1.into()), FinalType::Number(3.into())];
+
+    assert_eq!(
+        prepare_sqlx_query(&query(serde_json::json!([1, 3])), DbKind::Postgres).unwrap(),
+        (
+            "SELECT * FROM todos WHERE \"id\" = ANY($1)".to_string(),
+            vec![FinalType::Array(list.clone())]
+        )
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query(serde_json::json!([1, 3])), DbKind::Sqlite).unwrap(),
+        (
+            "SELECT * FROM todos WHERE \"id\" in (?, ?)".to_string(),
+            list
+        )
+    );
+
+    // Lists of nulls keep one placeholder per value
+    assert_eq!(
+        prepare_sqlx_query(&query(serde_json::json!([null])), DbKind::Postgres)
+            .unwrap()
+            .1,
+        vec![FinalType::Null]
+    );
+}
+
+/// Test that scalar functions wrap the column of a constraint, with the name of each backend
+#[test]
+fn test_scalar_function_sql() {
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "title", "fn": "length", "operator": ">", "value": 100 }
+        }
+    }))
+    .unwrap();
+
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::Sqlite).unwrap(),
+        (
+            "SELECT * FROM todos WHERE LENGTH(\"title\") > ?".to_string(),
+            vec![FinalType::Number(100.into())]
+        )
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::Postgres).unwrap().0,
+        "SELECT * FROM todos WHERE LENGTH(\"title\") > $1"
+    );
+    // MySQL `LENGTH` counts bytes
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::MySql).unwrap().0,
+        "SELECT * FROM todos WHERE CHAR_LENGTH(\"title\") > ?"
+    );
+    assert_eq!(
+        query.condition.unwrap().to_string(),
+        "LENGTH(\"title\") > 100"
+    );
+}
+
+/// Ids of the todos fetched for the `EXISTS` queries, with and without subquery condition
+fn exists_queries() -> Vec<(QueryTree, Vec<i32>)> {
+    let query = read_serialized_query("15_exists.json");
+    let mut unconditional = query.clone();
+    if let Some(Condition::Exists { subquery, .. }) = &mut unconditional.condition {
+        subquery.condition = None;
+    }
+
+    vec![(query, vec![1]), (unconditional, vec![1, 3])]
+}
+
+/// Test fetching todos filtered by a correlated `EXISTS` condition on SQLite
+#[tokio::test]
+async fn test_exists_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+    sqlx::query("CREATE TABLE comments (id INTEGER PRIMARY KEY, todo_id INTEGER, content TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query(INSERT_COMMENTS).execute(&pool).await.unwrap();
+
+    for (query, expected) in exists_queries() {
+        let ids: Vec<i32> = fetch_sqlite_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        assert_eq!(ids, expected);
+    }
+}
+
+/// Test fetching todos filtered by a correlated `EXISTS` condition on PostgreSQL
+#[tokio::test]
+async fn test_exists_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+    sqlx::query(
+        "CREATE TEMPORARY TABLE comments (id INTEGER PRIMARY KEY, todo_id INTEGER, content TEXT)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    sqlx::query(INSERT_COMMENTS).execute(&pool).await.unwrap();
+
+    for (query, expected) in exists_queries() {
+        let ids: Vec<i32> = fetch_postgres_query(&query, &pool)
+            .await
+            .unwrap()
+            .unwrap_many()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+
+        assert_eq!(ids, expected);
+    }
+}
+
+/// Query comparing two columns to the same value
+fn repeated_value_query() -> QueryTree {
+    serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "or",
+            "conditions": [
+                { "type": "single", "constraint": { "column": "title", "operator": "=", "value": "Second todo" } },
+                { "type": "single", "constraint": { "column": "content", "operator": "=", "value": "Second todo" } },
+                { "type": "single", "constraint": { "column": "id", "operator": "=", "value": 3 } }
+            ]
+        }
+    }))
+    .unwrap()
+}
+
+/// Test that identical values are bound once and referenced by every occurrence on
+/// PostgreSQL, and bound once per occurrence on the other backends
+#[tokio::test]
+async fn test_reused_placeholders_postgres() {
+    let query = repeated_value_query();
+    let value = FinalType::String("Second todo".to_string());
+
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::Postgres).unwrap(),
+        (
+            "SELECT * FROM todos WHERE (\"title\" = $1 OR \"content\" = $1 OR \"id\" = $2)"
+                .to_string(),
+            vec![value.clone(), FinalType::Number(3.into())]
+        )
+    );
+    assert_eq!(
+        prepare_sqlx_query(&query, DbKind::Sqlite).unwrap().1,
+        vec![value.clone(), value, FinalType::Number(3.into())]
+    );
+
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+
+    let mut ids: Vec<i32> = fetch_postgres_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many()
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+    ids.sort();
+
+    assert_eq!(ids, vec![2, 3]);
+}
+
+/// Test that an `in` list with more values than the PostgreSQL limit of 65535 bound
+/// parameters per statement is fetched, as it is bound as a single array
+#[tokio::test]
+async fn test_large_in_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "todos",
+        "condition": {
+            "type": "single",
+            "constraint": {
+                "column": "id",
+                "operator": "in",
+                "value": (2..70_000).collect::<Vec<i64>>()
+            }
+        }
+    }))
+    .unwrap();
+
+    let mut ids: Vec<i32> = fetch_postgres_query(&query, &pool)
+        .await
+        .unwrap()
+        .unwrap_many()
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+    ids.sort();
+
+    assert_eq!(ids, vec![2, 3]);
+}
+
+/// Test that a batch of independent queries is fetched in order,
+/// with per-query errors that do not fail the other queries
+#[tokio::test]
+async fn test_fetch_many() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let queries = vec![
+        read_serialized_query("04_many_with_condition.json"),
+        read_serialized_query("13_distinct_on.json"),
+        read_serialized_query("01_single.json"),
+        read_serialized_query("02_many.json"),
+    ];
+    let results = fetch_many_sqlite_queries(queries, &pool, 2).await;
+    assert_eq!(results.len(), 4);
+
+    let QueryData::Many(rows) = results[0].as_ref().unwrap() else {
+        panic!("Expected many rows");
+    };
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get::<i64, _>("id"), 2);
+
+    // DISTINCT ON is not supported by SQLite
+    assert!(matches!(results[1], Err(QueryError::Unsupported { .. })));
+
+    let QueryData::Single(Some(row)) = results[2].as_ref().unwrap() else {
+        panic!("Expected a single row");
+    };
+    assert_eq!(row.get::<i64, _>("id"), 1);
+
+    let QueryData::Many(rows) = results[3].as_ref().unwrap() else {
+        panic!("Expected many rows");
+    };
+    assert_eq!(rows.len(), 3);
+}
+
+/// Test that rows are serialized with the data model registered for their table at runtime
+#[tokio::test]
+async fn test_serialize_rows_dynamic() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let registry = TypeRegistry::new().with_table::<Todo>("todos");
+    assert!(registry.contains("todos"));
+
+    let query = read_serialized_query("02_many.json");
+    let rows = fetch_sqlite_query(&query, &pool).await.unwrap();
+    let value = serialize_rows_dynamic(&rows, "todos", &registry).unwrap();
+    assert_eq!(value, serialize_rows::<Todo, _>(&rows));
+    assert_eq!(value["data"].as_array().unwrap().len(), 3);
+
+    let SnapshotError::UnknownTable(table) =
+        serialize_rows_dynamic(&rows, "users", &registry).unwrap_err()
+    else {
+        panic!("Expected an unknown table error");
+    };
+    assert_eq!(table, "users");
+}
+
+/// Test that the database errors of queries carry their generated SQL and the types
+/// of their bindings, and their values in debug mode only
+#[tokio::test]
+async fn test_statement_error() {
+    let pool = dummy_sqlite_database().await;
+    let query: QueryTree = serde_json::from_value(serde_json::json!({
+        "return": "many",
+        "table": "missing_todos",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "title", "operator": "=", "value": "Secret title" }
+        }
+    }))
+    .unwrap();
+    let fetch = || fetch_sqlite_query_with_timeout(&query, &pool, Duration::from_secs(10));
+
+    let Err(err) = fetch().await else {
+        panic!("Expected the query to fail");
+    };
+    let QueryError::Statement { sql, bindings, .. } = &err else {
+        panic!("Expected a statement error, got {err:?}");
+    };
+    assert_eq!(sql, "SELECT * FROM missing_todos WHERE \"title\" = $1");
+    assert_eq!(bindings, "[string]");
+    assert!(err.database_error().is_some());
+
+    let message = err.to_string();
+    assert!(
+        message.contains("no such table: missing_todos"),
+        "{message}"
+    );
+    assert!(message.contains(sql), "{message}");
+    assert!(!message.contains("Secret title"), "{message}");
+
+    set_debug_bindings(true);
+    let result = fetch().await;
+    set_debug_bindings(false);
+    let Err(err) = result else {
+        panic!("Expected the query to fail");
+    };
+    let message = err.to_string();
+    assert!(message.contains("bound to ['Secret title']"), "{message}");
+}
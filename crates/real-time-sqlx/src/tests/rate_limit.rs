@@ -0,0 +1,38 @@
+//! Rate limiter tests
+
+use std::time::{Duration, Instant};
+
+use crate::{error::RateLimitError, rate_limit::RateLimiter};
+
+/// Test that bursts beyond the capacity are rejected with a retry delay
+#[test]
+fn test_rate_limit_burst() {
+    let limiter = RateLimiter::new(3, 1.0);
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        assert!(limiter.check_at("client", now).is_ok());
+    }
+
+    let RateLimitError::Exceeded { retry_after } = limiter.check_at("client", now).unwrap_err();
+    assert_eq!(retry_after, Duration::from_secs(1));
+
+    // Other connections have their own bucket
+    assert!(limiter.check_at("other", now).is_ok());
+
+    // A token is regained after the retry delay
+    assert!(limiter.check_at("client", now + retry_after).is_ok());
+    assert!(limiter.check_at("client", now + retry_after).is_err());
+}
+
+/// Test that operations at a steady rate within the refill rate always succeed
+#[test]
+fn test_rate_limit_steady() {
+    let limiter = RateLimiter::new(1, 10.0);
+    let start = Instant::now();
+
+    for i in 0..100 {
+        let now = start + Duration::from_millis(100 * i);
+        assert!(limiter.check_at("client", now).is_ok());
+    }
+}
@@ -0,0 +1,79 @@
+//! Session replay tests
+
+use serde_json::json;
+
+use crate::replay::{replay, ReplayEvent};
+
+use super::dummy::{dummy_sqlite_database, prepare_dummy_sqlite_database, Todo};
+
+/// A short recorded session on the todos table
+fn recorded_session() -> serde_json::Value {
+    json!([
+        {
+            "type": "subscribe",
+            "channelId": "all",
+            "query": { "return": "many", "table": "todos" }
+        },
+        {
+            "type": "subscribe",
+            "channelId": "second",
+            "query": {
+                "return": "single",
+                "table": "todos",
+                "condition": {
+                    "type": "single",
+                    "constraint": { "column": "id", "operator": "=", "value": 2 }
+                }
+            }
+        },
+        {
+            "type": "operation",
+            "operation": {
+                "type": "update",
+                "table": "todos",
+                "id": 2,
+                "data": { "title": "Updated todo" }
+            }
+        },
+        { "type": "unsubscribe", "channelId": "second" },
+        {
+            "type": "operation",
+            "operation": { "type": "delete", "table": "todos", "id": 2 }
+        }
+    ])
+}
+
+/// Test that a recorded session survives a serialization round trip, and that replaying
+/// it reproduces the messages received by each subscription
+#[tokio::test]
+async fn test_replay_session() {
+    let events: Vec<ReplayEvent> = serde_json::from_value(recorded_session()).unwrap();
+    let serialized = serde_json::to_value(&events).unwrap();
+    let events: Vec<ReplayEvent> = serde_json::from_value(serialized.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&events).unwrap(), serialized);
+
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let messages = replay::<Todo>(&events, &pool).await;
+    assert_eq!(messages.len(), 2);
+
+    let updated = json!({ "id": 2, "title": "Updated todo", "content": "This is the second todo" });
+
+    let all = &messages["all"];
+    assert_eq!(all.len(), 3);
+    assert_eq!(all[0]["type"], "many");
+    assert_eq!(all[0]["data"].as_array().unwrap().len(), 3);
+    assert_eq!(all[1]["type"], "update");
+    assert_eq!(all[1]["data"], updated);
+    assert_eq!(all[2]["type"], "delete");
+    assert_eq!(all[2]["id"], 2);
+
+    // The deletion happened after the unsubscription
+    let second = &messages["second"];
+    assert_eq!(second.len(), 2);
+    assert_eq!(second[0]["type"], "single");
+    assert_eq!(second[0]["data"]["title"], "Second todo");
+    assert_eq!(second[1]["type"], "update");
+    assert_eq!(second[1]["data"], updated);
+}
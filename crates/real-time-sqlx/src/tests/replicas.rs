@@ -0,0 +1,78 @@
+//! Read replica routing tests
+
+use std::fs;
+
+use serde_json::json;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Sqlite,
+};
+
+use crate::{
+    database::{
+        sqlite::{fetch_sqlite_query, granular_operation_sqlite},
+        ReadWritePools,
+    },
+    operations::serialize::GranularOperation,
+};
+
+use super::{
+    dummy::{prepare_dummy_sqlite_database, Todo},
+    utils::read_serialized_query,
+};
+
+/// Create and seed a SQLite database file
+async fn sqlite_file_database(name: &str) -> (Pool<Sqlite>, std::path::PathBuf) {
+    let path =
+        std::env::temp_dir().join(format!("real-time-sqlx-{name}-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let options = SqliteConnectOptions::new()
+        .filename(&path)
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .connect_with(options)
+        .await
+        .unwrap();
+    prepare_dummy_sqlite_database(&pool).await;
+
+    (pool, path)
+}
+
+/// Test that queries are routed to the read replica and operations to the primary,
+/// with two database files standing for a primary and a lagging replica
+#[tokio::test]
+async fn test_read_write_pools() {
+    let (primary, primary_path) = sqlite_file_database("primary").await;
+    let (replica, replica_path) = sqlite_file_database("replica").await;
+
+    // Without replica, queries are routed to the primary
+    let pools = ReadWritePools::new(primary.clone());
+    assert!(pools.reader().connect_options().get_filename() == primary_path);
+
+    let pools = pools.with_read(replica.clone());
+    assert!(pools.reader().connect_options().get_filename() == replica_path);
+    assert!(pools.writer().connect_options().get_filename() == primary_path);
+
+    let operation = GranularOperation::try_from(json!({
+        "type": "create",
+        "table": "todos",
+        "data": { "title": "Replicated later", "content": "" }
+    }))
+    .unwrap();
+    granular_operation_sqlite::<_, Todo>(operation, pools.writer())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The write reached the primary only, and the replica has not caught up yet
+    let query = read_serialized_query("02_many.json");
+    let rows = fetch_sqlite_query(&query, pools.reader()).await.unwrap();
+    assert_eq!(rows.unwrap_many().len(), 3);
+    let rows = fetch_sqlite_query(&query, pools.writer()).await.unwrap();
+    assert_eq!(rows.unwrap_many().len(), 4);
+
+    primary.close().await;
+    replica.close().await;
+    let _ = fs::remove_file(&primary_path);
+    let _ = fs::remove_file(&replica_path);
+}
@@ -0,0 +1,51 @@
+//! Test database seeding from JSON rows
+
+use serde_json::json;
+
+use crate::{
+    database::sqlite::{fetch_sqlite_query, seed_sqlite, sqlite_rows_to_json},
+    operations::serialize::object_array_from_value,
+    queries::serialize::QueryTree,
+};
+
+use super::dummy::dummy_sqlite_database;
+
+/// Test that seeded rows can be fetched back, with inferred column types
+#[tokio::test]
+async fn test_seed_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    let rows = object_array_from_value(json!([
+        { "id": 1, "title": "First", "score": 1.5, "done": true, "tags": ["a", "b"] },
+        { "id": 2, "title": "Second", "score": 2.5, "done": false, "tags": [] },
+        { "id": 3, "title": "Third", "score": 0.5, "done": false },
+    ]))
+    .unwrap();
+
+    seed_sqlite(&pool, "notes", &rows).await.unwrap();
+
+    let query: QueryTree = serde_json::from_value(json!({
+        "return": "many",
+        "table": "notes",
+        "condition": {
+            "type": "single",
+            "constraint": { "column": "score", "operator": ">", "value": 1 }
+        },
+        "orderBy": { "order": "asc", "column": "id" }
+    }))
+    .unwrap();
+    let fetched = fetch_sqlite_query(&query, &pool).await.unwrap();
+
+    assert_eq!(
+        sqlite_rows_to_json(&fetched.unwrap_many()),
+        json!([
+            { "id": 1, "title": "First", "score": 1.5, "done": true, "tags": "[\"a\",\"b\"]" },
+            { "id": 2, "title": "Second", "score": 2.5, "done": false, "tags": "[]" },
+        ])
+    );
+
+    // The integer id is the primary key
+    assert!(sqlx::query("INSERT INTO notes (id) VALUES (1)")
+        .execute(&pool)
+        .await
+        .is_err());
+}
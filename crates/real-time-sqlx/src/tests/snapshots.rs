@@ -0,0 +1,268 @@
+//! Snapshot difference tests
+
+use serde_json::json;
+
+use crate::{
+    error::SnapshotError,
+    operations::serialize::{object_from_value, JsonObject},
+    queries::serialize::{FinalType, QueryData},
+    snapshots::{
+        diff_snapshots, keyed_snapshot, snapshot_hash, with_snapshot_hash, wrap_snapshot,
+        CacheMutation, RowsEnvelope, SnapshotEnvelope, TaggedEnvelope,
+    },
+};
+
+use super::dummy::Todo;
+
+/// Build a todo
+fn todo(id: i32, title: &str) -> Todo {
+    Todo {
+        id,
+        title: title.to_string(),
+        content: "Content".to_string(),
+    }
+}
+
+/// Build the key of a todo
+fn key(id: i32) -> FinalType {
+    FinalType::Number(id.into())
+}
+
+/// Test the mutations between two snapshots with additions, removals and changes
+#[test]
+fn test_diff_snapshots() {
+    let old = vec![todo(1, "First"), todo(2, "Second"), todo(3, "Third")];
+    let new = vec![
+        todo(4, "Fourth"),
+        todo(1, "First"),
+        todo(3, "Third, updated"),
+    ];
+
+    assert_eq!(
+        diff_snapshots(&old, &new).unwrap(),
+        vec![
+            CacheMutation::Remove { id: key(2) },
+            CacheMutation::Insert {
+                data: todo(4, "Fourth")
+            },
+            CacheMutation::Update {
+                id: key(3),
+                data: todo(3, "Third, updated")
+            },
+        ]
+    );
+
+    // Identical snapshots produce no mutation
+    assert!(diff_snapshots(&new, &new).unwrap().is_empty());
+
+    // From and to empty snapshots
+    assert_eq!(diff_snapshots(&[], &old).unwrap().len(), 3);
+    assert_eq!(
+        diff_snapshots(&old, &[]).unwrap(),
+        vec![
+            CacheMutation::Remove { id: key(1) },
+            CacheMutation::Remove { id: key(2) },
+            CacheMutation::Remove { id: key(3) },
+        ]
+    );
+}
+
+/// Test the mutations between two snapshots of serialized rows
+#[test]
+fn test_diff_serialized_snapshots() {
+    let row = |value: serde_json::Value| object_from_value(value).unwrap();
+    let old = vec![
+        row(json!({ "id": 1, "title": "First" })),
+        row(json!({ "id": 2, "title": "Second" })),
+    ];
+    let new = vec![
+        row(json!({ "id": 2, "title": "Second", "done": true })),
+        row(json!({ "id": 3, "title": "Third" })),
+    ];
+
+    let mutations = serde_json::to_value(diff_snapshots(&old, &new).unwrap()).unwrap();
+    assert_eq!(
+        mutations,
+        json!([
+            { "type": "remove", "id": 1 },
+            { "type": "update", "id": 2, "data": { "id": 2, "title": "Second", "done": true } },
+            { "type": "insert", "data": { "id": 3, "title": "Third" } },
+        ])
+    );
+}
+
+/// Test that rows without primary key, such as projected rows, fail the difference
+/// instead of panicking
+#[test]
+fn test_diff_snapshots_missing_key() {
+    let row = |value: serde_json::Value| object_from_value(value).unwrap();
+    let old = vec![row(json!({ "id": 1, "title": "First" }))];
+    let new = vec![row(json!({ "title": "First" }))];
+
+    assert!(matches!(
+        diff_snapshots(&old, &new),
+        Err(SnapshotError::MissingKey(column)) if column == "id"
+    ));
+    assert!(matches!(
+        diff_snapshots(&new, &[]),
+        Err(SnapshotError::MissingKey(_))
+    ));
+}
+
+/// Test that snapshot hashes only depend on the values and order of the rows
+#[test]
+fn test_snapshot_hash() {
+    let snapshot = json!([
+        { "id": 1, "title": "First", "content": "Content" },
+        { "id": 2, "title": "Second", "content": "Content" },
+    ]);
+    // Same rows, with keys in another order
+    let identical = json!([
+        { "content": "Content", "title": "First", "id": 1 },
+        { "title": "Second", "id": 2, "content": "Content" },
+    ]);
+    let changed = json!([
+        { "id": 1, "title": "First", "content": "Content" },
+        { "id": 2, "title": "Changed", "content": "Content" },
+    ]);
+    let reordered = json!([
+        { "id": 2, "title": "Second", "content": "Content" },
+        { "id": 1, "title": "First", "content": "Content" },
+    ]);
+
+    let hash = snapshot_hash(&snapshot);
+    assert_eq!(hash.len(), 16);
+    assert_eq!(snapshot_hash(&identical), hash);
+    assert_ne!(snapshot_hash(&changed), hash);
+    assert_ne!(snapshot_hash(&reordered), hash);
+    // Stable across processes and Rust versions
+    assert_eq!(snapshot_hash(&json!([])), "09612b07b5ecb5a5");
+}
+
+/// Test that snapshots already known to the client are left out
+#[test]
+fn test_with_snapshot_hash() {
+    let snapshot = json!({ "type": "many", "data": [{ "id": 1, "title": "First" }] });
+    let hash = snapshot_hash(&snapshot["data"]);
+
+    let hashed = with_snapshot_hash(snapshot.clone(), None);
+    assert_eq!(hashed["hash"], hash.as_str());
+    assert_eq!(hashed["data"], snapshot["data"]);
+
+    assert_eq!(
+        with_snapshot_hash(snapshot.clone(), Some("0000000000000000")),
+        hashed
+    );
+    assert_eq!(
+        with_snapshot_hash(snapshot, Some(&hash)),
+        json!({ "type": "unchanged", "hash": hash })
+    );
+
+    let single = json!({ "type": "single", "data": { "id": 1 } });
+    assert_eq!(with_snapshot_hash(single.clone(), None), single);
+}
+
+/// Test that `Many` snapshots can be keyed by a column, and that keys must be unique
+#[test]
+fn test_keyed_snapshot() {
+    let snapshot = json!({
+        "type": "many",
+        "data": [
+            { "id": 1, "code": "a", "title": "First" },
+            { "id": 2, "code": "b", "title": "Second" }
+        ]
+    });
+
+    assert_eq!(
+        keyed_snapshot(snapshot.clone(), "id").unwrap(),
+        json!({
+            "type": "keyed",
+            "data": {
+                "1": { "id": 1, "code": "a", "title": "First" },
+                "2": { "id": 2, "code": "b", "title": "Second" }
+            }
+        })
+    );
+    assert_eq!(
+        keyed_snapshot(snapshot.clone(), "code").unwrap()["data"]["b"]["title"],
+        "Second"
+    );
+
+    // Single snapshots are left as is
+    let single = json!({ "type": "single", "data": { "id": 1 } });
+    assert_eq!(keyed_snapshot(single.clone(), "id").unwrap(), single);
+
+    let duplicated = json!({
+        "type": "many",
+        "data": [{ "id": 1, "code": "a" }, { "id": 2, "code": "a" }]
+    });
+    assert!(matches!(
+        keyed_snapshot(duplicated, "code"),
+        Err(SnapshotError::DuplicateKey(key)) if key == "a"
+    ));
+    assert!(matches!(
+        keyed_snapshot(snapshot, "missing"),
+        Err(SnapshotError::MissingKey(column)) if column == "missing"
+    ));
+}
+
+/// Custom envelope, `{ "items": [...], "count", "pagination": { "next" } }`
+struct ItemsEnvelope;
+
+impl SnapshotEnvelope for ItemsEnvelope {
+    fn wrap(&self, data: QueryData<serde_json::Value>, meta: JsonObject) -> serde_json::Value {
+        let items: Vec<serde_json::Value> = match data {
+            QueryData::Single(row) => row.into_iter().collect(),
+            QueryData::Many(rows) => rows,
+        };
+        json!({
+            "count": items.len(),
+            "items": items,
+            "pagination": { "next": meta.get("nextCursor").cloned().unwrap_or_default() },
+        })
+    }
+}
+
+/// Test that snapshots can be rewrapped in other envelopes, the default one
+/// leaving them unchanged
+#[test]
+fn test_wrap_snapshot() {
+    let rows = json!([
+        { "id": 1, "title": "First" },
+        { "id": 2, "title": "Second" }
+    ]);
+    let snapshot = json!({ "type": "many", "data": rows, "hash": "abc" });
+
+    // The default envelope keeps the current shape
+    assert_eq!(wrap_snapshot(snapshot.clone(), &TaggedEnvelope), snapshot);
+
+    // The other fields of the snapshot become metadata
+    assert_eq!(
+        wrap_snapshot(snapshot.clone(), &RowsEnvelope),
+        json!({ "rows": rows, "meta": { "hash": "abc" } })
+    );
+
+    // Pagination metadata in a custom envelope
+    let page = json!({ "type": "many", "data": rows, "nextCursor": 2 });
+    assert_eq!(
+        wrap_snapshot(page, &ItemsEnvelope),
+        json!({ "count": 2, "items": rows, "pagination": { "next": 2 } })
+    );
+
+    // Single snapshots have at most one row
+    assert_eq!(
+        wrap_snapshot(json!({ "type": "single", "data": null }), &RowsEnvelope),
+        json!({ "rows": [], "meta": {} })
+    );
+    assert_eq!(
+        wrap_snapshot(
+            json!({ "type": "single", "data": { "id": 1 } }),
+            &ItemsEnvelope
+        ),
+        json!({ "count": 1, "items": [{ "id": 1 }], "pagination": { "next": null } })
+    );
+
+    // Other snapshots are returned as is
+    let unchanged = json!({ "type": "unchanged", "hash": "abc" });
+    assert_eq!(wrap_snapshot(unchanged.clone(), &RowsEnvelope), unchanged);
+}
@@ -0,0 +1,6 @@
+-- Create a dummy table, isolated to the current session
+CREATE TEMPORARY TABLE todos (
+    id SERIAL PRIMARY KEY,
+    title VARCHAR(255) NOT NULL,
+    content VARCHAR(255) NOT NULL
+);
@@ -0,0 +1,155 @@
+//! Per-query timeout tests.
+//! Slow queries are emulated with views over expensive statements.
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{
+    cancellation::CancellationToken,
+    database::{
+        acquire_connection,
+        postgres::{fetch_postgres_query, fetch_postgres_query_with_timeout},
+        sqlite::{
+            fetch_many_sqlite_queries_with_acquire_timeout, fetch_sqlite_query,
+            fetch_sqlite_query_cancellable_with_acquire_timeout, fetch_sqlite_query_with_timeout,
+        },
+    },
+    error::QueryError,
+    queries::serialize::QueryTree,
+};
+
+use super::{
+    dummy::{
+        dummy_postgres_database, dummy_sqlite_database, prepare_dummy_postgres_database,
+        prepare_dummy_sqlite_database,
+    },
+    utils::read_serialized_query,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Query fetching all rows from the slow view
+fn slow_query() -> QueryTree {
+    serde_json::from_value(json!({ "return": "many", "table": "slow_todos" })).unwrap()
+}
+
+/// Test that a fast query completes normally under a timeout
+#[tokio::test]
+async fn test_timeout_fast_query() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let query = read_serialized_query("02_many.json");
+    let rows = fetch_sqlite_query_with_timeout(&query, &pool, TIMEOUT)
+        .await
+        .unwrap();
+
+    assert_eq!(rows.unwrap_many().len(), 3);
+}
+
+/// Test that a slow SQLite query is interrupted, and that the connection can be reused
+#[tokio::test]
+async fn test_timeout_sqlite() {
+    let pool = dummy_sqlite_database().await;
+    prepare_dummy_sqlite_database(&pool).await;
+
+    // Scan a hundred million generated rows without returning any of them
+    sqlx::query(
+        "CREATE VIEW slow_todos AS \
+        WITH RECURSIVE counter(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM counter WHERE x < 100000000) \
+        SELECT x AS id FROM counter WHERE x < 0",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let result = fetch_sqlite_query_with_timeout(&slow_query(), &pool, TIMEOUT).await;
+    assert!(matches!(result, Err(QueryError::Timeout(timeout)) if timeout == TIMEOUT));
+
+    // The pool holds a single connection, which must still be usable
+    let rows = fetch_sqlite_query(&read_serialized_query("02_many.json"), &pool)
+        .await
+        .unwrap();
+    assert_eq!(rows.unwrap_many().len(), 3);
+}
+
+/// Test that a slow PostgreSQL query is cancelled, and that the statement timeout
+/// does not leak to the pooled connection
+#[tokio::test]
+async fn test_timeout_postgres() {
+    let Some(pool) = dummy_postgres_database().await else {
+        return;
+    };
+    prepare_dummy_postgres_database(&pool).await;
+
+    sqlx::query(
+        "CREATE TEMPORARY VIEW slow_todos AS SELECT todos.* FROM todos CROSS JOIN pg_sleep(5)",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let result = fetch_postgres_query_with_timeout(&slow_query(), &pool, TIMEOUT).await;
+    assert!(matches!(result, Err(QueryError::Timeout(timeout)) if timeout == TIMEOUT));
+
+    let rows = fetch_postgres_query(&read_serialized_query("02_many.json"), &pool)
+        .await
+        .unwrap();
+    assert_eq!(rows.unwrap_many().len(), 3);
+
+    let (statement_timeout,): (String,) = sqlx::query_as("SHOW statement_timeout")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(statement_timeout, "0");
+}
+
+/// Test that a fetch waiting for a connection of an exhausted pool fails with
+/// `QueryError::PoolExhausted` instead of blocking
+#[tokio::test]
+async fn test_acquire_timeout() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(TIMEOUT * 3)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    prepare_dummy_sqlite_database(&pool).await;
+
+    // A transaction keeps the only connection busy while another fetch is attempted
+    let busy = pool.begin().await.unwrap();
+    let query = read_serialized_query("02_many.json");
+    let fetch = || async {
+        let mut conn = acquire_connection(&pool, Some(TIMEOUT)).await?;
+        Ok::<_, QueryError>(fetch_sqlite_query(&query, &mut *conn).await.unwrap())
+    };
+
+    let result = fetch().await;
+    assert!(matches!(result, Err(QueryError::PoolExhausted(timeout)) if timeout == TIMEOUT));
+
+    // Without explicit timeout, the acquire timeout of the pool applies
+    let result = acquire_connection(&pool, None).await;
+    assert!(matches!(result, Err(QueryError::PoolExhausted(timeout)) if timeout == TIMEOUT * 3));
+
+    // Batch and cancellable fetches acquire their connections within the timeout as well
+    let results = fetch_many_sqlite_queries_with_acquire_timeout(
+        vec![query.clone(), query.clone()],
+        &pool,
+        2,
+        Some(TIMEOUT),
+    )
+    .await;
+    assert!(results.iter().all(
+        |result| matches!(result, Err(QueryError::PoolExhausted(timeout)) if *timeout == TIMEOUT)
+    ));
+    let token = CancellationToken::new();
+    let result =
+        fetch_sqlite_query_cancellable_with_acquire_timeout(&query, &pool, &token, Some(TIMEOUT))
+            .await;
+    assert!(matches!(result, Err(QueryError::PoolExhausted(timeout)) if timeout == TIMEOUT));
+
+    // The fetch succeeds once the connection is released
+    busy.rollback().await.unwrap();
+    assert_eq!(fetch().await.unwrap().unwrap_many().len(), 3);
+}
@@ -0,0 +1,75 @@
+//! Write serialization tests
+
+use std::{fs, sync::Arc, time::Duration};
+
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::{
+    database::{sqlite::granular_operation_sqlite_with_options, DbKind, OperationOptions},
+    error::OperationError,
+    operations::serialize::{GranularOperation, OperationNotification},
+    writes::WriteSerializer,
+};
+
+use super::dummy::{prepare_dummy_sqlite_database, Todo};
+
+/// Test that concurrent operations on a SQLite database file, from several connections
+/// and without busy timeout, never fail with `SQLITE_BUSY` when their writes are serialized
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_write_serialization() {
+    assert!(!WriteSerializer::new(DbKind::Postgres).is_enabled());
+    assert!(!WriteSerializer::new(DbKind::MySql).is_enabled());
+
+    let path =
+        std::env::temp_dir().join(format!("real-time-sqlx-writes-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let options = SqliteConnectOptions::new()
+        .filename(&path)
+        .create_if_missing(true)
+        .busy_timeout(Duration::ZERO);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(8)
+        .connect_with(options)
+        .await
+        .unwrap();
+    prepare_dummy_sqlite_database(&pool).await;
+
+    let serializer = Arc::new(WriteSerializer::new(DbKind::Sqlite));
+    let tasks: Vec<_> = (0..50)
+        .map(|index| {
+            let pool = pool.clone();
+            let serializer = serializer.clone();
+            tokio::spawn(async move {
+                let operation = GranularOperation::try_from(json!({
+                    "type": "create_many",
+                    "table": "todos",
+                    "data": [
+                        { "title": format!("Todo {index}"), "content": "" },
+                        { "title": format!("Todo {index} bis"), "content": "" }
+                    ]
+                }))
+                .unwrap();
+                let options = OperationOptions::default();
+                let write =
+                    granular_operation_sqlite_with_options::<_, Todo>(operation, &pool, &options);
+                serializer.run(write).await
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        let result: Result<Option<OperationNotification<Todo>>, OperationError> =
+            task.await.unwrap();
+        assert!(result.unwrap().is_some());
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM todos")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 3 + 100);
+
+    pool.close().await;
+    let _ = fs::remove_file(&path);
+}
@@ -1,4 +1,11 @@
-use std::{fmt, iter::repeat};
+use std::{fmt, iter::repeat_n};
+
+use serde::{
+    de::{self, Visitor},
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+
+use crate::{database::DbKind, error::DeserializeError, operations::serialize::ConflictPolicy};
 
 /// Utility function to format a list of displayable items with a specific
 /// separator
@@ -41,6 +48,7 @@ pub(crate) fn ordered_keys(object: &serde_json::Map<String, serde_json::Value>)
 
 /// Convert a string with '?' placeholders to numbered '$1' placeholderss
 #[inline]
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
 pub(crate) fn to_numbered_placeholders(query: &str) -> String {
     let mut result = String::new();
     let mut counter = 1;
@@ -57,28 +65,26 @@ pub(crate) fn to_numbered_placeholders(query: &str) -> String {
     result
 }
 
+/// Convert a string with '"' quoted identifiers to MySQL '`' quoted identifiers.
+/// Generated queries only contain sanitized identifiers and placeholders, so
+/// double quotes can only come from identifier quoting.
+#[inline]
+#[cfg(feature = "mysql")]
+pub(crate) fn to_backtick_identifiers(query: &str) -> String {
+    query.replace('"', "`")
+}
+
 /// Create a placeholder string (?, ?, ?) for a given count of placeholders,
 /// for one value
 #[inline]
 pub(crate) fn placeholders(count: usize) -> String {
-    let str_placeholders = repeat("?".to_string())
-        .take(count)
+    let str_placeholders = repeat_n("?".to_string(), count)
         .collect::<Vec<String>>()
         .join(", ");
 
     format!("({str_placeholders})")
 }
 
-/// Create a placeholder string (?, ?, ?), (?, ?, ?), (?, ?, ?) for a given
-/// count of placeholders, for n values
-#[inline]
-pub(crate) fn repeat_placeholders(count: usize, n_repeat: usize) -> String {
-    repeat(placeholders(count))
-        .take(n_repeat)
-        .collect::<Vec<String>>()
-        .join(", ")
-}
-
 /// Sanitize table and column names to avoid SQL injection
 /// Only letters, numbers and underscores are allowed. No spaces
 #[inline]
@@ -96,7 +102,7 @@ pub(crate) fn update_statement(table: &str, keys: &[String]) -> String {
         .collect::<Vec<String>>()
         .join(", ");
 
-    format!("UPDATE {table} SET {columns} WHERE id = ? RETURNING *")
+    format!("UPDATE {table} SET {columns} WHERE id = ?")
 }
 
 /// Generate an INSERT statement from a table name and a list of keys
@@ -106,18 +112,67 @@ pub(crate) fn insert_statement(table: &str, keys: &[String]) -> String {
     let values_placeholders = placeholders(keys.len());
     let columns = format_iter(keys.iter().map(|s| sanitize_identifier(s)), ", ");
 
-    format!("INSERT INTO {table} ({columns}) VALUES {values_placeholders} RETURNING *")
+    format!("INSERT INTO {table} ({columns}) VALUES {values_placeholders}")
 }
 
-/// Generate an INSERT statement from a table name and a list of keys
-/// to insert multiple rows at once
+/// Generate an INSERT statement of several rows from a table name and a list of keys
+#[cfg(feature = "test-support")]
 #[inline]
-pub(crate) fn insert_many_statement(table: &str, keys: &[String], n_rows: usize) -> String {
+pub(crate) fn insert_many_statement(table: &str, keys: &[String], rows: usize) -> String {
     let table = sanitize_identifier(table);
-    let values_placeholders = repeat_placeholders(keys.len(), n_rows);
+    let values_placeholders = repeat_n(placeholders(keys.len()), rows)
+        .collect::<Vec<String>>()
+        .join(", ");
     let columns = format_iter(keys.iter().map(|s| sanitize_identifier(s)), ", ");
 
-    format!("INSERT INTO {table} ({columns}) VALUES {values_placeholders} RETURNING *")
+    format!("INSERT INTO {table} ({columns}) VALUES {values_placeholders}")
+}
+
+/// Generate an INSERT statement handling conflicts with existing rows
+/// according to a conflict policy, in the dialect of a database backend.
+/// Rows are replaced on `id` conflicts on PostgreSQL, which requires a conflict target.
+pub(crate) fn insert_statement_on_conflict(
+    table: &str,
+    keys: &[String],
+    on_conflict: ConflictPolicy,
+    backend: DbKind,
+) -> String {
+    let statement = insert_statement(table, keys);
+
+    match (on_conflict, backend) {
+        (ConflictPolicy::Error, _) => statement,
+        (ConflictPolicy::Ignore, DbKind::Sqlite) => {
+            statement.replacen("INSERT INTO", "INSERT OR IGNORE INTO", 1)
+        }
+        (ConflictPolicy::Replace, DbKind::Sqlite) => {
+            statement.replacen("INSERT INTO", "INSERT OR REPLACE INTO", 1)
+        }
+        (ConflictPolicy::Ignore, DbKind::MySql) => {
+            statement.replacen("INSERT INTO", "INSERT IGNORE INTO", 1)
+        }
+        (ConflictPolicy::Replace, DbKind::MySql) => {
+            statement.replacen("INSERT INTO", "REPLACE INTO", 1)
+        }
+        (ConflictPolicy::Ignore, DbKind::Postgres) => format!("{statement} ON CONFLICT DO NOTHING"),
+        (ConflictPolicy::Replace, DbKind::Postgres) => {
+            let mut columns: Vec<String> = keys
+                .iter()
+                .map(|key| sanitize_identifier(key))
+                .filter(|column| column != "id")
+                .collect();
+            if columns.is_empty() {
+                columns.push("id".to_string());
+            }
+
+            let assignments = format_iter(
+                columns
+                    .iter()
+                    .map(|column| format!("{column} = EXCLUDED.{column}")),
+                ", ",
+            );
+            format!("{statement} ON CONFLICT (id) DO UPDATE SET {assignments}")
+        }
+    }
 }
 
 /// Generate a DELETE statement from a table name and an id
@@ -125,7 +180,50 @@ pub(crate) fn insert_many_statement(table: &str, keys: &[String], n_rows: usize)
 pub(crate) fn delete_statement(table: &str) -> String {
     let table = sanitize_identifier(table);
 
-    format!("DELETE FROM {table} WHERE id = ? RETURNING *")
+    format!("DELETE FROM {table} WHERE id = ?")
+}
+
+/// Generate the list of selected or returned columns: all of them (`*`) by default,
+/// or the given ones
+#[inline]
+pub(crate) fn column_list(columns: Option<&[String]>) -> String {
+    match columns {
+        Some(columns) => columns
+            .iter()
+            .map(|column| format!("\"{}\"", sanitize_identifier(column)))
+            .collect::<Vec<String>>()
+            .join(", "),
+        None => "*".to_string(),
+    }
+}
+
+/// Generate a SELECT statement fetching a single row by id from a table name,
+/// with all its columns or the given ones
+#[inline]
+pub(crate) fn select_by_id_statement(table: &str, columns: Option<&[String]>) -> String {
+    let table = sanitize_identifier(table);
+    let columns = column_list(columns);
+
+    format!("SELECT {columns} FROM {table} WHERE id = ?")
+}
+
+/// Append a `RETURNING` clause to an INSERT, UPDATE or DELETE statement,
+/// for the backends that support it (SQLite, PostgreSQL).
+/// All the columns are returned by default (`RETURNING *`), or the given ones.
+#[inline]
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+pub(crate) fn with_returning(statement: String, columns: Option<&[String]>) -> String {
+    format!("{statement} RETURNING {}", column_list(columns))
+}
+
+/// Attach an optional client-supplied request id to a serialized response
+/// or notification object, for request/response correlation.
+#[inline]
+pub fn tag_request_id(mut value: serde_json::Value, request_id: Option<&str>) -> serde_json::Value {
+    if let (Some(request_id), serde_json::Value::Object(object)) = (request_id, &mut value) {
+        object.insert("requestId".to_string(), request_id.into());
+    }
+    value
 }
 
 /// SQL-like implementation of the LIKE operator
@@ -164,14 +262,202 @@ pub(crate) fn sql_like(filter: &str, value: &str) -> bool {
     )
 }
 
+/// Exclusive upper bound of the strings starting with a prefix, in code point order:
+/// the prefix with its last code point incremented. Trailing maximum code points
+/// (`U+10FFFF`) cannot be incremented, and are dropped to increment the previous one.
+/// Returns `None` when there is no such bound (empty prefix, or only maximum code points),
+/// as every string greater than or equal to the prefix then starts with it.
+pub fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+
+    while let Some(last) = chars.pop() {
+        // Surrogates are not valid code points, and are skipped
+        let next = match last {
+            '\u{D7FF}' => Some('\u{E000}'),
+            last => char::from_u32(last as u32 + 1),
+        };
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+
+    None
+}
+
 /// SQL-like implementation of the ILIKE operator
 pub(crate) fn sql_ilike(filter: &str, value: &str) -> bool {
     sql_like(&filter.to_lowercase(), &value.to_lowercase())
 }
 
+/// Token of a compiled LIKE pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LikeToken {
+    /// Literal character
+    Char(char),
+    /// '_': any single character
+    AnyChar,
+    /// '%': zero or more characters
+    AnyString,
+}
+
+/// LIKE pattern compiled once, to be matched against many values with the same
+/// results as `sql_like`, without parsing the pattern again nor recursing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LikePattern {
+    tokens: Vec<LikeToken>,
+}
+
+impl LikePattern {
+    /// Compile a LIKE pattern. Consecutive '%' are merged, as they match the same values.
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens: Vec<LikeToken> = Vec::with_capacity(pattern.len());
+
+        for c in pattern.chars() {
+            let token = match c {
+                '%' if tokens.last() == Some(&LikeToken::AnyString) => continue,
+                '%' => LikeToken::AnyString,
+                '_' => LikeToken::AnyChar,
+                c => LikeToken::Char(c),
+            };
+            tokens.push(token);
+        }
+
+        LikePattern { tokens }
+    }
+
+    /// Check if a value matches the pattern.
+    /// Iterative wildcard matching: on a mismatch, the last '%' is retried
+    /// one character further in the value.
+    pub fn matches(&self, value: &str) -> bool {
+        let tokens = &self.tokens;
+        // Token and value byte positions
+        let (mut t, mut v) = (0, 0);
+        // Token position after the last '%', and value position it was tried at
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while let Some(c) = value[v..].chars().next() {
+            match tokens.get(t) {
+                Some(LikeToken::AnyString) => {
+                    t += 1;
+                    backtrack = Some((t, v));
+                }
+                Some(LikeToken::AnyChar) => {
+                    t += 1;
+                    v += c.len_utf8();
+                }
+                Some(LikeToken::Char(expected)) if *expected == c => {
+                    t += 1;
+                    v += c.len_utf8();
+                }
+                _ => match backtrack {
+                    Some((after_any, start)) => {
+                        // Let the last '%' consume one more character
+                        let start = start + value[start..].chars().next().unwrap().len_utf8();
+                        backtrack = Some((after_any, start));
+                        t = after_any;
+                        v = start;
+                    }
+                    None => return false,
+                },
+            }
+        }
+
+        // The remaining tokens must all match the empty string
+        tokens[t..]
+            .iter()
+            .all(|token| *token == LikeToken::AnyString)
+    }
+}
+
+/// ILIKE pattern compiled once, matched against lowercased values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ILikePattern(LikePattern);
+
+impl ILikePattern {
+    /// Compile an ILIKE pattern
+    pub fn new(pattern: &str) -> Self {
+        ILikePattern(LikePattern::new(&pattern.to_lowercase()))
+    }
+
+    /// Check if a value matches the pattern, ignoring case
+    pub fn matches(&self, value: &str) -> bool {
+        self.0.matches(&value.to_lowercase())
+    }
+}
+
+/// Rebuild a JSON value with the keys of every object sorted, so that its
+/// serialization does not depend on insertion order
+pub(crate) fn sort_json_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let mut entries = object.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_json_keys(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(array) => {
+            serde_json::Value::Array(array.into_iter().map(sort_json_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// Deserializer that only records the field names of the struct deserialized from it
+struct FieldsDeserializer<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for FieldsDeserializer<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("fields recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Field names of a struct deriving `Deserialize`, after serde renaming.
+/// Returns `None` for types that are not deserialized as structs with a fixed
+/// set of fields (maps, structs with flattened fields...).
+pub fn struct_fields<'de, T: Deserialize<'de>>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldsDeserializer(&mut fields));
+    fields
+}
+
+/// Deserialize a node of a JSON document, reporting a failure at its JSON pointer path
+pub(crate) fn deserialize_at<'de, T: Deserialize<'de>>(
+    value: &'de serde_json::Value,
+    path: &str,
+) -> Result<T, DeserializeError> {
+    T::deserialize(value).map_err(|source| DeserializeError::Malformed {
+        path: path.to_string(),
+        source,
+    })
+}
+
 #[cfg(test)]
 mod test_utils {
-    use super::sql_like;
+    use super::{sql_ilike, sql_like, ILikePattern, LikePattern};
 
     #[test]
     /// The sql_like function was generated with ChatGPT
@@ -186,4 +472,42 @@ mod test_utils {
         assert!(sql_like("h_llo", "hello"));
         assert!(!sql_like("he_lo", "heeeelo"));
     }
+
+    /// Every combination of the given characters, up to a length
+    fn combinations(alphabet: &[char], max_length: usize) -> Vec<String> {
+        let mut all = vec![String::new()];
+        let mut previous = vec![String::new()];
+        for _ in 0..max_length {
+            previous = previous
+                .iter()
+                .flat_map(|prefix| alphabet.iter().map(move |c| format!("{prefix}{c}")))
+                .collect();
+            all.extend(previous.iter().cloned());
+        }
+        all
+    }
+
+    #[test]
+    /// Compiled patterns must match exactly the same values as `sql_like` and `sql_ilike`
+    fn test_compiled_patterns() {
+        let patterns = combinations(&['a', 'B', 'é', '%', '_'], 4);
+        let values = combinations(&['a', 'b', 'A', 'é'], 4);
+
+        for pattern in &patterns {
+            let like = LikePattern::new(pattern);
+            let ilike = ILikePattern::new(pattern);
+            for value in &values {
+                assert_eq!(
+                    like.matches(value),
+                    sql_like(pattern, value),
+                    "{pattern} LIKE {value}"
+                );
+                assert_eq!(
+                    ilike.matches(value),
+                    sql_ilike(pattern, value),
+                    "{pattern} ILIKE {value}"
+                );
+            }
+        }
+    }
 }
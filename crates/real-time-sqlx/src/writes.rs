@@ -0,0 +1,49 @@
+//! Serialization of the writes to databases allowing a single writer.
+
+use std::future::Future;
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::database::DbKind;
+
+/// Async lock serializing the writes to a database.
+///
+/// SQLite allows a single writer at a time: concurrent writes on the same pool wait for
+/// each other at the database level, and fail with `SQLITE_BUSY` once the busy timeout
+/// expires. Queuing them in the process instead avoids these errors without blocking
+/// reads. The lock covers the whole database rather than single tables, because SQLite
+/// locks the whole database file. It is a no-op for the other backends, which handle
+/// concurrent writers.
+#[derive(Debug, Default)]
+pub struct WriteSerializer {
+    lock: Option<Mutex<()>>,
+}
+
+impl WriteSerializer {
+    /// Create a write serializer for a database backend, only locking for SQLite
+    pub fn new(backend: DbKind) -> Self {
+        Self {
+            lock: (backend == DbKind::Sqlite).then(Mutex::default),
+        }
+    }
+
+    /// Whether writes are serialized
+    pub fn is_enabled(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    /// Wait for the ongoing write to finish, if any. Writes are serialized
+    /// until the returned guard is dropped.
+    pub async fn lock(&self) -> Option<MutexGuard<'_, ()>> {
+        match &self.lock {
+            Some(lock) => Some(lock.lock().await),
+            None => None,
+        }
+    }
+
+    /// Run a write once the previous ones are done
+    pub async fn run<F: Future>(&self, write: F) -> F::Output {
+        let _guard = self.lock().await;
+        write.await
+    }
+}